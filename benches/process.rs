@@ -0,0 +1,102 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+use tokio_stream::StreamExt;
+use toy_payment_engine::{
+    generate, process_transactions, Amount, CsvDialect, CsvSource, FixedDecimal, GenerateOptions,
+    Rounding, TransactionSource,
+};
+
+const TRANSACTIONS: u64 = 100_000;
+
+fn bench_process_transactions(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("process_transactions");
+    group.throughput(Throughput::Elements(TRANSACTIONS));
+    for dispute_ratio in [0.0, 0.01, 0.1] {
+        let options = GenerateOptions {
+            transactions: TRANSACTIONS,
+            dispute_ratio,
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        runtime.block_on(generate(&options, &mut data)).unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("dispute_ratio", dispute_ratio),
+            &data,
+            |b, data| {
+                b.to_async(&runtime)
+                    .iter(|| process_transactions(data.as_slice()))
+            },
+        );
+    }
+    group.finish();
+}
+
+// The arithmetic of the hot path, i.e. parsing, rounding and adding up the amounts
+fn apply_amounts<A: Amount>(amounts: &[String]) -> A {
+    amounts.iter().fold(A::ZERO, |balance, amount| {
+        let amount = A::parse_exact(amount)
+            .unwrap()
+            .round_to(4, Rounding::Truncate);
+        balance.checked_add(amount).unwrap().saturating_sub(A::ONE)
+    })
+}
+
+fn bench_amounts(c: &mut Criterion) {
+    let amounts: Vec<String> = (0..TRANSACTIONS)
+        .map(|i| format!("{}.{:04}", i % 1_000, i * 7 % 10_000))
+        .collect();
+    let mut group = c.benchmark_group("amounts");
+    group.throughput(Throughput::Elements(TRANSACTIONS));
+    group.bench_with_input(
+        BenchmarkId::new("backend", "rust_decimal"),
+        &amounts,
+        |b, amounts| b.iter(|| apply_amounts::<rust_decimal::Decimal>(amounts)),
+    );
+    group.bench_with_input(
+        BenchmarkId::new("backend", "fixed-point"),
+        &amounts,
+        |b, amounts| b.iter(|| apply_amounts::<FixedDecimal>(amounts)),
+    );
+    group.finish();
+}
+
+// Reading the records alone, deserialized or parsed by the fast path
+fn bench_parse(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let options = GenerateOptions {
+        transactions: TRANSACTIONS,
+        ..Default::default()
+    };
+    let mut data = Vec::new();
+    runtime.block_on(generate(&options, &mut data)).unwrap();
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    for fast_parse in [false, true] {
+        let dialect = CsvDialect {
+            fast_parse,
+            ..Default::default()
+        };
+        group.bench_with_input(
+            BenchmarkId::new("fast_parse", fast_parse),
+            &data,
+            |b, data| {
+                b.to_async(&runtime).iter(|| {
+                    CsvSource::new(data.as_slice())
+                        .dialect(dialect.clone())
+                        .into_stream()
+                        .fold(0, |records, tx| records + tx.is_ok() as usize)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_process_transactions,
+    bench_amounts,
+    bench_parse
+);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Any input has to be either processed or rejected with an error, without panicking
+fuzz_target!(|data: &[u8]| {
+    let _ = toy_payment_engine::process_transactions_bytes(data);
+});
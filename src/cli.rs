@@ -0,0 +1,1891 @@
+use std::{ffi::OsString, path::Path, sync::Arc, time::Duration};
+use tokio::{
+    io::{self, AsyncWriteExt},
+    sync::{mpsc, watch},
+};
+
+use clap::{
+    error::ErrorKind, parser::ValueSource, ArgAction, ArgMatches, CommandFactory, Parser,
+    Subcommand,
+};
+use tokio_stream::StreamExt;
+use toml_edit::{DocumentMut, Item, Value};
+use tracing::{info, warn};
+
+use crate::{
+    check_report, diff_reports, engine, file_digest, forward_events, generate, is_object_url,
+    limit_read_rate, list_input_files, logging, open_files, open_files_chunked, output, pipelined,
+    repl, AccrueInterest, Anonymizer, AtomicFile, AuditDigests, Checkpoint, ClientFilter,
+    ColumnMapping, Compression, CsvDialect, Decimal, DuplicateFilePolicy, EngineError, EventSink,
+    FeeSchedule, FileOrder, FileStore, GenerateOptions, HeldFundsPolicy, InputFormat,
+    JsonLinesSink, LogFormat, MergePolicy, NegativeBalancePolicy, OutputFormat, OutputScale,
+    PaymentEngine, PrecisionPolicy, RateLimit, RecordOrdering, RejectReason, ReleaseHeldFunds,
+    ReportOptions, ResolveStaleDisputes, Rounding, SortBy, TimeWindow, TransactionStream,
+    TypeFilter, WarnThrottle, WithdrawalLimit,
+};
+#[cfg(feature = "webhooks")]
+use crate::{AccountEvent, WebhookSink};
+
+// Using a struct here for maintanaibility reasons, so that if the application/engine needs
+// to handle other future command-line arguments, they can be easily added.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[command(after_help = EXIT_CODES_HELP)]
+struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // Options of `process`, the command run when none is given
+    #[command(flatten)]
+    pub process: ProcessArgs,
+}
+
+// Options of the commands processing input files
+#[derive(clap::Args, Debug)]
+struct ProcessArgs {
+    // Input file paths, in CSV or JSON Lines format, processed one after the other. The input
+    // files in a directory are processed in the order given with `--order`.
+    #[arg(index = 1, required = true, env = "TPE_INPUT", value_parser = parse_filepath)]
+    pub file_paths: Vec<String>,
+
+    // Path of a TOML file setting the options, with an `option = value` key per option named
+    // as its flag, e.g. `state_dir = "state"`. Options given on the command line, or with a
+    // `TPE_*` environment variable, take precedence. See `config print-default` for a template.
+    #[arg(long)]
+    pub config: Option<String>,
+
+    // Order of the input files found in directories: `name` (lexicographic) or `mtime` (the
+    // oldest first)
+    #[arg(long, default_value = "name")]
+    pub order: FileOrder,
+
+    // Format of the input files: `csv` or `jsonl`. If not specified, it's guessed from the
+    // extension of each file.
+    #[arg(long)]
+    pub format: Option<InputFormat>,
+
+    // Compression of the input files: `none`, `gzip` or `zstd`. If not specified, it's guessed
+    // from the extension of each file, e.g. `.csv.gz`.
+    #[arg(long)]
+    pub compression: Option<Compression>,
+
+    // Delimiter of the fields of CSV input files, e.g. `;` or `\t`
+    #[arg(long, default_value = ",", value_parser = parse_ascii_char)]
+    pub delimiter: u8,
+
+    // Character quoting the fields of CSV input files
+    #[arg(long, default_value = "\"", value_parser = parse_ascii_char)]
+    pub quote: u8,
+
+    // Read quotes in CSV input files as any other character
+    #[arg(long)]
+    pub no_quoting: bool,
+
+    // CSV input files have no header row: their columns are the ones given with `--columns`,
+    // in order
+    #[arg(long)]
+    pub no_headers: bool,
+
+    // Columns of CSV input files holding the fields of the transactions, when named otherwise,
+    // e.g. `type=txn_type,client=customer_id`
+    #[arg(long, conflicts_with = "map_file")]
+    pub map: Option<ColumnMapping>,
+
+    // Path of a TOML file mapping the fields of the transactions to the columns of CSV input
+    // files holding them, with a `field = "column"` line per field
+    #[arg(long)]
+    pub map_file: Option<String>,
+
+    // Read amounts as floats, as previous versions did, instead of parsing them exactly: amounts
+    // with many significant digits may lose precision
+    #[arg(long)]
+    pub float_amounts: bool,
+
+    // Parse CSV records with only the `type`, `client`, `tx` and `amount` columns straight from
+    // their bytes, skipping the deserialization of the fields
+    #[arg(long)]
+    pub fast_parse: bool,
+
+    // Columns of headerless CSV input files, in order
+    #[arg(long, requires = "no_headers", value_delimiter = ',', default_values_t = CsvDialect::DEFAULT_COLUMNS.map(String::from))]
+    pub columns: Vec<String>,
+
+    // Path of the file the accounts report is written to, instead of stdout
+    #[arg(long, env = "TPE_OUTPUT")]
+    pub output: Option<String>,
+
+    // File with the Ed25519 key the accounts report is signed with, as a hex string, the
+    // signature being written to `<output>.sig`, see `verify-report`
+    #[cfg(feature = "signing")]
+    #[arg(long, requires = "output", conflicts_with = "watch")]
+    pub signing_key: Option<String>,
+
+    // File with the secret key the client ids of the accounts report are replaced with
+    // pseudonyms with, through HMAC-SHA256, so that it can be shared. The other reports, state
+    // files and notifications carrying client ids can't be written.
+    #[arg(
+        long,
+        value_name = "KEY_FILE",
+        conflicts_with_all = [
+            "rejects", "open_disputes", "lock_report", "export_ledger", "balance_history",
+            "risk_report", "export_events", "events_stderr", "audit_digests", "save_snapshot",
+            "state_dir", "checkpoint",
+        ]
+    )]
+    #[cfg_attr(feature = "http", arg(conflicts_with = "serve"))]
+    #[cfg_attr(feature = "webhooks", arg(conflicts_with = "webhook"))]
+    pub anonymize: Option<String>,
+
+    // Path of the file the pseudonyms of the clients are written to with their id, in CSV
+    // format, for the authorized users to map them back
+    #[arg(long, requires = "anonymize", conflicts_with = "stream_output")]
+    pub anonymize_mapping: Option<String>,
+
+    // Accounts report in CSV format the output is expected to match, regardless of the order of
+    // the clients, e.g. a golden file. The rows that differ are printed to stderr, and the exit
+    // code is 5 if there are any.
+    #[arg(
+        long,
+        value_name = "GOLDEN_FILE",
+        conflicts_with_all = ["anonymize", "stream_output", "watch", "validate_only"]
+    )]
+    pub expect: Option<String>,
+
+    // Format of the accounts report: `csv`, `json` or `parquet`
+    #[arg(long, default_value = "csv")]
+    pub output_format: OutputFormat,
+
+    // Decimal places of the balances in the accounts report: `preserve` (as many as they have),
+    // `fixed` (exactly the scale, e.g. `1.5000`) or `minimal` (no trailing zeros, e.g. `1.5`)
+    #[arg(long, default_value = "preserve")]
+    pub output_scale: OutputScale,
+
+    // Add a `status` column to the accounts report: `active`, `locked` or `closed` (by a `close`
+    // transaction). Not written by default, for the readers of the original columns.
+    #[arg(long)]
+    pub status_column: bool,
+
+    // Write the accounts report in CSV format as the accounts are finalized, e.g. by each shard,
+    // instead of keeping them all in memory until the end. The rows aren't sorted, and there's
+    // always a row per client and currency.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "output_format", "sort_by", "watch", "state_dir", "save_snapshot", "interest_bps",
+            "held_funds_on_lock", "stale_dispute_days",
+            "export_ledger", "balance_history", "open_disputes", "lock_report", "risk_report",
+            "validate_only",
+        ]
+    )]
+    #[cfg_attr(feature = "http", arg(conflicts_with = "serve"))]
+    pub stream_output: bool,
+
+    // Path of the file listing the transactions not applied, with the reason why, in CSV format
+    #[arg(long)]
+    pub rejects: Option<String>,
+
+    // Path of the file the deposits and withdrawals still under dispute are written to, in CSV
+    // format, with their age when they have timestamps
+    #[arg(long)]
+    pub open_disputes: Option<String>,
+
+    // Path of the file the locked accounts are written to, in CSV format, with the tx id and the
+    // position in the input of the chargeback that locked each one of them
+    #[arg(long)]
+    pub lock_report: Option<String>,
+
+    // Path of the file the accounts to triage for fraud are written to, in CSV format: the ones
+    // with the most funds held, with the most chargebacks and with the largest withdrawal
+    #[arg(long, value_name = "PATH")]
+    pub risk_report: Option<String>,
+
+    // Number of accounts of each ranking of the risk report
+    #[arg(long, default_value_t = 10, requires = "risk_report")]
+    pub risk_top: usize,
+
+    // Path of the file every applied transaction is written to, in CSV format, with its final
+    // status, the time it has been processed and the resulting balances of the account
+    #[arg(long)]
+    pub export_ledger: Option<String>,
+
+    // Path of the file the balances of the accounts at the end of each day are written to, in
+    // CSV format, a row per day, client and currency. Only the transactions with a timestamp
+    // are tracked, and days without any transaction of a client have no row for it.
+    #[arg(long, value_name = "PATH")]
+    pub balance_history: Option<String>,
+
+    // Path of the file the final digests of the SHA-256 hash chains over the applied
+    // transactions, globally and per account, are written to in JSON format, see `verify`
+    #[arg(long)]
+    pub audit_digests: Option<String>,
+
+    // Path of the file the events of the accounts (funds deposited, held, accounts locked,
+    // transactions rejected...) are streamed to while processing, as JSON lines
+    #[arg(long)]
+    pub export_events: Option<String>,
+
+    // Write the events of the accounts to stderr as JSON lines while processing
+    #[arg(long)]
+    pub events_stderr: bool,
+
+    // URL of an HTTP endpoint notified of the accounts getting locked and of the chargebacks,
+    // with the events posted as JSON. Can be repeated.
+    #[cfg(feature = "webhooks")]
+    #[arg(long)]
+    pub webhook: Vec<String>,
+
+    // Write a summary of the batch in JSON format (clients, volumes, disputes, locked accounts
+    // and rejections by reason) to the given file, or to stderr if no path is given
+    #[arg(long, num_args = 0..=1, default_missing_value = "-")]
+    pub summary: Option<String>,
+
+    // Additional files processed simultaneously with the input ones, into the same accounts
+    #[arg(long, value_parser = parse_filepath)]
+    pub merge_source: Vec<String>,
+
+    // How records of multiple sources are merged: `interleave` or `priority` (in the order given,
+    // input files first). Records of the same source are always applied in order.
+    #[arg(long, default_value = "interleave")]
+    pub merge_policy: MergePolicy,
+
+    // Number of records of each source parsed ahead by a separate task, while the previous ones
+    // are applied. Zero parses the records only when they are applied.
+    #[arg(long, default_value_t = 1024)]
+    pub pipeline_depth: usize,
+
+    // Number of chunks of the uncompressed local input files parsed concurrently, the records
+    // still being applied in order. Records can't span several lines. Zero or one parses the
+    // files sequentially.
+    #[arg(long, default_value_t = 0, conflicts_with = "watch")]
+    pub parallel_read: usize,
+
+    // Maximum rate the input files are read at, in MB per second across all of them, e.g. so
+    // that reading them from shared storage doesn't starve other workloads
+    #[arg(long, value_parser = parse_read_rate)]
+    pub max_read_mbps: Option<f64>,
+
+    // Maximum number of records read but not applied yet, bounding the memory taken by the
+    // buffers between reading and applying them whatever the size of the input files. It's
+    // split between the records parsed ahead (see `--pipeline-depth`) and the ones waiting for
+    // the shard tasks.
+    #[arg(long, conflicts_with = "parallel_read")]
+    pub max_pending_records: Option<usize>,
+
+    // Keep following the input file as it grows, like `tail -f`: the records appended to it are
+    // applied as they arrive, and the accounts report rewritten periodically. It runs until
+    // interrupted, without writing the other reports.
+    #[arg(long, conflicts_with_all = ["merge_source", "checkpoint", "compression"])]
+    pub watch: bool,
+
+    // Minimum number of seconds between two rewrites of the accounts report in watch mode
+    #[arg(long, default_value_t = 5, requires = "watch")]
+    pub watch_interval: u64,
+
+    // Order of the accounts in the report: `client`, `total` or `available`, ascending
+    #[arg(long, default_value = "client")]
+    pub sort_by: SortBy,
+
+    // Maximum number of decimal places of amounts and balances
+    #[arg(long, default_value_t = 4)]
+    pub scale: u32,
+
+    // How amounts exceeding the scale are rounded: `truncate`, `half-up` or `half-even`
+    #[arg(long, default_value = "truncate")]
+    pub rounding: Rounding,
+
+    // Reject the transactions with amounts exceeding the scale, instead of rounding them
+    #[arg(long)]
+    pub reject_excess_precision: bool,
+
+    // Log and skip the records that can't be parsed, instead of aborting the whole run
+    #[arg(long)]
+    pub skip_invalid: bool,
+
+    // Abort on the first record that can't be parsed or gets rejected (e.g. a duplicate tx id,
+    // a negative amount or a dispute of an unknown tx), instead of logging it and going on
+    #[arg(long, conflicts_with = "skip_invalid")]
+    pub strict: bool,
+
+    // Only parse and validate the records, applying them to a simulated engine, and print a
+    // validation report in JSON format instead of the accounts report. No state is saved.
+    #[arg(long, conflicts_with_all = ["watch", "checkpoint", "save_snapshot"])]
+    pub validate_only: bool,
+
+    // Reject deposits and withdrawals reusing the tx id of another client
+    #[arg(long)]
+    pub unique_tx_ids: bool,
+
+    // Allow disputes on withdrawals, holding the withdrawn amount until resolved or chargebacked
+    #[arg(long)]
+    pub allow_withdrawal_disputes: bool,
+
+    // When the available funds can become negative: `forbid`, `allow-on-chargeback` (disputes
+    // of deposits already withdrawn) or `allow-always` (withdrawals and transfers too)
+    #[arg(long, default_value = "forbid")]
+    pub negative_balance: NegativeBalancePolicy,
+
+    // Only apply the transactions of these clients, and write their accounts, e.g. `1,2,5-10`
+    #[arg(long)]
+    pub clients: Option<ClientFilter>,
+
+    // Skip the transactions of these clients, and their accounts, e.g. `1,2,5-10`
+    #[arg(long)]
+    pub exclude_clients: Option<ClientFilter>,
+
+    // Only apply the transactions of these types, e.g. `deposits,withdrawals`, skipping the
+    // other ones
+    #[arg(long)]
+    pub only: Option<TypeFilter>,
+
+    // Only apply the transactions from this time on, in seconds since the Unix epoch
+    #[arg(long)]
+    pub from: Option<u64>,
+
+    // Only apply the transactions before this time, in seconds since the Unix epoch
+    #[arg(long)]
+    pub to: Option<u64>,
+
+    // Reject disputes of transactions older than this number of days, when both have timestamps
+    #[arg(long)]
+    pub dispute_window_days: Option<u64>,
+
+    // Maximum amount of a single withdrawal
+    #[arg(long)]
+    pub max_withdrawal: Option<Decimal>,
+
+    // Maximum amount withdrawn from an account per day, in each currency. Withdrawals without
+    // timestamps are on the same day as the previous one.
+    #[arg(long)]
+    pub daily_limit: Option<Decimal>,
+
+    // Apply administrative transactions, i.e. `unlock` re-enabling a locked account, instead of
+    // rejecting them
+    #[arg(long)]
+    pub allow_admin_ops: bool,
+
+    // TOML file with the fees charged on deposits and withdrawals, a flat amount or a percentage
+    // per transaction type, e.g. `[withdrawal]` and `percent = 1.5`
+    #[arg(long)]
+    pub fees: Option<String>,
+
+    // Resolve the disputes of transactions older than this number of days at the latest
+    // timestamp, once all the input files are processed
+    #[arg(long)]
+    pub stale_dispute_days: Option<u64>,
+
+    // What happens to the funds still held by disputes of the locked accounts once all the input
+    // files are processed: `keep` (held), `resolve` (released) or `chargeback` (reversed)
+    #[arg(long, default_value = "keep")]
+    pub held_funds_on_lock: HeldFundsPolicy,
+
+    // Interest credited on the available funds of the unlocked accounts once all the input files
+    // are processed, in basis points (e.g. 25 for 0.25%)
+    #[arg(long)]
+    pub interest_bps: Option<u32>,
+
+    // Number of tasks processing transactions concurrently, partitioning accounts by client id,
+    // and of the partitions of the accounts served over the HTTP API
+    #[arg(long, default_value_t = 1, env = "TPE_SHARDS")]
+    pub shards: usize,
+
+    // Order the records are applied in: `strict` (one at a time, in file order) or `per-client`
+    // (in file order for each client, with `--shards` tasks or one per CPU). By default, `strict`
+    // unless `--shards` is given.
+    #[arg(long)]
+    pub ordering: Option<RecordOrdering>,
+
+    // Format of the logs written to stderr: `text` or `json`
+    #[arg(long, default_value = "text", env = "TPE_LOG_FORMAT")]
+    pub log_format: LogFormat,
+
+    // Level of the logs written to stderr, e.g. `info` or `toy_payment_engine=debug`, instead of
+    // the one set with `RUST_LOG`
+    #[arg(long, env = "TPE_LOG_LEVEL")]
+    pub log_level: Option<String>,
+
+    // Maximum number of warnings logged per rejection reason (unlimited by default)
+    #[arg(long)]
+    pub warn_limit: Option<usize>,
+
+    // Maximum number of warnings logged for a specific rejection reason, e.g. `account_locked=10`
+    #[arg(long, value_parser = parse_reason_limit)]
+    pub warn_limit_for: Vec<(RejectReason, usize)>,
+
+    // Maximum memory taken by the transactions history, in MB, including the index of the
+    // transactions spilled but not the balances of the accounts. Beyond it, the least recently
+    // used transactions are spilled to a temporary file and loaded back when referenced.
+    #[arg(long, conflicts_with_all = ["shards", "checkpoint"])]
+    pub max_memory_mb: Option<usize>,
+
+    // Directory where accounts and transactions history are persisted between runs. The state
+    // of the previous run is loaded before processing, and replaced with the new one afterwards.
+    #[arg(long)]
+    pub state_dir: Option<String>,
+
+    // What to do with an input file already ingested in a previous run with the same state
+    // directory, or given twice: `skip` or `reject`. Files are identified by their content.
+    #[arg(long, default_value = "skip", requires = "state_dir")]
+    pub on_duplicate_file: DuplicateFilePolicy,
+
+    // File where the engine state is periodically saved while processing, together with the
+    // offset reached in each input file. It's removed once all the input files are processed.
+    #[arg(long, conflicts_with = "shards")]
+    pub checkpoint: Option<String>,
+
+    // Number of records processed between two checkpoints
+    #[arg(long, default_value_t = 100_000)]
+    pub checkpoint_interval: u64,
+
+    // Resume an interrupted run from its checkpoint, if any, instead of processing the input
+    // files from scratch
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+
+    // Snapshot of the accounts, with their transactions history, to start from instead of
+    // empty accounts
+    #[arg(long, conflicts_with_all = ["state_dir", "resume"])]
+    pub load_snapshot: Option<String>,
+
+    // Accounts report in CSV format with the balances and lock state the clients start from,
+    // e.g. the output of the previous run, without their transactions history
+    #[arg(long, value_name = "REPORT", conflicts_with_all = ["state_dir", "resume"])]
+    pub initial_balances: Option<String>,
+
+    // File where a snapshot of the accounts, with their transactions history, is saved once the
+    // input files are processed
+    #[arg(long)]
+    pub save_snapshot: Option<String>,
+
+    // Address the HTTP API is served on once the input files are processed, e.g. `127.0.0.1:8080`,
+    // instead of writing the accounts report
+    #[cfg(feature = "http")]
+    #[arg(long)]
+    pub serve: Option<std::net::SocketAddr>,
+
+    // Show a live dashboard of the processing in the terminal, with the progress, the
+    // throughput, the accounts with the most volume and the rejects. Logs are off unless
+    // `--log-level` is given.
+    #[cfg(feature = "tui")]
+    #[arg(long, conflicts_with_all = ["watch", "validate_only"])]
+    pub tui: bool,
+}
+
+// Commands of the command line, `process` being run when none is given
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Processes the input files and writes the accounts report (default)
+    Process(ProcessArgs),
+    /// Only parses and validates the input files, printing a validation report in JSON format,
+    /// as `process --validate-only`
+    Validate(ProcessArgs),
+    /// Processes the input files and serves the accounts over an HTTP API, as `process --serve`
+    #[cfg(feature = "http")]
+    Serve {
+        // Address the HTTP API is served on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: std::net::SocketAddr,
+        #[command(flatten)]
+        args: ProcessArgs,
+    },
+    /// Prints the balance deltas per client between two accounts reports in CSV format, and
+    /// the accounts that got locked or unlocked
+    Diff {
+        // Accounts report used as a reference, e.g. the golden output
+        old: String,
+        // Accounts report compared to the reference one
+        new: String,
+    },
+    /// Processes the input files with the default options, and prints the balances differing
+    /// from the expected ones, with their deltas, in the format of `diff`
+    Reconcile {
+        // Accounts report with the expected balances of the clients
+        #[arg(long)]
+        expected: String,
+        // Input file paths, as for the main command
+        #[arg(required = true, value_parser = parse_filepath)]
+        file_paths: Vec<String>,
+    },
+    /// Checks the signature of an accounts report written with `--signing-key`
+    #[cfg(feature = "signing")]
+    VerifyReport {
+        // Accounts report
+        report: String,
+        // Signature of the report, `<report>.sig` if not specified
+        #[arg(long)]
+        signature: Option<String>,
+        // File with the public key of the signing key, as a hex string
+        #[arg(long)]
+        public_key: String,
+    },
+    /// Processes the input files and prints the hash chains whose final digest differs from the
+    /// one written with `--audit-digests`, `global` or the client id of an account
+    Verify {
+        // Digests written with `--audit-digests`, by a run with the same options
+        #[arg(long)]
+        digests: String,
+        #[command(flatten)]
+        args: ProcessArgs,
+    },
+    /// Prints the balances and the transactions history of a client from a snapshot of the
+    /// accounts, see `--save-snapshot`
+    Inspect {
+        // Snapshot of the accounts
+        #[arg(long)]
+        snapshot: String,
+        // Client whose account is printed
+        #[arg(long)]
+        client: u16,
+    },
+    /// Reads transactions and queries about the accounts from stdin, e.g. `deposit 1 100 5.0`
+    /// or `account 1`, applying them to accounts kept in memory (`help` lists the commands)
+    Repl {
+        // Allow disputes on withdrawals, as for the main command
+        #[arg(long)]
+        allow_withdrawal_disputes: bool,
+        // Apply administrative transactions, as for the main command
+        #[arg(long)]
+        allow_admin_ops: bool,
+    },
+    /// Writes synthetic transactions in CSV format, e.g. to benchmark the engine
+    Generate {
+        // Number of clients the transactions are spread over
+        #[arg(long, default_value_t = 1000)]
+        clients: u16,
+        // Number of transactions generated
+        #[arg(long, default_value_t = 100_000)]
+        transactions: u64,
+        // Share of the transactions disputing a previous deposit, between 0 and 1
+        #[arg(long, default_value_t = 0.01)]
+        dispute_ratio: f64,
+        // Seed of the random generator, the same one always generating the same transactions
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        // Path of the file written, stdout if not specified
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Manages the config files read with `--config`
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+// Commands about the config files
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Prints a config file with all the options commented out, set to their default value
+    PrintDefault,
+}
+
+// Options that can't be set in a config file
+const CONFIG_EXCLUDED: [&str; 3] = ["config", "help", "version"];
+
+// Time waited for records to be appended to the input file in watch mode, once all read
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Maximum number of appended records applied before rewriting the accounts report
+const WATCH_BATCH_SIZE: usize = 100_000;
+
+// Size of the chunks of the input files parsed concurrently, in bytes
+const READ_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+// Accounts waiting to be written to the report, when streamed
+const ACCOUNT_STREAM_CAPACITY: usize = 1024;
+
+fn parse_filepath(file_path: &str) -> Result<String, String> {
+    let path = Path::new(file_path);
+
+    // Check that the path exists, objects in object storage being checked once read
+    if !is_object_url(path) && !path.exists() {
+        return Err(String::from("File path doesn't exist"));
+    }
+
+    // Directories are read for the input files they contain
+    if path.is_dir() {
+        return Ok(file_path.into());
+    }
+
+    // Check that the file is a CSV or a JSON Lines one, possibly compressed
+    if let Some(ext) = Compression::data_path(path).extension() {
+        if let Some(ext_str) = ext.to_str() {
+            if ["csv", "jsonl", "ndjson"].contains(&ext_str.to_lowercase().as_str()) {
+                Ok(file_path.into())
+            } else {
+                Err(String::from("File is not in CSV or JSON Lines format"))
+            }
+        } else {
+            Err(String::from("Unable to convert file path to string"))
+        }
+    } else {
+        Err(String::from("File path hasn't any extension"))
+    }
+}
+
+fn parse_ascii_char(arg: &str) -> Result<u8, String> {
+    match arg {
+        "\\t" => Ok(b'\t'),
+        _ => match arg.as_bytes() {
+            [byte] if byte.is_ascii() => Ok(*byte),
+            _ => Err(format!("Expected a single ASCII character, not `{arg}`")),
+        },
+    }
+}
+
+fn parse_reason_limit(arg: &str) -> Result<(RejectReason, usize), String> {
+    let (reason, limit) = arg
+        .split_once('=')
+        .ok_or_else(|| String::from("Expected format is `<reason>=<limit>`"))?;
+    let limit = limit
+        .parse()
+        .map_err(|_| format!("Invalid limit `{limit}`"))?;
+    Ok((reason.parse()?, limit))
+}
+
+fn parse_read_rate(arg: &str) -> Result<f64, String> {
+    match arg.parse() {
+        Ok(rate) if rate > 0.0 && f64::is_finite(rate) => Ok(rate),
+        _ => Err(format!("Invalid rate `{arg}`, expected a positive number")),
+    }
+}
+
+/// Exit code of a run with all the records applied.
+pub const EXIT_SUCCESS: u8 = 0;
+/// Exit code of a run failing for any other reason than the ones below.
+pub const EXIT_FAILURE: u8 = 1;
+/// Exit code of a run completed with records rejected, or skipped being invalid.
+pub const EXIT_REJECTED: u8 = 2;
+/// Exit code of a run aborted on a record that can't be parsed.
+pub const EXIT_PARSE_ERROR: u8 = 3;
+/// Exit code of a run with invalid command-line arguments.
+pub const EXIT_INVALID_ARGS: u8 = 4;
+/// Exit code of a reconciliation finding balances differing from the expected ones.
+pub const EXIT_DISCREPANCIES: u8 = 5;
+/// Exit code of a run interrupted by a signal, its reports being partial.
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0    all the records applied
+  1    failure
+  2    completed with records rejected or skipped
+  3    aborted on a record that can't be parsed
+  4    invalid arguments
+  5    balances differing from the expected ones (reconcile, --expect)
+  130  interrupted, the reports being partial";
+
+/// How a run of the command line ended, when it didn't fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// All the records have been applied.
+    Clean,
+    /// Some records have been rejected, or skipped being invalid.
+    Rejected,
+    /// Some balances, or audit digests, differ from the expected ones, or a report signature
+    /// is invalid, see `--expect` and the `reconcile`, `verify` and `verify-report` subcommands.
+    Discrepancies,
+}
+
+/// Returns the exit code of the command line for the result of [`run`].
+pub fn exit_code(result: &Result<Outcome, EngineError>) -> u8 {
+    match result {
+        Ok(Outcome::Clean) => EXIT_SUCCESS,
+        Ok(Outcome::Rejected) => EXIT_REJECTED,
+        Ok(Outcome::Discrepancies) => EXIT_DISCREPANCIES,
+        Err(EngineError::Interrupted) => EXIT_INTERRUPTED,
+        // A JSON error comes from a file such as a snapshot or a checkpoint, not from a record:
+        // the invalid records of JSON Lines sources are reported as malformed, and the accounts
+        // reports read back have errors of their own
+        Err(EngineError::CsvError(_)) => EXIT_PARSE_ERROR,
+        Err(e) if e.is_invalid_record() => EXIT_PARSE_ERROR,
+        Err(_) => EXIT_FAILURE,
+    }
+}
+
+// Prints the error about the command-line arguments and exits, unless it's about printing the
+// help or the version
+fn exit_on_args_error(e: clap::Error) -> ! {
+    let _ = e.print();
+    let code = match e.use_stderr() {
+        true => EXIT_INVALID_ARGS,
+        false => EXIT_SUCCESS,
+    };
+    std::process::exit(code.into())
+}
+
+// Parses the command-line arguments, with the ones setting the options of the config file given
+// with `--config`, if any, inserted before the options of the command processing input files
+async fn parse_args() -> Result<Cli, EngineError> {
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    if let Ok(given) = Cli::command()
+        .ignore_errors(true)
+        .try_get_matches_from(&argv)
+    {
+        let given = match given.subcommand() {
+            None => Some((&given, 1)),
+            Some(("process" | "validate" | "serve" | "verify", given)) => Some((given, 2)),
+            Some(_) => None,
+        };
+        if let Some((given, at)) = given {
+            if let Some(path) = given.get_one::<String>("config") {
+                let config = config_args(path, given).await?;
+                argv.splice(at..at, config.into_iter().map(OsString::from));
+            }
+        }
+    }
+    Ok(Cli::try_parse_from(argv).unwrap_or_else(|e| exit_on_args_error(e)))
+}
+
+// Reads a config file into the command-line arguments setting its options. The options given on
+// the command line or with an environment variable, or conflicting with one given there, are
+// left out.
+async fn config_args(path: &str, given: &ArgMatches) -> Result<Vec<String>, EngineError> {
+    let data = tokio::fs::read_to_string(path).await?;
+    let doc: DocumentMut = data
+        .parse()
+        .map_err(|e| EngineError::ConfigError(format!("{e}")))?;
+    let cmd = Cli::command();
+    let given: Vec<_> = cmd
+        .get_arguments()
+        .filter(|arg| {
+            matches!(
+                given.value_source(arg.get_id().as_str()),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        })
+        .collect();
+
+    let mut args = Vec::new();
+    for (key, item) in doc.iter() {
+        let id = key.replace('-', "_");
+        let arg = cmd
+            .get_arguments()
+            .find(|arg| {
+                arg.get_id() == id.as_str()
+                    && arg.get_long().is_some()
+                    && !CONFIG_EXCLUDED.contains(&id.as_str())
+            })
+            .ok_or_else(|| EngineError::ConfigError(format!("Unknown option `{key}`")))?;
+        if given.iter().any(|given| {
+            given.get_id() == arg.get_id()
+                || cmd.get_arg_conflicts_with(given).contains(&arg)
+                || cmd.get_arg_conflicts_with(arg).contains(given)
+        }) {
+            continue;
+        }
+
+        let flag = format!("--{}", arg.get_long().unwrap_or_default());
+        let values: Vec<&Value> = match item {
+            Item::Value(Value::Array(values)) => values.iter().collect(),
+            Item::Value(value) => vec![value],
+            _ => {
+                return Err(EngineError::ConfigError(format!(
+                    "Expected a value for `{key}`"
+                )))
+            }
+        };
+        for value in values {
+            let value = match value {
+                Value::String(s) => s.value().clone(),
+                Value::Integer(i) => i.value().to_string(),
+                Value::Float(f) => f.value().to_string(),
+                Value::Boolean(b) => b.value().to_string(),
+                _ => {
+                    return Err(EngineError::ConfigError(format!(
+                        "Expected a string, a number or a boolean for `{key}`"
+                    )))
+                }
+            };
+            match (arg.get_action().takes_values(), value.as_str()) {
+                (true, _) => args.push(format!("{flag}={value}")),
+                (false, "true") => args.push(flag.clone()),
+                (false, "false") => {}
+                (false, _) => {
+                    return Err(EngineError::ConfigError(format!(
+                        "Expected a boolean for `{key}`"
+                    )))
+                }
+            }
+        }
+    }
+    Ok(args)
+}
+
+// Returns a config file with all the options commented out, set to their default value or to a
+// placeholder when they have none
+fn default_config() -> String {
+    let mut config = String::from(
+        "# Options of the payment engine, read with `--config`. Options given on the command line,\n\
+         # or with a `TPE_*` environment variable, take precedence. Uncomment the ones to set.\n\n",
+    );
+    let cmd = Cli::command();
+    for arg in cmd.get_arguments() {
+        let id = arg.get_id().as_str();
+        if arg.get_long().is_none() || arg.is_hide_set() || CONFIG_EXCLUDED.contains(&id) {
+            continue;
+        }
+        let value = |value: &str| match value.parse::<f64>().is_ok() || value == "true" {
+            true => value.to_string(),
+            false => format!("{value:?}"),
+        };
+        let defaults: Vec<String> = arg
+            .get_default_values()
+            .iter()
+            .map(|default| value(&default.to_string_lossy()))
+            .collect();
+        let value = match defaults.as_slice() {
+            _ if !arg.get_action().takes_values() => String::from("false"),
+            [] if matches!(arg.get_action(), ArgAction::Append) => {
+                format!("[\"<{}>\"]", id.to_uppercase())
+            }
+            [] => format!("\"<{}>\"", id.to_uppercase()),
+            [default] => default.clone(),
+            defaults => format!("[{}]", defaults.join(", ")),
+        };
+        config.push_str(&format!("# {id} = {value}\n"));
+    }
+    config
+}
+
+// Returns the number of shards applying the records in the given order
+fn ordering_shards(ordering: Option<RecordOrdering>, shards: usize) -> Result<usize, &'static str> {
+    match (ordering, shards) {
+        (Some(RecordOrdering::Strict), 2..) => Err(
+            "`--ordering strict` applies the records with a single task, `--shards` can't be used",
+        ),
+        (Some(RecordOrdering::PerClient), 1) => {
+            Ok(std::thread::available_parallelism().map_or(1, |cpus| cpus.get()))
+        }
+        (_, shards) => Ok(shards),
+    }
+}
+
+pub async fn run() -> Result<Outcome, engine::EngineError> {
+    // Init
+    let Cli {
+        command,
+        process: mut args,
+    } = parse_args().await?;
+    let mut expected = None;
+    let mut digests = None;
+    let command = match command {
+        Some(Command::Process(process)) => {
+            args = process;
+            None
+        }
+        Some(Command::Validate(process)) => {
+            args = process;
+            if args.watch || args.checkpoint.is_some() || args.save_snapshot.is_some() {
+                exit_on_args_error(Cli::command().error(
+                    ErrorKind::ArgumentConflict,
+                    "`validate` can't be used with `--watch`, `--checkpoint` or `--save-snapshot`",
+                ));
+            }
+            args.validate_only = true;
+            None
+        }
+        #[cfg(feature = "http")]
+        Some(Command::Serve {
+            addr,
+            args: process,
+        }) => {
+            args = process;
+            args.serve = Some(addr);
+            None
+        }
+        Some(Command::Verify {
+            digests: path,
+            args: process,
+        }) => {
+            args = process;
+            args.audit_digests = None;
+            digests = Some(path);
+            None
+        }
+        Some(Command::Reconcile {
+            expected: path,
+            file_paths,
+        }) => {
+            args.file_paths = file_paths;
+            expected = Some(path);
+            None
+        }
+        command => command,
+    };
+    #[cfg(feature = "tui")]
+    let log_level = args.log_level.as_deref().or(args.tui.then_some("off"));
+    #[cfg(not(feature = "tui"))]
+    let log_level = args.log_level.as_deref();
+    logging::init(args.log_format, log_level);
+    limit_read_rate(
+        args.max_read_mbps
+            .map(|mbps| RateLimit::new(mbps * 1_000_000.0)),
+    );
+    info!("Payment engine started.");
+    let mut file_paths = Vec::new();
+    for path in &args.file_paths {
+        if Path::new(path).is_dir() {
+            let files = list_input_files(path, args.order).await?;
+            info!(dir = %path, files = files.len(), "Input files found");
+            file_paths.extend(files.iter().map(|file| file.display().to_string()));
+        } else {
+            file_paths.push(path.clone());
+        }
+    }
+    args.file_paths = file_paths;
+    // A directory may hold no input files
+    if args.watch && args.file_paths.len() != 1 {
+        exit_on_args_error(Cli::command().error(
+            ErrorKind::ArgumentConflict,
+            "`--watch` follows exactly one input file",
+        ));
+    }
+
+    args.shards = ordering_shards(args.ordering, args.shards).unwrap_or_else(|message| {
+        exit_on_args_error(Cli::command().error(ErrorKind::ArgumentConflict, message))
+    });
+    if args.shards > 1 && (args.checkpoint.is_some() || args.max_memory_mb.is_some()) {
+        exit_on_args_error(Cli::command().error(
+            ErrorKind::ArgumentConflict,
+            "`--ordering per-client` and `--shards` can't be used with `--checkpoint` or `--max-memory-mb`",
+        ));
+    }
+
+    match &command {
+        Some(Command::Diff { old, new }) => {
+            let old = tokio::fs::File::open(old).await?;
+            let new = tokio::fs::File::open(new).await?;
+            let rows = diff_reports(old, new, io::stdout()).await?;
+            info!(rows, "Reports compared");
+            return Ok(Outcome::Clean);
+        }
+        #[cfg(feature = "signing")]
+        Some(Command::VerifyReport {
+            report,
+            signature,
+            public_key,
+        }) => {
+            let signature = signature.clone().unwrap_or_else(|| format!("{report}.sig"));
+            if crate::verify_report(report, &signature, public_key).await? {
+                info!(report = %report, "Signature verified");
+                return Ok(Outcome::Clean);
+            }
+            warn!(report = %report, signature = %signature, "Invalid signature");
+            return Ok(Outcome::Discrepancies);
+        }
+        Some(Command::Config {
+            command: ConfigCommand::PrintDefault,
+        }) => {
+            print!("{}", default_config());
+            return Ok(Outcome::Clean);
+        }
+        Some(Command::Inspect { snapshot, client }) => {
+            let mut engine = PaymentEngine::new();
+            engine.load_snapshot(snapshot).await?;
+            let account = engine
+                .account(*client)
+                .ok_or(EngineError::ClientNotFound(*client))?;
+            output::write_account(account, io::stdout()).await?;
+            return Ok(Outcome::Clean);
+        }
+        Some(Command::Repl {
+            allow_withdrawal_disputes,
+            allow_admin_ops,
+        }) => {
+            let mut engine = PaymentEngine::builder()
+                .allow_withdrawal_disputes(*allow_withdrawal_disputes)
+                .allow_admin_ops(*allow_admin_ops)
+                .build();
+            repl(&mut engine, io::BufReader::new(io::stdin()), io::stdout()).await?;
+            return Ok(Outcome::Clean);
+        }
+        Some(Command::Generate {
+            clients,
+            transactions,
+            dispute_ratio,
+            seed,
+            output,
+        }) => {
+            let options = GenerateOptions {
+                clients: *clients,
+                transactions: *transactions,
+                dispute_ratio: *dispute_ratio,
+                seed: *seed,
+            };
+            match output {
+                Some(path) => {
+                    let mut file = AtomicFile::create(path).await?;
+                    generate(&options, file.file()).await?;
+                    file.commit().await?;
+                }
+                None => {
+                    generate(&options, io::stdout()).await?;
+                }
+            }
+            info!(transactions, "Transactions generated");
+            return Ok(Outcome::Clean);
+        }
+        _ => {}
+    }
+
+    // Setup the engine, restoring the state of previous runs
+    let throttle = args.warn_limit_for.into_iter().fold(
+        WarnThrottle::new(args.warn_limit),
+        |throttle, (reason, limit)| throttle.with_limit(reason, limit),
+    );
+    let precision =
+        PrecisionPolicy::new(args.scale, args.rounding).reject_excess(args.reject_excess_precision);
+    let clients = ClientFilter {
+        include: args.clients.take().map(|f| f.include).unwrap_or_default(),
+        exclude: args
+            .exclude_clients
+            .take()
+            .map(|f| f.include)
+            .unwrap_or_default(),
+    };
+    // Records parsed ahead by each source, and pending in the channels of the shard tasks
+    let mut pending_shard_records = None;
+    if let Some(max) = args.max_pending_records {
+        let ahead = match (args.pipeline_depth, args.shards) {
+            (0, _) => 0,
+            (_, 1) => max,
+            _ => max / 2,
+        };
+        if args.pipeline_depth > 0 {
+            let sources = 1 + args.merge_source.len();
+            args.pipeline_depth = args.pipeline_depth.min(ahead / sources).max(1);
+        }
+        pending_shard_records = Some(max - ahead);
+    }
+    let mut builder = PaymentEngine::builder()
+        .throttle(throttle)
+        .precision(precision)
+        .allow_withdrawal_disputes(args.allow_withdrawal_disputes)
+        .allow_admin_ops(args.allow_admin_ops)
+        .negative_balance(args.negative_balance)
+        .dispute_window(
+            args.dispute_window_days
+                .map(|days| Duration::from_secs(days.saturating_mul(24 * 60 * 60))),
+        )
+        .unique_tx_ids(args.unique_tx_ids)
+        .shards(args.shards)
+        .collect_rejects(args.rejects.is_some() || args.validate_only)
+        .collect_ledger(
+            args.export_ledger.is_some() || args.audit_digests.is_some() || digests.is_some(),
+        )
+        .collect_balance_history(args.balance_history.is_some())
+        .skip_invalid(args.skip_invalid || (args.validate_only && !args.strict))
+        .strict(args.strict)
+        .sort_by(args.sort_by)
+        .window(TimeWindow {
+            from: args.from,
+            to: args.to,
+        })
+        .clients(clients.clone())
+        .types(args.only.take().unwrap_or_default());
+    if args.max_withdrawal.is_some() || args.daily_limit.is_some() {
+        builder = builder.validator(WithdrawalLimit {
+            per_tx: args.max_withdrawal,
+            cumulative: args.daily_limit,
+        });
+    }
+    if let Some(path) = &args.fees {
+        builder = builder.fees(FeeSchedule::load(path).await?);
+    }
+    // Passes over the accounts once all the input files are processed
+    if let Some(days) = args.stale_dispute_days {
+        builder = builder.finalizer(ResolveStaleDisputes {
+            max_age: Duration::from_secs(days.saturating_mul(24 * 60 * 60)),
+        });
+    }
+    if args.held_funds_on_lock != HeldFundsPolicy::Keep {
+        builder = builder.finalizer(ReleaseHeldFunds(args.held_funds_on_lock));
+    }
+    if let Some(bps) = args.interest_bps {
+        builder = builder.finalizer(AccrueInterest { bps });
+    }
+    // SIGINT and SIGTERM stop the processing, the reports being written for the records
+    // processed so far
+    let (interrupt, interrupted) = watch::channel(false);
+    let interrupt = Arc::new(interrupt);
+    let signal = interrupt.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        warn!("Interrupted, stopping");
+        signal.send_replace(true);
+    });
+    builder = builder.interrupt(interrupted.clone());
+    if let Some(path) = &args.checkpoint {
+        builder = builder.checkpoint(path, args.checkpoint_interval);
+    }
+    if let Some(max_memory_mb) = args.max_memory_mb {
+        let path = std::env::temp_dir().join(format!("tpe_spill_{}.jsonl", std::process::id()));
+        builder = builder.spill(path, max_memory_mb * 1024 * 1024);
+    }
+    // The events are sent to the sinks while processing, by a separate task
+    #[cfg(feature = "webhooks")]
+    let webhooks = args.webhook.clone();
+    #[cfg(not(feature = "webhooks"))]
+    let webhooks: Vec<String> = Vec::new();
+    #[cfg(feature = "tui")]
+    let dashboard = args.tui.then(crate::Dashboard::new);
+    #[cfg(not(feature = "tui"))]
+    let dashboard = None::<()>;
+    let events_writer = if args.export_events.is_some()
+        || args.events_stderr
+        || !webhooks.is_empty()
+        || dashboard.is_some()
+    {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        builder = builder.events(sender);
+        let mut file = match &args.export_events {
+            Some(path) => Some(AtomicFile::create(path).await?),
+            None => None,
+        };
+        let events_stderr = args.events_stderr;
+        #[cfg(feature = "tui")]
+        let sink = dashboard.clone();
+        Some(tokio::spawn(async move {
+            let mut sinks: Vec<Box<dyn EventSink + '_>> = Vec::new();
+            if let Some(file) = &mut file {
+                sinks.push(Box::new(JsonLinesSink::new(file.file())));
+            }
+            if events_stderr {
+                sinks.push(Box::new(JsonLinesSink::stderr()));
+            }
+            #[cfg(feature = "webhooks")]
+            for url in webhooks {
+                sinks.push(Box::new(
+                    WebhookSink::new(url).filter(AccountEvent::is_alert),
+                ));
+            }
+            #[cfg(feature = "tui")]
+            if let Some(dashboard) = sink {
+                sinks.push(Box::new(dashboard));
+            }
+            let count = forward_events(receiver, &mut sinks).await?;
+            drop(sinks);
+            if let Some(file) = file {
+                file.commit().await?;
+            }
+            Ok::<_, EngineError>(count)
+        }))
+    } else {
+        None
+    };
+    let options = ReportOptions {
+        precision,
+        format: args.output_format,
+        scale: args.output_scale,
+        clients: clients.clone(),
+        anonymizer: match &args.anonymize {
+            Some(path) => {
+                let key = tokio::fs::read(path).await?;
+                Some(Anonymizer::new(key.trim_ascii()))
+            }
+            None => None,
+        },
+        status_column: args.status_column,
+    };
+    if let Some(max) = pending_shard_records {
+        builder = builder.max_pending_records(max);
+    }
+    let account_writer = if args.stream_output {
+        let (sender, receiver) = mpsc::channel(ACCOUNT_STREAM_CAPACITY);
+        builder = builder.stream_accounts(sender);
+        let path = args.output.clone();
+        let options = options.clone();
+        Some(tokio::spawn(async move {
+            match path {
+                Some(path) => {
+                    let mut file = AtomicFile::create(path).await?;
+                    let count =
+                        output::write_account_stream(receiver, &options, file.file()).await?;
+                    Ok::<_, EngineError>((count, Some(file)))
+                }
+                None => {
+                    let count =
+                        output::write_account_stream(receiver, &options, io::stdout()).await?;
+                    Ok((count, None))
+                }
+            }
+        }))
+    } else {
+        None
+    };
+    let mut engine = builder.build();
+    let mut store = args.state_dir.map(FileStore::open).transpose()?;
+    if let Some(store) = &mut store {
+        info!("Loading state of previous runs");
+        engine.restore(store)?;
+    }
+    // Digests of the input files, recorded in the store once their state is saved
+    let mut ingested = Vec::new();
+    if let (Some(store), false) = (&store, args.watch) {
+        let policy = args.on_duplicate_file;
+        let mut check = |path: &String| -> Result<bool, EngineError> {
+            // Objects in object storage aren't downloaded only to be identified
+            if is_object_url(path) {
+                warn!(path = %path, "Object not checked against the ingested files");
+                return Ok(true);
+            }
+            let digest = file_digest(path)?;
+            let duplicate =
+                store.is_ingested(&digest)? || ingested.iter().any(|(seen, _)| *seen == digest);
+            match (duplicate, policy) {
+                (false, _) => {
+                    ingested.push((digest, path.clone()));
+                    Ok(true)
+                }
+                (true, DuplicateFilePolicy::Skip) => {
+                    warn!(path = %path, "Skipping file already ingested");
+                    Ok(false)
+                }
+                (true, DuplicateFilePolicy::Reject) => {
+                    Err(EngineError::DuplicateFile(path.clone()))
+                }
+            }
+        };
+        let mut file_paths = Vec::new();
+        for path in args.file_paths {
+            if check(&path)? {
+                file_paths.push(path);
+            }
+        }
+        args.file_paths = file_paths;
+        let mut merge_source = Vec::new();
+        for path in args.merge_source {
+            if check(&path)? {
+                merge_source.push(path);
+            }
+        }
+        args.merge_source = merge_source;
+    }
+    if let Some(path) = &args.load_snapshot {
+        info!(path = %path, "Loading snapshot");
+        engine.load_snapshot(path).await?;
+    }
+    if let Some(path) = &args.initial_balances {
+        info!(path = %path, "Loading initial balances");
+        let file = tokio::fs::File::open(path).await?;
+        let count = engine.load_initial_balances(file).await?;
+        info!(count, "Initial balances loaded");
+    }
+
+    // The input files are a single source, followed by the ones to merge
+    let source_paths: Vec<Vec<String>> = std::iter::once(args.file_paths)
+        .chain(args.merge_source.into_iter().map(|path| vec![path]))
+        .collect();
+    let checkpoint = match &args.checkpoint {
+        Some(path) if args.resume => Checkpoint::load(path).await?,
+        _ => None,
+    };
+    let mut offsets = vec![None; source_paths.len()];
+    if let Some(checkpoint) = checkpoint {
+        if checkpoint.sources() > source_paths.len() {
+            return Err(EngineError::CheckpointError(format!(
+                "checkpoint has {} sources, {} given",
+                checkpoint.sources(),
+                source_paths.len()
+            )));
+        }
+        info!("Resuming from checkpoint");
+        for (source, offset) in offsets.iter_mut().enumerate() {
+            *offset = checkpoint.offset(source);
+        }
+        engine.resume(checkpoint);
+    }
+
+    // Read files containing transactions
+    info!("Reading data from input files.");
+    let dialect = CsvDialect {
+        delimiter: args.delimiter,
+        quote: args.quote,
+        quoting: !args.no_quoting,
+        columns: args.no_headers.then(|| args.columns.clone()),
+        float_amounts: args.float_amounts,
+        fast_parse: args.fast_parse,
+        mapping: match (&args.map, &args.map_file) {
+            (_, Some(path)) => ColumnMapping::load(path).await?,
+            (mapping, None) => mapping.clone().unwrap_or_default(),
+        },
+    };
+    if args.watch {
+        let path = &source_paths[0][0];
+        info!(path = %path, "Watching input file");
+        let format = args.format.unwrap_or_else(|| InputFormat::from_path(path));
+        let records = format.follow(path, &dialect, WATCH_POLL_INTERVAL).await?;
+        let mut interrupted = interrupted.clone();
+        let records = futures::StreamExt::take_until(records, async move {
+            let _ = interrupted.wait_for(|interrupted| *interrupted).await;
+        });
+        let interval = Duration::from_secs(args.watch_interval);
+        let mut batches = std::pin::pin!(records.chunks_timeout(WATCH_BATCH_SIZE, interval));
+        while let Some(batch) = batches.next().await {
+            let batch: TransactionStream = Box::pin(tokio_stream::iter(batch));
+            for stats in engine
+                .process_sources(vec![batch], args.merge_policy)
+                .await?
+            {
+                info!(
+                    rows = stats.rows,
+                    applied = stats.applied,
+                    rejected = stats.rejected,
+                    "Appended records processed"
+                );
+            }
+            write_report(&engine, &options, args.output.as_deref()).await?;
+        }
+        return Ok(Outcome::Clean);
+    }
+    let mut sources: Vec<TransactionStream> = Vec::new();
+    for (paths, offset) in source_paths.iter().zip(offsets) {
+        let source = match offset {
+            // The record at the offset has already been processed
+            Some(offset) => Box::pin(
+                open_files(paths, args.format, args.compression, &dialect, offset)
+                    .await?
+                    .skip(1),
+            ),
+            None if args.parallel_read > 1 => {
+                open_files_chunked(
+                    paths,
+                    args.format,
+                    args.compression,
+                    &dialect,
+                    READ_CHUNK_SIZE,
+                    args.parallel_read,
+                )
+                .await?
+            }
+            None => open_files(paths, args.format, args.compression, &dialect, 0).await?,
+        };
+        sources.push(match args.pipeline_depth {
+            0 => source,
+            depth => pipelined(source, depth),
+        });
+    }
+
+    // Process transactions data
+    info!("Processing transactions data");
+    #[cfg(feature = "tui")]
+    let dashboard = match dashboard {
+        Some(dashboard) => {
+            let mut total_bytes = 0;
+            for path in source_paths.iter().flatten() {
+                let metadata = tokio::fs::metadata(path).await;
+                total_bytes += metadata.map_or(0, |metadata| metadata.len());
+            }
+            Some(dashboard.show(total_bytes, interrupt.clone()))
+        }
+        None => None,
+    };
+    let stats = engine.process_sources(sources, args.merge_policy).await;
+    #[cfg(feature = "tui")]
+    if let Some(dashboard) = dashboard {
+        dashboard.close().await?;
+    }
+    let stats = stats?;
+    let outcome = match stats.iter().any(|stats| stats.rejected + stats.invalid > 0) {
+        true => Outcome::Rejected,
+        false => Outcome::Clean,
+    };
+    for (paths, stats) in source_paths.iter().zip(&stats) {
+        info!(
+            source = %paths.join(", "),
+            rows = stats.rows,
+            applied = stats.applied,
+            rejected = stats.rejected,
+            invalid = stats.invalid,
+            out_of_window = stats.out_of_window,
+            filtered = stats.filtered,
+            max_lag = stats.max_lag,
+            "Source processed"
+        );
+    }
+
+    if args.validate_only {
+        let sources: Vec<_> = source_paths.iter().map(|paths| paths.join(", ")).collect();
+        output::write_validation(
+            sources.iter().map(String::as_str).zip(&stats),
+            engine.rejected(),
+            io::stdout(),
+        )
+        .await?;
+        return match engine.interrupted() {
+            true => Err(EngineError::Interrupted),
+            false => Ok(outcome),
+        };
+    }
+
+    // The outputs of an interrupted run are written to `.partial` files instead
+    let interrupted = engine.interrupted();
+    if interrupted {
+        warn!("Processing interrupted, the reports are partial");
+    }
+    let partial = |path: &str| match interrupted {
+        true => format!("{path}.partial"),
+        false => path.to_string(),
+    };
+
+    if !interrupted {
+        engine.finalize()?;
+    }
+
+    if let Some(writer) = events_writer {
+        engine.close_events();
+        let count = match writer.await {
+            Ok(result) => result?,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        };
+        info!(count, "Events written");
+    }
+
+    match args.summary.as_deref() {
+        Some("-") => output::write_summary(&engine.summary(), io::stderr()).await?,
+        Some(path) => {
+            let path = partial(path);
+            info!(path = %path, "Writing summary");
+            let mut file = AtomicFile::create(path).await?;
+            output::write_summary(&engine.summary(), file.file()).await?;
+            file.commit().await?;
+        }
+        None => {}
+    }
+
+    for (currency, fees) in engine.fees_collected() {
+        info!(currency = %currency, %fees, "Fees collected");
+    }
+
+    if let Some(store) = &mut store {
+        if interrupted {
+            warn!("State of the interrupted run not saved");
+        } else {
+            info!("Saving state");
+            engine.persist(store)?;
+            for (digest, path) in &ingested {
+                store.record_ingested(digest, path)?;
+            }
+        }
+    }
+
+    if let Some(path) = args.save_snapshot.as_deref().map(partial) {
+        info!(path = %path, "Saving snapshot");
+        engine.save_snapshot(path).await?;
+    }
+
+    if let Some(path) = args.rejects.as_deref().map(partial) {
+        info!(path = %path, "Writing rejected transactions");
+        let mut file = AtomicFile::create(path).await?;
+        output::write_rejects(engine.rejected().iter(), file.file()).await?;
+        file.commit().await?;
+    }
+
+    if let Some(path) = args.open_disputes.as_deref().map(partial) {
+        info!(path = %path, "Writing open disputes");
+        let mut file = AtomicFile::create(path).await?;
+        output::write_open_disputes(&engine, file.file()).await?;
+        file.commit().await?;
+    }
+
+    if let Some(path) = args.lock_report.as_deref().map(partial) {
+        info!(path = %path, "Writing lock report");
+        let mut file = AtomicFile::create(path).await?;
+        output::write_lock_report(engine.sorted_accounts().into_iter(), file.file()).await?;
+        file.commit().await?;
+    }
+
+    if let Some(path) = args.risk_report.as_deref().map(partial) {
+        info!(path = %path, "Writing risk report");
+        let mut file = AtomicFile::create(path).await?;
+        output::write_risk_report(&engine, args.risk_top, file.file()).await?;
+        file.commit().await?;
+    }
+
+    if let Some(path) = args.export_ledger.as_deref().map(partial) {
+        info!(path = %path, "Writing ledger");
+        let mut file = AtomicFile::create(path).await?;
+        output::write_ledger(&engine, file.file()).await?;
+        file.commit().await?;
+    }
+
+    if let Some(path) = args.balance_history.as_deref().map(partial) {
+        info!(path = %path, "Writing balance history");
+        let mut file = AtomicFile::create(path).await?;
+        output::write_balance_history(&engine, file.file()).await?;
+        file.commit().await?;
+    }
+
+    if let Some(path) = args.audit_digests.as_deref().map(partial) {
+        info!(path = %path, "Writing audit digests");
+        AuditDigests::from_ledger(engine.ledger())
+            .save(path)
+            .await?;
+    }
+
+    if let Some(path) = &digests {
+        let expected = AuditDigests::load(path).await?;
+        let mismatches = expected.mismatches(&AuditDigests::from_ledger(engine.ledger()));
+        let mut stdout = io::stdout();
+        for chain in &mismatches {
+            stdout.write_all(format!("{chain}\n").as_bytes()).await?;
+        }
+        stdout.flush().await?;
+        info!(mismatches = mismatches.len(), "Audit digests verified");
+        if interrupted {
+            return Err(EngineError::Interrupted);
+        }
+        return Ok(match mismatches.len() {
+            0 => outcome,
+            _ => Outcome::Discrepancies,
+        });
+    }
+
+    #[cfg(feature = "http")]
+    if let Some(addr) = args.serve.filter(|_| !interrupted) {
+        info!(%addr, "Serving HTTP API");
+        let engine = std::sync::Arc::new(crate::AccountRegistry::new(engine, args.shards));
+        return crate::http::serve(engine, addr).await.map(|()| outcome);
+    }
+
+    if let Some(path) = &expected {
+        let expected = tokio::fs::File::open(path).await?;
+        let outcome = reconcile(&engine, &options, expected, outcome, io::stdout()).await?;
+        if interrupted {
+            return Err(EngineError::Interrupted);
+        }
+        return Ok(outcome);
+    }
+
+    // Output info on accounts
+    if let Some(writer) = account_writer {
+        let (count, file) = match writer.await {
+            Ok(result) => result?,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        };
+        if let (Some(mut file), Some(path)) = (file, args.output.as_deref()) {
+            file.set_path(partial(path));
+            file.commit().await?;
+        }
+        info!(count, "Accounts streamed");
+    } else {
+        write_report(
+            &engine,
+            &options,
+            args.output.as_deref().map(partial).as_deref(),
+        )
+        .await?;
+    }
+    if let (Some(anonymizer), Some(path)) = (&options.anonymizer, &args.anonymize_mapping) {
+        let path = partial(path);
+        info!(path = %path, "Writing pseudonyms mapping");
+        let clients = engine
+            .sorted_accounts()
+            .into_iter()
+            .map(|acc| acc.client_id)
+            .filter(|client_id| options.clients.contains(*client_id));
+        let mut file = AtomicFile::create(path).await?;
+        anonymizer.write_mapping(clients, file.file()).await?;
+        file.commit().await?;
+    }
+    #[cfg(feature = "signing")]
+    if let (Some(key), Some(path)) = (&args.signing_key, args.output.as_deref().map(partial)) {
+        let signer = crate::ReportSigner::load(key).await?;
+        let signature = signer.sign_file(&path).await?;
+        info!(path = %signature, public_key = %signer.public_key(), "Accounts report signed");
+    }
+    if interrupted {
+        return Err(EngineError::Interrupted);
+    }
+    info!("All transactions data processed");
+    engine.check_overflows()?;
+    if let Some(path) = &args.expect {
+        let options = ReportOptions {
+            format: OutputFormat::Csv,
+            ..options
+        };
+        let mut report = Vec::new();
+        output::write_accounts(engine.sorted_accounts().into_iter(), &options, &mut report).await?;
+        let expected = tokio::fs::File::open(path).await?;
+        let mismatches = check_report(expected, report.as_slice(), io::stderr()).await?;
+        info!(mismatches, "Accounts report checked");
+        if mismatches > 0 {
+            return Ok(Outcome::Discrepancies);
+        }
+    }
+    Ok(outcome)
+}
+
+// Completes on SIGINT, or SIGTERM on Unix
+async fn shutdown_signal() {
+    let interrupt = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        () = interrupt => {}
+        () = terminate => {}
+    }
+}
+
+// Writes the accounts report to the file at `path`, or to stdout
+async fn write_report(
+    engine: &PaymentEngine,
+    options: &ReportOptions,
+    path: Option<&str>,
+) -> Result<(), EngineError> {
+    if let Some(path) = path {
+        info!(path = %path, "Writing accounts report");
+        let mut file = AtomicFile::create(path).await?;
+        output::write_accounts(engine.sorted_accounts().into_iter(), options, file.file()).await?;
+        Ok(file.commit().await?)
+    } else {
+        output::write_accounts(engine.sorted_accounts().into_iter(), options, io::stdout()).await
+    }
+}
+
+// Writes the differences between the `expected` accounts report and the balances of the engine
+// to `wrt`, returning the outcome of the run given the one of the processing
+async fn reconcile<R, W>(
+    engine: &PaymentEngine,
+    options: &ReportOptions,
+    expected: R,
+    outcome: Outcome,
+    wrt: W,
+) -> Result<Outcome, EngineError>
+where
+    R: io::AsyncRead + Send + Unpin,
+    W: io::AsyncWrite + Unpin,
+{
+    let mut report = Vec::new();
+    output::write_accounts(engine.sorted_accounts().into_iter(), options, &mut report).await?;
+    let rows = diff_reports(expected, report.as_slice(), wrt).await?;
+    info!(rows, "Balances reconciled");
+    Ok(match rows {
+        0 => outcome,
+        _ => Outcome::Discrepancies,
+    })
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(EXIT_SUCCESS, exit_code(&Ok(Outcome::Clean)));
+        assert_eq!(EXIT_REJECTED, exit_code(&Ok(Outcome::Rejected)));
+        assert_eq!(EXIT_DISCREPANCIES, exit_code(&Ok(Outcome::Discrepancies)));
+        assert_eq!(EXIT_INTERRUPTED, exit_code(&Err(EngineError::Interrupted)));
+
+        let malformed = EngineError::MalformedRecord {
+            line: 2,
+            record: "deposit,a,1,1.0".into(),
+            source: "invalid client id".into(),
+        };
+        assert_eq!(EXIT_PARSE_ERROR, exit_code(&Err(malformed)));
+        // An invalid snapshot or checkpoint isn't an invalid record
+        let json = serde_json::from_str::<u16>("{").unwrap_err();
+        assert_eq!(EXIT_FAILURE, exit_code(&Err(json.into())));
+        let report = EngineError::ReportError("missing field `total`".into());
+        assert_eq!(EXIT_FAILURE, exit_code(&Err(report)));
+        let io = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert_eq!(EXIT_FAILURE, exit_code(&Err(io.into())));
+    }
+
+    #[tokio::test]
+    async fn test_config_args() {
+        let path = std::env::temp_dir().join(format!("tpe_config_{}.toml", std::process::id()));
+        let config = "sort-by = \"total\"\noutput_format = \"json\"\nshards = 4\n\
+                      skip_invalid = true\nstrict = false\nmerge_source = [\"a.csv\", \"b.csv\"]\n";
+        std::fs::write(&path, config).unwrap();
+        let path = path.display().to_string();
+
+        // The command line takes precedence, leaving out the options conflicting with its ones
+        let given = Cli::command()
+            .ignore_errors(true)
+            .try_get_matches_from(["tpe", "--sort-by", "client", "--stream-output"])
+            .unwrap();
+        let args = config_args(&path, &given).await.unwrap();
+        assert_eq!(
+            vec![
+                "--shards=4",
+                "--skip-invalid",
+                "--merge-source=a.csv",
+                "--merge-source=b.csv"
+            ],
+            args
+        );
+
+        // So do the `TPE_*` environment variables, which no other test sets
+        let input = std::env::temp_dir().join(format!("tpe_env_{}.csv", std::process::id()));
+        std::fs::write(&input, "type,client,tx,amount\n").unwrap();
+        let input = input.display().to_string();
+        std::env::set_var("TPE_SHARDS", "2");
+        std::env::set_var("TPE_INPUT", &input);
+        let given = Cli::command()
+            .ignore_errors(true)
+            .try_get_matches_from(["tpe"])
+            .unwrap();
+        let args = config_args(&path, &given).await.unwrap();
+        let cli = Cli::try_parse_from(["tpe"]).unwrap();
+        std::env::remove_var("TPE_SHARDS");
+        std::env::remove_var("TPE_INPUT");
+        assert!(!args.contains(&"--shards=4".to_string()));
+        assert!(args.contains(&"--skip-invalid".to_string()));
+        assert_eq!(
+            (vec![input.clone()], 2),
+            (cli.process.file_paths, cli.process.shards)
+        );
+        std::fs::remove_file(&input).unwrap();
+
+        // Every option of the template is known
+        let template = default_config().replace("# ", "");
+        std::fs::write(
+            &path,
+            template.lines().skip(3).collect::<Vec<_>>().join("\n"),
+        )
+        .unwrap();
+        assert!(config_args(&path, &given).await.is_ok());
+
+        std::fs::write(&path, "unknown = 1").unwrap();
+        assert!(config_args(&path, &given).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let path = std::env::temp_dir().join(format!("tpe_cli_{}.csv", std::process::id()));
+        std::fs::write(&path, "type,client,tx,amount\n").unwrap();
+        let path = path.display().to_string();
+
+        // Processing is the default command, for backwards compatibility
+        let cli = Cli::try_parse_from(["tpe", "--shards", "2", &path]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(
+            (vec![path.clone()], 2),
+            (cli.process.file_paths, cli.process.shards)
+        );
+
+        let cli = Cli::try_parse_from(["tpe", "process", "--shards", "2", &path]).unwrap();
+        let Some(Command::Process(args)) = cli.command else {
+            panic!("Expected the process command");
+        };
+        assert_eq!((vec![path.clone()], 2), (args.file_paths, args.shards));
+        assert!(matches!(
+            Cli::try_parse_from(["tpe", "validate", &path])
+                .unwrap()
+                .command,
+            Some(Command::Validate(_))
+        ));
+        let cli = Cli::try_parse_from(["tpe", "reconcile", "--expected", "a.csv", &path]).unwrap();
+        let Some(Command::Reconcile {
+            expected,
+            file_paths,
+        }) = cli.command
+        else {
+            panic!("Expected the reconcile command");
+        };
+        assert_eq!(
+            ("a.csv", vec![path.clone()]),
+            (expected.as_str(), file_paths)
+        );
+        // Options of the main command can't be given before another command
+        assert!(Cli::try_parse_from(["tpe", "--shards", "2", "validate", &path]).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_anonymize_conflicts() {
+        let path = std::env::temp_dir().join(format!("tpe_anon_{}.csv", std::process::id()));
+        std::fs::write(&path, "type,client,tx,amount\n").unwrap();
+        let path = path.display().to_string();
+
+        assert!(Cli::try_parse_from(["tpe", "--anonymize", "key", &path]).is_ok());
+        for output in [
+            "--audit-digests",
+            "--save-snapshot",
+            "--state-dir",
+            "--checkpoint",
+        ] {
+            let e = Cli::try_parse_from(["tpe", "--anonymize", "key", output, "out", &path])
+                .unwrap_err();
+            assert_eq!(ErrorKind::ArgumentConflict, e.kind(), "{output}");
+        }
+        #[cfg(feature = "webhooks")]
+        assert!(
+            Cli::try_parse_from(["tpe", "--anonymize", "key", "--webhook", "http://x", &path])
+                .is_err()
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reconcile() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.5\ndeposit,2,2,2.0\n";
+        let mut engine = PaymentEngine::new();
+        engine
+            .process(crate::CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+        let options = ReportOptions::default();
+
+        let expected = "client,available,held,total,locked\n1,1.5,0,1.5,false\n2,2.0,0,2.0,false\n";
+        let mut output = Vec::new();
+        let outcome = reconcile(
+            &engine,
+            &options,
+            expected.as_bytes(),
+            Outcome::Rejected,
+            &mut output,
+        )
+        .await
+        .unwrap();
+        assert_eq!(Outcome::Rejected, outcome);
+        assert!(output.is_empty());
+
+        // The balances differing from the expected ones are written
+        let expected = "client,available,held,total,locked\n1,1.5,0,1.5,false\n2,3.0,0,3.0,false\n";
+        let mut output = Vec::new();
+        let outcome = reconcile(
+            &engine,
+            &options,
+            expected.as_bytes(),
+            Outcome::Clean,
+            &mut output,
+        )
+        .await
+        .unwrap();
+        assert_eq!(Outcome::Discrepancies, outcome);
+        assert_eq!(
+            "client,currency,available,held,total,locked\n2,,-1.0,0,-1.0,\n",
+            String::from_utf8(output).unwrap()
+        );
+        assert_eq!(EXIT_DISCREPANCIES, exit_code(&Ok(outcome)));
+    }
+
+    #[test]
+    fn test_ordering_shards() {
+        assert_eq!(Ok(1), ordering_shards(None, 1));
+        assert_eq!(Ok(4), ordering_shards(None, 4));
+        assert_eq!(Ok(1), ordering_shards(Some(RecordOrdering::Strict), 1));
+        assert!(ordering_shards(Some(RecordOrdering::Strict), 4).is_err());
+        assert_eq!(Ok(4), ordering_shards(Some(RecordOrdering::PerClient), 4));
+        assert!(ordering_shards(Some(RecordOrdering::PerClient), 1).unwrap() >= 1);
+    }
+}
@@ -0,0 +1,157 @@
+//! Conformance suite of the reference engine.
+//!
+//! Each [`Scenario`] is made of an input CSV and the accounts report the reference engine
+//! produces for it. Alternative implementations can feed every input to their own engine and
+//! check the resulting report with [`Scenario::verify`], proving they match the reference
+//! dispute semantics.
+use std::collections::BTreeMap;
+
+use crate::{process_transactions, Decimal, EngineError};
+
+/// A conformance scenario: an input file and the expected accounts report.
+#[derive(Debug, Clone, Copy)]
+pub struct Scenario {
+    pub name: &'static str,
+    /// Input transactions, in CSV format
+    pub input: &'static str,
+    /// Expected accounts report, in CSV format
+    pub expected: &'static str,
+}
+
+macro_rules! scenario {
+    ($name:literal) => {
+        Scenario {
+            name: $name,
+            input: include_str!(concat!("../res/conformance/", $name, ".csv")),
+            expected: include_str!(concat!("../res/conformance/", $name, ".expected.csv")),
+        }
+    };
+}
+
+const SCENARIOS: &[Scenario] = &[
+    scenario!("deposit_withdrawal"),
+    scenario!("dispute_holds_funds"),
+    scenario!("dispute_resolve"),
+    scenario!("dispute_chargeback_locks"),
+    scenario!("dispute_lifecycle_final"),
+    scenario!("invalid_references_ignored"),
+    scenario!("invalid_records_ignored"),
+];
+
+/// Returns all the scenarios of the conformance suite.
+pub fn scenarios() -> &'static [Scenario] {
+    SCENARIOS
+}
+
+// A row of an accounts report
+type AccountRow = (Decimal, Decimal, Decimal, bool);
+
+fn parse_report(report: &str) -> Result<BTreeMap<u16, AccountRow>, String> {
+    let mut rows = BTreeMap::new();
+    for line in report
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+    {
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let [client, available, held, total, locked] = fields[..] else {
+            return Err(format!("Malformed report row `{line}`"));
+        };
+        let parse_decimal = |value: &str| {
+            value
+                .parse::<Decimal>()
+                .map_err(|e| format!("{e}: `{line}`"))
+        };
+        let row = (
+            parse_decimal(available)?,
+            parse_decimal(held)?,
+            parse_decimal(total)?,
+            locked
+                .parse()
+                .map_err(|_| format!("Invalid lock flag: `{line}`"))?,
+        );
+        let client = client
+            .parse()
+            .map_err(|_| format!("Invalid client id: `{line}`"))?;
+        if rows.insert(client, row).is_some() {
+            return Err(format!("Client #{client} reported more than once"));
+        }
+    }
+    Ok(rows)
+}
+
+impl Scenario {
+    /// Checks that `report` (a CSV accounts report) matches the expected one.
+    ///
+    /// Rows are compared regardless of their order and amounts by value, so `1.50` and `1.5`
+    /// are considered equal.
+    pub fn verify(&self, report: &str) -> Result<(), String> {
+        let expected = parse_report(self.expected)?;
+        let actual = parse_report(report)?;
+
+        let mut mismatches = Vec::new();
+        for (client, row) in &expected {
+            match actual.get(client) {
+                Some(actual_row) if actual_row == row => {}
+                Some(actual_row) => mismatches.push(format!(
+                    "client #{client}: expected {row:?}, found {actual_row:?}"
+                )),
+                None => mismatches.push(format!("client #{client}: missing")),
+            }
+        }
+        for client in actual
+            .keys()
+            .filter(|client| !expected.contains_key(client))
+        {
+            mismatches.push(format!("client #{client}: unexpected"));
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Scenario `{}` failed:\n{}",
+                self.name,
+                mismatches.join("\n")
+            ))
+        }
+    }
+
+    /// Runs the scenario input through the reference engine, returning its accounts report.
+    pub async fn run_reference(&self) -> Result<String, EngineError> {
+        let accounts = process_transactions(self.input.as_bytes()).await?;
+
+        let mut report = Vec::new();
+        let mut wrt = csv_async::AsyncSerializer::from_writer(&mut report);
+        for (_, acc) in accounts {
+            wrt.serialize(acc).await?;
+        }
+        wrt.flush().await?;
+        drop(wrt);
+        Ok(String::from_utf8_lossy(&report).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod conformance_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reference_conformance() {
+        for scenario in scenarios() {
+            let report = scenario.run_reference().await.unwrap();
+            scenario.verify(&report).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_verify_mismatch() {
+        let scenario = scenarios()[0];
+        assert!(scenario
+            .verify("client,available,held,total,locked\n1,1.50,0,1.5,false\n2,2.0,0,2,false")
+            .is_ok());
+        assert!(scenario
+            .verify("client,available,held,total,locked\n1,1.5,0,1.5,false")
+            .is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::{engine::read_report, Balances, CurrencyCode, Decimal, EngineError};
+
+// Row of the differences between two accounts reports
+#[derive(Serialize)]
+struct DiffRow {
+    client: u16,
+    currency: CurrencyCode,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    // `locked` or `unlocked` if the lock state changed, empty otherwise
+    locked: &'static str,
+}
+
+/// Compares two accounts reports in CSV format, e.g. the outputs of two versions of the engine,
+/// writing a row per client and currency whose balances or lock state differ, in CSV format.
+///
+/// Every row has the deltas of the balances from the `old` report to the `new` one (a client
+/// missing from a report has no funds in it), and whether the account got `locked` or
+/// `unlocked`. Returns the number of rows written, i.e. zero if the reports match.
+pub async fn diff_reports<R1, R2, W>(old: R1, new: R2, wrt: W) -> Result<usize, EngineError>
+where
+    R1: AsyncRead + Send + Unpin,
+    R2: AsyncRead + Send + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let old = read_report(old).await?;
+    let mut new = read_report(new).await?;
+
+    let mut keys: Vec<_> = old.keys().chain(new.keys()).cloned().collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+    let mut rows = 0;
+    for key in keys {
+        let old = old.get(&key).copied().unwrap_or_default();
+        let new = new.remove(&key).unwrap_or_default();
+        let (before, was_locked, after, locked) =
+            (old.balances, old.locked, new.balances, new.locked);
+        if before == after && was_locked == locked {
+            continue;
+        }
+        let (client, currency) = key;
+        wrt.serialize(DiffRow {
+            client,
+            currency,
+            available: after.available - before.available,
+            held: after.held - before.held,
+            total: after.total - before.total,
+            locked: match (was_locked, locked) {
+                (false, true) => "locked",
+                (true, false) => "unlocked",
+                _ => "",
+            },
+        })
+        .await?;
+        rows += 1;
+    }
+    wrt.flush().await?;
+    Ok(rows)
+}
+
+/// Compares an accounts report in CSV format to the expected one, e.g. a golden file validating
+/// an upgrade of the engine, regardless of the order of the rows.
+///
+/// Writes the rows of each client and currency whose balances or state differ, as
+/// `- <client>,<currency>,<available>,<held>,<total>,<locked>[,closed]` for the expected one
+/// and `+ ...` for the actual one, a row missing from a report being omitted. Returns the number of
+/// clients and currencies that differ, i.e. zero if the reports match.
+pub async fn check_report<R1, R2, W>(
+    expected: R1,
+    actual: R2,
+    mut wrt: W,
+) -> Result<usize, EngineError>
+where
+    R1: AsyncRead + Send + Unpin,
+    R2: AsyncRead + Send + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let expected = read_report(expected).await?;
+    let actual = read_report(actual).await?;
+
+    let mut keys: Vec<_> = expected.keys().chain(actual.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut mismatches = 0;
+    for key in keys {
+        let (before, after) = (expected.get(key), actual.get(key));
+        if before == after {
+            continue;
+        }
+        let (client, currency) = key;
+        for (sign, row) in [("-", before), ("+", after)] {
+            if let Some(entry) = row {
+                let Balances {
+                    available,
+                    held,
+                    total,
+                } = entry.balances;
+                let closed = if entry.closed { ",closed" } else { "" };
+                let line = format!(
+                    "{sign} {client},{currency},{available},{held},{total},{}{closed}\n",
+                    entry.locked
+                );
+                wrt.write_all(line.as_bytes()).await?;
+            }
+        }
+        mismatches += 1;
+    }
+    wrt.flush().await?;
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_diff_reports() {
+        let old = "client,available,held,total,locked\n1,1.5,0,1.5,false\n2,2.0,0,2.0,false\n3,1,0,1,false\n";
+        let new = "client,available,held,total,locked\n1,1.5,0,1.5,false\n2,0,0,0,true\n4,1.25,0,1.25,false\n";
+        let mut output = Vec::new();
+        let rows = diff_reports(old.as_bytes(), new.as_bytes(), &mut output)
+            .await
+            .unwrap();
+
+        assert_eq!(3, rows);
+        assert_eq!(
+            "client,currency,available,held,total,locked\n2,,-2.0,0,-2.0,locked\n3,,-1,0,-1,\n4,,1.25,0,1.25,\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_report() {
+        let expected = "client,available,held,total,locked\n1,1.5,0,1.5,false\n2,2.0,0,2.0,false\n3,1,0,1,false\n";
+        let actual = "client,available,held,total,locked\n4,1.25,0,1.25,false\n2,0,0,0,true\n3,1.00,0,1.0,false\n";
+        let mut output = Vec::new();
+        let mismatches = check_report(expected.as_bytes(), actual.as_bytes(), &mut output)
+            .await
+            .unwrap();
+
+        assert_eq!(3, mismatches);
+        assert_eq!(
+            "- 1,,1.5,0,1.5,false\n- 2,,2.0,0,2.0,false\n+ 2,,0,0,0,true\n+ 4,,1.25,0,1.25,false\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+}
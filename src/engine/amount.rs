@@ -0,0 +1,997 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    iter::Sum,
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+    str::FromStr,
+};
+
+use serde::{
+    de::{self, DeserializeOwned, Unexpected},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use thiserror::Error;
+
+use super::precision::Rounding;
+
+// The decimal type of the amounts and the balances, i.e. the backend selected by the features
+#[cfg(not(feature = "fixed-point"))]
+pub use rust_decimal::Decimal;
+#[cfg(feature = "fixed-point")]
+pub use FixedDecimal as Decimal;
+
+/// The arithmetic on the amounts the engine relies on, implemented by both decimal backends with
+/// the same observable behavior: the results, and their scale, are the same as long as they fit
+/// in the range of [`FixedDecimal`].
+pub trait Amount:
+    Copy
+    + Ord
+    + Hash
+    + Default
+    + fmt::Debug
+    + fmt::Display
+    + FromStr
+    + Neg<Output = Self>
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Serialize
+    + DeserializeOwned
+    + Send
+    + Sync
+    + 'static
+{
+    const ZERO: Self;
+    const ONE: Self;
+    const MAX: Self;
+
+    /// Returns `mantissa` * 10^-`scale`.
+    fn new(mantissa: i64, scale: u32) -> Self;
+    /// Parses `s` without rounding it, i.e. `None` if it doesn't fit.
+    fn parse_exact(s: &str) -> Option<Self>;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    fn checked_div(self, other: Self) -> Option<Self>;
+    fn saturating_add(self, other: Self) -> Self;
+    fn saturating_sub(self, other: Self) -> Self;
+    fn saturating_mul(self, other: Self) -> Self;
+    fn is_zero(&self) -> bool;
+    fn is_sign_negative(&self) -> bool;
+    /// Number of decimal places
+    fn scale(&self) -> u32;
+    fn mantissa(&self) -> i128;
+    /// Strips the trailing zeros.
+    fn normalize(&self) -> Self;
+    /// Sets the number of decimal places, rounding half away from zero when decreasing it.
+    fn rescale(&mut self, scale: u32);
+    /// Rounds to `dp` decimal places, if there are more.
+    fn round_to(&self, dp: u32, rounding: Rounding) -> Self;
+    fn to_f64(&self) -> f64;
+}
+
+impl Amount for rust_decimal::Decimal {
+    const ZERO: Self = Self::ZERO;
+    const ONE: Self = Self::ONE;
+    const MAX: Self = Self::MAX;
+
+    fn new(mantissa: i64, scale: u32) -> Self {
+        Self::new(mantissa, scale)
+    }
+
+    fn parse_exact(s: &str) -> Option<Self> {
+        Self::from_str_exact(s).ok()
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        self.checked_add(other)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        self.checked_sub(other)
+    }
+
+    fn checked_mul(self, other: Self) -> Option<Self> {
+        self.checked_mul(other)
+    }
+
+    fn checked_div(self, other: Self) -> Option<Self> {
+        self.checked_div(other)
+    }
+
+    fn saturating_add(self, other: Self) -> Self {
+        self.saturating_add(other)
+    }
+
+    fn saturating_sub(self, other: Self) -> Self {
+        self.saturating_sub(other)
+    }
+
+    fn saturating_mul(self, other: Self) -> Self {
+        self.saturating_mul(other)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.is_zero()
+    }
+
+    fn is_sign_negative(&self) -> bool {
+        self.is_sign_negative()
+    }
+
+    fn scale(&self) -> u32 {
+        self.scale()
+    }
+
+    fn mantissa(&self) -> i128 {
+        self.mantissa()
+    }
+
+    fn normalize(&self) -> Self {
+        self.normalize()
+    }
+
+    fn rescale(&mut self, scale: u32) {
+        self.rescale(scale)
+    }
+
+    fn round_to(&self, dp: u32, rounding: Rounding) -> Self {
+        use rust_decimal::RoundingStrategy;
+
+        let strategy = match rounding {
+            Rounding::Truncate => RoundingStrategy::ToZero,
+            Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+        };
+        self.round_dp_with_strategy(dp, strategy)
+    }
+
+    fn to_f64(&self) -> f64 {
+        rust_decimal::prelude::ToPrimitive::to_f64(self).unwrap_or_default()
+    }
+}
+
+/// An error parsing a [`FixedDecimal`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Invalid decimal: {0}")]
+pub struct InvalidDecimal(&'static str);
+
+/// A decimal number made of a 64-bit integer mantissa and at most
+/// [`FixedDecimal::MAX_SCALE`] decimal places: integer math only, faster than
+/// [`rust_decimal::Decimal`], with a range of about ±1.8e15 at 4 decimal places.
+///
+/// The results of the operations, and their scale, are the ones of [`rust_decimal::Decimal`]
+/// whenever they fit, e.g. `1.50 + 1 == 2.50` and `10 / 4 == 2.50`; otherwise they're rounded to
+/// the nearest even, or overflow.
+#[derive(Clone, Copy)]
+pub struct FixedDecimal {
+    // The absolute value, times 10^scale
+    mantissa: u64,
+    scale: u8,
+    // Apart from the mantissa, as the zero has a sign too, e.g. `-0.00`
+    negative: bool,
+}
+
+const POWERS_10: [u64; 20] = {
+    let mut powers = [1; 20];
+    let mut i = 1;
+    while i < powers.len() {
+        powers[i] = powers[i - 1] * 10;
+        i += 1;
+    }
+    powers
+};
+
+impl FixedDecimal {
+    pub const MAX_SCALE: u32 = 18;
+    pub const ZERO: Self = Self::from_parts(0, 0, false);
+    pub const ONE: Self = Self::from_parts(1, 0, false);
+    pub const TWO: Self = Self::from_parts(2, 0, false);
+    pub const TEN: Self = Self::from_parts(10, 0, false);
+    pub const ONE_HUNDRED: Self = Self::from_parts(100, 0, false);
+    pub const MAX: Self = Self::from_parts(u64::MAX, 0, false);
+    pub const MIN: Self = Self::from_parts(u64::MAX, 0, true);
+
+    const fn from_parts(mantissa: u64, scale: u32, negative: bool) -> Self {
+        Self {
+            mantissa,
+            scale: scale as u8,
+            negative: negative && mantissa != 0,
+        }
+    }
+
+    /// Returns `mantissa` * 10^-`scale`.
+    ///
+    /// # Panics
+    ///
+    /// If `scale` exceeds [`FixedDecimal::MAX_SCALE`].
+    pub fn new(mantissa: i64, scale: u32) -> Self {
+        assert!(
+            scale <= Self::MAX_SCALE,
+            "Scale exceeds maximum supported scale"
+        );
+        Self::from_parts(mantissa.unsigned_abs(), scale, mantissa < 0)
+    }
+
+    /// Parses `s` without rounding it, nor accepting the scientific notation.
+    pub fn from_str_exact(s: &str) -> Result<Self, InvalidDecimal> {
+        parse(s, true)
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale.into()
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        let mantissa = i128::from(self.mantissa);
+        if self.negative {
+            -mantissa
+        } else {
+            mantissa
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    pub fn is_sign_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_sign_positive(&self) -> bool {
+        !self.negative
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.add_signed(other, false)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.add_signed(other, true)
+    }
+
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        if self.is_zero() || other.is_zero() {
+            return Some(Self::ZERO);
+        }
+        fit(
+            u128::from(self.mantissa) * u128::from(other.mantissa),
+            self.scale() + other.scale(),
+            self.negative != other.negative,
+        )
+    }
+
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        if self.is_zero() {
+            return Some(Self::ZERO);
+        }
+        let (quotient, scale) = divide(
+            self.mantissa.into(),
+            self.scale.into(),
+            other.mantissa.into(),
+            other.scale.into(),
+        )?;
+        fit(quotient, scale, self.negative != other.negative)
+    }
+
+    pub fn saturating_add(self, other: Self) -> Self {
+        self.checked_add(other)
+            .unwrap_or(if self.negative { Self::MIN } else { Self::MAX })
+    }
+
+    pub fn saturating_sub(self, other: Self) -> Self {
+        self.checked_sub(other)
+            .unwrap_or(if self.negative { Self::MIN } else { Self::MAX })
+    }
+
+    pub fn saturating_mul(self, other: Self) -> Self {
+        self.checked_mul(other)
+            .unwrap_or(if self.negative != other.negative {
+                Self::MIN
+            } else {
+                Self::MAX
+            })
+    }
+
+    /// Strips the trailing zeros, and the sign of the zero.
+    pub fn normalize(&self) -> Self {
+        let (mut mantissa, mut scale) = (self.mantissa, self.scale());
+        while scale > 0 && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        Self::from_parts(mantissa, scale, self.negative)
+    }
+
+    /// Drops the decimal places beyond `scale`.
+    pub fn trunc_with_scale(&self, scale: u32) -> Self {
+        self.round_to(scale, Rounding::Truncate)
+    }
+
+    /// Sets the number of decimal places, rounding half away from zero when decreasing it, or
+    /// adding as many as fit when increasing it.
+    pub fn rescale(&mut self, scale: u32) {
+        let scale = scale.min(Self::MAX_SCALE);
+        if self.is_zero() {
+            self.scale = scale as u8;
+        } else if scale < self.scale() {
+            let mantissa = self.mantissa / POWERS_10[(self.scale() - scale - 1) as usize];
+            self.mantissa = mantissa / 10 + u64::from(mantissa % 10 >= 5);
+            self.scale = scale as u8;
+        } else {
+            while self.scale() < scale {
+                let Some(mantissa) = self.mantissa.checked_mul(10) else {
+                    break;
+                };
+                self.mantissa = mantissa;
+                self.scale += 1;
+            }
+        }
+    }
+
+    fn add_signed(self, other: Self, subtract: bool) -> Option<Self> {
+        // Like `rust_decimal`, the zeros leave the other operand as it is, scale included
+        if self.is_zero() {
+            let mut result = other;
+            if subtract && !other.is_zero() {
+                result.negative = !other.negative;
+            }
+            return Some(result);
+        }
+        if other.is_zero() {
+            return Some(self);
+        }
+        let scale = self.scale.max(other.scale);
+        let lhs = self.aligned(scale);
+        let rhs = other.aligned(scale);
+        let (mantissa, negative) = if self.negative == (other.negative != subtract) {
+            (lhs + rhs, self.negative)
+        } else if lhs >= rhs {
+            (lhs - rhs, self.negative)
+        } else {
+            (rhs - lhs, !self.negative)
+        };
+        fit(mantissa, scale.into(), negative)
+    }
+
+    // The absolute value with `scale` decimal places, at least the ones of `self`
+    fn aligned(&self, scale: u8) -> u128 {
+        u128::from(self.mantissa) * u128::from(POWERS_10[usize::from(scale - self.scale)])
+    }
+
+    fn signed(&self, scale: u8) -> i128 {
+        let aligned = self.aligned(scale) as i128;
+        if self.negative {
+            -aligned
+        } else {
+            aligned
+        }
+    }
+}
+
+// Rounds `mantissa` * 10^-`scale` to the nearest even value that fits, if any
+fn fit(mut mantissa: u128, mut scale: u32, negative: bool) -> Option<FixedDecimal> {
+    let mut dropped = 0;
+    let mut sticky = false;
+    while scale > FixedDecimal::MAX_SCALE || mantissa > u64::MAX.into() {
+        if scale == 0 {
+            return None;
+        }
+        sticky |= dropped != 0;
+        dropped = mantissa % 10;
+        mantissa /= 10;
+        scale -= 1;
+    }
+    if dropped > 5 || dropped == 5 && (sticky || mantissa % 2 == 1) {
+        mantissa += 1;
+        if mantissa > u64::MAX.into() {
+            return fit(mantissa, scale, negative);
+        }
+    }
+    Some(FixedDecimal::from_parts(mantissa as u64, scale, negative))
+}
+
+// The largest mantissa and scale of `rust_decimal`, whose division is replicated to get the same
+// scales
+const MAX_96: u128 = (1 << 96) - 1;
+const MAX_REFERENCE_SCALE: i32 = 28;
+
+// Divides the mantissas the way `rust_decimal` does: while there is a remainder, the quotient is
+// scaled up by at most 9 digits at a time, within 96 bits and 28 decimal places, and the trailing
+// zeros are then partially stripped, e.g. `10 / 4 == 2.50`.
+fn divide(
+    dividend: u128,
+    dividend_scale: i32,
+    divisor: u128,
+    divisor_scale: i32,
+) -> Option<(u128, u32)> {
+    let mut scale = dividend_scale - divisor_scale;
+    let mut quotient = dividend / divisor;
+    let mut remainder = dividend % divisor;
+    let mut unscale = false;
+    loop {
+        let power = if remainder == 0 {
+            if scale >= 0 {
+                break;
+            }
+            (-scale).min(9)
+        } else {
+            unscale = true;
+            let power = if scale == MAX_REFERENCE_SCALE {
+                0
+            } else {
+                division_power(quotient, scale)?
+            };
+            if power == 0 {
+                let twice = remainder * 2;
+                if twice > divisor || twice == divisor && quotient % 2 == 1 {
+                    quotient += 1;
+                    if quotient > MAX_96 {
+                        (quotient, scale) = unscale_overflow(quotient, scale, true)?;
+                    }
+                }
+                break;
+            }
+            power
+        };
+        let factor = u128::from(POWERS_10[power as usize]);
+        scale += power;
+        quotient = Some(quotient * factor).filter(|quotient| *quotient <= MAX_96)?;
+        let remainder_scaled = remainder * factor;
+        quotient += remainder_scaled / divisor;
+        remainder = remainder_scaled % divisor;
+        if quotient > MAX_96 {
+            (quotient, scale) = unscale_overflow(quotient, scale, remainder != 0)?;
+            break;
+        }
+    }
+    if unscale {
+        // By 10^8 only while the lower 32 bits are zero, as `rust_decimal` does
+        while scale >= 8 && quotient.is_multiple_of(1 << 32) && quotient.is_multiple_of(100_000_000)
+        {
+            quotient /= 100_000_000;
+            scale -= 8;
+        }
+        for (digits, power) in [(4, 10_000), (2, 100), (1, 10)] {
+            if scale >= digits && quotient.is_multiple_of(power) {
+                quotient /= power;
+                scale -= digits;
+            }
+        }
+    }
+    Some((quotient, scale as u32))
+}
+
+// The largest power of 10, up to 10^9, the quotient can be scaled up by
+fn division_power(quotient: u128, scale: i32) -> Option<i32> {
+    let max = if scale > MAX_REFERENCE_SCALE - 9 {
+        MAX_REFERENCE_SCALE - scale
+    } else {
+        9
+    };
+    let power = (0..=max)
+        .rev()
+        .find(|power| quotient <= MAX_96 / u128::from(POWERS_10[*power as usize]))
+        .unwrap_or_default();
+    // Like `rust_decimal`, overflowing only if a smaller power isn't enough to make the scale
+    // positive
+    (power == 9 || power + scale >= 0).then_some(power)
+}
+
+// Drops a digit of a quotient exceeding 96 bits, rounding it to the nearest even
+fn unscale_overflow(quotient: u128, scale: i32, sticky: bool) -> Option<(u128, i32)> {
+    let scale = scale - 1;
+    if scale < 0 {
+        return None;
+    }
+    let (mut quotient, remainder) = (quotient / 10, quotient % 10);
+    if remainder > 5 || remainder == 5 && (sticky || quotient % 2 == 1) {
+        quotient += 1;
+    }
+    Some((quotient, scale))
+}
+
+fn parse(s: &str, exact: bool) -> Result<FixedDecimal, InvalidDecimal> {
+    if !exact {
+        if let Some((base, exponent)) = s.split_once(['e', 'E']) {
+            return parse_scientific(base, exponent);
+        }
+    }
+    let (negative, digits) = match s.as_bytes().first() {
+        None => return Err(InvalidDecimal("empty")),
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        Some(_) => (false, s),
+    };
+    let mut mantissa = 0u64;
+    let mut scale = 0;
+    let mut point = false;
+    let mut any_digit = false;
+    // The first of the digits exceeding the range, rounding the rest
+    let mut dropped = None;
+    for byte in digits.bytes() {
+        match byte {
+            b'0'..=b'9' => {
+                any_digit = true;
+                if dropped.is_some() {
+                    continue;
+                }
+                let digit = u64::from(byte - b'0');
+                match mantissa.checked_mul(10).and_then(|m| m.checked_add(digit)) {
+                    Some(next) if !point || scale < FixedDecimal::MAX_SCALE => {
+                        mantissa = next;
+                        scale += u32::from(point);
+                    }
+                    _ if !point => return Err(InvalidDecimal("overflow from too many digits")),
+                    _ if exact => return Err(InvalidDecimal("underflow from too many digits")),
+                    _ => dropped = Some(digit),
+                }
+            }
+            b'.' if point => return Err(InvalidDecimal("two decimal points")),
+            b'.' => point = true,
+            b'_' if !any_digit => return Err(InvalidDecimal("must start lead with a number")),
+            b'_' => {}
+            _ => return Err(InvalidDecimal("unknown character")),
+        }
+    }
+    if !any_digit {
+        return Err(InvalidDecimal("no digits found"));
+    }
+    if dropped.is_some_and(|digit| digit >= 5) {
+        return fit(u128::from(mantissa) + 1, scale, negative)
+            .ok_or(InvalidDecimal("overflow from too many digits"));
+    }
+    Ok(FixedDecimal::from_parts(mantissa, scale, negative))
+}
+
+fn parse_scientific(base: &str, exponent: &str) -> Result<FixedDecimal, InvalidDecimal> {
+    const FAILED: InvalidDecimal = InvalidDecimal("failed to parse");
+    const EXCEEDED: InvalidDecimal = InvalidDecimal("scale exceeds the maximum precision");
+
+    let mut value = parse(base, false)?;
+    if let Some(exponent) = exponent.strip_prefix('-') {
+        let exponent: u32 = exponent.parse().map_err(|_| FAILED)?;
+        let scale = value.scale().saturating_add(exponent);
+        if scale > FixedDecimal::MAX_SCALE {
+            return Err(EXCEEDED);
+        }
+        value.scale = scale as u8;
+    } else {
+        let exponent: u32 = exponent.parse().map_err(|_| FAILED)?;
+        if exponent <= value.scale() {
+            value.scale -= exponent as u8;
+        } else {
+            if exponent > MAX_REFERENCE_SCALE as u32 {
+                return Err(EXCEEDED);
+            }
+            for _ in 0..exponent {
+                value = value
+                    .checked_mul(FixedDecimal::TEN)
+                    .ok_or(InvalidDecimal("exceeds the maximum possible value"))?;
+            }
+            value = value.normalize();
+        }
+    }
+    Ok(value)
+}
+
+impl Amount for FixedDecimal {
+    const ZERO: Self = Self::ZERO;
+    const ONE: Self = Self::ONE;
+    const MAX: Self = Self::MAX;
+
+    fn new(mantissa: i64, scale: u32) -> Self {
+        Self::new(mantissa, scale)
+    }
+
+    fn parse_exact(s: &str) -> Option<Self> {
+        Self::from_str_exact(s).ok()
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        self.checked_add(other)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        self.checked_sub(other)
+    }
+
+    fn checked_mul(self, other: Self) -> Option<Self> {
+        self.checked_mul(other)
+    }
+
+    fn checked_div(self, other: Self) -> Option<Self> {
+        self.checked_div(other)
+    }
+
+    fn saturating_add(self, other: Self) -> Self {
+        self.saturating_add(other)
+    }
+
+    fn saturating_sub(self, other: Self) -> Self {
+        self.saturating_sub(other)
+    }
+
+    fn saturating_mul(self, other: Self) -> Self {
+        self.saturating_mul(other)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.is_zero()
+    }
+
+    fn is_sign_negative(&self) -> bool {
+        self.is_sign_negative()
+    }
+
+    fn scale(&self) -> u32 {
+        self.scale()
+    }
+
+    fn mantissa(&self) -> i128 {
+        self.mantissa()
+    }
+
+    fn normalize(&self) -> Self {
+        self.normalize()
+    }
+
+    fn rescale(&mut self, scale: u32) {
+        self.rescale(scale)
+    }
+
+    fn round_to(&self, dp: u32, rounding: Rounding) -> Self {
+        if self.scale() <= dp {
+            return *self;
+        }
+        if self.is_zero() {
+            return Self {
+                scale: dp as u8,
+                ..*self
+            };
+        }
+        let power = POWERS_10[(self.scale() - dp) as usize];
+        let (quotient, remainder) = (self.mantissa / power, self.mantissa % power);
+        let half = power / 2;
+        let round_up = match rounding {
+            Rounding::Truncate => false,
+            Rounding::HalfUp => remainder >= half,
+            Rounding::HalfEven => remainder > half || remainder == half && quotient % 2 == 1,
+        };
+        Self::from_parts(quotient + u64::from(round_up), dp, self.negative)
+    }
+
+    fn to_f64(&self) -> f64 {
+        let power = POWERS_10[self.scale as usize];
+        let integral = (self.mantissa / power) as f64;
+        let fraction = (self.mantissa % power) as f64 / power as f64;
+        // Rounded to the scale, as `rust_decimal` does
+        let value = if fraction == 0.0 {
+            integral
+        } else {
+            let power = 10f64.powi(self.scale.into());
+            ((integral + fraction) * power).round() / power
+        };
+        if self.negative {
+            -value
+        } else {
+            value
+        }
+    }
+}
+
+impl Default for FixedDecimal {
+    fn default() -> Self {
+        Self::ZERO
+    }
+}
+
+impl PartialEq for FixedDecimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for FixedDecimal {}
+
+impl PartialOrd for FixedDecimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FixedDecimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        self.signed(scale).cmp(&other.signed(scale))
+    }
+}
+
+impl Hash for FixedDecimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let normalized = self.normalize();
+        normalized.mantissa().hash(state);
+        normalized.scale.hash(state);
+    }
+}
+
+impl fmt::Display for FixedDecimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.mantissa.to_string();
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return f.pad_integral(!self.negative, "", &digits);
+        }
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (integral, fraction) = digits.split_at(digits.len() - scale);
+        f.pad_integral(!self.negative, "", &format!("{integral}.{fraction}"))
+    }
+}
+
+impl fmt::Debug for FixedDecimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl FromStr for FixedDecimal {
+    type Err = InvalidDecimal;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s, false)
+    }
+}
+
+macro_rules! impl_from_integer {
+    ($($signed:ty),* ; $($unsigned:ty),*) => {
+        $(impl From<$signed> for FixedDecimal {
+            fn from(value: $signed) -> Self {
+                Self::from_parts(value.unsigned_abs().into(), 0, value < 0)
+            }
+        })*
+        $(impl From<$unsigned> for FixedDecimal {
+            fn from(value: $unsigned) -> Self {
+                Self::from_parts(value.into(), 0, false)
+            }
+        })*
+    };
+}
+
+impl_from_integer!(i8, i16, i32, i64; u8, u16, u32, u64);
+
+impl Neg for FixedDecimal {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            negative: !self.negative,
+            ..self
+        }
+    }
+}
+
+impl Add for FixedDecimal {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.checked_add(other).expect("Addition overflowed")
+    }
+}
+
+impl Sub for FixedDecimal {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.checked_sub(other).expect("Subtraction overflowed")
+    }
+}
+
+impl Mul for FixedDecimal {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        self.checked_mul(other).expect("Multiplication overflowed")
+    }
+}
+
+impl Div for FixedDecimal {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self {
+        assert!(!other.is_zero(), "Division by zero");
+        self.checked_div(other).expect("Division overflowed")
+    }
+}
+
+impl AddAssign for FixedDecimal {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for FixedDecimal {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl AddAssign<&Self> for FixedDecimal {
+    fn add_assign(&mut self, other: &Self) {
+        *self = *self + *other;
+    }
+}
+
+impl SubAssign<&Self> for FixedDecimal {
+    fn sub_assign(&mut self, other: &Self) {
+        *self = *self - *other;
+    }
+}
+
+impl Sum for FixedDecimal {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, Add::add)
+    }
+}
+
+impl Serialize for FixedDecimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for FixedDecimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FixedDecimalVisitor)
+    }
+}
+
+struct FixedDecimalVisitor;
+
+impl de::Visitor<'_> for FixedDecimalVisitor {
+    type Value = FixedDecimal;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a Decimal type representing a fixed-point number")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        v.to_string()
+            .parse()
+            .map_err(|_| E::invalid_value(Unexpected::Float(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse()
+            .map_err(|_| E::invalid_value(Unexpected::Str(v), &self))
+    }
+}
+
+#[cfg(test)]
+mod amount_tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    type Reference = rust_decimal::Decimal;
+
+    // The same checks on both backends
+    fn check_amount<A: Amount>() {
+        let parse = |s: &str| A::parse_exact(s).unwrap();
+        let show = |amount: A| amount.to_string();
+
+        assert_eq!("2.50", show(parse("1.50") + A::ONE));
+        assert_eq!("0.0", show(parse("1.5") - parse("1.5")));
+        assert_eq!("2.500", show(parse("2.50") * parse("1.0")));
+        assert_eq!("0", show(parse("0.00") * parse("5")));
+        assert_eq!("2.50", show(A::new(10, 0) / A::new(4, 0)));
+        assert_eq!("0.10", show(A::new(10, 0) / A::new(100, 0)));
+        assert_eq!("0.0025", show(A::new(25, 0) / A::new(10_000, 0)));
+        assert_eq!("3", show(parse("7.5") / parse("2.5")));
+        assert_eq!("-0", show(-A::ZERO));
+        assert_eq!("-0.005", show(A::new(-5, 3)));
+        assert_eq!(None, A::parse_exact("1e3"));
+        assert_eq!(None, A::ONE.checked_div(A::ZERO));
+        assert_eq!(A::MAX, A::MAX.saturating_add(A::ONE));
+
+        let mut rescaled = parse("1.235");
+        rescaled.rescale(2);
+        assert_eq!("1.24", show(rescaled));
+        assert_eq!("1.5", show(parse("1.500").normalize()));
+        assert_eq!(
+            "-1.23",
+            show(parse("-1.235").round_to(2, Rounding::Truncate))
+        );
+        assert_eq!("-1.24", show(parse("-1.235").round_to(2, Rounding::HalfUp)));
+        assert_eq!(
+            "-1.24",
+            show(parse("-1.235").round_to(2, Rounding::HalfEven))
+        );
+        assert_eq!("1.22", show(parse("1.225").round_to(2, Rounding::HalfEven)));
+        assert_eq!(parse("1.5"), parse("1.50"));
+        assert!(parse("-0.01") < A::ZERO);
+        assert_eq!(0.1, parse("0.10").to_f64());
+        assert_eq!(r#""1.50""#, serde_json::to_string(&parse("1.50")).unwrap());
+        assert_eq!(parse("2.5"), serde_json::from_str::<A>("2.5").unwrap());
+    }
+
+    #[test]
+    fn test_backends() {
+        check_amount::<Reference>();
+        check_amount::<FixedDecimal>();
+    }
+
+    fn operand() -> impl Strategy<Value = (i64, u32)> {
+        prop_oneof![
+            (-10_000i64..10_000, 0u32..5),
+            (-1_000_000_000_000i64..1_000_000_000_000, 0u32..9),
+        ]
+    }
+
+    // Whether `fixed` is the same as `reference`, if the latter fits in a `FixedDecimal`
+    fn same(
+        fixed: Option<FixedDecimal>,
+        reference: Option<Reference>,
+    ) -> Result<(), TestCaseError> {
+        match reference {
+            Some(reference)
+                if reference.scale() > FixedDecimal::MAX_SCALE
+                    || reference.mantissa().unsigned_abs() > u64::MAX.into() => {}
+            reference => prop_assert_eq!(
+                reference.map(|amount| amount.to_string()),
+                fixed.map(|amount| amount.to_string())
+            ),
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn test_same_arithmetic(
+            (lhs, lhs_scale) in operand(),
+            (rhs, rhs_scale) in operand(),
+            scale in 0u32..12,
+            rounding in prop_oneof![
+                Just(Rounding::Truncate),
+                Just(Rounding::HalfUp),
+                Just(Rounding::HalfEven),
+            ],
+        ) {
+            let (a, b) = (FixedDecimal::new(lhs, lhs_scale), FixedDecimal::new(rhs, rhs_scale));
+            let (x, y) = (Reference::new(lhs, lhs_scale), Reference::new(rhs, rhs_scale));
+            prop_assert_eq!(Amount::to_f64(&a), Amount::to_f64(&x));
+            same(a.checked_add(b), x.checked_add(y))?;
+            same(a.checked_sub(b), x.checked_sub(y))?;
+            same(a.checked_mul(b), x.checked_mul(y))?;
+            same(a.checked_div(b), x.checked_div(y))?;
+            same(Some(a.normalize()), Some(x.normalize()))?;
+            same(Some(a.round_to(scale, rounding)), Some(x.round_to(scale, rounding)))?;
+            same(Some(-a), Some(-x))?;
+            let (mut a, mut x) = (a, x);
+            a.rescale(scale);
+            x.rescale(scale);
+            same(Some(a), Some(x))?;
+            prop_assert_eq!(a.cmp(&b), x.cmp(&y));
+        }
+
+        #[test]
+        fn test_same_parsing(chars in prop::collection::vec(0usize..20, 0..16)) {
+            let s: String = chars.into_iter().map(|i| "0123456789012345.-_e".as_bytes()[i] as char).collect();
+            same(s.parse().ok(), s.parse().ok())?;
+            same(FixedDecimal::from_str_exact(&s).ok(), Reference::from_str_exact(&s).ok())?;
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use super::{
+    error::EngineError,
+    fs::AtomicFile,
+    ledger::LedgerEntry,
+    model::{Transaction, TransactionType},
+};
+
+/// The final digests of the SHA-256 hash chains over the applied transactions, globally and
+/// per account, providing tamper-evidence of the ledger.
+///
+/// Each link of a chain is the digest of the previous one (32 zero bytes for the first one)
+/// followed by the transaction in JSON format, so changing, adding or removing a transaction
+/// changes the final digest. The chains follow the order of the transactions in their sources,
+/// i.e. by source and then by offset, whatever the sharding, followed by the ones generated by
+/// the engine in the order they have been applied. A transfer is part of the chains of both the
+/// sender and the counterparty.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditDigests {
+    /// Digest of the chain over all the transactions, as a hex string
+    pub global: String,
+    /// Digest of the chain over the transactions of each client, as a hex string
+    pub accounts: BTreeMap<u16, String>,
+}
+
+impl AuditDigests {
+    /// Computes the digests of the transactions of a ledger, see
+    /// [`PaymentEngine::ledger`](super::PaymentEngine::ledger).
+    pub fn from_ledger(ledger: &[LedgerEntry]) -> Self {
+        // The ledger of a sharded run has the transactions of each shard one after the other
+        let mut entries: Vec<_> = ledger.iter().collect();
+        entries.sort_by_key(|entry| (entry.synthetic, entry.tx.source, entry.tx.offset));
+        let mut global = [0; 32];
+        let mut accounts = BTreeMap::new();
+        for LedgerEntry { tx, .. } in entries {
+            global = chain(&global, tx);
+            let counterparty = tx
+                .counterparty
+                .filter(|_| tx.tx_type == TransactionType::Transfer);
+            for client_id in std::iter::once(tx.client_id).chain(counterparty) {
+                let account = accounts.entry(client_id).or_insert([0; 32]);
+                *account = chain(account, tx);
+            }
+        }
+        Self {
+            global: hex(&global),
+            accounts: accounts
+                .into_iter()
+                .map(|(client_id, digest)| (client_id, hex(&digest)))
+                .collect(),
+        }
+    }
+
+    /// Loads the digests written with [`AuditDigests::save`].
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let content = tokio::fs::read(path).await?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Writes the digests to `path` atomically, in JSON format.
+    pub async fn save(&self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        let content = serde_json::to_vec_pretty(self)?;
+        let mut file = AtomicFile::create(path).await?;
+        file.file().write_all(&content).await?;
+        Ok(file.commit().await?)
+    }
+
+    /// Returns the chains whose digest differs from the one in `other`, `global` or the client
+    /// id of an account, including the ones missing from either.
+    pub fn mismatches(&self, other: &Self) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        if self.global != other.global {
+            mismatches.push("global".to_string());
+        }
+        let clients: std::collections::BTreeSet<_> =
+            self.accounts.keys().chain(other.accounts.keys()).collect();
+        for client_id in clients {
+            if self.accounts.get(client_id) != other.accounts.get(client_id) {
+                mismatches.push(client_id.to_string());
+            }
+        }
+        mismatches
+    }
+}
+
+// Returns the next link of a chain, after `tx`
+fn chain(previous: &[u8; 32], tx: &Transaction) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(previous);
+    // Serializing a transaction can't fail, its fields being plain values
+    hasher.update(serde_json::to_vec(tx).unwrap_or_default());
+    hasher.finalize().into()
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod audit_tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::{Decimal, TransactionType};
+
+    // Entries of deposits of one unit, given the client and tx ids, at increasing offsets
+    fn deposits(ids: &[(u16, u32)]) -> Vec<LedgerEntry> {
+        ids.iter()
+            .map(|&(client_id, tx_id)| {
+                let mut tx = Transaction::new(
+                    TransactionType::Deposit,
+                    client_id,
+                    tx_id,
+                    Some(Decimal::ONE),
+                );
+                tx.offset = u64::from(tx_id) * 100;
+                LedgerEntry {
+                    tx,
+                    processed_at: SystemTime::now(),
+                    balances: Default::default(),
+                    locked: false,
+                    synthetic: false,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_audit_digests() {
+        let ledger = deposits(&[(1, 1), (2, 2), (1, 3)]);
+        let digests = AuditDigests::from_ledger(&ledger);
+        assert_eq!(64, digests.global.len());
+        assert_eq!(vec![&1, &2], digests.accounts.keys().collect::<Vec<_>>());
+        assert_eq!(digests, AuditDigests::from_ledger(&ledger));
+
+        // Tampering with a transaction changes the chains it's part of only
+        let mut tampered = ledger.clone();
+        tampered[2].tx.amount = Some(Decimal::TEN);
+        let mismatches = digests.mismatches(&AuditDigests::from_ledger(&tampered));
+        assert_eq!(vec!["global", "1"], mismatches);
+        // Removing a transaction
+        let removed = [ledger[0].clone(), ledger[2].clone()];
+        let mismatches = digests.mismatches(&AuditDigests::from_ledger(&removed));
+        assert_eq!(vec!["global", "2"], mismatches);
+    }
+
+    #[test]
+    fn test_audit_digests_order() {
+        let ledger = deposits(&[(1, 1), (2, 2), (1, 3), (2, 4)]);
+        let digests = AuditDigests::from_ledger(&ledger);
+        // The ledger of two shards, one after the other
+        let sharded = deposits(&[(2, 2), (2, 4), (1, 1), (1, 3)]);
+        assert_eq!(digests, AuditDigests::from_ledger(&sharded));
+
+        // The transactions generated by the engine follow the ones of the sources
+        let mut interest = deposits(&[(1, 5)]).remove(0);
+        interest.tx.tx_type = TransactionType::Interest;
+        interest.tx.offset = 0;
+        interest.synthetic = true;
+        let first = [vec![interest.clone()], ledger.clone()].concat();
+        let last = [ledger, vec![interest]].concat();
+        assert_eq!(
+            AuditDigests::from_ledger(&last),
+            AuditDigests::from_ledger(&first)
+        );
+    }
+
+    #[test]
+    fn test_audit_digests_transfer() {
+        let mut ledger = deposits(&[(1, 1), (2, 2), (3, 3)]);
+        let mut transfer = Transaction::new(TransactionType::Transfer, 1, 4, Some(Decimal::ONE));
+        transfer.counterparty = Some(2);
+        transfer.offset = 400;
+        ledger.push(LedgerEntry {
+            tx: transfer,
+            ..ledger[0].clone()
+        });
+        let digests = AuditDigests::from_ledger(&ledger);
+
+        // The transfer is part of the chains of both the sender and the counterparty
+        let mut tampered = ledger.clone();
+        tampered[3].tx.amount = Some(Decimal::TEN);
+        let mismatches = digests.mismatches(&AuditDigests::from_ledger(&tampered));
+        assert_eq!(vec!["global", "1", "2"], mismatches);
+    }
+}
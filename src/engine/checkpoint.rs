@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt};
+
+use super::{error::EngineError, fs::AtomicFile, model::ClientAccount, store::AccountState};
+
+/// State of an interrupted run: the accounts, and how far each source has been processed.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    accounts: Vec<AccountState>,
+    // Offset of the last record processed, per source
+    offsets: Vec<Option<u64>>,
+}
+
+impl Checkpoint {
+    /// Loads the checkpoint saved at `path`, if any.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Option<Self>, EngineError> {
+        match fs::read(path).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Number of sources processed by the interrupted run.
+    pub fn sources(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Byte offset of the last record processed from the source with index `source`, if any.
+    /// Reading has to be resumed from the record following it.
+    pub fn offset(&self, source: usize) -> Option<u64> {
+        self.offsets.get(source).copied().flatten()
+    }
+
+    pub(super) fn into_accounts(self) -> HashMap<u16, ClientAccount> {
+        self.accounts
+            .into_iter()
+            .map(ClientAccount::from)
+            .map(|account| (account.client_id, account))
+            .collect()
+    }
+}
+
+/// Periodically saves a [`Checkpoint`] while processing sources.
+#[derive(Debug, Clone)]
+pub(super) struct Checkpointer {
+    path: PathBuf,
+    // Records processed between two checkpoints
+    interval: u64,
+    offsets: Vec<Option<u64>>,
+    // Records processed since the last checkpoint
+    processed: u64,
+}
+
+impl Checkpointer {
+    pub(super) fn new(path: PathBuf, interval: u64) -> Self {
+        Self {
+            path,
+            interval: interval.max(1),
+            offsets: Vec::new(),
+            processed: 0,
+        }
+    }
+
+    /// Restores the offsets of an interrupted run, so that they are kept for the sources
+    /// without any record processed before the next checkpoint.
+    pub(super) fn resume(&mut self, checkpoint: &Checkpoint) {
+        self.offsets = checkpoint.offsets.clone();
+    }
+
+    /// Registers a processed record, saving a checkpoint if the interval has elapsed.
+    pub(super) async fn record_processed(
+        &mut self,
+        source: usize,
+        offset: u64,
+        accounts: &HashMap<u16, ClientAccount>,
+    ) -> Result<(), EngineError> {
+        if self.offsets.len() <= source {
+            self.offsets.resize(source + 1, None);
+        }
+        self.offsets[source] = Some(offset);
+        self.processed += 1;
+
+        if self.processed == self.interval {
+            self.save(accounts).await?;
+            self.processed = 0;
+        }
+        Ok(())
+    }
+
+    /// Saves a checkpoint with the offsets of the records processed so far.
+    pub(super) async fn save(
+        &self,
+        accounts: &HashMap<u16, ClientAccount>,
+    ) -> Result<(), EngineError> {
+        let checkpoint = Checkpoint {
+            accounts: accounts.values().map(AccountState::from).collect(),
+            offsets: self.offsets.clone(),
+        };
+        let mut file = AtomicFile::create(&self.path).await?;
+        file.file()
+            .write_all(&serde_json::to_vec(&checkpoint)?)
+            .await?;
+        file.commit().await?;
+        Ok(())
+    }
+
+    /// Removes the checkpoint, once all the sources have been processed.
+    pub(super) async fn finish(&self) -> Result<(), EngineError> {
+        match fs::remove_file(&self.path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod checkpoint_tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::engine::{
+        Compression, CsvDialect, Decimal, InputFormat, MergePolicy, PaymentEngine,
+        TransactionStream,
+    };
+
+    #[tokio::test]
+    async fn test_resume_interrupted_run() {
+        let dir = std::env::temp_dir().join(format!("tpe_checkpoint_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.csv");
+        let path = dir.join("checkpoint.json");
+        std::fs::write(
+            &input,
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\ndeposit,1,3,3.0\ndeposit,1,4,4.0\n",
+        )
+        .unwrap();
+
+        // The run is interrupted by an error after two records
+        let mut engine = PaymentEngine::builder().checkpoint(&path, 1).build();
+        let records = InputFormat::Csv
+            .open_at(&input, Compression::None, &CsvDialect::default(), 0)
+            .await
+            .unwrap()
+            .take(2);
+        let failure = tokio_stream::once(Err(std::io::Error::other("interrupted").into()));
+        let source: TransactionStream = Box::pin(records.chain(failure));
+        assert!(engine
+            .process_sources(vec![source], MergePolicy::default())
+            .await
+            .is_err());
+
+        let checkpoint = Checkpoint::load(&path).await.unwrap().unwrap();
+        assert_eq!(1, checkpoint.sources());
+        let offset = checkpoint.offset(0).unwrap();
+
+        let mut engine = PaymentEngine::builder().checkpoint(&path, 1).build();
+        engine.resume(checkpoint);
+        let source = InputFormat::Csv
+            .open_at(&input, Compression::None, &CsvDialect::default(), offset)
+            .await
+            .unwrap();
+        let stats = engine
+            .process_sources(vec![Box::pin(source.skip(1))], MergePolicy::default())
+            .await
+            .unwrap();
+        assert_eq!(2, stats[0].applied);
+        assert_eq!(Decimal::TEN, engine.account(1).unwrap().total);
+        assert!(Checkpoint::load(&path).await.unwrap().is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use std::{str::FromStr, time::Duration};
+
+use super::{fees::FeeSchedule, precision::PrecisionPolicy, validation::Validators};
+
+/// Options affecting how transactions are applied to the accounts
+#[derive(Debug, Default, Clone)]
+pub struct EngineConfig {
+    /// Whether withdrawals can be disputed. When a withdrawal is disputed, the withdrawn amount
+    /// is held as money potentially owed back to the client, and re-credited on chargeback.
+    pub allow_withdrawal_disputes: bool,
+    /// Whether tx ids have to be unique across clients, rejecting a deposit or withdrawal with
+    /// the tx id of another client's one.
+    pub unique_tx_ids: bool,
+    /// Whether administrative operations, e.g. unlocking an account, are applied instead of
+    /// being rejected.
+    pub allow_admin_ops: bool,
+    /// Precision the amounts of the transactions are rounded to when applied.
+    pub precision: PrecisionPolicy,
+    /// Fees charged on deposits and withdrawals, none by default.
+    pub fees: FeeSchedule,
+    /// When the available funds of an account can become negative.
+    pub negative_balance: NegativeBalancePolicy,
+    /// Rules checked before applying a transaction to an account.
+    pub validators: Validators,
+    /// How long after a transaction it can be disputed, when both the transaction and the
+    /// dispute have a timestamp. Unlimited by default.
+    pub dispute_window: Option<Duration>,
+}
+
+/// When a transaction can take more than the available funds of an account, leaving them
+/// negative. Otherwise it's rejected for insufficient funds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NegativeBalancePolicy {
+    /// Available funds never become negative.
+    #[default]
+    Forbid,
+    /// Disputes of deposits whose funds have already been withdrawn are applied anyway, so
+    /// that they can be chargebacked, and so are their reversals.
+    AllowOnChargeback,
+    /// Disputes, withdrawals and transfers can always exceed the available funds.
+    AllowAlways,
+}
+
+impl FromStr for NegativeBalancePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "forbid" => Ok(Self::Forbid),
+            "allow-on-chargeback" => Ok(Self::AllowOnChargeback),
+            "allow-always" => Ok(Self::AllowAlways),
+            _ => Err(format!("Unknown negative balance policy `{s}`")),
+        }
+    }
+}
+
+/// What happens to the funds still held by disputes of an account locked by a chargeback, once
+/// the sources are processed, see [`PaymentEngine::release_held_funds`](super::PaymentEngine::release_held_funds).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HeldFundsPolicy {
+    /// The funds stay held until the disputes are settled by later transactions.
+    #[default]
+    Keep,
+    /// The disputes are resolved, releasing the funds.
+    Resolve,
+    /// The disputes are chargebacked, reversing the transactions.
+    Chargeback,
+}
+
+impl FromStr for HeldFundsPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Self::Keep),
+            "resolve" => Ok(Self::Resolve),
+            "chargeback" => Ok(Self::Chargeback),
+            _ => Err(format!("Unknown held funds policy `{s}`")),
+        }
+    }
+}
+
+/// The order accounts are listed in, see [`PaymentEngine::sorted_accounts`](super::PaymentEngine::sorted_accounts).
+/// Accounts with the same balance are ordered by client id.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// Ascending client id
+    #[default]
+    Client,
+    /// Ascending total funds
+    Total,
+    /// Ascending available funds
+    Available,
+}
+
+/// The order records are applied in, see [`EngineBuilder::shards`](super::EngineBuilder::shards).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RecordOrdering {
+    /// Records are applied one at a time, in the order they are read
+    #[default]
+    Strict,
+    /// Records of different clients are applied in parallel, possibly out of order, while the
+    /// ones of each client are applied in the order they are read
+    PerClient,
+}
+
+impl FromStr for RecordOrdering {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(Self::Strict),
+            "per-client" => Ok(Self::PerClient),
+            _ => Err(format!("Unknown ordering `{s}`")),
+        }
+    }
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(Self::Client),
+            "total" => Ok(Self::Total),
+            "available" => Ok(Self::Available),
+            _ => Err(format!("Unknown sort key `{s}`")),
+        }
+    }
+}
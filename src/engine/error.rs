@@ -1,28 +1,164 @@
-use std::fmt::Display;
+use thiserror::Error;
 
-#[derive(Debug)]
+use super::{
+    model::TransactionType,
+    reject::{RejectReason, RejectedTransaction},
+};
+
+#[derive(Debug, Error)]
 pub enum EngineError {
-    CsvError(csv_async::Error),
-    IoError(std::io::Error),
+    #[cfg(feature = "io")]
+    #[error("CSV data reading error: {0}")]
+    CsvError(#[from] csv_async::Error),
+    #[error("JSON data reading error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    /// An error reading from object storage
+    #[cfg(feature = "object-store")]
+    #[error("Object storage error: {0}")]
+    ObjectStoreError(#[from] object_store::Error),
+    /// A record that can't be deserialized into a transaction
+    #[error("Malformed record at line {line} `{record}`: {source}")]
+    MalformedRecord {
+        line: u64,
+        record: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A record whose type isn't any of the supported [`TransactionType`]s
+    #[error("Unknown transaction type `{tx_type}` at line {line} `{record}`")]
+    UnknownTransactionType {
+        line: u64,
+        record: String,
+        tx_type: String,
+    },
+    /// A transaction not allowed in the current status of the referenced one
+    #[error("Invalid transition of tx {tx_id} on {action:?}: {reason}")]
+    InvalidTransition {
+        tx_id: u32,
+        action: TransactionType,
+        reason: RejectReason,
+    },
+    /// A transaction on the locked account of a client
+    #[error("Account of client {client_id} is locked, tx {tx_id} not applied")]
+    AccountLocked { client_id: u16, tx_id: u32 },
+    /// A transaction rejected for any other reason
+    #[error("Tx {tx_id} rejected: {reason}")]
+    Rejected { tx_id: u32, reason: RejectReason },
+    #[error("Checkpoint error: {0}")]
+    CheckpointError(String),
+    /// A fee schedule that can't be read
+    #[error("Fee schedule error: {0}")]
+    FeeScheduleError(String),
+    /// A column mapping that can't be read
+    #[error("Column mapping error: {0}")]
+    ColumnMappingError(String),
+    /// An accounts report that can't be read, e.g. the expected one
+    #[error("Accounts report error: {0}")]
+    ReportError(String),
+    /// A config file that can't be read
+    #[error("Config file error: {0}")]
+    ConfigError(String),
+    /// A signing key, public key or signature that can't be read
+    #[error("Signing error: {0}")]
+    SigningError(String),
+    /// No account for the client, e.g. in a snapshot
+    #[error("No account for client {0}")]
+    ClientNotFound(u16),
+    /// An input file already ingested in a previous run, or given twice
+    #[error("File already ingested: {0}")]
+    DuplicateFile(String),
+    /// Arithmetic overflows occurred on the accounts of the clients
+    #[error("Arithmetic overflow on the accounts of clients {0:?}")]
+    ArithmeticOverflow(Vec<u16>),
+    /// Processing interrupted by a signal, the reports only covering the records processed
+    /// before it
+    #[error("Processing interrupted")]
+    Interrupted,
+    /// The account of a client breaking an invariant, see [`PaymentEngine::check_invariants`](super::PaymentEngine::check_invariants)
+    #[error("Invariant violated on the account of client {client_id}: {invariant}")]
+    InvariantViolated {
+        client_id: u16,
+        invariant: &'static str,
+    },
 }
 
-impl Display for EngineError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            EngineError::CsvError(e) => writeln!(f, "CSV data reading error: {e:?}"),
-            EngineError::IoError(e) => writeln!(f, "IO error: {e:?}"),
-        }
+impl EngineError {
+    /// Whether the error is about a single invalid record, so that the following ones can
+    /// still be read.
+    pub fn is_invalid_record(&self) -> bool {
+        matches!(
+            self,
+            Self::MalformedRecord { .. } | Self::UnknownTransactionType { .. }
+        )
     }
 }
 
-impl From<csv_async::Error> for EngineError {
-    fn from(value: csv_async::Error) -> Self {
-        Self::CsvError(value)
+impl From<RejectedTransaction> for EngineError {
+    fn from(value: RejectedTransaction) -> Self {
+        let RejectedTransaction { tx, reason } = value;
+        match reason {
+            RejectReason::AccountLocked => Self::AccountLocked {
+                client_id: tx.client_id,
+                tx_id: tx.tx_id,
+            },
+            RejectReason::DuplicateTransaction
+            | RejectReason::DuplicateIdempotencyKey
+            | RejectReason::NotVerified
+            | RejectReason::NotDisputed
+            | RejectReason::AlreadyDisputed
+            | RejectReason::AlreadyResolved
+            | RejectReason::AlreadyChargebacked
+            | RejectReason::AlreadyReversed
+            | RejectReason::NotLocked => Self::InvalidTransition {
+                tx_id: tx.tx_id,
+                action: tx.tx_type,
+                reason,
+            },
+            _ => Self::Rejected {
+                tx_id: tx.tx_id,
+                reason,
+            },
+        }
     }
 }
 
-impl From<std::io::Error> for EngineError {
-    fn from(value: std::io::Error) -> Self {
-        Self::IoError(value)
+#[cfg(test)]
+mod error_tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::engine::model::Transaction;
+
+    #[test]
+    fn test_rejected_transaction_causes() {
+        let tx = Transaction::new(TransactionType::Resolve, 1, 2, None);
+        let error = EngineError::from(RejectedTransaction {
+            tx: tx.clone(),
+            reason: RejectReason::NotDisputed,
+        });
+        assert!(matches!(
+            error,
+            EngineError::InvalidTransition {
+                tx_id: 2,
+                action: TransactionType::Resolve,
+                reason: RejectReason::NotDisputed
+            }
+        ));
+
+        let error = EngineError::from(RejectedTransaction {
+            tx,
+            reason: RejectReason::AccountLocked,
+        });
+        assert!(matches!(
+            error,
+            EngineError::AccountLocked {
+                client_id: 1,
+                tx_id: 2
+            }
+        ));
+
+        let error = EngineError::from(std::io::Error::other("failure"));
+        assert!(error.source().is_some());
     }
 }
@@ -0,0 +1,127 @@
+use serde::{Serialize, Serializer};
+
+use super::{
+    amount::Decimal,
+    model::{CurrencyCode, TransactionType},
+    reject::RejectReason,
+};
+
+/// Funds moved in an account by a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FundsMovement {
+    pub client: u16,
+    pub tx: u32,
+    pub amount: Decimal,
+    /// Currency of the amount, the default one if not specified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<CurrencyCode>,
+}
+
+/// A change of the state of an account, resulting from applying a transaction to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AccountEvent {
+    /// Funds credited by a deposit, net of the fee.
+    FundsDeposited(FundsMovement),
+    /// Funds debited by a withdrawal, excluding the fee.
+    FundsWithdrawn(FundsMovement),
+    /// Fee charged on a deposit or withdrawal.
+    FeeCharged(FundsMovement),
+    /// Funds held by the dispute of a transaction.
+    FundsHeld(FundsMovement),
+    /// Funds released by the resolution of a dispute.
+    FundsReleased(FundsMovement),
+    /// Funds reversed by the chargeback of a disputed transaction.
+    FundsReversed(FundsMovement),
+    /// A deposit or withdrawal undone by a reversal, with the amount debited or credited back.
+    TxReversed(FundsMovement),
+    /// Funds moved by a transfer to the account of the counterparty.
+    FundsTransferred {
+        #[serde(flatten)]
+        funds: FundsMovement,
+        counterparty: u16,
+    },
+    /// Interest credited to the account.
+    InterestCredited(FundsMovement),
+    /// The account has been locked by a chargeback.
+    AccountLocked { client: u16, tx: u32 },
+    /// The account has been unlocked by an administrative operation.
+    AccountUnlocked { client: u16, tx: u32 },
+    /// The account has been closed at the request of the client.
+    AccountClosed { client: u16, tx: u32 },
+    /// A transaction has been rejected, leaving the account unchanged.
+    TxRejected {
+        client: u16,
+        tx: u32,
+        #[serde(rename = "type")]
+        tx_type: TransactionType,
+        #[serde(serialize_with = "reason_code")]
+        reason: RejectReason,
+    },
+}
+
+impl AccountEvent {
+    /// Returns the client of the account the event is about.
+    pub fn client_id(&self) -> u16 {
+        match self {
+            AccountEvent::FundsDeposited(funds)
+            | AccountEvent::FundsWithdrawn(funds)
+            | AccountEvent::FeeCharged(funds)
+            | AccountEvent::FundsHeld(funds)
+            | AccountEvent::FundsReleased(funds)
+            | AccountEvent::FundsReversed(funds)
+            | AccountEvent::TxReversed(funds)
+            | AccountEvent::FundsTransferred { funds, .. }
+            | AccountEvent::InterestCredited(funds) => funds.client,
+            AccountEvent::AccountLocked { client, .. }
+            | AccountEvent::AccountUnlocked { client, .. }
+            | AccountEvent::AccountClosed { client, .. }
+            | AccountEvent::TxRejected { client, .. } => *client,
+        }
+    }
+
+    /// Whether the event is worth an alert: an account locked or a chargeback.
+    pub fn is_alert(&self) -> bool {
+        matches!(
+            self,
+            AccountEvent::AccountLocked { .. } | AccountEvent::FundsReversed(_)
+        )
+    }
+}
+
+fn reason_code<S: Serializer>(reason: &RejectReason, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(reason.code())
+}
+
+#[cfg(test)]
+mod event_tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize() {
+        let event = AccountEvent::FundsTransferred {
+            funds: FundsMovement {
+                client: 1,
+                tx: 2,
+                amount: Decimal::new(15, 1),
+                currency: None,
+            },
+            counterparty: 3,
+        };
+        assert_eq!(
+            r#"{"event":"funds_transferred","client":1,"tx":2,"amount":"1.5","counterparty":3}"#,
+            serde_json::to_string(&event).unwrap()
+        );
+
+        let event = AccountEvent::TxRejected {
+            client: 1,
+            tx: 2,
+            tx_type: TransactionType::Withdrawal,
+            reason: RejectReason::InsufficientFunds,
+        };
+        assert_eq!(
+            r#"{"event":"tx_rejected","client":1,"tx":2,"type":"withdrawal","reason":"insufficient_funds"}"#,
+            serde_json::to_string(&event).unwrap()
+        );
+    }
+}
@@ -0,0 +1,147 @@
+#[cfg(feature = "io")]
+use std::path::Path;
+use std::{collections::HashMap, str::FromStr};
+
+use toml_edit::{DocumentMut, Item, Value};
+
+#[cfg(feature = "io")]
+use super::error::EngineError;
+use super::{amount::Decimal, model::TransactionType, reject::RejectReason};
+
+/// A fee charged on a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fee {
+    /// A fixed amount, whatever the amount of the transaction
+    Flat(Decimal),
+    /// A percentage of the amount of the transaction
+    Percent(Decimal),
+}
+
+impl Fee {
+    /// Returns the fee charged on `amount`.
+    pub fn on(&self, amount: Decimal) -> Result<Decimal, RejectReason> {
+        match self {
+            Fee::Flat(fee) => Ok(*fee),
+            Fee::Percent(percent) => amount
+                .checked_mul(*percent)
+                .map(|fee| fee / Decimal::ONE_HUNDRED)
+                .ok_or(RejectReason::ArithmeticOverflow),
+        }
+    }
+}
+
+/// The fees charged per transaction type, only on deposits and withdrawals.
+///
+/// The fee of a deposit is deducted from the amount credited, while the one of a withdrawal is
+/// debited on top of the amount. Disputes involve the amount net of the fees, which are never
+/// given back.
+///
+/// It's read from a TOML file with a table per transaction type, e.g.:
+///
+/// ```toml
+/// [deposit]
+/// flat = 0.5
+///
+/// [withdrawal]
+/// percent = 1.5
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FeeSchedule {
+    fees: HashMap<TransactionType, Fee>,
+}
+
+impl FeeSchedule {
+    /// Sets the fee charged on the transactions of type `tx_type`.
+    pub fn with_fee(mut self, tx_type: TransactionType, fee: Fee) -> Self {
+        self.fees.insert(tx_type, fee);
+        self
+    }
+
+    /// Returns the fee charged on the transactions of type `tx_type`, if any.
+    pub fn fee(&self, tx_type: TransactionType) -> Option<Fee> {
+        self.fees.get(&tx_type).copied()
+    }
+
+    /// Reads the fee schedule from a TOML file.
+    #[cfg(feature = "io")]
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let data = tokio::fs::read_to_string(path).await?;
+        data.parse().map_err(EngineError::FeeScheduleError)
+    }
+}
+
+impl FromStr for FeeSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let doc: DocumentMut = s.parse().map_err(|e| format!("{e}"))?;
+        let mut schedule = FeeSchedule::default();
+        for (key, item) in doc.iter() {
+            let tx_type: TransactionType = key.parse()?;
+            if !matches!(
+                tx_type,
+                TransactionType::Deposit | TransactionType::Withdrawal
+            ) {
+                return Err(format!(
+                    "Fees apply only to deposits and withdrawals, not to `{key}`"
+                ));
+            }
+            let table = item
+                .as_table()
+                .ok_or_else(|| format!("Expected a table for `{key}`"))?;
+            let fee = match (table.get("flat"), table.get("percent")) {
+                (Some(flat), None) => Fee::Flat(decimal(flat, key)?),
+                (None, Some(percent)) => Fee::Percent(decimal(percent, key)?),
+                _ => return Err(format!("Expected either `flat` or `percent` for `{key}`")),
+            };
+            let (Fee::Flat(value) | Fee::Percent(value)) = fee;
+            if value.is_sign_negative() {
+                return Err(format!("Negative fee for `{key}`"));
+            }
+            schedule = schedule.with_fee(tx_type, fee);
+        }
+        Ok(schedule)
+    }
+}
+
+// Reads a decimal given as a number, or as a string to avoid any loss of precision
+fn decimal(item: &Item, key: &str) -> Result<Decimal, String> {
+    let value = match item.as_value() {
+        Some(Value::Float(value)) => value.value().to_string(),
+        Some(Value::Integer(value)) => value.value().to_string(),
+        Some(Value::String(value)) => value.value().clone(),
+        _ => String::new(),
+    };
+    Decimal::from_str(&value).map_err(|_| format!("Invalid fee for `{key}`"))
+}
+
+#[cfg(test)]
+mod fees_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_schedule() {
+        let schedule: FeeSchedule = "[deposit]\nflat = 0.5\n\n[withdrawal]\npercent = \"1.25\"\n"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            Some(Fee::Flat(Decimal::new(5, 1))),
+            schedule.fee(TransactionType::Deposit)
+        );
+        assert_eq!(
+            Ok(Decimal::new(25, 2)),
+            schedule
+                .fee(TransactionType::Withdrawal)
+                .unwrap()
+                .on(Decimal::new(20, 0))
+        );
+        assert_eq!(None, schedule.fee(TransactionType::Dispute));
+
+        assert!("[dispute]\nflat = 1".parse::<FeeSchedule>().is_err());
+        assert!("[deposit]\nflat = 1\npercent = 1"
+            .parse::<FeeSchedule>()
+            .is_err());
+        assert!("[deposit]\nflat = -1".parse::<FeeSchedule>().is_err());
+        assert!("[deposit]\nflat = true".parse::<FeeSchedule>().is_err());
+    }
+}
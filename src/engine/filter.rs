@@ -0,0 +1,107 @@
+use std::{ops::RangeInclusive, str::FromStr};
+
+use super::model::TransactionType;
+
+/// Clients whose records are applied while processing sources, and whose accounts are written
+/// to the reports: the ones in any of the `include` ranges (all of them if there are none), and
+/// in none of the `exclude` ones.
+///
+/// It's parsed from a list of client ids and ranges, e.g. `1,2,5-10`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClientFilter {
+    pub include: Vec<RangeInclusive<u16>>,
+    pub exclude: Vec<RangeInclusive<u16>>,
+}
+
+impl ClientFilter {
+    /// Whether the client is selected by the filter.
+    pub fn contains(&self, client_id: u16) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|range| range.contains(&client_id)))
+            && !self.exclude.iter().any(|range| range.contains(&client_id))
+    }
+
+    /// Parses a list of client ids and ranges, e.g. `1,2,5-10`.
+    pub fn parse_ranges(s: &str) -> Result<Vec<RangeInclusive<u16>>, String> {
+        let parse = |id: &str| {
+            id.trim()
+                .parse::<u16>()
+                .map_err(|e| format!("Invalid client id `{id}`: {e}"))
+        };
+        s.split(',')
+            .filter(|item| !item.trim().is_empty())
+            .map(|item| match item.split_once('-') {
+                Some((start, end)) => Ok(parse(start)?..=parse(end)?),
+                None => parse(item).map(|id| id..=id),
+            })
+            .collect()
+    }
+}
+
+impl FromStr for ClientFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            include: Self::parse_ranges(s)?,
+            exclude: Vec::new(),
+        })
+    }
+}
+
+/// Types of the transactions applied while processing sources, all of them if empty.
+///
+/// It's parsed from a list of type names, singular or plural, e.g. `deposits,withdrawals`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TypeFilter {
+    pub types: Vec<TransactionType>,
+}
+
+impl TypeFilter {
+    /// Whether the transaction type is selected by the filter.
+    pub fn contains(&self, tx_type: TransactionType) -> bool {
+        self.types.is_empty() || self.types.contains(&tx_type)
+    }
+}
+
+impl FromStr for TypeFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let types = s
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                name.parse()
+                    .or_else(|e| name.strip_suffix('s').ok_or(e)?.parse())
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { types })
+    }
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use super::*;
+
+    #[test]
+    fn test_client_filter() {
+        let mut filter: ClientFilter = "1, 2,5-10".parse().unwrap();
+        assert!(filter.contains(1) && filter.contains(5) && filter.contains(10));
+        assert!(!filter.contains(3) && !filter.contains(11));
+        filter.exclude = ClientFilter::parse_ranges("7-8").unwrap();
+        assert!(filter.contains(6) && !filter.contains(7));
+        assert!(ClientFilter::default().contains(42));
+        assert!("1-x".parse::<ClientFilter>().is_err());
+    }
+
+    #[test]
+    fn test_type_filter() {
+        let filter: TypeFilter = "deposits, withdrawal".parse().unwrap();
+        assert!(filter.contains(TransactionType::Deposit));
+        assert!(filter.contains(TransactionType::Withdrawal));
+        assert!(!filter.contains(TransactionType::Dispute));
+        assert!(TypeFilter::default().contains(TransactionType::Chargeback));
+        assert!("refunds".parse::<TypeFilter>().is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::{fmt::Debug, time::Duration};
+
+use tracing::info;
+
+use super::{
+    config::HeldFundsPolicy,
+    error::EngineError,
+    model::{Transaction, TransactionType},
+    payment_engine::PaymentEngine,
+};
+
+/// A pass over the accounts once all the records of the sources are consumed, run by
+/// [`PaymentEngine::finalize`], e.g. to settle the disputes left open or to credit interest.
+///
+/// Finalizers are run in the order they are registered with
+/// [`EngineBuilder::finalizer`](super::EngineBuilder::finalizer). The transactions they apply
+/// with [`PaymentEngine::apply_final`] are collected like the records of the sources.
+pub trait Finalizer: Debug + Send {
+    /// Finalizes the accounts of `engine`.
+    fn finalize(&mut self, engine: &mut PaymentEngine) -> Result<(), EngineError>;
+}
+
+/// Credits interest at `bps` basis points of the available funds, see
+/// [`PaymentEngine::accrue_interest`].
+#[derive(Debug, Clone, Copy)]
+pub struct AccrueInterest {
+    pub bps: u32,
+}
+
+impl Finalizer for AccrueInterest {
+    fn finalize(&mut self, engine: &mut PaymentEngine) -> Result<(), EngineError> {
+        let applied = engine.accrue_interest(self.bps)?;
+        info!(bps = self.bps, applied, "Interest credited");
+        Ok(())
+    }
+}
+
+/// Settles the disputes still open on the locked accounts, see
+/// [`PaymentEngine::release_held_funds`].
+#[derive(Debug, Clone, Copy)]
+pub struct ReleaseHeldFunds(pub HeldFundsPolicy);
+
+impl Finalizer for ReleaseHeldFunds {
+    fn finalize(&mut self, engine: &mut PaymentEngine) -> Result<(), EngineError> {
+        let applied = engine.release_held_funds(self.0)?;
+        info!(policy = ?self.0, applied, "Held funds of the locked accounts settled");
+        Ok(())
+    }
+}
+
+/// Resolves the disputes of the transactions older than `max_age` at the latest timestamp of
+/// the transactions applied, releasing their funds. The disputes of transactions without a
+/// timestamp are left open.
+#[derive(Debug, Clone, Copy)]
+pub struct ResolveStaleDisputes {
+    pub max_age: Duration,
+}
+
+impl Finalizer for ResolveStaleDisputes {
+    fn finalize(&mut self, engine: &mut PaymentEngine) -> Result<(), EngineError> {
+        let Some(latest) = engine.latest_timestamp() else {
+            return Ok(());
+        };
+        let stale = latest.saturating_sub(self.max_age.as_secs());
+        let disputes: Vec<_> = engine
+            .open_disputes()?
+            .into_iter()
+            .filter(|(.., tx)| tx.timestamp.is_some_and(|timestamp| timestamp < stale))
+            .collect();
+
+        let mut applied = 0;
+        for (client_id, tx_id, _) in disputes {
+            let resolve = Transaction::new(TransactionType::Resolve, client_id, tx_id, None);
+            if engine.apply_final(resolve)? {
+                applied += 1;
+            }
+        }
+        info!(applied, "Stale disputes resolved");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod finalize_tests {
+    use super::*;
+    use crate::engine::{CsvSource, Decimal};
+
+    // Closes the accounts without funds
+    #[derive(Debug)]
+    struct CloseEmptyAccounts;
+
+    impl Finalizer for CloseEmptyAccounts {
+        fn finalize(&mut self, engine: &mut PaymentEngine) -> Result<(), EngineError> {
+            let clients: Vec<_> = engine
+                .sorted_accounts()
+                .into_iter()
+                .filter(|acc| acc.total.is_zero())
+                .map(|acc| acc.client_id)
+                .collect();
+            for client_id in clients {
+                let close = Transaction::new(TransactionType::Close, client_id, 0, None);
+                engine.apply_final(close)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_finalize() {
+        let day = 24 * 60 * 60;
+        let data = format!(
+            "type,client,tx,amount,timestamp\ndeposit,1,1,5.0,0\ndeposit,2,2,3.0,{}\n\
+             dispute,1,1,,{}\ndispute,2,2,,{}\nwithdrawal,3,3,1.0,{}\n",
+            5 * day,
+            6 * day,
+            6 * day,
+            10 * day
+        );
+        let mut engine = PaymentEngine::builder()
+            .collect_ledger(true)
+            .finalizer(ResolveStaleDisputes {
+                max_age: Duration::from_secs(7 * day),
+            })
+            .finalizer(CloseEmptyAccounts)
+            .build();
+        engine
+            .process(CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+        engine.finalize().unwrap();
+
+        // Only the deposit older than 7 days is resolved
+        assert_eq!(Decimal::new(5, 0), engine.account(1).unwrap().available);
+        assert_eq!(Decimal::new(3, 0), engine.account(2).unwrap().held);
+        assert!(engine.account(3).unwrap().closed);
+        let finalized: Vec<_> = engine.ledger()[4..]
+            .iter()
+            .map(|entry| (entry.tx.tx_type, entry.tx.client_id))
+            .collect();
+        assert_eq!(
+            vec![(TransactionType::Resolve, 1), (TransactionType::Close, 3)],
+            finalized
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{de::Error, Deserialize, Deserializer};
+use tokio::{
+    fs::{self, File},
+    io::{self, AsyncRead},
+};
+use tokio_stream::StreamExt;
+
+use super::{
+    amount::Decimal,
+    error::EngineError,
+    model::{Balances, CurrencyCode},
+};
+
+// Row of an accounts report, as written by `write_accounts` in CSV format
+#[derive(Deserialize)]
+struct ReportRow {
+    client: u16,
+    #[serde(default)]
+    currency: Option<CurrencyCode>,
+    #[serde(deserialize_with = "decimal")]
+    available: Decimal,
+    #[serde(deserialize_with = "decimal")]
+    held: Decimal,
+    #[serde(deserialize_with = "decimal")]
+    total: Decimal,
+    locked: bool,
+    // `closed` for the accounts closed, if the report has the column
+    #[serde(default)]
+    status: Option<String>,
+}
+
+// Parses the amounts from their text, without any loss of precision
+fn decimal<'de, D: Deserializer<'de>>(de: D) -> Result<Decimal, D::Error> {
+    let value = String::deserialize(de)?;
+    Decimal::from_str(&value).map_err(D::Error::custom)
+}
+
+// Balances and state of the account of a client in a currency, in an accounts report
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct ReportEntry {
+    pub(crate) balances: Balances,
+    pub(crate) locked: bool,
+    pub(crate) closed: bool,
+}
+
+// Accounts of the clients, by currency (an empty code for the default one)
+type Report = BTreeMap<(u16, CurrencyCode), ReportEntry>;
+
+pub(crate) async fn read_report<R: AsyncRead + Send + Unpin>(
+    rdr: R,
+) -> Result<Report, EngineError> {
+    let mut rows = csv_async::AsyncReaderBuilder::new()
+        .trim(csv_async::Trim::All)
+        .create_deserializer(rdr)
+        .into_deserialize::<ReportRow>();
+    let mut report = Report::new();
+    while let Some(row) = rows.next().await {
+        let row = row.map_err(|e| EngineError::ReportError(format!("{e}")))?;
+        let balances = Balances {
+            available: row.available,
+            held: row.held,
+            total: row.total,
+        };
+        let entry = ReportEntry {
+            balances,
+            locked: row.locked,
+            closed: row.status.as_deref() == Some("closed"),
+        };
+        report.insert((row.client, row.currency.unwrap_or_default()), entry);
+    }
+    Ok(report)
+}
+
+/// A file written atomically: data goes to a temporary file in the same directory, which
+/// replaces the target one only when [`AtomicFile::commit`] is called. If the file is dropped
+/// without committing, the temporary file is removed and the target one is left untouched.
+pub struct AtomicFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    file: Option<File>,
+    committed: bool,
+}
+
+impl AtomicFile {
+    pub async fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(path.file_name().unwrap_or_default());
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+
+        let file = File::create(&tmp_path).await?;
+        Ok(Self {
+            path,
+            tmp_path,
+            file: Some(file),
+            committed: false,
+        })
+    }
+
+    pub fn file(&mut self) -> &mut File {
+        self.file
+            .as_mut()
+            .expect("file is available until committed")
+    }
+
+    /// Changes the path the data is moved to when committed, e.g. once it's known to be partial.
+    pub fn set_path(&mut self, path: impl Into<PathBuf>) {
+        self.path = path.into();
+    }
+
+    /// Persists the data written so far and moves it to the target path.
+    pub async fn commit(mut self) -> io::Result<()> {
+        let file = self.file.take().expect("file is available until committed");
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&self.tmp_path, &self.path).await?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // Not committed, the temporary file has to be discarded
+        if !self.committed {
+            let _ = std::fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod fs_tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_atomic_file() {
+        let dir = std::env::temp_dir().join(format!("tpe_fs_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("accounts.csv");
+
+        // Dropped without committing: nothing is written
+        let mut file = AtomicFile::create(&path).await.unwrap();
+        file.file().write_all(b"partial").await.unwrap();
+        drop(file);
+        assert!(!path.exists());
+        assert_eq!(0, std::fs::read_dir(&dir).unwrap().count());
+
+        let mut file = AtomicFile::create(&path).await.unwrap();
+        file.file().write_all(b"complete").await.unwrap();
+        file.commit().await.unwrap();
+        assert_eq!("complete", std::fs::read_to_string(&path).unwrap());
+        assert_eq!(1, std::fs::read_dir(&dir).unwrap().count());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+
+use super::model::{Balances, ClientAccount, CurrencyCode, SECONDS_PER_DAY};
+
+/// End-of-day balances of the accounts, collected while processing sources (see
+/// [`EngineBuilder::collect_balance_history`](super::EngineBuilder::collect_balance_history)).
+///
+/// Only the transactions with a timestamp are tracked, by UTC day: the balances of an account
+/// in a currency are the ones after its last transaction of the day, in processing order. Days
+/// without transactions of an account have no balances, they're the same as the previous ones.
+/// A transaction dated before the last day tracked for an account in its currency is skipped,
+/// as the balances of that day would include the later ones.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BalanceHistory {
+    days: BTreeMap<(u64, u16, CurrencyCode), Balances>,
+    // Last day tracked for each client and currency
+    last_days: BTreeMap<(u16, CurrencyCode), u64>,
+}
+
+impl BalanceHistory {
+    // Records the balances of `account` in `currency` after a transaction at `timestamp`
+    pub(super) fn record(
+        &mut self,
+        timestamp: u64,
+        account: &ClientAccount,
+        currency: Option<&str>,
+    ) {
+        let day = timestamp / SECONDS_PER_DAY;
+        let code = currency.unwrap_or_default().to_string();
+        let last_day = self
+            .last_days
+            .entry((account.client_id, code.clone()))
+            .or_insert(day);
+        if day < *last_day {
+            return;
+        }
+        *last_day = day;
+        self.days
+            .insert((day, account.client_id, code), account.balances(currency));
+    }
+
+    // Adds the balances collected by `other` (e.g. a shard) to this history
+    pub(super) fn merge(&mut self, other: BalanceHistory) {
+        self.days.extend(other.days);
+        for (key, day) in other.last_days {
+            let last_day = self.last_days.entry(key).or_insert(day);
+            *last_day = (*last_day).max(day);
+        }
+    }
+
+    /// Iterates over the balances by day, client and currency, with an empty code for the
+    /// default one. Days are given by the time they start at, in seconds since the Unix epoch.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u16, &str, &Balances)> {
+        self.days.iter().map(|((day, client, currency), balances)| {
+            (day * SECONDS_PER_DAY, *client, currency.as_str(), balances)
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.days.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.days.is_empty()
+    }
+}
@@ -0,0 +1,30 @@
+use std::fmt::Debug;
+
+use super::model::{ClientAccount, Transaction};
+
+/// What to do with a transaction, as decided by [`TransactionHooks::before_apply`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// Apply the transaction as is.
+    Apply,
+    /// Apply this transaction instead, e.g. enriched with more data.
+    Replace(Transaction),
+    /// Skip the transaction, neither applied nor rejected.
+    Skip,
+}
+
+/// Callbacks around every record applied while processing sources, to filter or enrich the
+/// transactions, or record side effects, without changing the processing itself.
+///
+/// Records are applied one at a time with hooks, even if the engine is configured with shards.
+pub trait TransactionHooks: Debug + Send {
+    /// Decides what to do with a transaction before applying it. Transactions are applied as is
+    /// by default.
+    fn before_apply(&mut self, _tx: &Transaction) -> Decision {
+        Decision::Apply
+    }
+
+    /// Called with a transaction applied, and the account of its client right after it.
+    /// Rejected transactions don't get here.
+    fn after_apply(&mut self, _tx: &Transaction, _account: &ClientAccount) {}
+}
@@ -0,0 +1,18 @@
+use std::time::SystemTime;
+
+use super::model::{Balances, Transaction};
+
+/// A transaction applied to an account while processing sources, with the balances of the
+/// account in the currency of the transaction right after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerEntry {
+    pub tx: Transaction,
+    /// When the transaction has been applied
+    pub processed_at: SystemTime,
+    pub balances: Balances,
+    /// Whether the account is locked after the transaction
+    pub locked: bool,
+    /// Whether the transaction has been generated by the engine, e.g. interest, rather than read
+    /// from a source
+    pub synthetic: bool,
+}
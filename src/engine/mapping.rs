@@ -0,0 +1,97 @@
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use toml_edit::DocumentMut;
+
+use super::error::EngineError;
+
+/// Names of the CSV columns holding the fields of the transactions, so that data with other
+/// header names can be read as is. Columns not mapped are read as the field with their name.
+///
+/// It's parsed from a list of `field=column` pairs, e.g. `type=txn_type,client=customer_id`,
+/// or loaded from a TOML file with a `field = "column"` key per field:
+///
+/// ```toml
+/// type = "txn_type"
+/// client = "customer_id"
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ColumnMapping {
+    // Field read from each column mapped
+    fields: HashMap<String, String>,
+}
+
+impl ColumnMapping {
+    /// Reads the `field` of the transactions from the column named `column`.
+    pub fn with(mut self, field: impl Into<String>, column: impl Into<String>) -> Self {
+        self.fields.insert(column.into(), field.into());
+        self
+    }
+
+    /// Returns the field read from the column named `column`.
+    pub fn field<'a>(&'a self, column: &'a str) -> &'a str {
+        self.fields.get(column).map_or(column, String::as_str)
+    }
+
+    /// Reads the mapping from a TOML file.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let data = tokio::fs::read_to_string(path).await?;
+        let doc: DocumentMut = data
+            .parse()
+            .map_err(|e| EngineError::ColumnMappingError(format!("{e}")))?;
+        let mut mapping = ColumnMapping::default();
+        for (field, item) in doc.iter() {
+            let column = item.as_str().ok_or_else(|| {
+                EngineError::ColumnMappingError(format!("Expected a column name for `{field}`"))
+            })?;
+            mapping = mapping.with(field, column);
+        }
+        Ok(mapping)
+    }
+}
+
+impl FromStr for ColumnMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .filter(|pair| !pair.trim().is_empty())
+            .try_fold(ColumnMapping::default(), |mapping, pair| {
+                match pair.split_once('=') {
+                    Some((field, column)) if !field.trim().is_empty() => {
+                        Ok(mapping.with(field.trim(), column.trim()))
+                    }
+                    _ => Err(format!("Expected `<field>=<column>`, not `{pair}`")),
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod mapping_tests {
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::engine::{CsvDialect, CsvSource, TransactionSource, TransactionType};
+
+    #[tokio::test]
+    async fn test_column_mapping() {
+        let mapping: ColumnMapping = "type=txn_type, client=customer_id".parse().unwrap();
+        assert_eq!("type", mapping.field("txn_type"));
+        assert_eq!("tx", mapping.field("tx"));
+        assert!("type".parse::<ColumnMapping>().is_err());
+
+        let data = "txn_type,customer_id,tx,amount\ndeposit,7,1,1.0";
+        let dialect = CsvDialect {
+            mapping,
+            ..Default::default()
+        };
+        let records: Vec<_> = CsvSource::new(data.as_bytes())
+            .dialect(dialect)
+            .into_stream()
+            .collect::<Result<_, _>>()
+            .await
+            .unwrap();
+        assert_eq!(TransactionType::Deposit, records[0].tx_type);
+        assert_eq!(7, records[0].client_id);
+    }
+}
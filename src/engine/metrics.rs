@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use super::{model::TransactionType, reject::RejectReason};
+
+/// Records a transaction applied by the engine, or rejected, in `elapsed` time, and whether the
+/// account was locked before and after it. Metrics are recorded only with the `metrics` feature.
+#[cfg(feature = "metrics")]
+pub(super) fn record_transaction(
+    tx_type: TransactionType,
+    outcome: Result<(), RejectReason>,
+    elapsed: Duration,
+    was_locked: bool,
+    locked: bool,
+) {
+    metrics::counter!("tpe_transactions_total", "type" => tx_type.code()).increment(1);
+    if let Err(reason) = outcome {
+        metrics::counter!("tpe_rejects_total", "reason" => reason.code()).increment(1);
+    }
+    match (was_locked, locked) {
+        (false, true) => metrics::gauge!("tpe_locked_accounts").increment(1.0),
+        (true, false) => metrics::gauge!("tpe_locked_accounts").decrement(1.0),
+        _ => {}
+    }
+    metrics::histogram!("tpe_transaction_seconds").record(elapsed.as_secs_f64());
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(super) fn record_transaction(
+    _tx_type: TransactionType,
+    _outcome: Result<(), RejectReason>,
+    _elapsed: Duration,
+    _was_locked: bool,
+    _locked: bool,
+) {
+}
+
+/// Records a record waiting for room in the full buffer of a processing `stage`, e.g. the
+/// channel feeding a shard task.
+#[cfg(feature = "metrics")]
+pub(super) fn record_stall(stage: &'static str) {
+    metrics::counter!("tpe_backpressure_stalls_total", "stage" => stage).increment(1);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(super) fn record_stall(_stage: &'static str) {}
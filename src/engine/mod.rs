@@ -1,6 +1,124 @@
+// The core of the engine, i.e. the model of the accounts and the logic applying the
+// transactions to them: free of any I/O, so that it can be compiled to WebAssembly as well
+mod amount;
+mod config;
 mod error;
+mod event;
+mod fees;
 mod model;
+mod precision;
+mod reject;
+mod validation;
+
+// Processing of the transaction sources, and storage of the state, behind the `io` feature
+#[cfg(feature = "io")]
+mod audit;
+#[cfg(feature = "io")]
+mod checkpoint;
+#[cfg(feature = "io")]
+mod filter;
+#[cfg(feature = "io")]
+mod finalize;
+#[cfg(feature = "io")]
+mod fs;
+#[cfg(feature = "io")]
+mod history;
+#[cfg(feature = "io")]
+mod hooks;
+#[cfg(feature = "io")]
+mod ledger;
+#[cfg(feature = "io")]
+mod mapping;
+#[cfg(feature = "io")]
+mod metrics;
+#[cfg(feature = "io")]
+mod object;
+#[cfg(feature = "io")]
+mod payment_engine;
+#[cfg(feature = "io")]
 mod processor;
+#[cfg(feature = "io")]
+mod registry;
+#[cfg(feature = "io")]
+mod risk;
+#[cfg(feature = "signing")]
+mod signing;
+#[cfg(feature = "io")]
+mod sink;
+#[cfg(feature = "io")]
+mod source;
+#[cfg(feature = "io")]
+mod spill;
+#[cfg(feature = "io")]
+mod store;
+#[cfg(feature = "io")]
+mod summary;
+#[cfg(feature = "io")]
+mod throttle;
 
+pub use amount::{Amount, Decimal, FixedDecimal, InvalidDecimal};
+pub use config::{EngineConfig, HeldFundsPolicy, NegativeBalancePolicy, RecordOrdering, SortBy};
 pub use error::EngineError;
-pub use processor::process_transactions;
+pub use event::{AccountEvent, FundsMovement};
+pub use fees::{Fee, FeeSchedule};
+pub use model::{
+    Balances, ClientAccount, CurrencyCode, DisputeOrigin, LockInfo, StoredTx, Transaction,
+    TransactionStatus, TransactionType,
+};
+pub use precision::{PrecisionPolicy, Rounding};
+pub use reject::{RejectReason, RejectedTransaction};
+pub use validation::{
+    AccountNotLocked, PositiveAmount, UniqueTransaction, Validator, Validators, WithdrawalLimit,
+};
+
+#[cfg(feature = "io")]
+pub use audit::AuditDigests;
+#[cfg(feature = "io")]
+pub use checkpoint::Checkpoint;
+#[cfg(feature = "io")]
+pub use filter::{ClientFilter, TypeFilter};
+#[cfg(feature = "io")]
+pub use finalize::{AccrueInterest, Finalizer, ReleaseHeldFunds, ResolveStaleDisputes};
+#[cfg(feature = "io")]
+pub(crate) use fs::read_report;
+#[cfg(feature = "io")]
+pub use fs::AtomicFile;
+#[cfg(feature = "io")]
+pub use history::BalanceHistory;
+#[cfg(feature = "io")]
+pub use hooks::{Decision, TransactionHooks};
+#[cfg(feature = "io")]
+pub use ledger::LedgerEntry;
+#[cfg(feature = "io")]
+pub use mapping::ColumnMapping;
+#[cfg(feature = "io")]
+pub use object::is_object_url;
+#[cfg(feature = "io")]
+pub use payment_engine::{EngineBuilder, PaymentEngine};
+#[cfg(feature = "io")]
+pub use processor::{
+    process_transactions, process_transactions_bytes, process_transactions_with_hooks,
+};
+#[cfg(feature = "io")]
+pub use registry::AccountRegistry;
+#[cfg(feature = "io")]
+pub use risk::RiskReport;
+#[cfg(feature = "signing")]
+pub use signing::{verify_report, ReportSigner};
+#[cfg(feature = "webhooks")]
+pub use sink::WebhookSink;
+#[cfg(feature = "io")]
+pub use sink::{forward_events, EventSink, JsonLinesSink};
+#[cfg(feature = "io")]
+pub use source::{
+    input_bytes_read, limit_read_rate, list_input_files, open_files, open_files_chunked, pipelined,
+    Compression, CsvDialect, CsvSource, FileOrder, Follow, InputFormat, JsonLinesSource,
+    MergePolicy, MergedSource, RateLimit, RateLimited, SourceStats, TimeWindow, TransactionSource,
+    TransactionStream,
+};
+#[cfg(feature = "io")]
+pub use store::{file_digest, DuplicateFilePolicy, FileStore, MemoryStore, StateStore};
+#[cfg(feature = "io")]
+pub use summary::Summary;
+#[cfg(feature = "io")]
+pub use throttle::WarnThrottle;
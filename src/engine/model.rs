@@ -1,11 +1,102 @@
-use std::collections::{hash_map::Entry, HashMap};
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
 
-use log::warn;
-use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 
+use super::{
+    amount::Decimal,
+    config::{EngineConfig, NegativeBalancePolicy},
+    event::{AccountEvent, FundsMovement},
+    reject::RejectReason,
+};
+
+// Seconds in a day, to group the withdrawals checked against the cumulative limit and the
+// balances of the history by day
+pub(super) const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// An amount that isn't a decimal number, or can't be represented exactly.
+#[derive(Debug, thiserror::Error)]
+#[error("invalid amount `{0}`")]
+pub(super) struct InvalidAmount(String);
+
+/// Parses an amount from its decimal representation, without going through a float: amounts
+/// with more digits than a [`Decimal`] holds are rejected rather than rounded.
+pub(super) fn parse_amount(amount: &str) -> Result<Decimal, InvalidAmount> {
+    Decimal::from_str_exact(amount.trim()).map_err(|_| InvalidAmount(amount.into()))
+}
+
+// (De)serialization of the amounts of the transactions. Amounts are written as their exact
+// decimal representation; given as strings they are parsed exactly, numbers (e.g. in JSON)
+// from their shortest representation.
+mod amount {
+    use std::fmt;
+
+    use serde::{de, Deserializer, Serializer};
+
+    use crate::Decimal;
+
+    pub fn serialize<S: Serializer>(
+        amount: &Option<Decimal>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match amount {
+            Some(amount) => serializer.collect_str(amount),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Decimal>, D::Error> {
+        deserializer.deserialize_option(AmountVisitor)
+    }
+
+    struct AmountVisitor;
+
+    impl<'de> de::Visitor<'de> for AmountVisitor {
+        type Value = Option<Decimal>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a decimal amount")
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_any(self)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            super::parse_amount(v).map(Some).map_err(E::custom)
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+            self.visit_str(&v.to_string())
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(Some(v.into()))
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(Some(v.into()))
+        }
+    }
+}
+
 /// The different types of transaction to handle
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     /// A deposit is a credit to the client's asset account.
@@ -18,11 +109,135 @@ pub enum TransactionType {
     Resolve,
     /// A chargeback is the final state of a dispute and represents the client reversing a transaction.
     Chargeback,
+    /// A transfer moves funds from the client's account to the one of the counterparty, possible
+    /// only if the client has enough available funds and neither account is locked. It's
+    /// registered in the history of the client, but it can't be disputed or reversed.
+    Transfer,
+    /// Interest credited by the engine on the available funds, at the end of the processing.
+    /// It's not registered in the account history, so it can't be disputed, and it's rejected
+    /// when read from the sources.
+    Interest,
+    /// An unlock is an administrative operation re-enabling a locked account after a manual
+    /// review. It's applied only if admin operations are allowed.
+    Unlock,
+    /// A close is a request of the client to close the account, which then rejects any further
+    /// transaction. It's possible only if no funds are held by disputes.
+    Close,
+    /// A reversal is a correction initiated by the bank, undoing a deposit or withdrawal outright
+    /// without going through a dispute. Disputed transactions can't be reversed.
+    Reversal,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+impl TransactionType {
+    pub const ALL: [TransactionType; 10] = [
+        TransactionType::Deposit,
+        TransactionType::Withdrawal,
+        TransactionType::Dispute,
+        TransactionType::Resolve,
+        TransactionType::Chargeback,
+        TransactionType::Transfer,
+        TransactionType::Interest,
+        TransactionType::Unlock,
+        TransactionType::Close,
+        TransactionType::Reversal,
+    ];
+
+    /// Name of the type in the input records, e.g. `deposit`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Deposit => "deposit",
+            Self::Withdrawal => "withdrawal",
+            Self::Dispute => "dispute",
+            Self::Resolve => "resolve",
+            Self::Chargeback => "chargeback",
+            Self::Transfer => "transfer",
+            Self::Interest => "interest",
+            Self::Unlock => "unlock",
+            Self::Close => "close",
+            Self::Reversal => "reversal",
+        }
+    }
+
+    /// Whether the transaction references another one of the account history.
+    pub fn is_referencing(&self) -> bool {
+        matches!(
+            self,
+            Self::Dispute | Self::Resolve | Self::Chargeback | Self::Reversal
+        )
+    }
+
+    /// Whether the transaction is registered in the account history, i.e. it's a deposit,
+    /// withdrawal or transfer.
+    pub fn is_registered(&self) -> bool {
+        matches!(self, Self::Deposit | Self::Withdrawal | Self::Transfer)
+    }
+
+    /// Whether the transaction moves funds of its own amount, i.e. it's a deposit, withdrawal,
+    /// transfer or interest. These can't be applied to a locked account.
+    pub fn moves_funds(&self) -> bool {
+        matches!(
+            self,
+            Self::Deposit | Self::Withdrawal | Self::Transfer | Self::Interest
+        )
+    }
+}
+
+impl FromStr for TransactionType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|tx_type| tx_type.code() == s)
+            .ok_or_else(|| format!("Unknown transaction type `{s}`"))
+    }
+}
+
+/// Who initiated a dispute, e.g. to tell the chargebacks requested by the clients from the
+/// reversals initiated by their bank.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum DisputeOrigin {
+    /// The client disputed the transaction with the engine operator.
+    Client,
+    /// The merchant disputed the transaction on behalf of the client.
+    Merchant,
+    /// The bank of the client reversed the transaction.
+    Bank,
+}
+
+impl DisputeOrigin {
+    pub const ALL: [DisputeOrigin; 3] = [
+        DisputeOrigin::Client,
+        DisputeOrigin::Merchant,
+        DisputeOrigin::Bank,
+    ];
+
+    /// Name of the origin in the input records, e.g. `bank`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Client => "client",
+            Self::Merchant => "merchant",
+            Self::Bank => "bank",
+        }
+    }
+}
+
+impl FromStr for DisputeOrigin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|origin| origin.code() == s)
+            .ok_or_else(|| format!("Unknown dispute origin `{s}`"))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum TransactionStatus {
     /// A loaded transaction. The transaction hasn't been verified yet.
+    #[default]
     Loaded,
     /// A verified transaction
     Verified,
@@ -32,16 +247,45 @@ pub enum TransactionStatus {
     Resolved,
     /// A chargebacked transaction
     Chargebacked,
+    /// A transaction undone by a reversal
+    Reversed,
 }
 
-impl Default for TransactionStatus {
-    fn default() -> Self {
-        Self::Loaded
+impl TransactionStatus {
+    /// Returns the status a transaction moves to when `action` is applied to it, if the
+    /// transition is legal. The legal transitions are:
+    ///
+    /// ```text
+    /// Loaded --deposit|withdrawal|transfer--> Verified --dispute--> Disputed --resolve--> Resolved
+    ///                                    |                          \--chargeback--> Chargebacked
+    ///                                    \--reversal--> Reversed
+    /// ```
+    ///
+    /// `Resolved`, `Chargebacked` and `Reversed` are final states, except after a partial dispute: the
+    /// transaction is then verified again, to dispute the rest of its amount.
+    pub fn transition(&self, action: TransactionType) -> Result<TransactionStatus, RejectReason> {
+        use TransactionStatus::*;
+        use TransactionType::*;
+
+        match (self, action) {
+            (Loaded, Deposit | Withdrawal | Transfer) => Ok(Verified),
+            (_, Deposit | Withdrawal | Transfer) => Err(RejectReason::DuplicateTransaction),
+            (Verified, Dispute) => Ok(Disputed),
+            (Disputed, Resolve) => Ok(Resolved),
+            (Disputed, Chargeback) => Ok(Chargebacked),
+            (Verified, Reversal) => Ok(Reversed),
+            (Loaded, _) => Err(RejectReason::NotVerified),
+            (Verified, _) => Err(RejectReason::NotDisputed),
+            (Disputed, _) => Err(RejectReason::AlreadyDisputed),
+            (Resolved, _) => Err(RejectReason::AlreadyResolved),
+            (Chargebacked, _) => Err(RejectReason::AlreadyChargebacked),
+            (Reversed, _) => Err(RejectReason::AlreadyReversed),
+        }
     }
 }
 
 /// Represents a single transaction record
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Transaction {
     #[serde(alias = "type")]
     pub tx_type: TransactionType,
@@ -49,13 +293,119 @@ pub struct Transaction {
     pub client_id: u16,
     #[serde(alias = "tx")]
     pub tx_id: u32,
-    #[serde(with = "rust_decimal::serde::float_option")]
+    #[serde(default, with = "amount")]
     pub amount: Option<Decimal>,
+    /// Currency of the amount, the default one if not specified
+    #[serde(default)]
+    pub currency: Option<CurrencyCode>,
+    /// Client credited by a transfer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub counterparty: Option<u16>,
+    /// Key identifying the logical transaction, so that re-sending it (even with another tx id)
+    /// is rejected once applied
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Time of the transaction, in seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
     #[serde(default)]
     pub status: TransactionStatus,
+    /// Who initiated a dispute, if known. Ignored on the other transactions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<DisputeOrigin>,
+    /// Index of the source the transaction has been read from
+    #[serde(skip)]
+    pub source: usize,
+    /// Byte offset of the record in its source
+    #[serde(skip)]
+    pub offset: u64,
+}
+
+impl Transaction {
+    pub fn new(
+        tx_type: TransactionType,
+        client_id: u16,
+        tx_id: u32,
+        amount: Option<Decimal>,
+    ) -> Self {
+        Self {
+            tx_type,
+            client_id,
+            tx_id,
+            amount,
+            currency: None,
+            counterparty: None,
+            idempotency_key: None,
+            timestamp: None,
+            status: TransactionStatus::default(),
+            origin: None,
+            source: 0,
+            offset: 0,
+        }
+    }
+}
+
+/// A deposit or withdrawal registered in an account history. Only the data needed to dispute
+/// it is kept, instead of the whole record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredTx {
+    pub kind: TransactionType,
+    pub amount: Decimal,
+    /// Currency of the amount, the default one if not specified
+    pub currency: Option<CurrencyCode>,
+    pub status: TransactionStatus,
+    /// Index of the source the transaction has been read from
+    pub source: usize,
+    /// Time of the transaction, in seconds since the Unix epoch
+    pub timestamp: Option<u64>,
+    /// Who initiated the last dispute of the transaction, if disputed with a known origin
+    pub dispute_origin: Option<DisputeOrigin>,
+    /// Part of the amount held by the current dispute, if it doesn't dispute all of what is
+    /// left to dispute
+    pub disputed: Option<Decimal>,
+    /// Part of the amount settled by the disputes already resolved or chargebacked
+    pub settled: Decimal,
+}
+
+impl StoredTx {
+    /// Amount that can still be disputed, i.e. not settled by previous partial disputes.
+    pub fn disputable(&self) -> Decimal {
+        self.amount - self.settled
+    }
+
+    /// Amount held by the current dispute, when the transaction is disputed.
+    pub fn held(&self) -> Decimal {
+        self.disputed.unwrap_or_else(|| self.disputable())
+    }
+}
+
+/// Code of a currency, e.g. `USD`
+pub type CurrencyCode = String;
+
+/// The funds of an account in a single currency
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Balances {
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+}
+
+/// The chargeback that locked an account, see [`ClientAccount::lock_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockInfo {
+    /// Tx id of the chargeback
+    pub tx: u32,
+    /// Index of the source the chargeback has been read from
+    pub source: usize,
+    /// Byte offset of the chargeback in its source
+    pub offset: u64,
+    /// Time of the chargeback, in seconds since the Unix epoch
+    pub timestamp: Option<u64>,
 }
 
-#[derive(Debug, Default, Serialize)]
+/// The account of a client. The balances of the transactions without a currency are kept in
+/// the account fields, the ones of the other currencies in [`ClientAccount::currencies`].
+#[derive(Debug, Default, Clone, Serialize)]
 pub struct ClientAccount {
     #[serde(rename(serialize = "client"))]
     pub client_id: u16,
@@ -63,8 +413,29 @@ pub struct ClientAccount {
     pub held: Decimal,
     pub total: Decimal,
     pub locked: bool,
+    /// Whether the account has been closed, rejecting any transaction
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub closed: bool,
+    /// Whether an arithmetic overflow occurred on the account, which then rejects any transaction
+    #[serde(skip)]
+    pub error: bool,
+    #[serde(skip)]
+    pub currencies: BTreeMap<CurrencyCode, Balances>,
+    /// Fees charged on the transactions of the account, by currency (an empty code for the
+    /// default one)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fees_collected: BTreeMap<CurrencyCode, Decimal>,
+    /// Amount withdrawn from the account on the day of the last withdrawal, by currency (an
+    /// empty code for the default one), checked against the cumulative withdrawal limit
     #[serde(skip)]
-    txs: HashMap<u32, Transaction>,
+    pub withdrawn: BTreeMap<CurrencyCode, Decimal>,
+    /// Day of the last withdrawal with a timestamp, in days since the Unix epoch
+    #[serde(skip)]
+    pub withdrawal_day: Option<u64>,
+    #[serde(skip)]
+    pub(super) txs: HashMap<u32, StoredTx>,
+    #[serde(skip)]
+    pub(super) lock: Option<LockInfo>,
 }
 
 impl ClientAccount {
@@ -75,244 +446,575 @@ impl ClientAccount {
         }
     }
 
-    pub fn update(&mut self, data: Transaction) {
+    /// Returns the chargeback that locked the account, if locked. Accounts locked before their
+    /// state was saved by older versions have no lock info.
+    pub fn lock_info(&self) -> Option<&LockInfo> {
+        self.lock.as_ref().filter(|_| self.locked)
+    }
+
+    /// Returns the transaction registered with `tx_id`, if any.
+    pub fn transaction(&self, tx_id: u32) -> Option<&StoredTx> {
+        self.txs.get(&tx_id)
+    }
+
+    /// Iterates over the transactions registered in the account history, with their ids.
+    pub fn transactions(&self) -> impl Iterator<Item = (u32, &StoredTx)> {
+        self.txs.iter().map(|(&tx_id, tx)| (tx_id, tx))
+    }
+
+    /// Returns the balances in `currency`, or in the default one if `None`.
+    pub fn balances(&self, currency: Option<&str>) -> Balances {
+        match currency {
+            Some(currency) => self.currencies.get(currency).copied().unwrap_or_default(),
+            None => Balances {
+                available: self.available,
+                held: self.held,
+                total: self.total,
+            },
+        }
+    }
+
+    /// Returns the amount withdrawn from the account in `currency` (or in the default one if
+    /// `None`) on the day of `timestamp`. Withdrawals without timestamps are on the same day as
+    /// the last one.
+    pub fn withdrawn(&self, currency: Option<&str>, timestamp: Option<u64>) -> Decimal {
+        if timestamp
+            .is_some_and(|timestamp| Some(timestamp / SECONDS_PER_DAY) != self.withdrawal_day)
+        {
+            return Decimal::ZERO;
+        }
+        self.withdrawn
+            .get(currency.unwrap_or_default())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Whether the account has any funds or transactions in the default currency.
+    pub fn uses_default_currency(&self) -> bool {
+        self.currencies.is_empty()
+            || self.balances(None) != Balances::default()
+            || self.txs.values().any(|tx| tx.currency.is_none())
+    }
+
+    // Adds the deltas to the `available`, `held` and `total` funds in `currency`. If any of
+    // them overflows, no funds are changed and the account is marked as in error.
+    fn move_funds(
+        &mut self,
+        currency: Option<&CurrencyCode>,
+        available: Decimal,
+        held: Decimal,
+        total: Decimal,
+    ) -> Result<(), RejectReason> {
+        let balances = self.moved_funds(currency, available, held, total)?;
+        self.set_balances(currency, balances);
+        Ok(())
+    }
+
+    // Returns the funds in `currency` with the deltas added, without changing them. If any of
+    // them overflows, the account is marked as in error.
+    fn moved_funds(
+        &mut self,
+        currency: Option<&CurrencyCode>,
+        available: Decimal,
+        held: Decimal,
+        total: Decimal,
+    ) -> Result<Balances, RejectReason> {
+        let current = self.balances(currency.map(String::as_str));
+        let updated = current
+            .available
+            .checked_add(available)
+            .zip(current.held.checked_add(held))
+            .zip(current.total.checked_add(total));
+        let Some(((available, held), total)) = updated else {
+            self.error = true;
+            return Err(RejectReason::ArithmeticOverflow);
+        };
+        Ok(Balances {
+            available,
+            held,
+            total,
+        })
+    }
+
+    fn set_balances(&mut self, currency: Option<&CurrencyCode>, balances: Balances) {
+        match currency {
+            Some(currency) => {
+                self.currencies.insert(currency.clone(), balances);
+            }
+            None => {
+                self.available = balances.available;
+                self.held = balances.held;
+                self.total = balances.total;
+            }
+        }
+    }
+
+    // Returns the transaction referenced by a dispute, resolve or chargeback, checking that
+    // the currencies match if the referencing one has a currency too
+    fn referenced(&self, data: &Transaction) -> Result<&StoredTx, RejectReason> {
+        let tx = self
+            .txs
+            .get(&data.tx_id)
+            .ok_or(RejectReason::TransactionNotFound)?;
+        // Settling a transfer on the account of one client only would unbalance the other one
+        if tx.kind == TransactionType::Transfer {
+            return Err(RejectReason::TransferNotDisputable);
+        }
+        if data.currency.is_some() && data.currency != tx.currency {
+            return Err(RejectReason::CurrencyMismatch);
+        }
+        Ok(tx)
+    }
+
+    /// Applies a transaction to the account, returning the events describing how the account
+    /// changed, or the reason why the transaction is rejected.
+    pub fn update(
+        &mut self,
+        mut data: Transaction,
+        config: &EngineConfig,
+    ) -> Result<Vec<AccountEvent>, RejectReason> {
+        if self.error {
+            return Err(RejectReason::AccountInError);
+        }
+        if self.closed {
+            return Err(RejectReason::AccountClosed);
+        }
+
+        data.amount = data
+            .amount
+            .map(|amount| config.precision.check(amount))
+            .transpose()?;
+        if data.tx_type != TransactionType::Transfer {
+            config.validators.validate(&data, self)?;
+        }
         match data.tx_type {
-            TransactionType::Deposit => self.deposit(data),
-            TransactionType::Withdrawal => self.withdrawal(data),
-            TransactionType::Dispute => self.dispute(&data),
+            TransactionType::Deposit => self.deposit(data, config),
+            TransactionType::Withdrawal => self.withdrawal(data, config),
+            TransactionType::Dispute => self.dispute(&data, config),
             TransactionType::Resolve => self.resolve(&data),
             TransactionType::Chargeback => self.chargeback(&data),
+            // A transfer involves the account of the counterparty too, see `transfer`
+            TransactionType::Transfer => Err(RejectReason::InvalidCounterparty),
+            TransactionType::Interest => self.interest(data),
+            TransactionType::Unlock => self.unlock(&data, config),
+            TransactionType::Close => self.close(&data),
+            TransactionType::Reversal => self.reversal(&data, config),
         }
     }
 
-    fn deposit(&mut self, mut data: Transaction) {
-        // Check that account is not locked
-        if !self.locked {
-            // Check that the transaction is not already registered
-            if let Entry::Vacant(_) = self.txs.entry(data.tx_id) {
-                // A Deposit should always have a valid `amount` specified, otherwise we have an invalid record
-                if let Some(amount) = data.amount {
-                    if amount > Decimal::ZERO {
-                        // For a Deposit we only need to increase `total` and `available` fields
-                        self.total += amount;
-                        self.available += amount;
-                        data.status = TransactionStatus::Verified;
-                        self.txs.insert(data.tx_id, data); //register tx
-                    } else {
-                        warn!(
-                            "Unable to process tx: amount not valid - account: #{:?}, amount: {:?}",
-                            self.client_id, amount
-                        );
-                    }
-                } else {
-                    warn!("Transaction with id {:?} doesn't have an `amount` specified, skipping update for account #{:?}", data.tx_id, self.client_id)
-                    // In this case we don't register the transaction, to optimize the logic.
-                    // Transactions have unique global identifiers, and we can think to a system that instaed of
-                    // generating always new txs IDs, can reuse the ones that are related to invalid records.
-                    // Also, txs with invalid data can be stored for logging/debugging reasons.
-                }
-            } else {
-                warn!(
-                    "Account #{:?} already has a transaction with id {:?} registered, skipping",
-                    self.client_id, data.tx_id
-                );
-            }
-        } else {
-            warn!(
-                "Account #{:?} is locked, skipping update for transaction {:?}",
-                self.client_id, data.tx_id
-            );
+    fn deposit(
+        &mut self,
+        data: Transaction,
+        config: &EngineConfig,
+    ) -> Result<Vec<AccountEvent>, RejectReason> {
+        // A Deposit should always have a valid `amount` specified (checked by the validators),
+        // otherwise we have an invalid record. In this case we don't register the transaction,
+        // to optimize the logic.
+        // Transactions have unique global identifiers, and we can think to a system that instaed of
+        // generating always new txs IDs, can reuse the ones that are related to invalid records.
+        // Also, txs with invalid data can be stored for logging/debugging reasons.
+        let amount = data.amount.ok_or(RejectReason::MissingAmount)?;
+
+        // The fee is deducted from the amount credited
+        let fee = self.fee(&data, amount, config)?;
+        if fee >= amount {
+            return Err(RejectReason::AmountBelowFee);
         }
+        let amount = amount - fee;
+
+        // For a Deposit we only need to increase `total` and `available` fields
+        let status = data.status.transition(data.tx_type)?;
+        self.move_funds(data.currency.as_ref(), amount, Decimal::ZERO, amount)?;
+        self.collect_fee(data.currency.as_ref(), fee);
+        let events = self.fee_events(&data, AccountEvent::FundsDeposited, amount, fee);
+        self.register(data, amount, status);
+        Ok(events)
     }
 
-    fn withdrawal(&mut self, mut data: Transaction) {
-        // Check that account is not locked
-        if !self.locked {
-            // Check that the transaction is not already registered
-            if let Entry::Vacant(_) = self.txs.entry(data.tx_id) {
-                // A Withdrawal should always have a valid `amount` specified, otherwise we have an invalid record
-                if let Some(amount) = data.amount {
-                    if amount > Decimal::ZERO {
-                        // For a Withdrawal we need to check that `available` >= `amount`
-                        if self.available >= amount {
-                            self.total -= amount;
-                            self.available -= amount;
-                            data.status = TransactionStatus::Verified;
-                            self.txs.insert(data.tx_id, data); // register tx
-                        } else {
-                            warn!("Unable to process withdrawal tx: not enough funds - account: #{:?}, available: {:?}, amount: {:?}", self.client_id, self.available, amount);
-                        }
-                    } else {
-                        warn!(
-                            "Unable to process tx: amount not valid - account: #{:?}, amount: {:?}",
-                            self.client_id, amount
-                        );
-                    }
-                } else {
-                    warn!("Transaction with id {:?} doesn't have an `amount` specified, skipping update for account #{:?}", data.tx_id, self.client_id)
-                    // In this case we don't register the transaction, to optimize the logic.
-                    // Transactions have unique global identifiers, and we can think to a system that instaed of
-                    // generating always new txs IDs, can reuse the ones that are related to invalid records.
-                    // Also, txs with invalid data can be stored for logging/debugging reasons.
-                }
-            } else {
-                warn!(
-                    "Account #{:?} already has a transaction with id {:?} registered, skipping",
-                    self.client_id, data.tx_id
-                );
+    fn withdrawal(
+        &mut self,
+        data: Transaction,
+        config: &EngineConfig,
+    ) -> Result<Vec<AccountEvent>, RejectReason> {
+        // A Withdrawal should always have a valid `amount` specified (checked by the validators),
+        // otherwise we have an invalid record
+        let amount = data.amount.ok_or(RejectReason::MissingAmount)?;
+
+        // The fee is debited on top of the amount
+        let fee = self.fee(&data, amount, config)?;
+        let debit = amount
+            .checked_add(fee)
+            .ok_or(RejectReason::ArithmeticOverflow)?;
+
+        // For a Withdrawal we need to check that `available` >= `amount`
+        self.check_available(data.currency.as_deref(), debit, false, config)?;
+
+        let status = data.status.transition(data.tx_type)?;
+        self.move_funds(data.currency.as_ref(), -debit, Decimal::ZERO, -debit)?;
+        self.collect_fee(data.currency.as_ref(), fee);
+        let day = data.timestamp.map(|timestamp| timestamp / SECONDS_PER_DAY);
+        if day.is_some() && day != self.withdrawal_day {
+            self.withdrawn.clear();
+            self.withdrawal_day = day;
+        }
+        let withdrawn = self
+            .withdrawn
+            .entry(data.currency.clone().unwrap_or_default())
+            .or_default();
+        *withdrawn = withdrawn.saturating_add(amount);
+        let events = self.fee_events(&data, AccountEvent::FundsWithdrawn, amount, fee);
+        self.register(data, amount, status);
+        Ok(events)
+    }
+
+    fn dispute(
+        &mut self,
+        data: &Transaction,
+        config: &EngineConfig,
+    ) -> Result<Vec<AccountEvent>, RejectReason> {
+        // Check that the transaction exists
+        let tx = self.referenced(data)?;
+
+        // Check that the transaction can be disputed at all
+        if tx.kind == TransactionType::Withdrawal && !config.allow_withdrawal_disputes {
+            return Err(RejectReason::WithdrawalNotDisputable);
+        }
+        if let (Some(window), Some(registered), Some(disputed)) =
+            (config.dispute_window, tx.timestamp, data.timestamp)
+        {
+            if disputed.saturating_sub(registered) > window.as_secs() {
+                return Err(RejectReason::DisputeWindowExpired);
             }
+        }
+
+        // We can dispute only verified transactions, so transactions that have already changed accounts' funds
+        let status = tx.status.transition(data.tx_type)?;
+        // A dispute with an amount holds only that part of the transaction
+        let disputable = tx.disputable();
+        let amount = match data.amount {
+            None => disputable,
+            Some(amount) if amount <= Decimal::ZERO => return Err(RejectReason::InvalidAmount),
+            Some(amount) if amount > disputable => return Err(RejectReason::DisputeExceedsAmount),
+            Some(amount) => amount,
+        };
+        let currency = tx.currency.clone();
+        if tx.kind == TransactionType::Withdrawal {
+            // For a Withdrawal the funds have already left the account: the amount is held
+            // as money potentially owed back to the client
+            self.move_funds(currency.as_ref(), Decimal::ZERO, amount, amount)?;
         } else {
-            warn!(
-                "Account #{:?} is locked, skipping update for transaction {:?}",
-                self.client_id, data.tx_id
-            );
+            // Check that available amount is enough
+            self.check_available(currency.as_deref(), amount, true, config)?;
+            self.move_funds(currency.as_ref(), -amount, amount, Decimal::ZERO)?;
         }
+        self.set_status(data.tx_id, status);
+        if let Some(tx) = self.txs.get_mut(&data.tx_id) {
+            tx.dispute_origin = data.origin;
+            tx.disputed = Some(amount).filter(|&amount| amount < disputable);
+        }
+        Ok(vec![AccountEvent::FundsHeld(
+            self.funds(data.tx_id, amount, currency),
+        )])
     }
 
-    fn dispute(&mut self, data: &Transaction) {
+    fn resolve(&mut self, data: &Transaction) -> Result<Vec<AccountEvent>, RejectReason> {
         // Check that the transaction exists
-        if let Some(tx) = self.txs.get_mut(&data.tx_id) {
-            // Check the status
-            println!("TO DISPUTE: {tx:?}");
-            match tx.status {
-                // We can dispute only verified transactions, so transactions that have already changed accounts' funds
-                TransactionStatus::Verified => {
-                    if let Some(amount) = tx.amount {
-                        // Check that available amount is enough
-                        if self.available >= amount {
-                            self.available -= amount;
-                            self.held += amount;
-                            tx.status = TransactionStatus::Disputed;
-                        } else {
-                            warn!("Dispute for transaction with id {:?} can't be processed: not enough funds - available: {:?}, amount: {:?}", tx.tx_id, self.available, amount);
-                        }
-                    } else {
-                        warn!("Dispute for transaction with id {:?} can't be processed: amount not valid", tx.tx_id);
-                    }
-                }
-                TransactionStatus::Loaded => warn!(
-                    "Unable to process dispute tx: tx with id {:?} has not been verified",
-                    data.tx_id
-                ),
-                TransactionStatus::Disputed => warn!(
-                    "Unable to process dispute tx: tx with id {:?} is already under dispute",
-                    data.tx_id
-                ),
-                TransactionStatus::Resolved => warn!(
-                    "Unable to process dispute tx: tx with id {:?} has been already resolved",
-                    data.tx_id
-                ),
-                TransactionStatus::Chargebacked => warn!(
-                    "Unable to process dispute tx: tx with id {:?} has been already chargebacked",
-                    data.tx_id
-                ),
-            }
+        let tx = self.referenced(data)?;
+
+        // We can resolve only disputed transactions
+        let status = tx.status.transition(data.tx_type)?;
+        let (tx_type, amount, currency) = (tx.kind, tx.held(), tx.currency.clone());
+        // Check that held amount is enough
+        if self.balances(currency.as_deref()).held < amount {
+            return Err(RejectReason::InsufficientFunds);
+        }
+        if tx_type == TransactionType::Withdrawal {
+            // The Withdrawal is confirmed, so nothing is owed back to the client
+            self.move_funds(currency.as_ref(), Decimal::ZERO, -amount, -amount)?;
         } else {
-            warn!(
-                "Unable to process dispute tx: tx with id {:?} not found",
-                data.tx_id
-            );
+            self.move_funds(currency.as_ref(), amount, -amount, Decimal::ZERO)?;
         }
+        self.settle(data.tx_id, status, amount);
+        Ok(vec![AccountEvent::FundsReleased(
+            self.funds(data.tx_id, amount, currency),
+        )])
     }
 
-    fn resolve(&mut self, data: &Transaction) {
+    fn reversal(
+        &mut self,
+        data: &Transaction,
+        config: &EngineConfig,
+    ) -> Result<Vec<AccountEvent>, RejectReason> {
         // Check that the transaction exists
-        if let Some(tx) = self.txs.get_mut(&data.tx_id) {
-            // Check the status
-            match tx.status {
-                // We can resolve only disputed transactions
-                TransactionStatus::Disputed => {
-                    if let Some(amount) = tx.amount {
-                        // Check that held amount is enough
-                        if self.held >= amount {
-                            self.available += amount;
-                            self.held -= amount;
-                            tx.status = TransactionStatus::Resolved;
-                        } else {
-                            warn!("Resolve for transaction with id {:?} can't be processed: not enough funds - held: {:?}, amount: {:?}", tx.tx_id, self.held, amount);
-                        }
-                    } else {
-                        warn!("Resolve for transaction with id {:?} can't be processed: amount not valid", tx.tx_id);
-                    }
-                }
-                TransactionStatus::Loaded => warn!(
-                    "Unable to process resolve tx: tx with id {:?} has not been verified",
-                    data.tx_id
-                ),
-                TransactionStatus::Verified => warn!(
-                    "Unable to process resolve tx: tx with id {:?} is not under dispute",
-                    data.tx_id
-                ),
-                TransactionStatus::Resolved => warn!(
-                    "Unable to process resolve tx: tx with id {:?} has been already resolved",
-                    data.tx_id
-                ),
-                TransactionStatus::Chargebacked => warn!(
-                    "Unable to process resolve tx: tx with id {:?} has been already chargebacked",
-                    data.tx_id
-                ),
-            }
+        let tx = self.referenced(data)?;
+
+        // We can reverse only verified transactions, not the ones under dispute
+        let status = tx.status.transition(data.tx_type)?;
+        // The part settled by previous partial disputes is not reversed again
+        let (tx_type, amount, currency) = (tx.kind, tx.disputable(), tx.currency.clone());
+        if tx_type == TransactionType::Withdrawal {
+            // The withdrawn funds are credited back, while the fee charged on it is kept
+            self.move_funds(currency.as_ref(), amount, Decimal::ZERO, amount)?;
         } else {
-            warn!(
-                "Unable to process dispute tx: tx with id {:?} not found",
-                data.tx_id
-            );
+            // As for a dispute, funds already spent are taken back only if the negative
+            // balance policy allows it
+            self.check_available(currency.as_deref(), amount, true, config)?;
+            self.move_funds(currency.as_ref(), -amount, Decimal::ZERO, -amount)?;
         }
+        self.set_status(data.tx_id, status);
+        Ok(vec![AccountEvent::TxReversed(
+            self.funds(data.tx_id, amount, currency),
+        )])
     }
 
-    fn chargeback(&mut self, data: &Transaction) {
+    fn chargeback(&mut self, data: &Transaction) -> Result<Vec<AccountEvent>, RejectReason> {
         // Check that the transaction exists
-        if let Some(tx) = self.txs.get_mut(&data.tx_id) {
-            // Check the status
-            match tx.status {
-                // We can chargeback only disputed transactions
-                TransactionStatus::Disputed => {
-                    if let Some(amount) = tx.amount {
-                        
-                        if self.held >= amount {
-                            self.held -= amount;
-                            self.total -= amount;
-                            self.locked = true;
-                            tx.status = TransactionStatus::Chargebacked;
-                        } else {
-                            warn!("Chargeback for transaction with id {:?} can't be processed: not enough funds - held: {:?}, amount: {:?}", tx.tx_id, self.held, amount);
-                        }
-                    } else {
-                        warn!("Chargeback for transaction with id {:?} can't be processed: amount not valid", tx.tx_id);
-                    }
-                }
-                TransactionStatus::Loaded => warn!(
-                    "Unable to process chargeback tx: tx with id {:?} has not been verified",
-                    data.tx_id
-                ),
-                TransactionStatus::Verified => warn!(
-                    "Unable to process chargeback tx: tx with id {:?} has not been disputed",
-                    data.tx_id
-                ),
-                TransactionStatus::Resolved => warn!(
-                    "Unable to process chargeback tx: tx with id {:?} has been already resolved",
-                    data.tx_id
-                ),
-                TransactionStatus::Chargebacked => warn!(
-                    "Unable to process chargeback tx: tx with id {:?} has been already chargebacked",
-                    data.tx_id
-                ),
-            }
+        let tx = self.referenced(data)?;
+
+        // We can chargeback only disputed transactions
+        let status = tx.status.transition(data.tx_type)?;
+        let (tx_type, amount, currency) = (tx.kind, tx.held(), tx.currency.clone());
+        if self.balances(currency.as_deref()).held < amount {
+            return Err(RejectReason::InsufficientFunds);
+        }
+        if tx_type == TransactionType::Withdrawal {
+            // The Withdrawal is reversed, re-crediting the held amount to the client
+            self.move_funds(currency.as_ref(), amount, -amount, Decimal::ZERO)?;
         } else {
-            warn!(
-                "Unable to process dispute tx: tx with id {:?} not found",
-                data.tx_id
-            );
+            self.move_funds(currency.as_ref(), Decimal::ZERO, -amount, -amount)?;
+        }
+        let was_locked = std::mem::replace(&mut self.locked, true);
+        self.settle(data.tx_id, status, amount);
+        let mut events = vec![AccountEvent::FundsReversed(
+            self.funds(data.tx_id, amount, currency),
+        )];
+        if !was_locked {
+            self.lock = Some(LockInfo {
+                tx: data.tx_id,
+                source: data.source,
+                offset: data.offset,
+                timestamp: data.timestamp,
+            });
+            events.push(AccountEvent::AccountLocked {
+                client: self.client_id,
+                tx: data.tx_id,
+            });
+        }
+        Ok(events)
+    }
+
+    /// Moves the amount of a transfer from this account to the one of the counterparty, `to`.
+    /// Either both accounts are updated, or none.
+    pub fn transfer(
+        &mut self,
+        to: &mut ClientAccount,
+        mut data: Transaction,
+        config: &EngineConfig,
+    ) -> Result<Vec<AccountEvent>, RejectReason> {
+        if data.counterparty != Some(to.client_id) || to.client_id == self.client_id {
+            return Err(RejectReason::InvalidCounterparty);
+        }
+        if self.error || to.error {
+            return Err(RejectReason::AccountInError);
+        }
+        if self.closed || to.closed {
+            return Err(RejectReason::AccountClosed);
+        }
+        data.amount = data
+            .amount
+            .map(|amount| config.precision.check(amount))
+            .transpose()?;
+        config.validators.validate(&data, self)?;
+        // The validators check the account of the client only
+        if to.locked {
+            return Err(RejectReason::AccountLocked);
+        }
+        let amount = data.amount.ok_or(RejectReason::MissingAmount)?;
+        self.check_available(data.currency.as_deref(), amount, false, config)?;
+
+        let status = data.status.transition(data.tx_type)?;
+
+        // Both legs are checked before changing either account: the debit can overflow too,
+        // when negative balances are allowed
+        let currency = data.currency.as_ref();
+        let credit = to.moved_funds(currency, amount, Decimal::ZERO, amount)?;
+        let debit = self.moved_funds(currency, -amount, Decimal::ZERO, -amount)?;
+        to.set_balances(currency, credit);
+        self.set_balances(currency, debit);
+        let events = vec![AccountEvent::FundsTransferred {
+            funds: self.funds(data.tx_id, amount, data.currency.clone()),
+            counterparty: to.client_id,
+        }];
+        self.register(data, amount, status);
+        Ok(events)
+    }
+
+    fn interest(&mut self, data: Transaction) -> Result<Vec<AccountEvent>, RejectReason> {
+        let amount = data.amount.ok_or(RejectReason::MissingAmount)?;
+        self.move_funds(data.currency.as_ref(), amount, Decimal::ZERO, amount)?;
+        Ok(vec![AccountEvent::InterestCredited(self.funds(
+            data.tx_id,
+            amount,
+            data.currency,
+        ))])
+    }
+
+    fn unlock(
+        &mut self,
+        data: &Transaction,
+        config: &EngineConfig,
+    ) -> Result<Vec<AccountEvent>, RejectReason> {
+        if !config.allow_admin_ops {
+            return Err(RejectReason::AdminOpNotAllowed);
+        }
+
+        // Only a locked account can be unlocked
+        if !self.locked {
+            return Err(RejectReason::NotLocked);
+        }
+        self.locked = false;
+        self.lock = None;
+        Ok(vec![AccountEvent::AccountUnlocked {
+            client: self.client_id,
+            tx: data.tx_id,
+        }])
+    }
+
+    fn close(&mut self, data: &Transaction) -> Result<Vec<AccountEvent>, RejectReason> {
+        // Funds held by disputes couldn't be released anymore
+        let currencies = self.currencies.values();
+        if std::iter::once(&self.balances(None))
+            .chain(currencies)
+            .any(|balances| !balances.held.is_zero())
+        {
+            return Err(RejectReason::FundsHeld);
+        }
+        self.closed = true;
+        Ok(vec![AccountEvent::AccountClosed {
+            client: self.client_id,
+            tx: data.tx_id,
+        }])
+    }
+
+    // Checks that `amount` can be taken from the available funds in `currency`, according to
+    // the negative balance policy, given whether it's held by a dispute
+    fn check_available(
+        &self,
+        currency: Option<&str>,
+        amount: Decimal,
+        dispute: bool,
+        config: &EngineConfig,
+    ) -> Result<(), RejectReason> {
+        let allow_negative = match config.negative_balance {
+            NegativeBalancePolicy::Forbid => false,
+            NegativeBalancePolicy::AllowOnChargeback => dispute,
+            NegativeBalancePolicy::AllowAlways => true,
+        };
+        if !allow_negative && self.balances(currency).available < amount {
+            return Err(RejectReason::InsufficientFunds);
+        }
+        Ok(())
+    }
+
+    // Returns the fee charged on a deposit or withdrawal of `amount`
+    fn fee(
+        &self,
+        data: &Transaction,
+        amount: Decimal,
+        config: &EngineConfig,
+    ) -> Result<Decimal, RejectReason> {
+        match config.fees.fee(data.tx_type) {
+            Some(fee) => Ok(config.precision.apply(fee.on(amount)?)),
+            None => Ok(Decimal::ZERO),
+        }
+    }
+
+    fn collect_fee(&mut self, currency: Option<&CurrencyCode>, fee: Decimal) {
+        if !fee.is_zero() {
+            *self
+                .fees_collected
+                .entry(currency.cloned().unwrap_or_default())
+                .or_default() += fee;
+        }
+    }
+
+    fn funds(&self, tx_id: u32, amount: Decimal, currency: Option<CurrencyCode>) -> FundsMovement {
+        FundsMovement {
+            client: self.client_id,
+            tx: tx_id,
+            amount,
+            currency,
+        }
+    }
+
+    // Returns the events of a deposit or withdrawal of `amount`, followed by the fee charged
+    // on it if any
+    fn fee_events(
+        &self,
+        data: &Transaction,
+        event: fn(FundsMovement) -> AccountEvent,
+        amount: Decimal,
+        fee: Decimal,
+    ) -> Vec<AccountEvent> {
+        let mut events = vec![event(self.funds(data.tx_id, amount, data.currency.clone()))];
+        if !fee.is_zero() {
+            events.push(AccountEvent::FeeCharged(self.funds(
+                data.tx_id,
+                fee,
+                data.currency.clone(),
+            )));
+        }
+        events
+    }
+
+    // Registers a deposit or withdrawal in the account history
+    fn register(&mut self, data: Transaction, amount: Decimal, status: TransactionStatus) {
+        let tx = StoredTx {
+            kind: data.tx_type,
+            amount,
+            currency: data.currency,
+            status,
+            source: data.source,
+            timestamp: data.timestamp,
+            dispute_origin: None,
+            disputed: None,
+            settled: Decimal::ZERO,
+        };
+        self.txs.insert(data.tx_id, tx);
+    }
+
+    // Settles the `amount` held by the dispute of a transaction, which stays disputable for the
+    // rest of its amount after a partial dispute
+    fn settle(&mut self, tx_id: u32, status: TransactionStatus, amount: Decimal) {
+        if let Some(tx) = self.txs.get_mut(&tx_id) {
+            tx.settled += amount;
+            tx.disputed = None;
+            tx.status = if tx.settled < tx.amount {
+                TransactionStatus::Verified
+            } else {
+                status
+            };
+        }
+    }
+
+    fn set_status(&mut self, tx_id: u32, status: TransactionStatus) {
+        if let Some(tx) = self.txs.get_mut(&tx_id) {
+            tx.status = status;
         }
     }
 }
 
 #[cfg(test)]
 mod model_tests {
+    use std::time::Duration;
+
+    #[cfg(feature = "io")]
     use tokio::io;
+    #[cfg(feature = "io")]
     use tokio_stream::StreamExt;
 
     use super::*;
+    use crate::engine::fees::{Fee, FeeSchedule};
 
     #[test]
     fn test_precision() {
@@ -320,6 +1022,636 @@ mod model_tests {
         assert_eq!(numb.trunc_with_scale(4), Decimal::new(11234, 4));
     }
 
+    #[test]
+    fn test_status_transitions() {
+        use TransactionStatus::*;
+        use TransactionType::*;
+
+        let statuses = [Loaded, Verified, Disputed, Resolved, Chargebacked, Reversed];
+        let actions = [Deposit, Withdrawal, Dispute, Resolve, Chargeback, Reversal];
+        let legal = [
+            (Loaded, Deposit, Verified),
+            (Loaded, Withdrawal, Verified),
+            (Verified, Dispute, Disputed),
+            (Disputed, Resolve, Resolved),
+            (Disputed, Chargeback, Chargebacked),
+            (Verified, Reversal, Reversed),
+        ];
+
+        for status in &statuses {
+            for action in actions {
+                let expected = legal
+                    .iter()
+                    .find(|(from, on, _)| from == status && *on == action);
+                match (status.transition(action), expected) {
+                    (Ok(next), Some((_, _, to))) => assert_eq!(*to, next),
+                    (Err(_), None) => {}
+                    (result, _) => panic!("{status:?} --{action:?}--> {result:?} is not expected"),
+                }
+            }
+        }
+        assert_eq!(
+            Err(RejectReason::AlreadyResolved),
+            Resolved.transition(Dispute)
+        );
+        assert_eq!(
+            Err(RejectReason::NotDisputed),
+            Verified.transition(Chargeback)
+        );
+        assert_eq!(
+            Err(RejectReason::AlreadyReversed),
+            Reversed.transition(Dispute)
+        );
+    }
+
+    #[test]
+    fn test_status_persisted() {
+        use TransactionType::*;
+
+        // Applies the actions on tx 1, a deposit of 10, returning the outcome of the last one
+        let apply = |actions: &[TransactionType]| {
+            let config = EngineConfig::default();
+            let mut account = ClientAccount::new(1);
+            let deposit = Transaction::new(Deposit, 1, 1, Some(Decimal::TEN));
+            account.update(deposit, &config).unwrap();
+            let (last, actions) = actions.split_last().unwrap();
+            for action in actions {
+                account
+                    .update(Transaction::new(*action, 1, 1, None), &config)
+                    .unwrap();
+            }
+            let outcome = account.update(Transaction::new(*last, 1, 1, None), &config);
+            (account, outcome.map(|_| ()))
+        };
+
+        let (account, outcome) = apply(&[Dispute, Dispute]);
+        assert_eq!(Err(RejectReason::AlreadyDisputed), outcome);
+        assert_eq!(Decimal::TEN, account.held);
+        assert_eq!(Decimal::ZERO, account.available);
+
+        let (account, outcome) = apply(&[Dispute, Resolve, Dispute]);
+        assert_eq!(Err(RejectReason::AlreadyResolved), outcome);
+        assert_eq!(Decimal::ZERO, account.held);
+        assert_eq!(Decimal::TEN, account.available);
+        assert_eq!(
+            TransactionStatus::Resolved,
+            account.transaction(1).unwrap().status
+        );
+
+        let (account, outcome) = apply(&[Dispute, Chargeback, Resolve]);
+        assert_eq!(Err(RejectReason::AlreadyChargebacked), outcome);
+        assert_eq!(Decimal::ZERO, account.total);
+        assert!(account.locked);
+    }
+
+    fn disputed_withdrawal_account() -> (ClientAccount, EngineConfig) {
+        let config = EngineConfig {
+            allow_withdrawal_disputes: true,
+            ..Default::default()
+        };
+        let mut account = ClientAccount::new(1);
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 1, 2, Some(Decimal::ONE));
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 2, None);
+        account.update(deposit, &config).unwrap();
+        account.update(withdrawal, &config).unwrap();
+        account.update(dispute, &config).unwrap();
+        (account, config)
+    }
+
+    #[test]
+    fn test_withdrawal_dispute_not_allowed() {
+        let config = EngineConfig::default();
+        let mut account = ClientAccount::new(1);
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 1, 2, Some(Decimal::ONE));
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 2, None);
+        account.update(deposit, &config).unwrap();
+        account.update(withdrawal, &config).unwrap();
+        assert_eq!(
+            Err(RejectReason::WithdrawalNotDisputable),
+            account.update(dispute, &config)
+        );
+    }
+
+    #[test]
+    fn test_withdrawal_dispute_holds_owed_amount() {
+        let (account, _) = disputed_withdrawal_account();
+        assert_eq!(Decimal::new(9, 0), account.available);
+        assert_eq!(Decimal::ONE, account.held);
+        assert_eq!(Decimal::TEN, account.total);
+    }
+
+    #[test]
+    fn test_withdrawal_dispute_resolve() {
+        let (mut account, config) = disputed_withdrawal_account();
+        let resolve = Transaction::new(TransactionType::Resolve, 1, 2, None);
+        account.update(resolve, &config).unwrap();
+        assert_eq!(Decimal::new(9, 0), account.available);
+        assert_eq!(Decimal::ZERO, account.held);
+        assert_eq!(Decimal::new(9, 0), account.total);
+        assert!(!account.locked);
+    }
+
+    #[test]
+    fn test_withdrawal_dispute_chargeback() {
+        let (mut account, config) = disputed_withdrawal_account();
+        let chargeback = Transaction::new(TransactionType::Chargeback, 1, 2, None);
+        account.update(chargeback, &config).unwrap();
+        assert_eq!(Decimal::TEN, account.available);
+        assert_eq!(Decimal::ZERO, account.held);
+        assert_eq!(Decimal::TEN, account.total);
+        assert!(account.locked);
+    }
+
+    #[test]
+    fn test_amounts_rounded_on_ingestion() {
+        let config = EngineConfig::default();
+        let mut account = ClientAccount::new(1);
+        let deposit = Transaction::new(
+            TransactionType::Deposit,
+            1,
+            1,
+            Some(Decimal::new(1123499, 6)),
+        );
+        account.update(deposit, &config).unwrap();
+        assert_eq!(Decimal::new(11234, 4), account.total);
+        assert_eq!(
+            Decimal::new(11234, 4),
+            account.transaction(1).unwrap().amount
+        );
+
+        // Amounts rounded to zero are not valid
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::new(1, 5)));
+        assert_eq!(
+            Err(RejectReason::InvalidAmount),
+            account.update(deposit, &config)
+        );
+    }
+
+    #[test]
+    fn test_overflow_marks_account_in_error() {
+        let config = EngineConfig::default();
+        let mut account = ClientAccount::new(1);
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::MAX));
+        account.update(deposit, &config).unwrap();
+
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::ONE));
+        assert_eq!(
+            Err(RejectReason::ArithmeticOverflow),
+            account.update(deposit, &config)
+        );
+        assert!(account.error);
+        assert_eq!(Decimal::MAX, account.total);
+        assert!(account.transaction(2).is_none());
+
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 1, 3, Some(Decimal::ONE));
+        assert_eq!(
+            Err(RejectReason::AccountInError),
+            account.update(withdrawal, &config)
+        );
+    }
+
+    #[test]
+    fn test_unlock() {
+        let mut config = EngineConfig::default();
+        let mut account = ClientAccount::new(1);
+        let unlock = Transaction::new(TransactionType::Unlock, 1, 3, None);
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+        let chargeback = Transaction::new(TransactionType::Chargeback, 1, 1, None);
+        account.update(deposit, &config).unwrap();
+        account.update(dispute, &config).unwrap();
+        account.update(chargeback, &config).unwrap();
+        assert_eq!(
+            Err(RejectReason::AdminOpNotAllowed),
+            account.update(unlock.clone(), &config)
+        );
+        assert!(account.locked);
+
+        config.allow_admin_ops = true;
+        account.update(unlock.clone(), &config).unwrap();
+        assert!(!account.locked);
+        assert_eq!(
+            Err(RejectReason::NotLocked),
+            account.update(unlock, &config)
+        );
+
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::ONE));
+        account.update(deposit, &config).unwrap();
+        assert_eq!(Decimal::ONE, account.total);
+    }
+
+    #[test]
+    fn test_fees() {
+        let config = EngineConfig {
+            fees: FeeSchedule::default()
+                .with_fee(TransactionType::Deposit, Fee::Flat(Decimal::ONE))
+                .with_fee(TransactionType::Withdrawal, Fee::Percent(Decimal::TEN)),
+            ..Default::default()
+        };
+        let mut account = ClientAccount::new(1);
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE));
+        assert_eq!(
+            Err(RejectReason::AmountBelowFee),
+            account.update(deposit, &config)
+        );
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::new(12, 0)));
+        account.update(deposit, &config).unwrap();
+        assert_eq!(Decimal::new(11, 0), account.total);
+
+        // 10 plus a fee of 1 leaves nothing
+        let withdrawal = Transaction::new(
+            TransactionType::Withdrawal,
+            1,
+            3,
+            Some(Decimal::new(105, 1)),
+        );
+        assert_eq!(
+            Err(RejectReason::InsufficientFunds),
+            account.update(withdrawal, &config)
+        );
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 1, 4, Some(Decimal::TEN));
+        account.update(withdrawal, &config).unwrap();
+        assert_eq!(Decimal::ZERO, account.available);
+        assert_eq!(Some(&Decimal::TWO), account.fees_collected.get(""));
+
+        // Disputes involve the amount net of fees
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 2, None);
+        assert_eq!(
+            Err(RejectReason::InsufficientFunds),
+            account.update(dispute, &config)
+        );
+        assert_eq!(Decimal::new(11, 0), account.transaction(2).unwrap().amount);
+    }
+
+    #[test]
+    fn test_events() {
+        let config = EngineConfig {
+            fees: FeeSchedule::default()
+                .with_fee(TransactionType::Deposit, Fee::Flat(Decimal::ONE)),
+            ..Default::default()
+        };
+        let mut account = ClientAccount::new(1);
+        let funds = |amount| FundsMovement {
+            client: 1,
+            tx: 1,
+            amount,
+            currency: None,
+        };
+
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        assert_eq!(
+            Ok(vec![
+                AccountEvent::FundsDeposited(funds(Decimal::new(9, 0))),
+                AccountEvent::FeeCharged(funds(Decimal::ONE)),
+            ]),
+            account.update(deposit, &config)
+        );
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+        assert_eq!(
+            Ok(vec![AccountEvent::FundsHeld(funds(Decimal::new(9, 0)))]),
+            account.update(dispute, &config)
+        );
+        let chargeback = Transaction::new(TransactionType::Chargeback, 1, 1, None);
+        assert_eq!(
+            Ok(vec![
+                AccountEvent::FundsReversed(funds(Decimal::new(9, 0))),
+                AccountEvent::AccountLocked { client: 1, tx: 1 },
+            ]),
+            account.update(chargeback, &config)
+        );
+    }
+
+    #[test]
+    fn test_dispute_window() {
+        let config = EngineConfig {
+            dispute_window: Some(Duration::from_secs(100)),
+            ..Default::default()
+        };
+        let mut account = ClientAccount::new(1);
+        for tx_id in [1, 2] {
+            let mut deposit =
+                Transaction::new(TransactionType::Deposit, 1, tx_id, Some(Decimal::ONE));
+            deposit.timestamp = Some(1000);
+            account.update(deposit, &config).unwrap();
+        }
+
+        let mut dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+        dispute.timestamp = Some(1100);
+        assert!(account.update(dispute, &config).is_ok());
+        let mut dispute = Transaction::new(TransactionType::Dispute, 1, 2, None);
+        dispute.timestamp = Some(1101);
+        assert_eq!(
+            Err(RejectReason::DisputeWindowExpired),
+            account.update(dispute, &config)
+        );
+        assert_eq!(Decimal::ONE, account.held);
+    }
+
+    #[test]
+    fn test_negative_balance_policy() {
+        use NegativeBalancePolicy::*;
+
+        for (policy, dispute_applied, overdraft_applied) in [
+            (Forbid, false, false),
+            (AllowOnChargeback, true, false),
+            (AllowAlways, true, true),
+        ] {
+            let config = EngineConfig {
+                negative_balance: policy,
+                ..Default::default()
+            };
+            let mut account = ClientAccount::new(1);
+            let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+            let withdrawal =
+                Transaction::new(TransactionType::Withdrawal, 1, 2, Some(Decimal::new(8, 0)));
+            account.update(deposit, &config).unwrap();
+            account.update(withdrawal, &config).unwrap();
+
+            // The deposit has already been mostly withdrawn
+            let dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+            let chargeback = Transaction::new(TransactionType::Chargeback, 1, 1, None);
+            assert_eq!(dispute_applied, account.update(dispute, &config).is_ok());
+            if dispute_applied {
+                account.update(chargeback, &config).unwrap();
+                assert_eq!(Decimal::new(-8, 0), account.available);
+                assert_eq!(Decimal::new(-8, 0), account.total);
+                assert!(account.locked);
+            }
+
+            let mut account = ClientAccount::new(2);
+            let withdrawal =
+                Transaction::new(TransactionType::Withdrawal, 2, 3, Some(Decimal::ONE));
+            assert_eq!(
+                overdraft_applied,
+                account.update(withdrawal, &config).is_ok()
+            );
+        }
+    }
+
+    #[test]
+    fn test_close() {
+        let config = EngineConfig::default();
+        let mut account = ClientAccount::new(1);
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+        let resolve = Transaction::new(TransactionType::Resolve, 1, 1, None);
+        let close = Transaction::new(TransactionType::Close, 1, 2, None);
+        account.update(deposit, &config).unwrap();
+        account.update(dispute.clone(), &config).unwrap();
+        assert_eq!(
+            Err(RejectReason::FundsHeld),
+            account.update(close.clone(), &config)
+        );
+
+        account.update(resolve, &config).unwrap();
+        assert_eq!(
+            Ok(vec![AccountEvent::AccountClosed { client: 1, tx: 2 }]),
+            account.update(close.clone(), &config)
+        );
+        assert!(account.closed && !account.locked);
+        assert_eq!(Decimal::TEN, account.available);
+        for tx in [dispute, close] {
+            assert_eq!(
+                Err(RejectReason::AccountClosed),
+                account.update(tx, &config)
+            );
+        }
+
+        let mut to = ClientAccount::new(2);
+        let mut transfer = Transaction::new(TransactionType::Transfer, 2, 3, Some(Decimal::ONE));
+        transfer.counterparty = Some(1);
+        assert_eq!(
+            Err(RejectReason::AccountClosed),
+            to.transfer(&mut account, transfer, &config)
+        );
+    }
+
+    #[test]
+    fn test_transfer() {
+        let config = EngineConfig {
+            negative_balance: NegativeBalancePolicy::AllowAlways,
+            ..Default::default()
+        };
+        let transfer = |tx_id, amount| {
+            let mut transfer = Transaction::new(TransactionType::Transfer, 1, tx_id, Some(amount));
+            transfer.counterparty = Some(2);
+            transfer
+        };
+        let mut account = ClientAccount::new(1);
+        let mut to = ClientAccount::new(2);
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 1, 1, Some(Decimal::MAX));
+        account.update(withdrawal, &config).unwrap();
+
+        // The debit overflows after the credit has been checked: neither account is changed
+        assert_eq!(
+            Err(RejectReason::ArithmeticOverflow),
+            account.transfer(&mut to, transfer(2, Decimal::MAX), &config)
+        );
+        assert!(account.error && !to.error);
+        assert_eq!(-Decimal::MAX, account.total);
+        assert_eq!(Decimal::ZERO, to.total);
+
+        let mut account = ClientAccount::new(1);
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        account.update(deposit, &config).unwrap();
+        account
+            .transfer(&mut to, transfer(2, Decimal::ONE), &config)
+            .unwrap();
+        assert_eq!(
+            Err(RejectReason::DuplicateTransaction),
+            account.transfer(&mut to, transfer(2, Decimal::ONE), &config)
+        );
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::ONE));
+        assert_eq!(
+            Err(RejectReason::DuplicateTransaction),
+            account.update(deposit, &config)
+        );
+        for tx_type in [TransactionType::Dispute, TransactionType::Reversal] {
+            assert_eq!(
+                Err(RejectReason::TransferNotDisputable),
+                account.update(Transaction::new(tx_type, 1, 2, None), &config)
+            );
+        }
+        assert_eq!(Decimal::new(9, 0), account.total);
+        assert_eq!(Decimal::ONE, to.total);
+    }
+
+    #[test]
+    fn test_reversal() {
+        let config = EngineConfig::default();
+        let mut account = ClientAccount::new(1);
+        let reversal = |tx_id| Transaction::new(TransactionType::Reversal, 1, tx_id, None);
+        for tx in [
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN)),
+            Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::new(5, 0))),
+            Transaction::new(TransactionType::Withdrawal, 1, 3, Some(Decimal::new(3, 0))),
+            Transaction::new(TransactionType::Dispute, 1, 2, None),
+        ] {
+            account.update(tx, &config).unwrap();
+        }
+
+        account.update(reversal(3), &config).unwrap();
+        assert_eq!(
+            Ok(vec![AccountEvent::TxReversed(FundsMovement {
+                client: 1,
+                tx: 1,
+                amount: Decimal::TEN,
+                currency: None,
+            })]),
+            account.update(reversal(1), &config)
+        );
+        assert_eq!(Decimal::ZERO, account.available);
+        assert_eq!(Decimal::new(5, 0), account.held);
+        assert_eq!(Decimal::new(5, 0), account.total);
+        assert!(!account.locked);
+        assert_eq!(
+            TransactionStatus::Reversed,
+            account.transaction(1).unwrap().status
+        );
+
+        // Disputed and reversed transactions can't be reversed, nor disputed
+        assert_eq!(
+            Err(RejectReason::AlreadyDisputed),
+            account.update(reversal(2), &config)
+        );
+        assert_eq!(
+            Err(RejectReason::AlreadyReversed),
+            account.update(reversal(1), &config)
+        );
+        assert_eq!(
+            Err(RejectReason::AlreadyReversed),
+            account.update(
+                Transaction::new(TransactionType::Dispute, 1, 1, None),
+                &config
+            )
+        );
+
+        // Funds already spent are taken back only if the negative balance policy allows it
+        for (policy, applied) in [
+            (NegativeBalancePolicy::Forbid, false),
+            (NegativeBalancePolicy::AllowOnChargeback, true),
+        ] {
+            let config = EngineConfig {
+                negative_balance: policy,
+                ..Default::default()
+            };
+            let mut account = ClientAccount::new(1);
+            let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+            let withdrawal =
+                Transaction::new(TransactionType::Withdrawal, 1, 2, Some(Decimal::new(8, 0)));
+            account.update(deposit, &config).unwrap();
+            account.update(withdrawal, &config).unwrap();
+            assert_eq!(applied, account.update(reversal(1), &config).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_partial_dispute() {
+        let config = EngineConfig::default();
+        let mut account = ClientAccount::new(1);
+        let dispute = |amount| Transaction::new(TransactionType::Dispute, 1, 1, amount);
+        let resolve = Transaction::new(TransactionType::Resolve, 1, 1, None);
+        let chargeback = Transaction::new(TransactionType::Chargeback, 1, 1, None);
+        account
+            .update(
+                Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN)),
+                &config,
+            )
+            .unwrap();
+        assert_eq!(
+            Err(RejectReason::DisputeExceedsAmount),
+            account.update(dispute(Some(Decimal::new(11, 0))), &config)
+        );
+        assert_eq!(
+            Err(RejectReason::InvalidAmount),
+            account.update(dispute(Some(Decimal::ZERO)), &config)
+        );
+
+        // Only the disputed part is held, and the rest stays disputable once resolved
+        account
+            .update(dispute(Some(Decimal::new(4, 0))), &config)
+            .unwrap();
+        assert_eq!(Decimal::new(6, 0), account.available);
+        assert_eq!(Decimal::new(4, 0), account.held);
+        account.update(resolve.clone(), &config).unwrap();
+        let tx = account.transaction(1).unwrap();
+        assert_eq!(TransactionStatus::Verified, tx.status);
+        assert_eq!(Decimal::new(6, 0), tx.disputable());
+        assert_eq!(
+            Err(RejectReason::DisputeExceedsAmount),
+            account.update(dispute(Some(Decimal::new(7, 0))), &config)
+        );
+
+        // A dispute without an amount holds all of what is left to dispute
+        account.update(dispute(None), &config).unwrap();
+        assert_eq!(Decimal::new(6, 0), account.held);
+        account.update(chargeback, &config).unwrap();
+        assert_eq!(Decimal::new(4, 0), account.available);
+        assert_eq!(Decimal::new(4, 0), account.total);
+        assert!(account.locked);
+        assert_eq!(
+            TransactionStatus::Chargebacked,
+            account.transaction(1).unwrap().status
+        );
+        assert_eq!(
+            Err(RejectReason::AlreadyChargebacked),
+            account.update(resolve, &config)
+        );
+    }
+
+    #[test]
+    fn test_currencies() {
+        let config = EngineConfig::default();
+        let mut account = ClientAccount::new(1);
+        let mut deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        deposit.currency = Some("EUR".into());
+        account.update(deposit, &config).unwrap();
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::ONE));
+        account.update(deposit, &config).unwrap();
+
+        // Withdrawals can only use the funds in their own currency
+        let mut withdrawal =
+            Transaction::new(TransactionType::Withdrawal, 1, 3, Some(Decimal::TWO));
+        withdrawal.currency = Some("USD".into());
+        assert_eq!(
+            Err(RejectReason::InsufficientFunds),
+            account.update(withdrawal, &config)
+        );
+        assert!(!account.currencies.contains_key("USD"));
+
+        let mut dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+        dispute.currency = Some("USD".into());
+        assert_eq!(
+            Err(RejectReason::CurrencyMismatch),
+            account.update(dispute, &config)
+        );
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+        account.update(dispute, &config).unwrap();
+
+        let eur = account.balances(Some("EUR"));
+        assert_eq!(Decimal::ZERO, eur.available);
+        assert_eq!(Decimal::TEN, eur.held);
+        assert_eq!(Decimal::TEN, eur.total);
+        assert_eq!(Decimal::ONE, account.available);
+        assert_eq!(Decimal::ZERO, account.held);
+        assert!(account.uses_default_currency());
+    }
+
+    #[test]
+    fn test_amount_round_trip() {
+        // More significant digits than an f64 holds
+        let amount = parse_amount("123456789.123456789").unwrap();
+        let tx = Transaction::new(TransactionType::Deposit, 1, 1, Some(amount));
+
+        let json = serde_json::to_string(&tx).unwrap();
+        assert!(json.contains(r#""amount":"123456789.123456789""#), "{json}");
+        let read: Transaction = serde_json::from_str(&json).unwrap();
+        assert_eq!(Some(amount), read.amount);
+    }
+
+    #[cfg(feature = "io")]
     #[tokio::test]
     async fn test_serialize() {
         let tx = Transaction {
@@ -327,7 +1659,14 @@ mod model_tests {
             client_id: 1u16,
             tx_id: 123u32,
             amount: Some(Decimal::ZERO),
+            currency: None,
+            counterparty: None,
+            idempotency_key: None,
+            origin: None,
+            timestamp: None,
             status: TransactionStatus::Loaded,
+            source: 0,
+            offset: 0,
         };
 
         let mut wrt = csv_async::AsyncSerializer::from_writer(io::stdout());
@@ -335,6 +1674,7 @@ mod model_tests {
         wrt.serialize(tx).await.unwrap();
     }
 
+    #[cfg(feature = "io")]
     #[tokio::test]
     async fn test_deserialize_with_whitespaces() {
         let data = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 2, 2, 2.0\ndeposit, 1, 3, 2.0\nwithdrawal, 1, 4, 1.5\nwithdrawal, 2, 5, 3.0\ndispute, 1, 1, ";
@@ -350,6 +1690,7 @@ mod model_tests {
         }
     }
 
+    #[cfg(feature = "io")]
     #[tokio::test]
     async fn test_deserialize_without_whitespaces() {
         let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,1,3,2.0\nwithdrawal,1,4,1.5\nwithdrawal,2,5,3.0";
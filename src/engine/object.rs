@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use tokio::io::{self, AsyncBufRead};
+
+use super::error::EngineError;
+
+/// Schemes of the URLs of objects in object storage, read instead of local files
+const OBJECT_SCHEMES: [&str; 8] = ["s3", "s3a", "gs", "az", "adl", "azure", "abfs", "abfss"];
+
+/// A reader of the content of an object
+pub(super) type ObjectReader = std::pin::Pin<Box<dyn AsyncBufRead + Send>>;
+
+/// Whether `path` is the URL of an object in object storage, e.g. `s3://bucket/key`, rather
+/// than the path of a local file.
+pub fn is_object_url(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .to_str()
+        .and_then(|path| path.split_once("://"))
+        .is_some_and(|(scheme, _)| OBJECT_SCHEMES.contains(&scheme.to_lowercase().as_str()))
+}
+
+/// Returns the size of the object at `url`.
+pub(super) async fn object_len(url: &Path) -> Result<u64, EngineError> {
+    #[cfg(feature = "object-store")]
+    {
+        let (store, path) = store(url)?;
+        Ok(store.head(&path).await?.size as u64)
+    }
+    #[cfg(not(feature = "object-store"))]
+    Err(unsupported(url))
+}
+
+/// Opens the object at `url`, streaming its content from `offset` without downloading it first.
+pub(super) async fn open_object(url: &Path, offset: u64) -> Result<ObjectReader, EngineError> {
+    #[cfg(feature = "object-store")]
+    {
+        use futures::TryStreamExt;
+        use object_store::{GetOptions, GetRange};
+
+        let (store, path) = store(url)?;
+        let options = GetOptions {
+            range: (offset > 0).then_some(GetRange::Offset(offset as usize)),
+            ..Default::default()
+        };
+        let stream = store
+            .get_opts(&path, options)
+            .await?
+            .into_stream()
+            .map_err(io::Error::other);
+        Ok(Box::pin(tokio_util::io::StreamReader::new(stream)))
+    }
+    #[cfg(not(feature = "object-store"))]
+    {
+        let _ = offset;
+        Err(unsupported(url))
+    }
+}
+
+// Opens the store holding the object at `url`, configured with the `AWS_*`, `GOOGLE_*` and
+// `AZURE_*` environment variables, e.g. `AWS_REGION`
+#[cfg(feature = "object-store")]
+fn store(
+    url: &Path,
+) -> Result<(Box<dyn object_store::ObjectStore>, object_store::path::Path), EngineError> {
+    let url = url.to_str().unwrap_or_default();
+    let url = url::Url::parse(url).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let options = std::env::vars()
+        .filter(|(key, _)| {
+            ["AWS_", "GOOGLE_", "AZURE_"]
+                .iter()
+                .any(|p| key.starts_with(p))
+        })
+        .map(|(key, value)| (key.to_lowercase(), value));
+    Ok(object_store::parse_url_opts(&url, options)?)
+}
+
+#[cfg(not(feature = "object-store"))]
+fn unsupported(url: &Path) -> EngineError {
+    EngineError::IoError(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "reading `{}` requires the `object-store` feature",
+            url.display()
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod object_tests {
+    use super::*;
+
+    #[test]
+    fn test_is_object_url() {
+        assert!(is_object_url("s3://bucket/transactions.csv"));
+        assert!(is_object_url("GS://bucket/dir/transactions.csv.gz"));
+        assert!(is_object_url("az://container/transactions.jsonl"));
+        assert!(!is_object_url("res/transactions.csv"));
+        assert!(!is_object_url("/tmp/s3://transactions.csv"));
+        assert!(!is_object_url("file:///tmp/transactions.csv"));
+    }
+}
@@ -0,0 +1,1271 @@
+use std::{
+    borrow::Borrow,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncWriteExt},
+    sync::{mpsc, watch},
+};
+
+use super::{
+    amount::Decimal,
+    checkpoint::{Checkpoint, Checkpointer},
+    config::{EngineConfig, NegativeBalancePolicy, SortBy},
+    error::EngineError,
+    event::AccountEvent,
+    fees::FeeSchedule,
+    filter::{ClientFilter, TypeFilter},
+    finalize::Finalizer,
+    fs::{read_report, AtomicFile},
+    history::BalanceHistory,
+    hooks::TransactionHooks,
+    ledger::LedgerEntry,
+    metrics,
+    model::{
+        Balances, ClientAccount, CurrencyCode, StoredTx, Transaction, TransactionStatus,
+        TransactionType,
+    },
+    precision::PrecisionPolicy,
+    processor::SHARD_CHANNEL_CAPACITY,
+    reject::{RejectReason, RejectedTransaction},
+    source::TimeWindow,
+    spill::Spill,
+    store::{AccountState, StateStore},
+    summary::Summary,
+    throttle::WarnThrottle,
+    validation::Validator,
+};
+
+/// The payment engine, keeping the state of all the client accounts.
+///
+/// Transactions can be applied one at a time with [`PaymentEngine::apply`], or read from
+/// a [`TransactionSource`](super::TransactionSource) with [`PaymentEngine::process`].
+#[derive(Debug)]
+pub struct PaymentEngine {
+    pub(super) accounts: HashMap<u16, ClientAccount>,
+    pub(super) config: EngineConfig,
+    // Used to log the transactions rejected while processing sources
+    pub(super) throttle: WarnThrottle,
+    // Number of tasks the accounts are partitioned over while processing sources
+    pub(super) shards: usize,
+    // Saves checkpoints while processing sources, if enabled
+    pub(super) checkpointer: Option<Checkpointer>,
+    // Transactions rejected while processing sources, if they have to be collected
+    pub(super) rejected: Option<Vec<RejectedTransaction>>,
+    // Transactions applied while processing sources, if the ledger has to be collected
+    pub(super) ledger: Option<Vec<LedgerEntry>>,
+    // End-of-day balances of the accounts, if they have to be collected
+    pub(super) balance_history: Option<BalanceHistory>,
+    // Spills the transactions history to disk while processing sources, if enabled
+    pub(super) spill: Option<Spill>,
+    // Client using each deposit and withdrawal tx id, `None` if used by several clients. Not
+    // kept by the shards, their records are checked when dispatched.
+    pub(super) tx_clients: Option<HashMap<u32, Option<u16>>>,
+    // Whether invalid records are skipped while processing sources, instead of aborting
+    pub(super) skip_invalid: bool,
+    // Whether any invalid or rejected record aborts the processing of sources
+    pub(super) strict: bool,
+    // Order of the accounts listed by `sorted_accounts`
+    pub(super) sort_by: SortBy,
+    // Volumes and counts of the transactions applied
+    pub(super) summary: Summary,
+    // Idempotency keys of the transactions applied, by client
+    pub(super) idempotency_keys: HashMap<u16, HashSet<String>>,
+    // Window of time of the records applied while processing sources
+    pub(super) window: TimeWindow,
+    // Clients whose records are applied while processing sources
+    pub(super) clients: ClientFilter,
+    // Types of the records applied while processing sources
+    pub(super) types: TypeFilter,
+    // Receives the events of the transactions applied or rejected, if streamed
+    pub(super) events: Option<mpsc::UnboundedSender<AccountEvent>>,
+    // Balances of the accounts locked by the transactions applied, when they got locked
+    pub(super) locked_balances: HashMap<u16, LockedBalances>,
+    // Receives `true` when processing sources has to be interrupted
+    pub(super) interrupt: Option<watch::Receiver<bool>>,
+    // Whether processing the last sources has been interrupted
+    pub(super) interrupted: bool,
+    // Called around every record applied while processing sources, if any
+    pub(super) hooks: Option<Box<dyn TransactionHooks>>,
+    // Run in order by `finalize`
+    pub(super) finalizers: Vec<Box<dyn Finalizer>>,
+    // Receives the accounts once the sources are processed, instead of keeping them, if streamed
+    pub(super) account_stream: Option<mpsc::Sender<ClientAccount>>,
+    // Latest timestamp of the transactions applied or rejected, if any has one
+    pub(super) latest_timestamp: Option<u64>,
+    // Highest tx id of the transactions applied or rejected, if any
+    pub(super) max_tx_id: Option<u32>,
+    // Capacity of the channel feeding each shard task
+    pub(super) shard_capacity: usize,
+    // Whether initial balances have been loaded, their held funds not being backed by disputes
+    pub(super) initial_balances: bool,
+}
+
+// Balances of an account in the default currency and in the other ones
+type LockedBalances = (Balances, BTreeMap<CurrencyCode, Balances>);
+
+impl SortBy {
+    /// Sorts the accounts in this order.
+    pub(super) fn sort<A: Borrow<ClientAccount>>(self, accounts: &mut [A]) {
+        match self {
+            Self::Client => accounts.sort_by_key(|acc| acc.borrow().client_id),
+            Self::Total => accounts.sort_by_key(|acc| (acc.borrow().total, acc.borrow().client_id)),
+            Self::Available => {
+                accounts.sort_by_key(|acc| (acc.borrow().available, acc.borrow().client_id))
+            }
+        }
+    }
+}
+
+impl Default for PaymentEngine {
+    fn default() -> Self {
+        Self {
+            accounts: HashMap::new(),
+            config: EngineConfig::default(),
+            throttle: WarnThrottle::default(),
+            shards: 1,
+            checkpointer: None,
+            rejected: None,
+            ledger: None,
+            balance_history: None,
+            spill: None,
+            tx_clients: Some(HashMap::new()),
+            skip_invalid: false,
+            strict: false,
+            sort_by: SortBy::default(),
+            summary: Summary::default(),
+            idempotency_keys: HashMap::new(),
+            window: TimeWindow::default(),
+            clients: ClientFilter::default(),
+            types: TypeFilter::default(),
+            events: None,
+            locked_balances: HashMap::new(),
+            interrupt: None,
+            interrupted: false,
+            hooks: None,
+            finalizers: Vec::new(),
+            account_stream: None,
+            latest_timestamp: None,
+            max_tx_id: None,
+            shard_capacity: SHARD_CHANNEL_CAPACITY,
+            initial_balances: false,
+        }
+    }
+}
+
+impl PaymentEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    /// Applies a transaction to the account of its client, creating the account if the client
+    /// is not known yet. If the transaction is rejected, the reason is returned.
+    ///
+    /// Interest is rejected with [`RejectReason::ReservedType`], as it's only credited by the
+    /// engine, see [`PaymentEngine::accrue_interest`].
+    pub fn apply(&mut self, tx: Transaction) -> Result<(), RejectReason> {
+        self.apply_tx(tx, false)
+    }
+
+    // Applies a transaction like `apply`, allowing the types reserved to the engine if it has
+    // been generated by the engine itself
+    pub(super) fn apply_tx(
+        &mut self,
+        tx: Transaction,
+        synthetic: bool,
+    ) -> Result<(), RejectReason> {
+        let start = Instant::now();
+        self.latest_timestamp = self.latest_timestamp.max(tx.timestamp);
+        self.max_tx_id = self.max_tx_id.max(Some(tx.tx_id));
+        let (tx_type, client_id) = (tx.tx_type, tx.client_id);
+        let was_locked = self.account(client_id).is_some_and(|acc| acc.locked);
+        let amount = tx.amount.map(|amount| self.config.precision.apply(amount));
+        let currency = tx.currency.clone();
+        let key = tx.idempotency_key.clone().filter(|key| !key.is_empty());
+        let (tx_id, timestamp) = (tx.tx_id, tx.timestamp);
+        let counterparty = tx
+            .counterparty
+            .filter(|_| tx_type == TransactionType::Transfer);
+        let outcome = Self::check_reserved_type(tx_type, synthetic)
+            .and_then(|()| self.check_idempotency_key(client_id, key.as_deref()))
+            .and_then(|()| self.check_tx_id(&tx))
+            .and_then(|()| match tx.counterparty {
+                Some(counterparty) if tx.tx_type == TransactionType::Transfer => {
+                    self.transfer(tx, counterparty)
+                }
+                _ => self
+                    .accounts
+                    .entry(client_id)
+                    .or_insert_with(|| ClientAccount::new(client_id))
+                    .update(tx, &self.config),
+            });
+
+        let outcome = match outcome {
+            Ok(events) => {
+                let origin = match tx_type {
+                    TransactionType::Dispute | TransactionType::Chargeback => self
+                        .account(client_id)
+                        .and_then(|acc| acc.transaction(tx_id))
+                        .and_then(|tx| tx.dispute_origin),
+                    _ => None,
+                };
+                self.summary
+                    .record(tx_type, amount, currency.as_ref(), origin);
+                if let (Some(history), Some(timestamp)) = (&mut self.balance_history, timestamp) {
+                    // A dispute, resolve, chargeback or reversal changes the balances in the
+                    // currency of the referenced transaction, even without one of its own
+                    let referenced = tx_type
+                        .is_referencing()
+                        .then(|| self.accounts.get(&client_id)?.transaction(tx_id))
+                        .flatten();
+                    let currency = match referenced {
+                        Some(tx) => tx.currency.as_deref(),
+                        None => currency.as_deref(),
+                    };
+                    for client in std::iter::once(client_id).chain(counterparty) {
+                        if let Some(account) = self.accounts.get(&client) {
+                            history.record(timestamp, account, currency);
+                        }
+                    }
+                }
+                if let Some(key) = key {
+                    self.idempotency_keys
+                        .entry(client_id)
+                        .or_default()
+                        .insert(key);
+                }
+                self.emit(events);
+                Ok(())
+            }
+            Err(reason) => {
+                self.emit(vec![AccountEvent::TxRejected {
+                    client: client_id,
+                    tx: tx_id,
+                    tx_type,
+                    reason,
+                }]);
+                Err(reason)
+            }
+        };
+        let locked = self.account(client_id).is_some_and(|acc| acc.locked);
+        // Disputes, resolves, chargebacks and reversals still settle the transactions of a
+        // locked account, only the ones moving funds of their own are rejected
+        if locked && (!was_locked || !tx_type.moves_funds()) {
+            let account = &self.accounts[&client_id];
+            let balances = (account.balances(None), account.currencies.clone());
+            self.locked_balances.insert(client_id, balances);
+        } else if was_locked && !locked {
+            self.locked_balances.remove(&client_id);
+        }
+        metrics::record_transaction(tx_type, outcome, start.elapsed(), was_locked, locked);
+        outcome
+    }
+
+    // Streams the events of a transaction to the sender set with `EngineBuilder::events`, if any
+    fn emit(&mut self, events: Vec<AccountEvent>) {
+        if let Some(sender) = &self.events {
+            for event in events {
+                // Nobody listening anymore, stop sending
+                if sender.send(event).is_err() {
+                    self.events = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Stops streaming the events of the transactions applied, closing the channel set with
+    /// [`EngineBuilder::events`].
+    pub fn close_events(&mut self) {
+        self.events = None;
+    }
+
+    // Applies a transfer between the accounts of the client and the counterparty, creating
+    // them if needed
+    fn transfer(
+        &mut self,
+        tx: Transaction,
+        counterparty: u16,
+    ) -> Result<Vec<AccountEvent>, RejectReason> {
+        if counterparty == tx.client_id {
+            return Err(RejectReason::InvalidCounterparty);
+        }
+        let mut to = self
+            .accounts
+            .remove(&counterparty)
+            .unwrap_or_else(|| ClientAccount::new(counterparty));
+        let client_id = tx.client_id;
+        let outcome = self
+            .accounts
+            .entry(client_id)
+            .or_insert_with(|| ClientAccount::new(client_id))
+            .transfer(&mut to, tx, &self.config);
+        self.accounts.insert(counterparty, to);
+        outcome
+    }
+
+    // Checks that a transaction not generated by the engine isn't of a type reserved to it
+    fn check_reserved_type(tx_type: TransactionType, synthetic: bool) -> Result<(), RejectReason> {
+        if tx_type == TransactionType::Interest && !synthetic {
+            return Err(RejectReason::ReservedType);
+        }
+        Ok(())
+    }
+
+    // Checks that no transaction with the same idempotency key has been applied to the account.
+    // Keys of rejected transactions can be reused, e.g. to retry them.
+    fn check_idempotency_key(&self, client_id: u16, key: Option<&str>) -> Result<(), RejectReason> {
+        match (key, self.idempotency_keys.get(&client_id)) {
+            (Some(key), Some(keys)) if keys.contains(key) => {
+                Err(RejectReason::DuplicateIdempotencyKey)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Checks the tx id of a transaction against the ones of the other clients: a deposit,
+    // withdrawal or transfer registers the client using it (even if then rejected), while a
+    // dispute, resolve or chargeback can't reference the transaction of another client. The tx
+    // ids of admin operations aren't checked.
+    pub(super) fn check_tx_id(&mut self, tx: &Transaction) -> Result<(), RejectReason> {
+        let Some(tx_clients) = &mut self.tx_clients else {
+            return Ok(());
+        };
+        if tx.tx_type.is_referencing() {
+            return match tx_clients.get(&tx.tx_id) {
+                Some(Some(owner)) if *owner != tx.client_id => Err(RejectReason::ClientMismatch),
+                _ => Ok(()),
+            };
+        }
+        if !tx.tx_type.is_registered() {
+            return Ok(());
+        }
+        match tx_clients.entry(tx.tx_id) {
+            Entry::Occupied(mut entry) if *entry.get() != Some(tx.client_id) => {
+                if self.config.unique_tx_ids {
+                    return Err(RejectReason::TxIdReused);
+                }
+                entry.insert(None);
+                Ok(())
+            }
+            Entry::Occupied(_) => Ok(()),
+            Entry::Vacant(entry) => {
+                entry.insert(Some(tx.client_id));
+                Ok(())
+            }
+        }
+    }
+
+    // Rebuilds the index of the tx ids from the transactions of the accounts, if kept
+    fn index_tx_ids(&mut self) {
+        let Some(tx_clients) = &mut self.tx_clients else {
+            return;
+        };
+        tx_clients.clear();
+        for acc in self.accounts.values() {
+            for (tx_id, _) in acc.transactions() {
+                tx_clients
+                    .entry(tx_id)
+                    .and_modify(|owner| *owner = None)
+                    .or_insert(Some(acc.client_id));
+            }
+        }
+    }
+
+    /// Returns an [`EngineError::ArithmeticOverflow`] if any account is in error after an
+    /// arithmetic overflow, listing their clients.
+    pub fn check_overflows(&self) -> Result<(), EngineError> {
+        let mut clients: Vec<_> = self
+            .accounts()
+            .filter(|acc| acc.error)
+            .map(|acc| acc.client_id)
+            .collect();
+        if clients.is_empty() {
+            return Ok(());
+        }
+        clients.sort();
+        Err(EngineError::ArithmeticOverflow(clients))
+    }
+
+    /// Checks the invariants every account has to satisfy, returning an
+    /// [`EngineError::InvariantViolated`] for the first account breaking one (by client id):
+    /// - in every currency, the total funds are the sum of the available and held ones;
+    /// - held funds are never negative, nor are available ones unless the
+    ///   [`NegativeBalancePolicy`] allows it;
+    /// - held funds are the sum of the amounts of the transactions under dispute (not checked
+    ///   when the history is spilled to disk, or initial balances have been loaded);
+    /// - the balances of an account locked by a transaction aren't changed by deposits,
+    ///   withdrawals, transfers or interest until it's unlocked.
+    pub fn check_invariants(&self) -> Result<(), EngineError> {
+        let mut accounts: Vec<_> = self.accounts().collect();
+        accounts.sort_by_key(|acc| acc.client_id);
+        for account in accounts {
+            let check_disputed = self.spill.is_none() && !self.initial_balances;
+            Self::check_account(account, &self.config, check_disputed)
+                .and_then(|()| match self.locked_balances.get(&account.client_id) {
+                    Some((balances, currencies))
+                        if account.balances(None) != *balances
+                            || account.currencies != *currencies =>
+                    {
+                        Err("locked account changed")
+                    }
+                    _ => Ok(()),
+                })
+                .map_err(|invariant| EngineError::InvariantViolated {
+                    client_id: account.client_id,
+                    invariant,
+                })?;
+        }
+        Ok(())
+    }
+
+    // Checks the invariants of the balances of an account, returning the one broken
+    fn check_account(
+        account: &ClientAccount,
+        config: &EngineConfig,
+        check_disputed: bool,
+    ) -> Result<(), &'static str> {
+        let mut disputed: BTreeMap<Option<&str>, Decimal> = BTreeMap::new();
+        if check_disputed {
+            for (_, tx) in account.transactions() {
+                if tx.status == TransactionStatus::Disputed {
+                    let held = disputed.entry(tx.currency.as_deref()).or_default();
+                    *held = held.saturating_add(tx.held());
+                }
+            }
+        }
+        let currencies = account
+            .currencies
+            .keys()
+            .map(|currency| Some(currency.as_str()));
+        for currency in std::iter::once(None).chain(currencies) {
+            let balances = account.balances(currency);
+            if balances.available.checked_add(balances.held) != Some(balances.total) {
+                return Err("total funds differ from available plus held ones");
+            }
+            if balances.held < Decimal::ZERO {
+                return Err("negative held funds");
+            }
+            if balances.available < Decimal::ZERO
+                && config.negative_balance == NegativeBalancePolicy::Forbid
+            {
+                return Err("negative available funds");
+            }
+            if check_disputed
+                && disputed.get(&currency).copied().unwrap_or_default() != balances.held
+            {
+                return Err("held funds differ from the disputed amounts");
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the finalizers of the engine in order, once all the records of the sources are
+    /// consumed, see [`Finalizer`]. An engine without finalizers is left unchanged.
+    pub fn finalize(&mut self) -> Result<(), EngineError> {
+        let mut finalizers = std::mem::take(&mut self.finalizers);
+        let result = finalizers
+            .iter_mut()
+            .try_for_each(|finalizer| finalizer.finalize(self));
+        self.finalizers = finalizers;
+        result
+    }
+
+    /// Returns the options affecting how transactions are applied.
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Returns the account of the client with id `client_id`, if any.
+    pub fn account(&self, client_id: u16) -> Option<&ClientAccount> {
+        self.accounts.get(&client_id)
+    }
+
+    /// Iterates over all the accounts, in arbitrary order.
+    pub fn accounts(&self) -> impl Iterator<Item = &ClientAccount> {
+        self.accounts.values()
+    }
+
+    /// Returns the fees collected on all the accounts, i.e. the balances of the global fee
+    /// account, by currency (an empty code for the default one).
+    pub fn fees_collected(&self) -> BTreeMap<CurrencyCode, Decimal> {
+        let mut fees = BTreeMap::<CurrencyCode, Decimal>::new();
+        for (currency, fee) in self.accounts().flat_map(|acc| &acc.fees_collected) {
+            *fees.entry(currency.clone()).or_default() += fee;
+        }
+        fees
+    }
+
+    /// Returns all the accounts, in the order set with [`EngineBuilder::sort_by`].
+    pub fn sorted_accounts(&self) -> Vec<&ClientAccount> {
+        let mut accounts: Vec<_> = self.accounts().collect();
+        self.sort_by.sort(&mut accounts);
+        accounts
+    }
+
+    /// Returns the transaction with id `tx_id` in the history of the client, whether it's kept
+    /// in memory or has been spilled to disk (see [`EngineBuilder::spill`]).
+    pub fn transaction(&self, client_id: u16, tx_id: u32) -> Result<Option<StoredTx>, EngineError> {
+        if let Some(tx) = self
+            .account(client_id)
+            .and_then(|acc| acc.transaction(tx_id))
+        {
+            return Ok(Some(tx.clone()));
+        }
+        match &self.spill {
+            Some(spill) => spill.get(client_id, tx_id),
+            None => Ok(None),
+        }
+    }
+
+    /// Replaces the accounts of the engine with the ones persisted in `store`.
+    pub fn restore(&mut self, store: &mut impl StateStore) -> Result<(), EngineError> {
+        self.accounts = store.load()?;
+        if let Some(spill) = &mut self.spill {
+            spill.clear();
+        }
+        self.index_tx_ids();
+        Ok(())
+    }
+
+    /// Persists the accounts of the engine, with their transactions history, in `store`.
+    pub fn persist(&self, store: &mut impl StateStore) -> Result<(), EngineError> {
+        self.with_all_accounts(|accounts| store.save(accounts))?
+    }
+
+    /// Saves the accounts of the engine, with their transactions history, to a JSON snapshot
+    /// at `path`, e.g. to inspect the state reached or to start another run from it.
+    pub async fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        let data = self.with_all_accounts(|accounts| {
+            let mut accounts: Vec<_> = accounts.values().collect();
+            accounts.sort_unstable_by_key(|account| account.client_id);
+            let states: Vec<_> = accounts.into_iter().map(AccountState::from).collect();
+            serde_json::to_vec_pretty(&states)
+        })??;
+        let mut file = AtomicFile::create(path).await?;
+        file.file().write_all(&data).await?;
+        Ok(file.commit().await?)
+    }
+
+    /// Replaces the accounts of the engine with the ones of the snapshot at `path`, see
+    /// [`PaymentEngine::save_snapshot`].
+    pub async fn load_snapshot(&mut self, path: impl AsRef<Path>) -> Result<(), EngineError> {
+        let states: Vec<AccountState> = serde_json::from_slice(&fs::read(path).await?)?;
+        self.accounts = states
+            .into_iter()
+            .map(ClientAccount::from)
+            .map(|account| (account.client_id, account))
+            .collect();
+        if let Some(spill) = &mut self.spill {
+            spill.clear();
+        }
+        self.index_tx_ids();
+        Ok(())
+    }
+
+    /// Sets the balances and state of the clients to the ones of an accounts report in CSV
+    /// format, e.g. the output of the previous run, so that new transactions can be processed
+    /// without replaying the full history. The accounts are closed if the report has a `status`
+    /// column saying so. Returns the number of balances set, by client and currency.
+    ///
+    /// The transactions behind the balances are unknown, so they can't be disputed, and the
+    /// funds held by their disputes stay held. The accounts missing from the report keep their
+    /// state.
+    pub async fn load_initial_balances<R>(&mut self, rdr: R) -> Result<usize, EngineError>
+    where
+        R: AsyncRead + Send + Unpin,
+    {
+        let report = read_report(rdr).await?;
+        let count = report.len();
+        let mut clients = Vec::new();
+        for ((client_id, currency), entry) in report {
+            let balances = entry.balances;
+            let account = self
+                .accounts
+                .entry(client_id)
+                .or_insert_with(|| ClientAccount::new(client_id));
+            if currency.is_empty() {
+                account.available = balances.available;
+                account.held = balances.held;
+                account.total = balances.total;
+            } else {
+                account.currencies.insert(currency, balances);
+            }
+            // The rows of a client, one per currency, are consecutive
+            if clients.last() == Some(&client_id) {
+                account.locked |= entry.locked;
+                account.closed |= entry.closed;
+            } else {
+                account.locked = entry.locked;
+                account.closed = entry.closed;
+                clients.push(client_id);
+            }
+        }
+        for client_id in clients {
+            Self::check_account(&self.accounts[&client_id], &self.config, false).map_err(
+                |invariant| EngineError::InvariantViolated {
+                    client_id,
+                    invariant,
+                },
+            )?;
+        }
+        self.initial_balances = true;
+        Ok(count)
+    }
+
+    // Calls `f` with the accounts, including the transactions spilled to disk, if any
+    pub(super) fn with_all_accounts<T>(
+        &self,
+        f: impl FnOnce(&HashMap<u16, ClientAccount>) -> T,
+    ) -> Result<T, EngineError> {
+        match &self.spill {
+            Some(spill) if spill.len() > 0 => {
+                let mut accounts = self.accounts.clone();
+                spill.restore_all(&mut accounts)?;
+                Ok(f(&accounts))
+            }
+            _ => Ok(f(&self.accounts)),
+        }
+    }
+
+    /// Replaces the accounts of the engine with the ones of an interrupted run. The sources
+    /// have to be reopened from the offsets of the checkpoint, see [`Checkpoint::offset`].
+    pub fn resume(&mut self, checkpoint: Checkpoint) {
+        if let Some(checkpointer) = &mut self.checkpointer {
+            checkpointer.resume(&checkpoint);
+        }
+        self.accounts = checkpoint.into_accounts();
+        if let Some(spill) = &mut self.spill {
+            spill.clear();
+        }
+        self.index_tx_ids();
+    }
+
+    /// Returns the transactions rejected while processing sources, if collected (see
+    /// [`EngineBuilder::collect_rejects`]).
+    pub fn rejected(&self) -> &[RejectedTransaction] {
+        self.rejected.as_deref().unwrap_or_default()
+    }
+
+    /// Whether processing the last sources has been interrupted, see
+    /// [`EngineBuilder::interrupt`]: the accounts only reflect the records read before.
+    pub fn interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    /// Takes the transactions rejected while processing sources so far, if collected.
+    pub fn take_rejected(&mut self) -> Vec<RejectedTransaction> {
+        self.rejected
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Returns the summary of the transactions applied so far, with the records rejected by
+    /// reason while processing sources.
+    pub fn summary(&self) -> Summary {
+        Summary {
+            clients: self.accounts.len(),
+            locked_accounts: self.accounts().filter(|acc| acc.locked).count(),
+            rejected: RejectReason::ALL
+                .into_iter()
+                .map(|reason| (reason.code(), self.throttle.count(reason)))
+                .filter(|(_, count)| *count > 0)
+                .collect(),
+            ..self.summary.clone()
+        }
+    }
+
+    /// Returns the deposits and withdrawals still under dispute, with their clients and tx ids,
+    /// sorted by client and tx id.
+    pub fn open_disputes(&self) -> Result<Vec<(u16, u32, StoredTx)>, EngineError> {
+        self.with_all_accounts(|accounts| {
+            let mut disputes: Vec<_> = accounts
+                .values()
+                .flat_map(|account| {
+                    account
+                        .transactions()
+                        .filter(|(_, tx)| tx.status == TransactionStatus::Disputed)
+                        .map(|(tx_id, tx)| (account.client_id, tx_id, tx.clone()))
+                })
+                .collect();
+            disputes.sort_unstable_by_key(|(client_id, tx_id, _)| (*client_id, *tx_id));
+            disputes
+        })
+    }
+
+    /// Returns the latest timestamp of the transactions applied so far, if any has one.
+    pub fn latest_timestamp(&self) -> Option<u64> {
+        self.latest_timestamp
+    }
+
+    /// Returns the transactions applied while processing sources, if collected (see
+    /// [`EngineBuilder::collect_ledger`]).
+    pub fn ledger(&self) -> &[LedgerEntry] {
+        self.ledger.as_deref().unwrap_or_default()
+    }
+
+    /// Returns the end-of-day balances of the accounts, if collected (see
+    /// [`EngineBuilder::collect_balance_history`]).
+    pub fn balance_history(&self) -> Option<&BalanceHistory> {
+        self.balance_history.as_ref()
+    }
+
+    /// Consumes the engine, returning the accounts indexed by client id.
+    pub fn into_accounts(self) -> HashMap<u16, ClientAccount> {
+        self.accounts
+    }
+}
+
+/// Builder to configure a [`PaymentEngine`].
+#[derive(Debug)]
+pub struct EngineBuilder {
+    config: EngineConfig,
+    throttle: WarnThrottle,
+    shards: usize,
+    checkpointer: Option<Checkpointer>,
+    collect_rejects: bool,
+    collect_ledger: bool,
+    collect_balance_history: bool,
+    spill: Option<Spill>,
+    skip_invalid: bool,
+    strict: bool,
+    sort_by: SortBy,
+    window: TimeWindow,
+    clients: ClientFilter,
+    types: TypeFilter,
+    events: Option<mpsc::UnboundedSender<AccountEvent>>,
+    interrupt: Option<watch::Receiver<bool>>,
+    hooks: Option<Box<dyn TransactionHooks>>,
+    finalizers: Vec<Box<dyn Finalizer>>,
+    account_stream: Option<mpsc::Sender<ClientAccount>>,
+    max_pending_records: Option<usize>,
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        Self {
+            config: EngineConfig::default(),
+            throttle: WarnThrottle::default(),
+            shards: 1,
+            checkpointer: None,
+            collect_rejects: false,
+            collect_ledger: false,
+            collect_balance_history: false,
+            spill: None,
+            skip_invalid: false,
+            strict: false,
+            sort_by: SortBy::default(),
+            window: TimeWindow::default(),
+            clients: ClientFilter::default(),
+            types: TypeFilter::default(),
+            events: None,
+            interrupt: None,
+            hooks: None,
+            finalizers: Vec::new(),
+            account_stream: None,
+            max_pending_records: None,
+        }
+    }
+}
+
+impl EngineBuilder {
+    /// Sets whether withdrawals can be disputed (not allowed by default).
+    pub fn allow_withdrawal_disputes(mut self, allow: bool) -> Self {
+        self.config.allow_withdrawal_disputes = allow;
+        self
+    }
+
+    /// Sets whether administrative operations, e.g. unlocking an account, are applied (not
+    /// allowed by default).
+    pub fn allow_admin_ops(mut self, allow: bool) -> Self {
+        self.config.allow_admin_ops = allow;
+        self
+    }
+
+    /// Sets the precision the amounts of the transactions are rounded to (4 decimal places,
+    /// truncating, by default).
+    pub fn precision(mut self, precision: PrecisionPolicy) -> Self {
+        self.config.precision = precision;
+        self
+    }
+
+    /// Sets how the warnings about rejected transactions are throttled.
+    pub fn throttle(mut self, throttle: WarnThrottle) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// Sets the number of tasks processing transactions concurrently. Accounts are partitioned
+    /// over the tasks by `client_id % shards`, so that transactions of the same client are still
+    /// applied in order, see [`RecordOrdering::PerClient`](super::RecordOrdering). With sharding,
+    /// the warning limits of the throttle apply to each task.
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.shards = shards.max(1);
+        self
+    }
+
+    /// Saves a [`Checkpoint`] to `path` every `interval` records processed, removing it once
+    /// all the sources have been processed. Checkpoints are saved only without sharding.
+    pub fn checkpoint(mut self, path: impl Into<PathBuf>, interval: u64) -> Self {
+        self.checkpointer = Some(Checkpointer::new(path.into(), interval));
+        self
+    }
+
+    /// Sets whether the transactions rejected while processing sources are collected, together
+    /// with the reason, besides being logged. With sharding, they keep their order only per client.
+    pub fn collect_rejects(mut self, collect: bool) -> Self {
+        self.collect_rejects = collect;
+        self
+    }
+
+    /// Sets whether the transactions applied while processing sources are collected in a
+    /// ledger, with the resulting balances. With sharding, they keep their order only per client.
+    pub fn collect_ledger(mut self, collect: bool) -> Self {
+        self.collect_ledger = collect;
+        self
+    }
+
+    /// Sets whether the balances of the accounts at the end of each day are collected while
+    /// applying transactions with timestamps, see [`BalanceHistory`].
+    pub fn collect_balance_history(mut self, collect: bool) -> Self {
+        self.collect_balance_history = collect;
+        self
+    }
+
+    /// Keeps about `max_bytes` of transactions history in memory while processing sources,
+    /// spilling the least recently used transactions to a file at `path`, removed when the
+    /// engine is dropped. The budget includes the index of the spilled transactions, but not
+    /// the balances of the accounts. Transactions are spilled only without sharding and
+    /// checkpoints.
+    pub fn spill(mut self, path: impl Into<PathBuf>, max_bytes: usize) -> Self {
+        self.spill = Some(Spill::new(path.into(), max_bytes));
+        self
+    }
+
+    /// Sets whether tx ids have to be unique across clients, rejecting a deposit or withdrawal
+    /// with the tx id of another client's one (allowed by default).
+    pub fn unique_tx_ids(mut self, unique: bool) -> Self {
+        self.config.unique_tx_ids = unique;
+        self
+    }
+
+    /// Sets when the available funds of an account can become negative (never by default).
+    pub fn negative_balance(mut self, policy: NegativeBalancePolicy) -> Self {
+        self.config.negative_balance = policy;
+        self
+    }
+
+    /// Sets how long after a transaction it can be disputed, when both have a timestamp
+    /// (unlimited by default).
+    pub fn dispute_window(mut self, window: Option<Duration>) -> Self {
+        self.config.dispute_window = window;
+        self
+    }
+
+    /// Adds a custom rule transactions have to satisfy to be applied, checked after the
+    /// built-in ones (see [`Validators`](super::Validators)).
+    pub fn validator(mut self, validator: impl Validator + 'static) -> Self {
+        self.config.validators = self.config.validators.with(validator);
+        self
+    }
+
+    /// Sets the fees charged on deposits and withdrawals (none by default).
+    pub fn fees(mut self, fees: FeeSchedule) -> Self {
+        self.config.fees = fees;
+        self
+    }
+
+    /// Sets whether records that can't be deserialized are logged and skipped while processing
+    /// sources, instead of aborting (the default).
+    pub fn skip_invalid(mut self, skip: bool) -> Self {
+        self.skip_invalid = skip;
+        self
+    }
+
+    /// Streams the events of the transactions applied to the accounts, or rejected, to `sender`.
+    pub fn events(mut self, sender: mpsc::UnboundedSender<AccountEvent>) -> Self {
+        self.events = Some(sender);
+        self
+    }
+
+    /// Stops processing sources once `true` is sent through `interrupt`, e.g. on a signal,
+    /// leaving the records not read yet. See [`PaymentEngine::interrupted`].
+    pub fn interrupt(mut self, interrupt: watch::Receiver<bool>) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// Calls `hooks` around every record applied while processing sources, see
+    /// [`TransactionHooks`].
+    pub fn hooks(mut self, hooks: impl TransactionHooks + 'static) -> Self {
+        self.hooks = Some(Box::new(hooks));
+        self
+    }
+
+    /// Runs `finalizer` on the accounts with [`PaymentEngine::finalize`], after the ones already
+    /// registered.
+    pub fn finalizer(mut self, finalizer: impl Finalizer + 'static) -> Self {
+        self.finalizers.push(Box::new(finalizer));
+        self
+    }
+
+    /// Sends the accounts to `sender` once the sources are processed, instead of keeping them
+    /// in the engine, so that they can be written out without being all in memory at once.
+    /// With sharding, each task sends its accounts as soon as it has applied its records. The
+    /// sender is dropped afterwards, and the engine is left without accounts.
+    pub fn stream_accounts(mut self, sender: mpsc::Sender<ClientAccount>) -> Self {
+        self.account_stream = Some(sender);
+        self
+    }
+
+    /// Bounds the records waiting to be applied by the shard tasks to `max` in total, split
+    /// evenly over the shards, instead of 1024 per shard. Reading the sources waits for room in
+    /// the full channels, which is recorded as a backpressure stall with the `metrics` feature.
+    pub fn max_pending_records(mut self, max: usize) -> Self {
+        self.max_pending_records = Some(max);
+        self
+    }
+
+    /// Sets the window of time of the records applied while processing sources: records with a
+    /// timestamp outside of it are counted in
+    /// [`SourceStats::out_of_window`](super::SourceStats::out_of_window) and skipped.
+    pub fn window(mut self, window: TimeWindow) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the clients whose records are applied while processing sources: records of other
+    /// clients are counted in [`SourceStats::filtered`](super::SourceStats::filtered) and
+    /// skipped. Transfers to other clients are still applied.
+    pub fn clients(mut self, clients: ClientFilter) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    /// Sets the types of the records applied while processing sources: records of other types
+    /// are counted in [`SourceStats::filtered`](super::SourceStats::filtered) and skipped.
+    pub fn types(mut self, types: TypeFilter) -> Self {
+        self.types = types;
+        self
+    }
+
+    /// Sets whether processing sources aborts on the first invalid record or rejected
+    /// transaction, returning it as an [`EngineError`]. Takes precedence over
+    /// [`EngineBuilder::skip_invalid`]. Rejected transactions are just logged by default.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets the order of the accounts listed by [`PaymentEngine::sorted_accounts`] (ascending
+    /// client id by default).
+    pub fn sort_by(mut self, sort_by: SortBy) -> Self {
+        self.sort_by = sort_by;
+        self
+    }
+
+    pub fn build(self) -> PaymentEngine {
+        PaymentEngine {
+            config: self.config,
+            throttle: self.throttle,
+            shards: self.shards,
+            checkpointer: self.checkpointer,
+            rejected: self.collect_rejects.then(Vec::new),
+            ledger: self.collect_ledger.then(Vec::new),
+            balance_history: self.collect_balance_history.then(BalanceHistory::default),
+            spill: self.spill,
+            skip_invalid: self.skip_invalid,
+            strict: self.strict,
+            sort_by: self.sort_by,
+            window: self.window,
+            clients: self.clients,
+            types: self.types,
+            events: self.events,
+            interrupt: self.interrupt,
+            hooks: self.hooks,
+            finalizers: self.finalizers,
+            account_stream: self.account_stream,
+            shard_capacity: self
+                .max_pending_records
+                .map_or(SHARD_CHANNEL_CAPACITY, |max| (max / self.shards).max(1)),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod payment_engine_tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::engine::store::MemoryStore;
+
+    #[test]
+    fn test_events() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let mut engine = PaymentEngine::builder().events(sender).build();
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE));
+        engine.apply(deposit).unwrap();
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 1, 2, Some(Decimal::TEN));
+        engine.apply(withdrawal).unwrap_err();
+        engine.close_events();
+
+        assert!(matches!(
+            receiver.blocking_recv(),
+            Some(AccountEvent::FundsDeposited(_))
+        ));
+        assert_eq!(
+            Some(AccountEvent::TxRejected {
+                client: 1,
+                tx: 2,
+                tx_type: TransactionType::Withdrawal,
+                reason: RejectReason::InsufficientFunds,
+            }),
+            receiver.blocking_recv()
+        );
+        assert_eq!(None, receiver.blocking_recv());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut engine = PaymentEngine::new();
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        assert_eq!(Ok(()), engine.apply(deposit));
+
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 1, 2, Some(Decimal::ONE));
+        assert_eq!(Ok(()), engine.apply(withdrawal));
+
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 2, 3, Some(Decimal::ONE));
+        assert_eq!(
+            Err(RejectReason::InsufficientFunds),
+            engine.apply(withdrawal)
+        );
+
+        let account = engine.account(1).unwrap();
+        assert_eq!(Decimal::new(9, 0), account.available);
+        assert_eq!(Decimal::new(9, 0), account.total);
+        assert_eq!(Decimal::ZERO, engine.account(2).unwrap().total);
+        assert!(engine.account(3).is_none());
+        assert_eq!(2, engine.accounts().count());
+    }
+
+    #[test]
+    fn test_check_overflows() {
+        let mut engine = PaymentEngine::new();
+        for (client_id, tx_id) in [(2, 1), (2, 2), (1, 3)] {
+            let deposit = Transaction::new(
+                TransactionType::Deposit,
+                client_id,
+                tx_id,
+                Some(Decimal::MAX),
+            );
+            let _ = engine.apply(deposit);
+        }
+        assert!(matches!(
+            engine.check_overflows(),
+            Err(EngineError::ArithmeticOverflow(clients)) if clients == vec![2]
+        ));
+    }
+
+    #[test]
+    fn test_sorted_accounts() {
+        let deposits = [(3, 1, 2), (1, 2, 5), (2, 3, 2)];
+        let mut engine = PaymentEngine::builder().sort_by(SortBy::Total).build();
+        for (client_id, tx_id, amount) in deposits {
+            let deposit = Transaction::new(
+                TransactionType::Deposit,
+                client_id,
+                tx_id,
+                Some(Decimal::new(amount, 0)),
+            );
+            engine.apply(deposit).unwrap();
+        }
+        let clients: Vec<_> = engine
+            .sorted_accounts()
+            .iter()
+            .map(|acc| acc.client_id)
+            .collect();
+        assert_eq!(vec![2, 3, 1], clients);
+
+        engine.sort_by = SortBy::Client;
+        let clients: Vec<_> = engine
+            .sorted_accounts()
+            .iter()
+            .map(|acc| acc.client_id)
+            .collect();
+        assert_eq!(vec![1, 2, 3], clients);
+    }
+
+    #[test]
+    fn test_restore_from_store() {
+        let mut store = MemoryStore::new();
+        let mut engine = PaymentEngine::new();
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        assert_eq!(Ok(()), engine.apply(deposit));
+        engine.persist(&mut store).unwrap();
+
+        // A later run can dispute the transactions of the previous ones
+        let mut engine = PaymentEngine::new();
+        engine.restore(&mut store).unwrap();
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+        assert_eq!(Ok(()), engine.apply(dispute));
+        let account = engine.account(1).unwrap();
+        assert_eq!(Decimal::ZERO, account.available);
+        assert_eq!(Decimal::TEN, account.held);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let path = std::env::temp_dir().join(format!("tpe_snapshot_{}.json", std::process::id()));
+        let mut engine = PaymentEngine::new();
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        assert_eq!(Ok(()), engine.apply(deposit));
+        engine.save_snapshot(&path).await.unwrap();
+
+        let mut engine = PaymentEngine::new();
+        engine.load_snapshot(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+        assert_eq!(Ok(()), engine.apply(dispute));
+        assert_eq!(Decimal::TEN, engine.account(1).unwrap().held);
+    }
+
+    #[tokio::test]
+    async fn test_load_initial_balances() {
+        let report =
+            "client,currency,available,held,total,locked,status\n1,,10,2,12,false,active\n\
+                      1,EUR,3,0,3,false,active\n2,,5,0,5,true,closed\n";
+        let mut engine = PaymentEngine::new();
+        assert_eq!(
+            3,
+            engine
+                .load_initial_balances(report.as_bytes())
+                .await
+                .unwrap()
+        );
+        let account = engine.account(1).unwrap();
+        assert_eq!(Decimal::new(12, 0), account.total);
+        assert_eq!(Decimal::new(3, 0), account.balances(Some("EUR")).total);
+        assert!(engine
+            .account(2)
+            .is_some_and(|acc| acc.locked && acc.closed));
+        assert!(engine.check_invariants().is_ok());
+
+        // New transactions apply on top of the initial balances
+        let withdrawal = Transaction::new(TransactionType::Withdrawal, 1, 1, Some(Decimal::TEN));
+        assert_eq!(Ok(()), engine.apply(withdrawal));
+        assert_eq!(Decimal::ZERO, engine.account(1).unwrap().available);
+        let deposit = Transaction::new(TransactionType::Deposit, 2, 2, Some(Decimal::TEN));
+        assert!(engine.apply(deposit).is_err());
+
+        let report = "client,available,held,total,locked\n3,1,0,2,false\n";
+        assert!(matches!(
+            engine.load_initial_balances(report.as_bytes()).await,
+            Err(EngineError::InvariantViolated { client_id: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let mut engine = PaymentEngine::new();
+        for tx in [
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN)),
+            Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::ONE)),
+            Transaction::new(TransactionType::Dispute, 1, 1, None),
+            Transaction::new(TransactionType::Dispute, 1, 2, None),
+            Transaction::new(TransactionType::Chargeback, 1, 1, None),
+        ] {
+            engine.apply(tx).unwrap();
+        }
+        assert!(engine.check_invariants().is_ok());
+
+        // The disputes of a locked account can still be settled
+        let resolve = Transaction::new(TransactionType::Resolve, 1, 2, None);
+        engine.apply(resolve).unwrap();
+        assert!(engine.check_invariants().is_ok());
+
+        // Otherwise locked accounts can't change
+        let account = engine.accounts.get_mut(&1).unwrap();
+        account.available += Decimal::ONE;
+        account.total += Decimal::ONE;
+        assert!(matches!(
+            engine.check_invariants(),
+            Err(EngineError::InvariantViolated { client_id: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_reversal_of_locked_account() {
+        let mut engine = PaymentEngine::new();
+        for tx in [
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN)),
+            Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::ONE)),
+            Transaction::new(TransactionType::Dispute, 1, 1, None),
+            Transaction::new(TransactionType::Chargeback, 1, 1, None),
+        ] {
+            engine.apply(tx).unwrap();
+        }
+
+        // The bank can still correct the transactions of a locked account
+        let reversal = Transaction::new(TransactionType::Reversal, 1, 2, None);
+        assert_eq!(Ok(()), engine.apply(reversal));
+        let account = engine.account(1).unwrap();
+        assert!(account.locked);
+        assert_eq!(Decimal::ZERO, account.total);
+        assert!(engine.check_invariants().is_ok());
+    }
+
+    fn transaction() -> impl Strategy<Value = Transaction> {
+        let tx_type = prop_oneof![
+            Just(TransactionType::Deposit),
+            Just(TransactionType::Withdrawal),
+            Just(TransactionType::Dispute),
+            Just(TransactionType::Resolve),
+            Just(TransactionType::Chargeback),
+            Just(TransactionType::Unlock),
+            Just(TransactionType::Close),
+            Just(TransactionType::Reversal),
+        ];
+        (tx_type, 1u16..4, 1u32..30, -100i64..100_000, 0u8..10).prop_map(
+            |(tx_type, client_id, tx_id, amount, missing)| {
+                let amount = (missing > 0).then(|| Decimal::new(amount, 2));
+                Transaction::new(tx_type, client_id, tx_id, amount)
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn test_invariants(
+            txs in prop::collection::vec(transaction(), 1..100),
+            allow_withdrawal_disputes in any::<bool>(),
+            allow_admin_ops in any::<bool>(),
+            negative_balance in prop_oneof![
+                Just(NegativeBalancePolicy::Forbid),
+                Just(NegativeBalancePolicy::AllowOnChargeback),
+                Just(NegativeBalancePolicy::AllowAlways),
+            ],
+        ) {
+            let mut engine = PaymentEngine::builder()
+                .allow_withdrawal_disputes(allow_withdrawal_disputes)
+                .allow_admin_ops(allow_admin_ops)
+                .negative_balance(negative_balance)
+                .build();
+            for tx in txs {
+                let _ = engine.apply(tx);
+                if let Err(e) = engine.check_invariants() {
+                    return Err(TestCaseError::fail(e.to_string()));
+                }
+            }
+        }
+    }
+}
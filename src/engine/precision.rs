@@ -0,0 +1,124 @@
+use std::str::FromStr;
+
+use super::{
+    amount::{Amount, Decimal},
+    reject::RejectReason,
+};
+
+/// How amounts exceeding the scale of a [`PrecisionPolicy`] are rounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Extra digits are dropped, rounding towards zero.
+    #[default]
+    Truncate,
+    /// Rounding to the nearest value, away from zero at the midpoint.
+    HalfUp,
+    /// Rounding to the nearest value, to the even digit at the midpoint (banker's rounding).
+    HalfEven,
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "truncate" => Ok(Self::Truncate),
+            "half-up" => Ok(Self::HalfUp),
+            "half-even" => Ok(Self::HalfEven),
+            _ => Err(format!("Unknown rounding mode `{s}`")),
+        }
+    }
+}
+
+/// The precision amounts are kept with: the amounts of the transactions are rounded (or
+/// rejected) when applied, and the balances rounded when written to the reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrecisionPolicy {
+    /// Maximum number of decimal places
+    pub scale: u32,
+    pub rounding: Rounding,
+    /// Whether transactions with amounts exceeding the scale are rejected, instead of rounded
+    pub reject_excess: bool,
+}
+
+impl Default for PrecisionPolicy {
+    fn default() -> Self {
+        Self {
+            scale: 4,
+            rounding: Rounding::default(),
+            reject_excess: false,
+        }
+    }
+}
+
+impl PrecisionPolicy {
+    pub fn new(scale: u32, rounding: Rounding) -> Self {
+        Self {
+            scale,
+            rounding,
+            reject_excess: false,
+        }
+    }
+
+    /// Rejects the transactions with amounts exceeding the scale, instead of rounding them.
+    pub fn reject_excess(mut self, reject_excess: bool) -> Self {
+        self.reject_excess = reject_excess;
+        self
+    }
+
+    /// Returns the amount of a transaction as applied: rounded to the scale of the policy, or
+    /// rejected if it exceeds it and the policy rejects such amounts.
+    pub fn check(&self, amount: Decimal) -> Result<Decimal, RejectReason> {
+        if self.reject_excess && amount.normalize().scale() > self.scale {
+            return Err(RejectReason::ExcessPrecision);
+        }
+        Ok(self.apply(amount))
+    }
+
+    /// Rounds `amount` to the scale of the policy, if it exceeds it.
+    pub fn apply(&self, amount: Decimal) -> Decimal {
+        amount.round_to(self.scale, self.rounding)
+    }
+}
+
+#[cfg(test)]
+mod precision_tests {
+    use super::*;
+
+    #[test]
+    fn test_rounding() {
+        let amount = Decimal::new(-123455, 5);
+        let rounded = |rounding| PrecisionPolicy::new(4, rounding).apply(amount);
+        assert_eq!(Decimal::new(-12345, 4), rounded(Rounding::Truncate));
+        assert_eq!(Decimal::new(-12346, 4), rounded(Rounding::HalfUp));
+        assert_eq!(Decimal::new(-12346, 4), rounded(Rounding::HalfEven));
+        assert_eq!(
+            Decimal::new(12344, 4),
+            PrecisionPolicy::new(4, Rounding::HalfEven).apply(Decimal::new(123445, 5))
+        );
+
+        // Amounts within the scale are left untouched
+        assert_eq!(
+            Decimal::new(15, 1),
+            PrecisionPolicy::default().apply(Decimal::new(15, 1))
+        );
+    }
+
+    #[test]
+    fn test_reject_excess() {
+        let policy = PrecisionPolicy::default().reject_excess(true);
+        assert_eq!(
+            Err(RejectReason::ExcessPrecision),
+            policy.check(Decimal::new(123455, 5))
+        );
+        // Trailing zeros don't count
+        assert_eq!(
+            Ok(Decimal::new(15, 1)),
+            policy.check(Decimal::new(150000, 5))
+        );
+        assert_eq!(
+            Ok(Decimal::new(12345, 4)),
+            PrecisionPolicy::default().check(Decimal::new(123455, 5))
+        );
+    }
+}
@@ -1,46 +1,684 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    time::SystemTime,
+};
 
-use csv_async::{AsyncReaderBuilder, Trim};
-use tokio::io;
+use tokio::{
+    io,
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
+};
 use tokio_stream::StreamExt;
+use tracing::{debug_span, warn};
 
 use super::{
+    amount::Decimal,
+    config::HeldFundsPolicy,
     error::EngineError,
-    model::{ClientAccount, Transaction},
+    history::BalanceHistory,
+    hooks::{Decision, TransactionHooks},
+    ledger::LedgerEntry,
+    metrics,
+    model::{ClientAccount, Transaction, TransactionType},
+    payment_engine::PaymentEngine,
+    reject::{RejectReason, RejectedTransaction},
+    source::{
+        CsvSource, MergePolicy, MergedSource, SourceStats, TransactionSource, TransactionStream,
+    },
+    summary::Summary,
 };
 
 pub async fn process_transactions<AR: io::AsyncRead + Send + Unpin>(
     rdr: AR,
 ) -> Result<HashMap<u16, ClientAccount>, EngineError> {
-    // Read and deserialize data
-    let reader = AsyncReaderBuilder::new()
-        .trim(Trim::All)
-        .flexible(true)
-        .create_deserializer(rdr);
-    let mut iter = reader.into_deserialize::<Transaction>();
-
-    // Handle transaction records
-    let mut accounts: HashMap<u16, ClientAccount> = HashMap::new();
-    while let Some(record) = iter.try_next().await? {
-        if let Some(account) = accounts.get_mut(&record.client_id) {
-            // If we already have an existing account, then we have to handle the transaction record
-            account.update(record);
+    let mut engine = PaymentEngine::new();
+    engine.process(CsvSource::new(rdr)).await?;
+    Ok(engine.into_accounts())
+}
+
+/// Processes transaction records in CSV format like [`process_transactions`], calling `hooks`
+/// around every record applied, see [`TransactionHooks`].
+pub async fn process_transactions_with_hooks<AR, H>(
+    rdr: AR,
+    hooks: H,
+) -> Result<HashMap<u16, ClientAccount>, EngineError>
+where
+    AR: io::AsyncRead + Send + Unpin,
+    H: TransactionHooks + 'static,
+{
+    let mut engine = PaymentEngine::builder().hooks(hooks).build();
+    engine.process(CsvSource::new(rdr)).await?;
+    Ok(engine.into_accounts())
+}
+
+/// Processes transaction records in CSV format from a byte slice, synchronously: a single
+/// threaded runtime is started for the processing, so it must not be called from an async
+/// context. Meant for fuzzing the parser and the engine, e.g. with `cargo fuzz`.
+pub fn process_transactions_bytes(data: &[u8]) -> Result<HashMap<u16, ClientAccount>, EngineError> {
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    runtime.block_on(process_transactions(data))
+}
+
+/// Capacity of the channels feeding the shard tasks, by default
+pub(super) const SHARD_CHANNEL_CAPACITY: usize = 1024;
+
+// Messages sent to a shard task
+enum ShardMessage {
+    // A record to apply to the accounts of the shard
+    Record(Transaction),
+    // Takes the account of a client out of the shard, creating it if needed
+    Take(u16, oneshot::Sender<ClientAccount>),
+    // Puts back an account taken out of the shard
+    Put(ClientAccount),
+    // A transfer to the account of a client of another shard, sent back once applied
+    Transfer(Transaction, ClientAccount, oneshot::Sender<ClientAccount>),
+}
+
+impl PaymentEngine {
+    /// Processes the transaction records of `source`, whatever their format is.
+    pub async fn process<'a, S: TransactionSource<'a>>(
+        &mut self,
+        source: S,
+    ) -> Result<SourceStats, EngineError> {
+        let mut stats = self
+            .process_sources(vec![source.into_stream()], MergePolicy::default())
+            .await?;
+        Ok(stats.remove(0))
+    }
+
+    /// Processes several sources simultaneously into the same accounts state, merging their
+    /// records according to `policy`.
+    ///
+    /// Every applied transaction is attributed to the source it comes from, and statistics are
+    /// collected per source (returned in the same order as `sources`).
+    pub async fn process_sources(
+        &mut self,
+        sources: Vec<TransactionStream<'_>>,
+        policy: MergePolicy,
+    ) -> Result<Vec<SourceStats>, EngineError> {
+        let mut stats = vec![SourceStats::default(); sources.len()];
+        let mut records = MergedSource::new(sources, policy);
+        if let Some(interrupt) = &self.interrupt {
+            let mut interrupt = interrupt.clone();
+            records = records.until(async move {
+                // The sender being dropped doesn't interrupt the processing
+                if interrupt
+                    .wait_for(|interrupted| *interrupted)
+                    .await
+                    .is_err()
+                {
+                    std::future::pending::<()>().await;
+                }
+            });
+        }
+
+        // Hooks are called in the order of the records
+        let mut outcome = if self.shards > 1 && self.hooks.is_none() {
+            self.process_sharded(&mut records, &mut stats).await
         } else {
-            // Otherwise we need to create a new account and store the transaction
-            let mut new_account = ClientAccount::new(record.client_id);
-            new_account.update(record);
-            accounts.insert(new_account.client_id, new_account);
+            self.process_sequential(&mut records, &mut stats).await
+        };
+        self.interrupted = records.interrupted();
+        if let (Ok(_), Some(checkpointer)) = (&outcome, &self.checkpointer) {
+            // An interrupted run can be resumed from where it stopped
+            outcome = if self.interrupted {
+                checkpointer.save(&self.accounts).await
+            } else {
+                checkpointer.finish().await
+            };
+        }
+        if let (Ok(_), Some(stream)) = (&outcome, self.account_stream.take()) {
+            // The accounts of the shards have already been sent
+            for (_, account) in self.accounts.drain() {
+                if stream.send(account).await.is_err() {
+                    break;
+                }
+            }
+        }
+        self.throttle.log_summary();
+
+        outcome.map(|_| stats)
+    }
+
+    async fn process_sequential(
+        &mut self,
+        records: &mut MergedSource<'_>,
+        stats: &mut [SourceStats],
+    ) -> Result<(), EngineError> {
+        let mut pos = 0;
+        while let Some((source, record)) = records.next().await {
+            stats[source].record_read(pos);
+            pos += 1;
+
+            let mut record = match record {
+                Ok(record) => record,
+                Err(e) if self.skip_invalid && !self.strict && e.is_invalid_record() => {
+                    warn!(source, error = %e, "Skipping invalid record");
+                    stats[source].invalid += 1;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            if !self.window.contains(record.timestamp) {
+                stats[source].out_of_window += 1;
+                continue;
+            }
+            if !self.clients.contains(record.client_id) || !self.types.contains(record.tx_type) {
+                stats[source].filtered += 1;
+                continue;
+            }
+            match self.hooks.as_mut().map(|hooks| hooks.before_apply(&record)) {
+                Some(Decision::Skip) => {
+                    stats[source].skipped += 1;
+                    continue;
+                }
+                Some(Decision::Replace(tx)) => {
+                    record = Transaction {
+                        offset: record.offset,
+                        ..tx
+                    }
+                }
+                Some(Decision::Apply) | None => {}
+            }
+            record.source = source;
+            let (offset, tx_type, client_id, tx_id) = (
+                record.offset,
+                record.tx_type,
+                record.client_id,
+                record.tx_id,
+            );
+            // The referenced transaction has to be in memory to apply the record
+            if let Some(spill) = &mut self.spill {
+                spill.reload(&mut self.accounts, client_id, tx_id)?;
+            }
+            let copy = self.hooks.is_some().then(|| record.clone());
+            let applied = self.apply_record(record, false)?;
+            if let (true, Some(hooks), Some(tx)) = (applied, &mut self.hooks, copy) {
+                hooks.after_apply(&tx, &self.accounts[&client_id]);
+            }
+            if applied {
+                stats[source].applied += 1;
+            } else {
+                stats[source].rejected += 1;
+            }
+
+            if let Some(spill) = &mut self.spill {
+                if applied && tx_type.is_registered() {
+                    spill.track(client_id, tx_id);
+                }
+                spill.evict(&mut self.accounts)?;
+            }
+
+            if let Some(checkpointer) = &mut self.checkpointer {
+                checkpointer
+                    .record_processed(source, offset, &self.accounts)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Credits interest at `bps` basis points of the available funds of every account neither
+    /// locked nor closed, in every currency, once the sources are processed. Interest is
+    /// applied as synthetic [`TransactionType::Interest`] transactions, collected in the ledger
+    /// like the other ones, with distinct tx ids above the highest one seen so far. Returns the
+    /// number of transactions applied.
+    pub fn accrue_interest(&mut self, bps: u32) -> Result<u64, EngineError> {
+        let rate = Decimal::from(bps) / Decimal::from(10_000);
+        // The accounts may have been restored with the transactions of previous runs
+        let highest = self
+            .accounts()
+            .flat_map(|account| account.transactions().map(|(tx_id, _)| tx_id))
+            .max()
+            .max(self.max_tx_id);
+        let mut next_tx_id = highest.map_or(Some(0), |tx_id| tx_id.checked_add(1));
+        let mut txs = Vec::new();
+        for account in self.sorted_accounts() {
+            if account.locked || account.closed || account.error {
+                continue;
+            }
+            let currencies = std::iter::once(None).chain(account.currencies.keys().map(Some));
+            for currency in currencies {
+                let available = account.balances(currency.map(String::as_str)).available;
+                let amount = available
+                    .checked_mul(rate)
+                    .map(|amount| self.config.precision.apply(amount));
+                if let Some(amount) = amount.filter(|amount| *amount > Decimal::ZERO) {
+                    let Some(tx_id) = next_tx_id else {
+                        warn!(client_id = account.client_id, "No tx id left for interest");
+                        continue;
+                    };
+                    next_tx_id = tx_id.checked_add(1);
+                    let mut tx = Transaction::new(
+                        TransactionType::Interest,
+                        account.client_id,
+                        tx_id,
+                        Some(amount),
+                    );
+                    tx.currency = currency.cloned();
+                    txs.push(tx);
+                }
+            }
+        }
+
+        let mut applied = 0;
+        for tx in txs {
+            if self.apply_final(tx)? {
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Settles the disputes still open on the locked accounts according to `policy`, once the
+    /// sources are processed, so that their funds don't stay held forever. The disputes are
+    /// settled by synthetic [`TransactionType::Resolve`] or [`TransactionType::Chargeback`]
+    /// transactions, in order of client and tx id, collected in the ledger like the other ones.
+    /// Settling disputes changes the balances of the locked accounts, which is still allowed by
+    /// [`PaymentEngine::check_invariants`]. Returns the number of transactions applied.
+    pub fn release_held_funds(&mut self, policy: HeldFundsPolicy) -> Result<u64, EngineError> {
+        let tx_type = match policy {
+            HeldFundsPolicy::Keep => return Ok(0),
+            HeldFundsPolicy::Resolve => TransactionType::Resolve,
+            HeldFundsPolicy::Chargeback => TransactionType::Chargeback,
+        };
+        let disputes: Vec<_> = self
+            .open_disputes()?
+            .into_iter()
+            .filter(|(client_id, ..)| self.account(*client_id).is_some_and(|acc| acc.locked))
+            .map(|(client_id, tx_id, _)| (client_id, tx_id))
+            .collect();
+
+        let mut applied = 0;
+        for (client_id, tx_id) in disputes {
+            if self.apply_final(Transaction::new(tx_type, client_id, tx_id, None))? {
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    // Dispatches the records over `self.shards` tasks, each one owning the partition of the
+    // accounts with `client_id % shards` equal to its index.
+    async fn process_sharded(
+        &mut self,
+        records: &mut MergedSource<'_>,
+        stats: &mut [SourceStats],
+    ) -> Result<(), EngineError> {
+        let shards = self.shards;
+        let sources = stats.len();
+
+        let mut senders = Vec::with_capacity(shards);
+        let mut handles = Vec::with_capacity(shards);
+        // Move the current accounts to the shard owning them
+        for mut shard in self.split_shards(shards) {
+            let (sender, mut receiver) = mpsc::channel::<ShardMessage>(self.shard_capacity);
+            let stream = self.account_stream.clone();
+            handles.push(tokio::spawn(async move {
+                // Applied and rejected records per source
+                let mut counts = vec![(0, 0); sources];
+                while let Some(message) = receiver.recv().await {
+                    let (record, counterparty) = match message {
+                        ShardMessage::Record(record) => (record, None),
+                        ShardMessage::Take(client_id, reply) => {
+                            let account = shard.accounts.remove(&client_id);
+                            let _ = reply
+                                .send(account.unwrap_or_else(|| ClientAccount::new(client_id)));
+                            continue;
+                        }
+                        ShardMessage::Put(account) => {
+                            shard.accounts.insert(account.client_id, account);
+                            continue;
+                        }
+                        ShardMessage::Transfer(record, account, reply) => {
+                            let client_id = account.client_id;
+                            shard.accounts.insert(client_id, account);
+                            (record, Some((client_id, reply)))
+                        }
+                    };
+                    let source = record.source;
+                    let outcome = shard.apply_record(record, false);
+                    if let Some((client_id, reply)) = counterparty {
+                        if let Some(account) = shard.accounts.remove(&client_id) {
+                            let _ = reply.send(account);
+                        }
+                    }
+                    match outcome {
+                        Ok(true) => counts[source].0 += 1,
+                        Ok(false) => counts[source].1 += 1,
+                        // Dropping the receiver stops the dispatching of the records
+                        Err(e) => return (shard, counts, Err(e)),
+                    }
+                }
+                if let Some(stream) = stream {
+                    for (_, account) in shard.accounts.drain() {
+                        if stream.send(account).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+                (shard, counts, Ok(()))
+            }));
+            senders.push(sender);
+        }
+
+        let mut outcome = Ok(());
+        let mut pos = 0;
+        while let Some((source, record)) = records.next().await {
+            stats[source].record_read(pos);
+            pos += 1;
+
+            let mut record = match record {
+                Ok(record) => record,
+                Err(e) if self.skip_invalid && !self.strict && e.is_invalid_record() => {
+                    warn!(source, error = %e, "Skipping invalid record");
+                    stats[source].invalid += 1;
+                    continue;
+                }
+                Err(e) => {
+                    outcome = Err(e);
+                    break;
+                }
+            };
+            if !self.window.contains(record.timestamp) {
+                stats[source].out_of_window += 1;
+                continue;
+            }
+            if !self.clients.contains(record.client_id) || !self.types.contains(record.tx_type) {
+                stats[source].filtered += 1;
+                continue;
+            }
+            record.source = source;
+            // Tx ids are checked here, following the order of the records across all the shards
+            if let Err(reason) = self.check_tx_id(&record) {
+                let ids = (record.tx_type, record.client_id, record.tx_id);
+                if let Err(e) = self.reject_record(ids, Some(record), reason) {
+                    outcome = Err(e);
+                    break;
+                }
+                stats[source].rejected += 1;
+                continue;
+            }
+            let shard = record.client_id as usize % shards;
+            let sent = match record.counterparty {
+                Some(counterparty)
+                    if record.tx_type == TransactionType::Transfer
+                        && counterparty as usize % shards != shard =>
+                {
+                    let other = counterparty as usize % shards;
+                    Self::transfer_across(&senders[shard], &senders[other], record, counterparty)
+                        .await
+                }
+                _ => Self::send_to_shard(&senders[shard], ShardMessage::Record(record)).await,
+            };
+            if !sent {
+                // A shard task stopped early, the cause is reported when joining it
+                break;
+            }
+        }
+
+        // Wait for the shards to apply the pending records, even in case of errors,
+        // and merge their accounts back
+        drop(senders);
+        for handle in handles {
+            let (shard, counts, shard_outcome) = match handle.await {
+                Ok(result) => result,
+                Err(e) => std::panic::resume_unwind(e.into_panic()),
+            };
+            if outcome.is_ok() {
+                outcome = shard_outcome;
+            }
+            self.merge_shard(shard);
+            for (source_stats, (applied, rejected)) in stats.iter_mut().zip(counts) {
+                source_stats.applied += applied;
+                source_stats.rejected += rejected;
+            }
+        }
+
+        outcome
+    }
+
+    // Moves the accounts to the engines of `shards` shards, partitioned by `client_id % shards`,
+    // with the same options
+    pub(super) fn split_shards(&mut self, shards: usize) -> Vec<PaymentEngine> {
+        let mut partitions: Vec<HashMap<u16, ClientAccount>> =
+            (0..shards).map(|_| HashMap::new()).collect();
+        for (client_id, account) in self.accounts.drain() {
+            partitions[client_id as usize % shards].insert(client_id, account);
+        }
+        let mut key_partitions: Vec<HashMap<u16, HashSet<String>>> =
+            (0..shards).map(|_| HashMap::new()).collect();
+        for (client_id, keys) in self.idempotency_keys.drain() {
+            key_partitions[client_id as usize % shards].insert(client_id, keys);
+        }
+
+        partitions
+            .into_iter()
+            .zip(key_partitions)
+            .map(|(accounts, idempotency_keys)| self.shard(accounts, idempotency_keys))
+            .collect()
+    }
+
+    // Creates the engine of a shard owning the given accounts, with the same options
+    fn shard(
+        &self,
+        accounts: HashMap<u16, ClientAccount>,
+        idempotency_keys: HashMap<u16, HashSet<String>>,
+    ) -> PaymentEngine {
+        PaymentEngine {
+            accounts,
+            config: self.config.clone(),
+            throttle: self.throttle.fork(),
+            shards: 1,
+            checkpointer: None,
+            rejected: self.rejected.as_ref().map(|_| Vec::new()),
+            ledger: self.ledger.as_ref().map(|_| Vec::new()),
+            balance_history: self
+                .balance_history
+                .as_ref()
+                .map(|_| BalanceHistory::default()),
+            spill: None,
+            tx_clients: None,
+            skip_invalid: self.skip_invalid,
+            strict: self.strict,
+            sort_by: self.sort_by,
+            summary: Summary::default(),
+            idempotency_keys,
+            window: self.window,
+            clients: self.clients.clone(),
+            types: self.types.clone(),
+            events: self.events.clone(),
+            locked_balances: HashMap::new(),
+            interrupt: None,
+            interrupted: false,
+            hooks: None,
+            finalizers: Vec::new(),
+            account_stream: None,
+            latest_timestamp: None,
+            max_tx_id: None,
+            shard_capacity: self.shard_capacity,
+            initial_balances: self.initial_balances,
         }
     }
 
-    Ok(accounts)
+    // Merges back the accounts of a shard, with what it collected
+    pub(super) fn merge_shard(&mut self, shard: PaymentEngine) {
+        self.accounts.extend(shard.accounts);
+        self.throttle.merge(shard.throttle);
+        self.summary.merge(shard.summary);
+        self.idempotency_keys.extend(shard.idempotency_keys);
+        self.locked_balances.extend(shard.locked_balances);
+        self.latest_timestamp = self.latest_timestamp.max(shard.latest_timestamp);
+        self.max_tx_id = self.max_tx_id.max(shard.max_tx_id);
+        if let (Some(rejected), Some(shard_rejected)) = (&mut self.rejected, shard.rejected) {
+            rejected.extend(shard_rejected);
+        }
+        if let (Some(ledger), Some(shard_ledger)) = (&mut self.ledger, shard.ledger) {
+            ledger.extend(shard_ledger);
+        }
+        if let (Some(history), Some(shard_history)) =
+            (&mut self.balance_history, shard.balance_history)
+        {
+            history.merge(shard_history);
+        }
+    }
+
+    // Sends `message` to a shard, waiting for room in its channel if full. Returns whether the
+    // shard is still running.
+    async fn send_to_shard(sender: &mpsc::Sender<ShardMessage>, message: ShardMessage) -> bool {
+        match sender.try_send(message) {
+            Ok(()) => true,
+            Err(TrySendError::Full(message)) => {
+                metrics::record_stall("shard");
+                sender.send(message).await.is_ok()
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    // Applies a transfer whose counterparty is in another shard: its account is moved to the
+    // shard of the client for the time of the transfer. Returns whether the shards are still
+    // running.
+    async fn transfer_across(
+        shard: &mpsc::Sender<ShardMessage>,
+        other: &mpsc::Sender<ShardMessage>,
+        record: Transaction,
+        counterparty: u16,
+    ) -> bool {
+        let (reply, account) = oneshot::channel();
+        if other
+            .send(ShardMessage::Take(counterparty, reply))
+            .await
+            .is_err()
+        {
+            return false;
+        }
+        let Ok(account) = account.await else {
+            return false;
+        };
+        let (reply, returned) = oneshot::channel();
+        let account = match shard
+            .send(ShardMessage::Transfer(record, account, reply))
+            .await
+        {
+            Ok(()) => match returned.await {
+                Ok(account) => account,
+                Err(_) => return false,
+            },
+            // Still put the account back, for the other shard to return it
+            Err(mpsc::error::SendError(ShardMessage::Transfer(_, account, _))) => {
+                let _ = other.send(ShardMessage::Put(account)).await;
+                return false;
+            }
+            Err(_) => return false,
+        };
+        other.send(ShardMessage::Put(account)).await.is_ok()
+    }
+
+    /// Applies a transaction once the sources are processed, e.g. by a
+    /// [`Finalizer`](super::Finalizer), like a record read from them: collected in the ledger, or
+    /// in the rejected transactions and aborting the processing in strict mode. Unlike the records, it can be of a type reserved
+    /// to the engine, i.e. interest. Returns whether it has been applied.
+    pub fn apply_final(&mut self, tx: Transaction) -> Result<bool, EngineError> {
+        let (tx_type, client_id, tx_id) = (tx.tx_type, tx.client_id, tx.tx_id);
+        // The referenced transaction has to be in memory to apply the record
+        if let Some(spill) = &mut self.spill {
+            spill.reload(&mut self.accounts, client_id, tx_id)?;
+        }
+        let applied = self.apply_record(tx, true)?;
+        if let Some(spill) = &mut self.spill {
+            if applied && tx_type.is_registered() {
+                spill.track(client_id, tx_id);
+            }
+            spill.evict(&mut self.accounts)?;
+        }
+        Ok(applied)
+    }
+
+    // Applies a record read from a source, or generated by the engine if `synthetic`, logging
+    // the reason if it gets rejected. Returns whether it has been applied, or the error aborting
+    // the processing in strict mode.
+    fn apply_record(&mut self, record: Transaction, synthetic: bool) -> Result<bool, EngineError> {
+        let _span = debug_span!("update", client_id = record.client_id).entered();
+        let ids = (record.tx_type, record.client_id, record.tx_id);
+        // The record is consumed by the engine, keep a copy in case it has to be collected
+        let copy = (self.rejected.is_some() || self.ledger.is_some() || self.strict)
+            .then(|| record.clone());
+        let Err(reason) = self.apply_tx(record, synthetic) else {
+            if let (Some(ledger), Some(tx)) = (&mut self.ledger, copy) {
+                let account = &self.accounts[&tx.client_id];
+                ledger.push(LedgerEntry {
+                    processed_at: SystemTime::now(),
+                    balances: account.balances(tx.currency.as_deref()),
+                    locked: account.locked,
+                    tx,
+                    synthetic,
+                });
+            }
+            return Ok(true);
+        };
+        self.reject_record(ids, copy, reason)?;
+        Ok(false)
+    }
+
+    // Collects and logs a record rejected with `reason`, given its type, client and tx ids, and
+    // the record itself if kept. Returns the error aborting the processing in strict mode.
+    fn reject_record(
+        &mut self,
+        (tx_type, client_id, tx_id): (TransactionType, u16, u32),
+        record: Option<Transaction>,
+        reason: RejectReason,
+    ) -> Result<(), EngineError> {
+        if let (true, Some(tx)) = (self.strict, record.clone()) {
+            return Err(RejectedTransaction { tx, reason }.into());
+        }
+        if let (Some(rejected), Some(tx)) = (&mut self.rejected, record) {
+            rejected.push(RejectedTransaction { tx, reason });
+        }
+        self.throttle.warn(
+            reason,
+            client_id,
+            tx_id,
+            format_args!(
+                "Unable to process {tx_type:?} tx with id {tx_id:?} for account #{client_id:?}: {reason}"
+            ),
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod processor_tests {
+    use std::collections::BTreeMap;
+
     use super::*;
-    use rust_decimal::Decimal;
-    use tokio::{fs::File, io::BufReader};
+    use crate::engine::{
+        model::TransactionStatus,
+        payment_engine::EngineBuilder,
+        source::{JsonLinesSource, TimeWindow},
+    };
+    use tokio::{fs::File, io::BufReader, sync::watch};
+
+    // Processes the CSV `data` with one and two shards, by engines with the options set by
+    // `configure`, returning them with their stats
+    async fn process_in_shards(
+        data: &str,
+        configure: impl Fn(EngineBuilder) -> EngineBuilder,
+    ) -> Vec<(PaymentEngine, SourceStats)> {
+        let mut processed = Vec::new();
+        for shards in [1, 2] {
+            let mut engine = configure(PaymentEngine::builder().shards(shards)).build();
+            let stats = engine
+                .process(CsvSource::new(data.as_bytes()))
+                .await
+                .unwrap();
+            processed.push((engine, stats));
+        }
+        processed
+    }
 
     #[tokio::test]
     async fn test_success_resolve() {
@@ -101,4 +739,644 @@ mod processor_tests {
         assert_eq!(Decimal::new(5, 0), account.total);
         assert!(!account.locked);
     }
+
+    #[tokio::test]
+    async fn test_process_sources() {
+        let live = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\nwithdrawal,1,4,0.5";
+        let catch_up = r#"{"type": "deposit", "client": 1, "tx": 3, "amount": 2.0}
+{"type": "dispute", "client": 1, "tx": 3}"#;
+        let sources = vec![
+            CsvSource::new(live.as_bytes()).into_stream(),
+            JsonLinesSource::new(catch_up.as_bytes()).into_stream(),
+        ];
+
+        let mut engine = PaymentEngine::new();
+        let stats = engine
+            .process_sources(sources, MergePolicy::Priority)
+            .await
+            .unwrap();
+        let account = engine.account(1).unwrap();
+        assert_eq!(0, account.transaction(1).unwrap().source);
+        assert_eq!(1, account.transaction(3).unwrap().source);
+        assert_eq!(3, stats[0].applied);
+        assert_eq!(2, stats[1].rows);
+        assert_eq!(3, stats[1].max_lag);
+        assert_eq!(Decimal::new(5, 1), account.available);
+        assert_eq!(Decimal::new(2, 0), account.held);
+        assert_eq!(Decimal::new(25, 1), account.total);
+        assert_eq!(Decimal::new(2, 0), engine.account(2).unwrap().total);
+    }
+
+    #[tokio::test]
+    async fn test_sharded_matches_sequential() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,3,3,2.0\nwithdrawal,1,4,1.5\ndeposit,1,5,3.0\ndispute,2,2,\nchargeback,2,2,\ndeposit,2,6,1.0\nwithdrawal,3,7,0.5\ndispute,1,5,";
+
+        let mut sequential = PaymentEngine::new();
+        sequential
+            .process(CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+
+        // Also with a single record pending for all the shards, waiting for each to be applied
+        for max_pending in [None, Some(1)] {
+            let mut builder = PaymentEngine::builder().shards(2);
+            if let Some(max) = max_pending {
+                builder = builder.max_pending_records(max);
+            }
+            let mut sharded = builder.build();
+            let stats = sharded
+                .process(CsvSource::new(data.as_bytes()))
+                .await
+                .unwrap();
+            assert_eq!(10, stats.rows);
+            assert_eq!(8, stats.applied);
+            assert_eq!(2, stats.rejected);
+
+            assert_eq!(3, sharded.accounts().count());
+            for account in sequential.accounts() {
+                let sharded_account = sharded.account(account.client_id).unwrap();
+                assert_eq!(account.available, sharded_account.available);
+                assert_eq!(account.held, sharded_account.held);
+                assert_eq!(account.total, sharded_account.total);
+                assert_eq!(account.locked, sharded_account.locked);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transfers() {
+        let data = "type,client,tx,amount,counterparty\ndeposit,1,1,5.0,\ntransfer,1,2,3.0,2\ntransfer,2,3,4.0,1\ntransfer,1,4,1.0,3\ndeposit,2,5,1.0,\ndispute,2,5,,\nchargeback,2,5,,\ntransfer,1,6,1.0,2\ntransfer,3,7,1.0,3";
+
+        // With 2 shards, clients 1 and 3 are in a different shard than client 2
+        for (engine, stats) in
+            process_in_shards(data, |builder| builder.collect_rejects(true)).await
+        {
+            assert_eq!(6, stats.applied);
+            let mut reasons: Vec<_> = engine
+                .rejected()
+                .iter()
+                .map(|rejected| (rejected.tx.tx_id, rejected.reason))
+                .collect();
+            reasons.sort();
+            assert_eq!(
+                vec![
+                    (3, RejectReason::InsufficientFunds),
+                    (6, RejectReason::AccountLocked),
+                    (7, RejectReason::InvalidCounterparty)
+                ],
+                reasons
+            );
+            assert_eq!(Decimal::ONE, engine.account(1).unwrap().total);
+            assert_eq!(Decimal::new(3, 0), engine.account(2).unwrap().total);
+            assert!(engine.account(2).unwrap().locked);
+            assert_eq!(Decimal::ONE, engine.account(3).unwrap().available);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_accrue_interest() {
+        let data = "type,client,tx,amount,currency\ndeposit,1,1,100.0,\ndeposit,1,2,10.0,EUR\ndeposit,2,3,0.01,\ndeposit,3,4,5.0,\ndispute,3,4,,\nchargeback,3,4,,";
+
+        let mut engine = PaymentEngine::builder().collect_ledger(true).build();
+        engine
+            .process(CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+        // The interest of client 2 is truncated to zero, client 3 is locked
+        assert_eq!(2, engine.accrue_interest(25).unwrap());
+
+        let account = engine.account(1).unwrap();
+        assert_eq!(Decimal::new(10025, 2), account.available);
+        assert_eq!(Decimal::new(10025, 3), account.balances(Some("EUR")).total);
+        assert_eq!(Decimal::new(1, 2), engine.account(2).unwrap().total);
+        let interest: Vec<_> = engine
+            .ledger()
+            .iter()
+            .filter(|entry| entry.tx.tx_type == TransactionType::Interest)
+            .map(|entry| (entry.tx.tx_id, entry.balances.total))
+            .collect();
+        // The tx ids follow the highest one of the records
+        assert_eq!(
+            vec![(5, Decimal::new(10025, 2)), (6, Decimal::new(10025, 3))],
+            interest
+        );
+
+        assert_eq!(2, engine.accrue_interest(25).unwrap());
+        let tx_ids: Vec<_> = engine
+            .ledger()
+            .iter()
+            .filter(|entry| entry.tx.tx_type == TransactionType::Interest)
+            .map(|entry| entry.tx.tx_id)
+            .collect();
+        assert_eq!(vec![5, 6, 7, 8], tx_ids);
+    }
+
+    #[tokio::test]
+    async fn test_interest_records_rejected() {
+        let data = "type,client,tx,amount\ndeposit,1,1,100.0\ninterest,1,2,50.0\ninterest,1,2,50.0";
+
+        let mut engine = PaymentEngine::builder().collect_rejects(true).build();
+        let stats = engine
+            .process(CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+        assert_eq!(2, stats.rejected);
+        assert!(engine
+            .rejected()
+            .iter()
+            .all(|rejected| rejected.reason == RejectReason::ReservedType));
+        let interest = Transaction::new(TransactionType::Interest, 1, 3, Some(Decimal::ONE));
+        assert_eq!(Err(RejectReason::ReservedType), engine.apply(interest));
+        assert_eq!(Decimal::new(100, 0), engine.account(1).unwrap().total);
+
+        // Interest is still credited by the engine
+        assert_eq!(1, engine.accrue_interest(100).unwrap());
+        assert_eq!(Decimal::new(101, 0), engine.account(1).unwrap().total);
+    }
+
+    #[tokio::test]
+    async fn test_release_held_funds() {
+        let data = "type,client,tx,amount\ndeposit,1,1,5.0\ndeposit,1,2,3.0\ndeposit,1,3,2.0\ndispute,1,2,\ndispute,1,3,\ndispute,1,1,\nchargeback,1,1,\ndeposit,2,4,1.0\ndispute,2,4,";
+
+        for (policy, available, held, total) in [
+            (HeldFundsPolicy::Keep, 0, 5, 5),
+            (HeldFundsPolicy::Resolve, 5, 0, 5),
+            (HeldFundsPolicy::Chargeback, 0, 0, 0),
+        ] {
+            let mut engine = PaymentEngine::new();
+            engine
+                .process(CsvSource::new(data.as_bytes()))
+                .await
+                .unwrap();
+            let applied = engine.release_held_funds(policy).unwrap();
+            assert_eq!(
+                if policy == HeldFundsPolicy::Keep {
+                    0
+                } else {
+                    2
+                },
+                applied
+            );
+
+            let account = engine.account(1).unwrap();
+            assert_eq!(
+                [available, held, total].map(|amount| Decimal::new(amount, 0)),
+                [account.available, account.held, account.total]
+            );
+            assert!(engine.check_invariants().is_ok());
+            // Client 2 isn't locked
+            assert_eq!(Decimal::ONE, engine.account(2).unwrap().held);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary() {
+        let data = "type,client,tx,amount,currency\ndeposit,1,1,10.0,\ndeposit,2,2,5.0,EUR\ndeposit,3,3,3.0,\nwithdrawal,3,4,2.5,\nwithdrawal,2,5,6.0,EUR\ndispute,1,1,,\nchargeback,1,1,,\ndeposit,1,6,1.0,";
+
+        for (engine, _) in process_in_shards(data, |builder| builder).await {
+            let summary = engine.summary();
+            assert_eq!(3, summary.clients);
+            assert_eq!(1, summary.locked_accounts);
+            assert_eq!(
+                BTreeMap::from([
+                    (String::new(), Decimal::new(13, 0)),
+                    ("EUR".into(), Decimal::new(5, 0))
+                ]),
+                summary.deposits
+            );
+            assert_eq!(
+                BTreeMap::from([(String::new(), Decimal::new(25, 1))]),
+                summary.withdrawals
+            );
+            assert_eq!((1, 1), (summary.disputes, summary.chargebacks));
+            assert_eq!(
+                BTreeMap::from([("account_locked", 1), ("insufficient_funds", 1)]),
+                summary.rejected
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_by_origin() {
+        let data = "type,client,tx,amount,origin\ndeposit,1,1,10.0,\ndeposit,2,2,5.0,\ndeposit,3,3,3.0,\ndispute,1,1,,client\ndispute,2,2,,bank\ndispute,3,3,,\nchargeback,2,2,,\nchargeback,3,3,,";
+
+        for (engine, _) in process_in_shards(data, |builder| builder).await {
+            let summary = engine.summary();
+            assert_eq!((3, 2), (summary.disputes, summary.chargebacks));
+            assert_eq!(
+                BTreeMap::from([("bank", 1), ("client", 1)]),
+                summary.disputes_by_origin
+            );
+            assert_eq!(BTreeMap::from([("bank", 1)]), summary.chargebacks_by_origin);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_balance_history() {
+        let data = "type,client,tx,amount,currency,timestamp\ndeposit,1,1,10.0,,100\ndeposit,2,2,5.0,EUR,200\nwithdrawal,1,3,2.0,,300\ndeposit,1,4,1.0,,\ndeposit,1,5,1.0,,90000\ndispute,1,5,,,90100\ndeposit,1,6,1.0,,400\ndispute,2,2,,,90200";
+
+        for (engine, _) in
+            process_in_shards(data, |builder| builder.collect_balance_history(true)).await
+        {
+            let history: Vec<_> = engine
+                .balance_history()
+                .unwrap()
+                .iter()
+                .map(|(day, client, currency, balances)| {
+                    (day, client, currency, balances.available, balances.held)
+                })
+                .collect();
+            // The deposit dated on the first day, applied after the second one, is skipped
+            assert_eq!(
+                vec![
+                    (0, 1, "", Decimal::new(8, 0), Decimal::ZERO),
+                    (0, 2, "EUR", Decimal::new(5, 0), Decimal::ZERO),
+                    (86400, 1, "", Decimal::new(9, 0), Decimal::ONE),
+                    // The dispute without a currency holds the EUR of the deposit
+                    (86400, 2, "EUR", Decimal::ZERO, Decimal::new(5, 0)),
+                ],
+                history
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_keys() {
+        let data = "type,client,tx,amount,idempotency_key\ndeposit,1,1,1.0,a\ndeposit,1,2,1.0,a\nwithdrawal,2,3,1.0,b\ndeposit,2,4,2.0,b\ndeposit,2,5,1.0,b\ndeposit,1,6,1.0,b\ndeposit,2,7,1.0,";
+
+        for (engine, stats) in
+            process_in_shards(data, |builder| builder.collect_rejects(true)).await
+        {
+            assert_eq!(4, stats.applied);
+            let mut rejected: Vec<_> = engine
+                .rejected()
+                .iter()
+                .map(|rejected| (rejected.tx.tx_id, rejected.reason))
+                .collect();
+            rejected.sort();
+            assert_eq!(
+                vec![
+                    (2, RejectReason::DuplicateIdempotencyKey),
+                    (3, RejectReason::InsufficientFunds),
+                    (5, RejectReason::DuplicateIdempotencyKey)
+                ],
+                rejected
+            );
+            assert_eq!(Decimal::new(2, 0), engine.account(1).unwrap().total);
+            assert_eq!(Decimal::new(3, 0), engine.account(2).unwrap().total);
+        }
+    }
+
+    #[test]
+    fn test_process_transactions_bytes() {
+        let accounts =
+            process_transactions_bytes(b"type,client,tx,amount\ndeposit,1,1,1.5").unwrap();
+        assert_eq!(Decimal::new(15, 1), accounts[&1].total);
+
+        let header = b"type,client,tx,amount\n".to_vec();
+        let giant = format!("deposit,1,1,1.0,{}", "9".repeat(100_000));
+        let malformed: [&[u8]; 7] = [
+            b"deposit,1,1,\xff\xfe",
+            giant.as_bytes(),
+            b"deposit,1,1,1e99999",
+            b"deposit,1,1,79228162514264337593543950336",
+            b"deposit,1,1,-0.00000000000000000000000000001",
+            b"deposit,1,1,NaN",
+            b"deposit,65536,1,1.0",
+        ];
+        for record in malformed {
+            let data = [header.as_slice(), record].concat();
+            match process_transactions_bytes(&data) {
+                Ok(accounts) => assert!(accounts.values().all(|acc| acc.total.is_zero())),
+                Err(e) => assert!(e.is_invalid_record(), "{e}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skip_invalid() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,x,1.0\nrefund,1,2,1.0\ndeposit,1,3,2.0";
+
+        let mut engine = PaymentEngine::new();
+        assert!(matches!(
+            engine.process(CsvSource::new(data.as_bytes())).await,
+            Err(EngineError::MalformedRecord { line: 3, .. })
+        ));
+
+        for (engine, stats) in process_in_shards(data, |builder| builder.skip_invalid(true)).await {
+            assert_eq!(2, stats.applied);
+            assert_eq!(2, stats.invalid);
+            assert_eq!(Decimal::new(3, 0), engine.account(1).unwrap().total);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_window() {
+        let data = "type,client,tx,amount,timestamp\ndeposit,1,1,1.0,100\ndeposit,1,2,2.0,200\ndeposit,1,3,4.0,\ndeposit,1,4,8.0,300";
+
+        for (engine, stats) in process_in_shards(data, |builder| {
+            builder.window(TimeWindow {
+                from: Some(200),
+                to: Some(300),
+            })
+        })
+        .await
+        {
+            assert_eq!(2, stats.applied);
+            assert_eq!(2, stats.out_of_window);
+            assert_eq!(Decimal::new(6, 0), engine.account(1).unwrap().total);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hooks() {
+        // Skips client 2, doubles the deposits, and records the balances after each record
+        #[derive(Debug, Default)]
+        struct Hooks(std::sync::Arc<std::sync::Mutex<Vec<Decimal>>>);
+
+        impl TransactionHooks for Hooks {
+            fn before_apply(&mut self, tx: &Transaction) -> Decision {
+                match (tx.client_id, tx.amount) {
+                    (2, _) => Decision::Skip,
+                    (_, Some(amount)) if tx.tx_type == TransactionType::Deposit => {
+                        Decision::Replace(Transaction {
+                            amount: Some(amount * Decimal::TWO),
+                            ..tx.clone()
+                        })
+                    }
+                    _ => Decision::Apply,
+                }
+            }
+
+            fn after_apply(&mut self, _tx: &Transaction, account: &ClientAccount) {
+                self.0.lock().unwrap().push(account.available);
+            }
+        }
+
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,5.0\nwithdrawal,1,3,5.0\nwithdrawal,1,4,0.5";
+        let hooks = Hooks::default();
+        let balances = hooks.0.clone();
+        let accounts = process_transactions_with_hooks(data.as_bytes(), hooks)
+            .await
+            .unwrap();
+        assert_eq!(Decimal::new(15, 1), accounts[&1].available);
+        assert!(!accounts.contains_key(&2));
+        // The rejected withdrawal isn't recorded
+        assert_eq!(
+            vec![Decimal::TWO, Decimal::new(15, 1)],
+            *balances.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stream_accounts() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,3,3,3.0";
+        for shards in [1, 2] {
+            let (sender, mut receiver) = mpsc::channel(1);
+            let mut engine = PaymentEngine::builder()
+                .shards(shards)
+                .stream_accounts(sender)
+                .build();
+            let reader = tokio::spawn(async move {
+                let mut clients = Vec::new();
+                while let Some(account) = receiver.recv().await {
+                    clients.push(account.client_id);
+                }
+                clients.sort_unstable();
+                clients
+            });
+            engine
+                .process(CsvSource::new(data.as_bytes()))
+                .await
+                .unwrap();
+            drop(engine);
+            assert_eq!(vec![1, 2, 3], reader.await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interrupt() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\n";
+
+        for shards in [1, 2] {
+            let (interrupt, receiver) = watch::channel(false);
+            let mut engine = PaymentEngine::builder()
+                .shards(shards)
+                .interrupt(receiver)
+                .build();
+            // The source never ends after its first record
+            let source: TransactionStream = Box::pin(
+                CsvSource::new(data.as_bytes())
+                    .into_stream()
+                    .chain(tokio_stream::pending()),
+            );
+            tokio::spawn(async move {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                interrupt.send_replace(true);
+            });
+            let stats = engine
+                .process_sources(vec![source], MergePolicy::default())
+                .await
+                .unwrap();
+            assert_eq!(1, stats[0].applied);
+            assert!(engine.interrupted());
+            assert_eq!(Decimal::ONE, engine.account(1).unwrap().total);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strict() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,1.0\ndispute,1,3,\ndeposit,1,4,2.0";
+
+        for shards in [1, 2] {
+            let mut engine = PaymentEngine::builder().shards(shards).strict(true).build();
+            assert!(matches!(
+                engine.process(CsvSource::new(data.as_bytes())).await,
+                Err(EngineError::Rejected {
+                    tx_id: 3,
+                    reason: RejectReason::TransactionNotFound
+                })
+            ));
+            assert!(engine.account(1).unwrap().transaction(4).is_none());
+        }
+
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,1,1.0";
+        let mut engine = PaymentEngine::builder().strict(true).build();
+        assert!(matches!(
+            engine.process(CsvSource::new(data.as_bytes())).await,
+            Err(EngineError::InvalidTransition { tx_id: 1, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unique_tx_ids() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,1,2.0\ndeposit,2,2,3.0\nwithdrawal,2,1,1.0\ndeposit,1,1,1.0";
+
+        for (engine, stats) in process_in_shards(data, |builder| {
+            builder.unique_tx_ids(true).collect_rejects(true)
+        })
+        .await
+        {
+            assert_eq!(2, stats.applied);
+            let mut reasons: Vec<_> = engine
+                .rejected()
+                .iter()
+                .map(|rejected| (rejected.tx.offset, rejected.reason))
+                .collect();
+            reasons.sort();
+            let reasons: Vec<_> = reasons.into_iter().map(|(_, reason)| reason).collect();
+            assert_eq!(
+                vec![
+                    RejectReason::TxIdReused,
+                    RejectReason::TxIdReused,
+                    RejectReason::DuplicateTransaction
+                ],
+                reasons
+            );
+            assert_eq!(Decimal::new(3, 0), engine.account(2).unwrap().total);
+        }
+
+        // Allowed by default
+        let mut engine = PaymentEngine::new();
+        let stats = engine
+            .process(CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+        assert_eq!(3, stats.applied);
+    }
+
+    #[tokio::test]
+    async fn test_dispute_of_another_client() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndispute,2,1,\ndeposit,2,2,1.0\ndeposit,3,2,1.0\ndispute,2,2,\ndispute,1,3,";
+
+        for (engine, _) in process_in_shards(data, |builder| builder.collect_rejects(true)).await {
+            let mut rejected: Vec<_> = engine
+                .rejected()
+                .iter()
+                .map(|rejected| (rejected.tx.offset, rejected.reason))
+                .collect();
+            rejected.sort();
+            let reasons: Vec<_> = rejected.into_iter().map(|(_, reason)| reason).collect();
+            assert_eq!(
+                vec![
+                    RejectReason::ClientMismatch,
+                    RejectReason::TransactionNotFound
+                ],
+                reasons
+            );
+            // Tx id 2 is used by clients 2 and 3, the dispute is applied to the client's own
+            assert_eq!(Decimal::ONE, engine.account(2).unwrap().held);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spill() {
+        let path = std::env::temp_dir().join(format!("tpe_spill_{}.jsonl", std::process::id()));
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\ndeposit,2,3,3.0\ndeposit,1,1,5.0\ndispute,1,2,\ndeposit,2,4,1.0\nresolve,1,2,";
+
+        let mut engine = PaymentEngine::builder().spill(&path, 1).build();
+        let stats = engine
+            .process(CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+        assert!(path.exists());
+        assert_eq!(1, stats.rejected);
+        let account = engine.account(1).unwrap();
+        assert_eq!(Decimal::new(3, 0), account.total);
+        assert_eq!(Decimal::ZERO, account.held);
+        // The budget is too small to keep any transaction in memory
+        assert_eq!(
+            0,
+            engine
+                .accounts()
+                .map(|acc| acc.transactions().count())
+                .sum::<usize>()
+        );
+        let tx = engine.transaction(1, 2).unwrap().unwrap();
+        assert_eq!(TransactionStatus::Resolved, tx.status);
+
+        let mut store = crate::engine::MemoryStore::new();
+        engine.persist(&mut store).unwrap();
+        let accounts = crate::engine::StateStore::load(&mut store).unwrap();
+        assert_eq!(
+            4,
+            accounts
+                .values()
+                .map(|acc| acc.transactions().count())
+                .sum::<usize>()
+        );
+
+        drop(engine);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_collect_ledger() {
+        let data = "type,client,tx,amount\ndeposit,1,1,3.0\nwithdrawal,1,2,5.0\ndispute,1,1,";
+
+        let mut engine = PaymentEngine::builder().collect_ledger(true).build();
+        engine
+            .process(CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+        let ledger: Vec<_> = engine
+            .ledger()
+            .iter()
+            .map(|entry| {
+                (
+                    entry.tx.tx_type,
+                    entry.balances.available,
+                    entry.balances.held,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (TransactionType::Deposit, Decimal::new(3, 0), Decimal::ZERO),
+                (TransactionType::Dispute, Decimal::ZERO, Decimal::new(3, 0)),
+            ],
+            ledger
+        );
+    }
+
+    #[tokio::test]
+    async fn test_collect_rejects() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,2,2,1.0\ndeposit,1,1,2.0\ndispute,1,1,\nchargeback,1,1,\ndeposit,1,3,";
+
+        for (mut engine, _) in
+            process_in_shards(data, |builder| builder.collect_rejects(true)).await
+        {
+            let mut rejected = engine.take_rejected();
+            rejected.sort_by_key(|rejected| rejected.tx.offset);
+            let rejected: Vec<_> = rejected
+                .iter()
+                .map(|rejected| (rejected.tx.tx_type, rejected.tx.tx_id, rejected.reason))
+                .collect();
+            assert_eq!(
+                vec![
+                    (
+                        TransactionType::Withdrawal,
+                        2,
+                        RejectReason::InsufficientFunds
+                    ),
+                    (
+                        TransactionType::Deposit,
+                        1,
+                        RejectReason::DuplicateTransaction
+                    ),
+                    (TransactionType::Deposit, 3, RejectReason::AccountLocked),
+                ],
+                rejected
+            );
+            assert!(engine.rejected().is_empty());
+        }
+
+        // Not collected by default
+        let mut engine = PaymentEngine::new();
+        engine
+            .process(CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+        assert!(engine.rejected().is_empty());
+    }
 }
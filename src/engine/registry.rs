@@ -0,0 +1,222 @@
+use std::sync::{Mutex as SyncMutex, PoisonError};
+
+use tokio::sync::Mutex;
+
+use super::{
+    model::{ClientAccount, Transaction, TransactionType},
+    payment_engine::PaymentEngine,
+    reject::RejectReason,
+};
+
+/// The accounts of a [`PaymentEngine`] partitioned by client id over several locks, as with
+/// [`EngineBuilder::shards`](super::EngineBuilder::shards), so that transactions of clients of
+/// different partitions are applied in parallel, e.g. by the handlers of an HTTP API.
+///
+/// Transactions of the same client are applied in the order they are submitted, queueing for
+/// the lock of its partition. A transfer to a client of another partition locks both, always
+/// in the same order to avoid deadlocks: transactions of the client submitted while it waits
+/// for the lower partition may be applied before it.
+#[derive(Debug)]
+pub struct AccountRegistry {
+    // The engine the accounts come from, checking the tx ids across all the clients
+    index: SyncMutex<PaymentEngine>,
+    // Engines owning the accounts of each partition
+    partitions: Vec<Mutex<PaymentEngine>>,
+}
+
+impl AccountRegistry {
+    /// Moves the accounts of `engine` to `partitions` partitions, by `client_id % partitions`.
+    pub fn new(mut engine: PaymentEngine, partitions: usize) -> Self {
+        let partitions = engine
+            .split_shards(partitions.max(1))
+            .into_iter()
+            .map(Mutex::new)
+            .collect();
+        Self {
+            index: SyncMutex::new(engine),
+            partitions,
+        }
+    }
+
+    /// Applies a transaction to the account of its client, as [`PaymentEngine::apply`] does,
+    /// once the transactions of the clients of the same partition submitted before are applied.
+    pub async fn apply(&self, tx: Transaction) -> Result<(), RejectReason> {
+        let partition = self.partition(tx.client_id);
+        let counterparty = tx
+            .counterparty
+            .filter(|_| tx.tx_type == TransactionType::Transfer)
+            .map(|counterparty| (counterparty, self.partition(counterparty)));
+        match counterparty {
+            Some((counterparty, other)) if other != partition => {
+                let (mut engine, mut other) = if partition < other {
+                    let engine = self.partitions[partition].lock().await;
+                    (engine, self.partitions[other].lock().await)
+                } else {
+                    let other = self.partitions[other].lock().await;
+                    (self.partitions[partition].lock().await, other)
+                };
+                // The account of the counterparty is moved for the time of the transfer
+                if let Some(account) = other.accounts.remove(&counterparty) {
+                    engine.accounts.insert(counterparty, account);
+                }
+                let outcome = self.apply_to(&mut engine, tx);
+                if let Some(account) = engine.accounts.remove(&counterparty) {
+                    other.accounts.insert(counterparty, account);
+                }
+                outcome
+            }
+            _ => self.apply_to(&mut *self.partitions[partition].lock().await, tx),
+        }
+    }
+
+    /// Returns a copy of the account of a client, if any.
+    pub async fn account(&self, client_id: u16) -> Option<ClientAccount> {
+        let engine = self.partitions[self.partition(client_id)].lock().await;
+        engine.account(client_id).cloned()
+    }
+
+    /// Returns a copy of all the accounts, as [`PaymentEngine::sorted_accounts`] does.
+    pub async fn sorted_accounts(&self) -> Vec<ClientAccount> {
+        let mut accounts = Vec::new();
+        for partition in &self.partitions {
+            accounts.extend(partition.lock().await.accounts().cloned());
+        }
+        self.index().sort_by.sort(&mut accounts);
+        accounts
+    }
+
+    /// Returns a copy of up to `limit` accounts matching `filter`, by client id, starting after
+    /// the client `after` if any, along with the client id to continue from if there are more.
+    pub async fn accounts_page(
+        &self,
+        after: Option<u16>,
+        limit: usize,
+        filter: impl Fn(&ClientAccount) -> bool,
+    ) -> (Vec<ClientAccount>, Option<u16>) {
+        let limit = limit.max(1);
+        let mut accounts = Vec::new();
+        for partition in &self.partitions {
+            let engine = partition.lock().await;
+            let mut matching: Vec<_> = engine
+                .accounts()
+                .filter(|account| after.is_none_or(|after| account.client_id > after))
+                .filter(|account| filter(account))
+                .collect();
+            // Only the first accounts of each partition may be on the page, the others aren't
+            // copied
+            matching.sort_unstable_by_key(|account| account.client_id);
+            accounts.extend(matching.into_iter().take(limit + 1).cloned());
+        }
+        accounts.sort_unstable_by_key(|account| account.client_id);
+        let next = (accounts.len() > limit).then(|| accounts[limit - 1].client_id);
+        accounts.truncate(limit);
+        (accounts, next)
+    }
+
+    /// Moves the accounts back to a single engine.
+    pub fn into_engine(self) -> PaymentEngine {
+        let mut engine = self
+            .index
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner);
+        for partition in self.partitions {
+            engine.merge_shard(partition.into_inner());
+        }
+        engine
+    }
+
+    // Returns the partition of the account of a client
+    fn partition(&self, client_id: u16) -> usize {
+        client_id as usize % self.partitions.len()
+    }
+
+    // Locks the engine the accounts come from
+    fn index(&self) -> std::sync::MutexGuard<'_, PaymentEngine> {
+        self.index.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Applies a transaction to the engine of the partition of its client, once its tx id is
+    // checked against the ones of all the clients
+    fn apply_to(&self, engine: &mut PaymentEngine, tx: Transaction) -> Result<(), RejectReason> {
+        self.index().check_tx_id(&tx)?;
+        engine.apply(tx)
+    }
+}
+
+#[cfg(test)]
+mod registry_tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::Decimal;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_registry() {
+        let engine = PaymentEngine::builder().unique_tx_ids(true).build();
+        let registry = Arc::new(AccountRegistry::new(engine, 4));
+
+        // Deposits of all the clients applied concurrently, in order for each client
+        let tasks: Vec<_> = (1..=8)
+            .map(|client_id| {
+                let registry = registry.clone();
+                tokio::spawn(async move {
+                    for n in 0..10 {
+                        let tx_id = client_id as u32 * 100 + n;
+                        let deposit = Transaction::new(
+                            TransactionType::Deposit,
+                            client_id,
+                            tx_id,
+                            Some(Decimal::ONE),
+                        );
+                        registry.apply(deposit).await.unwrap();
+                    }
+                    let withdrawal = Transaction::new(
+                        TransactionType::Withdrawal,
+                        client_id,
+                        client_id as u32 * 100 + 10,
+                        Some(Decimal::TEN),
+                    );
+                    registry.apply(withdrawal).await.unwrap();
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // Tx ids are checked across the partitions
+        let reused = Transaction::new(TransactionType::Deposit, 2, 100, Some(Decimal::ONE));
+        assert_eq!(Err(RejectReason::TxIdReused), registry.apply(reused).await);
+
+        // Transfers move funds across the partitions
+        let mut deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        registry.apply(deposit.clone()).await.unwrap();
+        deposit.tx_type = TransactionType::Transfer;
+        deposit.tx_id = 2;
+        deposit.amount = Some(Decimal::new(4, 0));
+        deposit.counterparty = Some(2);
+        registry.apply(deposit).await.unwrap();
+        assert_eq!(Decimal::new(6, 0), registry.account(1).await.unwrap().total);
+        assert_eq!(Decimal::new(4, 0), registry.account(2).await.unwrap().total);
+
+        let accounts = registry.sorted_accounts().await;
+        assert_eq!(
+            (1..=8).collect::<Vec<_>>(),
+            accounts.iter().map(|acc| acc.client_id).collect::<Vec<_>>()
+        );
+        let (page, next) = registry
+            .accounts_page(Some(2), 3, |account| account.client_id != 4)
+            .await;
+        assert_eq!(
+            vec![3, 5, 6],
+            page.iter().map(|acc| acc.client_id).collect::<Vec<_>>()
+        );
+        assert_eq!(Some(6), next);
+        let (page, next) = registry.accounts_page(next, 3, |_| true).await;
+        assert_eq!(2, page.len());
+        assert_eq!(None, next);
+        let engine = Arc::into_inner(registry).unwrap().into_engine();
+        assert_eq!(8, engine.accounts().count());
+        assert_eq!(Decimal::ZERO, engine.account(3).unwrap().total);
+    }
+}
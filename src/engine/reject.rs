@@ -0,0 +1,207 @@
+use std::{fmt::Display, str::FromStr};
+
+use super::model::Transaction;
+
+/// The reasons for which a transaction record can be rejected by the engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RejectReason {
+    /// The account is locked and can't be updated anymore.
+    AccountLocked,
+    /// The account already has a transaction registered with the same id.
+    DuplicateTransaction,
+    /// A deposit or withdrawal without an `amount` specified.
+    MissingAmount,
+    /// A deposit, withdrawal or dispute with a zero or negative `amount`.
+    InvalidAmount,
+    /// The account has not enough funds to process the transaction.
+    InsufficientFunds,
+    /// The referenced transaction doesn't exist.
+    TransactionNotFound,
+    /// The referenced transaction is a withdrawal, and withdrawals can't be disputed.
+    WithdrawalNotDisputable,
+    /// The referenced transaction has not been verified.
+    NotVerified,
+    /// The referenced transaction is not under dispute.
+    NotDisputed,
+    /// The referenced transaction is already under dispute.
+    AlreadyDisputed,
+    /// The referenced transaction has already been resolved.
+    AlreadyResolved,
+    /// The referenced transaction has already been chargebacked.
+    AlreadyChargebacked,
+    /// The referenced transaction is in a different currency.
+    CurrencyMismatch,
+    /// Applying the transaction would overflow the balances of the account.
+    ArithmeticOverflow,
+    /// An arithmetic overflow occurred on the account, which can't be updated anymore.
+    AccountInError,
+    /// The tx id is already used by a transaction of another client.
+    TxIdReused,
+    /// The referenced transaction belongs to another client.
+    ClientMismatch,
+    /// An administrative operation, while they are not allowed.
+    AdminOpNotAllowed,
+    /// An unlock of an account that is not locked.
+    NotLocked,
+    /// A transfer without a counterparty, or to the client itself.
+    InvalidCounterparty,
+    /// A deposit whose amount doesn't cover the fee charged on it.
+    AmountBelowFee,
+    /// A transaction with the idempotency key of one already applied to the account.
+    DuplicateIdempotencyKey,
+    /// A transaction breaking a custom validation rule.
+    RuleViolated,
+    /// A withdrawal exceeding the limit of a single withdrawal, or of the total withdrawn.
+    WithdrawalLimitExceeded,
+    /// A dispute of a transaction older than the dispute window.
+    DisputeWindowExpired,
+    /// An amount with more decimal places than the scale of the precision policy.
+    ExcessPrecision,
+    /// A transaction of an account that has been closed.
+    AccountClosed,
+    /// A close of an account with funds held by disputes.
+    FundsHeld,
+    /// A dispute of more than the amount of the referenced transaction left to dispute.
+    DisputeExceedsAmount,
+    /// The referenced transaction has already been reversed.
+    AlreadyReversed,
+    /// A transaction of a type only generated by the engine, e.g. interest.
+    ReservedType,
+    /// The referenced transaction is a transfer, and transfers can't be disputed or reversed.
+    TransferNotDisputable,
+}
+
+impl RejectReason {
+    pub const ALL: [RejectReason; 32] = [
+        RejectReason::AccountLocked,
+        RejectReason::DuplicateTransaction,
+        RejectReason::MissingAmount,
+        RejectReason::InvalidAmount,
+        RejectReason::InsufficientFunds,
+        RejectReason::TransactionNotFound,
+        RejectReason::WithdrawalNotDisputable,
+        RejectReason::NotVerified,
+        RejectReason::NotDisputed,
+        RejectReason::AlreadyDisputed,
+        RejectReason::AlreadyResolved,
+        RejectReason::AlreadyChargebacked,
+        RejectReason::CurrencyMismatch,
+        RejectReason::ArithmeticOverflow,
+        RejectReason::AccountInError,
+        RejectReason::TxIdReused,
+        RejectReason::ClientMismatch,
+        RejectReason::AdminOpNotAllowed,
+        RejectReason::NotLocked,
+        RejectReason::InvalidCounterparty,
+        RejectReason::AmountBelowFee,
+        RejectReason::DuplicateIdempotencyKey,
+        RejectReason::RuleViolated,
+        RejectReason::WithdrawalLimitExceeded,
+        RejectReason::DisputeWindowExpired,
+        RejectReason::ExcessPrecision,
+        RejectReason::AccountClosed,
+        RejectReason::FundsHeld,
+        RejectReason::DisputeExceedsAmount,
+        RejectReason::AlreadyReversed,
+        RejectReason::ReservedType,
+        RejectReason::TransferNotDisputable,
+    ];
+
+    /// Machine-readable code of the reason, used for configuration and reporting.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RejectReason::AccountLocked => "account_locked",
+            RejectReason::DuplicateTransaction => "duplicate_tx",
+            RejectReason::MissingAmount => "missing_amount",
+            RejectReason::InvalidAmount => "invalid_amount",
+            RejectReason::InsufficientFunds => "insufficient_funds",
+            RejectReason::TransactionNotFound => "tx_not_found",
+            RejectReason::WithdrawalNotDisputable => "withdrawal_not_disputable",
+            RejectReason::NotVerified => "not_verified",
+            RejectReason::NotDisputed => "not_disputed",
+            RejectReason::AlreadyDisputed => "already_disputed",
+            RejectReason::AlreadyResolved => "already_resolved",
+            RejectReason::AlreadyChargebacked => "already_chargebacked",
+            RejectReason::CurrencyMismatch => "currency_mismatch",
+            RejectReason::ArithmeticOverflow => "arithmetic_overflow",
+            RejectReason::AccountInError => "account_in_error",
+            RejectReason::TxIdReused => "tx_id_reused",
+            RejectReason::ClientMismatch => "client_mismatch",
+            RejectReason::AdminOpNotAllowed => "admin_op_not_allowed",
+            RejectReason::NotLocked => "not_locked",
+            RejectReason::InvalidCounterparty => "invalid_counterparty",
+            RejectReason::AmountBelowFee => "amount_below_fee",
+            RejectReason::DuplicateIdempotencyKey => "duplicate_idempotency_key",
+            RejectReason::RuleViolated => "rule_violated",
+            RejectReason::WithdrawalLimitExceeded => "withdrawal_limit_exceeded",
+            RejectReason::DisputeWindowExpired => "dispute_window_expired",
+            RejectReason::ExcessPrecision => "excess_precision",
+            RejectReason::AccountClosed => "account_closed",
+            RejectReason::FundsHeld => "funds_held",
+            RejectReason::DisputeExceedsAmount => "dispute_exceeds_amount",
+            RejectReason::AlreadyReversed => "already_reversed",
+            RejectReason::ReservedType => "reserved_type",
+            RejectReason::TransferNotDisputable => "transfer_not_disputable",
+        }
+    }
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            RejectReason::AccountLocked => "account is locked",
+            RejectReason::DuplicateTransaction => "transaction id already registered",
+            RejectReason::MissingAmount => "amount not specified",
+            RejectReason::InvalidAmount => "amount not valid",
+            RejectReason::InsufficientFunds => "not enough funds",
+            RejectReason::TransactionNotFound => "referenced tx not found",
+            RejectReason::WithdrawalNotDisputable => {
+                "referenced tx is a withdrawal, not disputable"
+            }
+            RejectReason::NotVerified => "referenced tx has not been verified",
+            RejectReason::NotDisputed => "referenced tx is not under dispute",
+            RejectReason::AlreadyDisputed => "referenced tx is already under dispute",
+            RejectReason::AlreadyResolved => "referenced tx has been already resolved",
+            RejectReason::AlreadyChargebacked => "referenced tx has been already chargebacked",
+            RejectReason::CurrencyMismatch => "referenced tx is in a different currency",
+            RejectReason::ArithmeticOverflow => "balances would overflow",
+            RejectReason::AccountInError => "account is in error after an overflow",
+            RejectReason::TxIdReused => "tx id already used by another client",
+            RejectReason::ClientMismatch => "referenced tx belongs to another client",
+            RejectReason::AdminOpNotAllowed => "admin operations are not allowed",
+            RejectReason::NotLocked => "account is not locked",
+            RejectReason::InvalidCounterparty => "counterparty missing or same as the client",
+            RejectReason::AmountBelowFee => "amount doesn't cover the fee",
+            RejectReason::DuplicateIdempotencyKey => "idempotency key already used",
+            RejectReason::RuleViolated => "validation rule not satisfied",
+            RejectReason::WithdrawalLimitExceeded => "withdrawal limit exceeded",
+            RejectReason::DisputeWindowExpired => "transaction too old to be disputed",
+            RejectReason::ExcessPrecision => "amount with too many decimal places",
+            RejectReason::AccountClosed => "account is closed",
+            RejectReason::FundsHeld => "account has funds held by disputes",
+            RejectReason::DisputeExceedsAmount => "amount exceeds what is left to dispute",
+            RejectReason::AlreadyReversed => "referenced tx has been already reversed",
+            RejectReason::ReservedType => "transaction type reserved to the engine",
+            RejectReason::TransferNotDisputable => "referenced tx is a transfer, not disputable",
+        };
+        write!(f, "{msg}")
+    }
+}
+
+impl FromStr for RejectReason {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RejectReason::ALL
+            .into_iter()
+            .find(|reason| reason.code() == s)
+            .ok_or_else(|| format!("Unknown rejection reason `{s}`"))
+    }
+}
+
+/// A transaction record not applied by the engine, together with the reason why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedTransaction {
+    pub tx: Transaction,
+    pub reason: RejectReason,
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use super::{
+    amount::Decimal,
+    error::EngineError,
+    model::{ClientAccount, TransactionStatus, TransactionType},
+    payment_engine::PaymentEngine,
+};
+
+/// The accounts to triage first for fraud, see [`PaymentEngine::risk_report`]. Each list has
+/// the clients with their value, in decreasing order of value then by client id.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RiskReport {
+    /// Clients with the most funds held by disputes
+    pub held: Vec<(u16, Decimal)>,
+    /// Clients with the most transactions chargebacked
+    pub chargebacks: Vec<(u16, u64)>,
+    /// Clients with the largest single withdrawal
+    pub withdrawals: Vec<(u16, Decimal)>,
+}
+
+impl PaymentEngine {
+    /// Returns the `n` accounts with the most funds held, with the most chargebacks and with the
+    /// largest withdrawal, in the default currency. Accounts without any are left out, and the
+    /// withdrawals reversed are not counted.
+    pub fn risk_report(&self, n: usize) -> Result<RiskReport, EngineError> {
+        self.with_all_accounts(|accounts| RiskReport {
+            held: top(accounts, n, |account| account.held),
+            chargebacks: top(accounts, n, |account| {
+                account
+                    .transactions()
+                    .filter(|(_, tx)| tx.status == TransactionStatus::Chargebacked)
+                    .count() as u64
+            }),
+            withdrawals: top(accounts, n, |account| {
+                account
+                    .transactions()
+                    .filter(|(_, tx)| {
+                        tx.kind == TransactionType::Withdrawal
+                            && tx.currency.is_none()
+                            && tx.status != TransactionStatus::Reversed
+                    })
+                    .map(|(_, tx)| tx.amount)
+                    .max()
+                    .unwrap_or_default()
+            }),
+        })
+    }
+}
+
+// Returns the `n` clients with the highest positive `value` of their account
+fn top<T: Ord + Default + Copy>(
+    accounts: &HashMap<u16, ClientAccount>,
+    n: usize,
+    value: impl Fn(&ClientAccount) -> T,
+) -> Vec<(u16, T)> {
+    let mut values: Vec<_> = accounts
+        .values()
+        .map(|account| (account.client_id, value(account)))
+        .filter(|(_, value)| *value > T::default())
+        .collect();
+    values.sort_unstable_by(|(client_a, a), (client_b, b)| b.cmp(a).then(client_a.cmp(client_b)));
+    values.truncate(n);
+    values
+}
+
+#[cfg(test)]
+mod risk_tests {
+    use super::*;
+    use crate::engine::CsvSource;
+
+    #[tokio::test]
+    async fn test_risk_report() {
+        let data = "type,client,tx,amount\ndeposit,1,1,10.0\ndeposit,2,2,5.0\ndeposit,3,3,8.0\n\
+                    dispute,1,1,\ndispute,2,2,\nchargeback,2,2,\nwithdrawal,3,4,2.0\n\
+                    withdrawal,3,5,3.0\nreversal,3,5,\ndeposit,4,6,9.0\ndeposit,4,7,3.0\n\
+                    withdrawal,4,8,3.0\ndispute,4,6,\n";
+        let mut engine = PaymentEngine::new();
+        engine
+            .process(CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+
+        let report = engine.risk_report(2).unwrap();
+        assert_eq!(
+            vec![(1, Decimal::TEN), (4, Decimal::new(9, 0))],
+            report.held
+        );
+        assert_eq!(vec![(2, 1)], report.chargebacks);
+        assert_eq!(
+            vec![(4, Decimal::new(3, 0)), (3, Decimal::new(2, 0))],
+            report.withdrawals
+        );
+    }
+}
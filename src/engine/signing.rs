@@ -0,0 +1,126 @@
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use super::error::EngineError;
+
+/// Signs the reports written by the engine with an Ed25519 key, so that their consumers can
+/// authenticate them with [`verify_report`].
+///
+/// Keys and signatures are stored as hex strings: the 32 bytes seed of the signing key, the
+/// 32 bytes of the public key and the 64 bytes of the signature.
+#[derive(Debug)]
+pub struct ReportSigner(SigningKey);
+
+impl ReportSigner {
+    /// Reads the signing key from a file.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let seed = read_hex(path).await?;
+        Ok(Self(SigningKey::from_bytes(&seed)))
+    }
+
+    /// Returns the public key verifying the signatures, as a hex string.
+    pub fn public_key(&self) -> String {
+        hex(&self.0.verifying_key().to_bytes())
+    }
+
+    /// Signs the file at `path`, writing the detached signature to `<path>.sig`. Returns the
+    /// path of the signature.
+    pub async fn sign_file(&self, path: &str) -> Result<String, EngineError> {
+        let report = tokio::fs::read(path).await?;
+        let signature = self.0.sign(&report);
+        let signature_path = format!("{path}.sig");
+        tokio::fs::write(&signature_path, hex(&signature.to_bytes())).await?;
+        Ok(signature_path)
+    }
+}
+
+/// Returns whether `signature` is a valid signature of the report at `path`, by the signing
+/// key of `public_key`, all of them being files.
+pub async fn verify_report(
+    path: impl AsRef<Path>,
+    signature: impl AsRef<Path>,
+    public_key: impl AsRef<Path>,
+) -> Result<bool, EngineError> {
+    let public_key = VerifyingKey::from_bytes(&read_hex(public_key).await?)
+        .map_err(|e| EngineError::SigningError(e.to_string()))?;
+    let signature = Signature::from_bytes(&read_hex(signature).await?);
+    let report = tokio::fs::read(path).await?;
+    Ok(public_key.verify(&report, &signature).is_ok())
+}
+
+// Reads a file with `N` bytes as a hex string, surrounded by whitespace or not
+async fn read_hex<const N: usize>(path: impl AsRef<Path>) -> Result<[u8; N], EngineError> {
+    let path = path.as_ref();
+    let content = tokio::fs::read_to_string(path).await?;
+    let content = content.trim();
+    let invalid = || {
+        EngineError::SigningError(format!(
+            "{} doesn't contain {N} bytes as a hex string",
+            path.display()
+        ))
+    };
+    if content.len() != N * 2 {
+        return Err(invalid());
+    }
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(content.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+    }
+    Ok(bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod signing_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sign_report() {
+        let dir = std::env::temp_dir().join(format!("tpe_signing_{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+        tokio::fs::write(path("key"), "42".repeat(32))
+            .await
+            .unwrap();
+        tokio::fs::write(path("report.csv"), "client,available,held,total,locked\n")
+            .await
+            .unwrap();
+
+        let signer = ReportSigner::load(path("key")).await.unwrap();
+        tokio::fs::write(path("key.pub"), signer.public_key())
+            .await
+            .unwrap();
+        let signature = signer.sign_file(&path("report.csv")).await.unwrap();
+        assert_eq!(path("report.csv.sig"), signature);
+        assert!(
+            verify_report(path("report.csv"), &signature, path("key.pub"))
+                .await
+                .unwrap()
+        );
+
+        // Tampered report
+        tokio::fs::write(
+            path("report.csv"),
+            "client,available,held,total,locked\n1,1,0,1,false\n",
+        )
+        .await
+        .unwrap();
+        assert!(
+            !verify_report(path("report.csv"), &signature, path("key.pub"))
+                .await
+                .unwrap()
+        );
+        assert!(
+            verify_report(path("report.csv"), path("key"), path("key.pub"))
+                .await
+                .is_err()
+        );
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
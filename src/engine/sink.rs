@@ -0,0 +1,152 @@
+use futures::future::BoxFuture;
+use tokio::{
+    io::{self, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+use super::{error::EngineError, event::AccountEvent};
+
+/// A destination of the events of the accounts, notified while processing.
+///
+/// The events are streamed by the engine through the channel set with
+/// [`EngineBuilder::events`](super::EngineBuilder::events), and sent to the sinks by
+/// [`forward_events`].
+pub trait EventSink: Send {
+    /// Sends an event to the sink.
+    fn send<'a>(&'a mut self, event: &'a AccountEvent) -> BoxFuture<'a, Result<(), EngineError>>;
+
+    /// Flushes the events sent, once there are no more.
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), EngineError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Writes the events as JSON lines.
+pub struct JsonLinesSink<W> {
+    wrt: W,
+}
+
+impl<W: AsyncWrite + Unpin + Send> JsonLinesSink<W> {
+    pub fn new(wrt: W) -> Self {
+        Self { wrt }
+    }
+}
+
+impl JsonLinesSink<io::Stderr> {
+    /// Writes the events to stderr.
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> EventSink for JsonLinesSink<W> {
+    fn send<'a>(&'a mut self, event: &'a AccountEvent) -> BoxFuture<'a, Result<(), EngineError>> {
+        Box::pin(async move {
+            let mut line = serde_json::to_vec(event)?;
+            line.push(b'\n');
+            self.wrt.write_all(&line).await?;
+            Ok(())
+        })
+    }
+
+    fn flush(&mut self) -> BoxFuture<'_, Result<(), EngineError>> {
+        Box::pin(async move { Ok(self.wrt.flush().await?) })
+    }
+}
+
+/// Posts the events as JSON to an HTTP endpoint. Notifications are best effort: failures are
+/// logged, without aborting the processing.
+#[cfg(feature = "webhooks")]
+pub struct WebhookSink {
+    client: reqwest::Client,
+    url: String,
+    // Events posted, all of them if not set
+    filter: Option<fn(&AccountEvent) -> bool>,
+}
+
+#[cfg(feature = "webhooks")]
+impl WebhookSink {
+    // Maximum time to wait for the endpoint to answer
+    const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into(),
+            filter: None,
+        }
+    }
+
+    /// Only posts the events `filter` returns `true` for, e.g. [`AccountEvent::is_alert`].
+    pub fn filter(mut self, filter: fn(&AccountEvent) -> bool) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+}
+
+#[cfg(feature = "webhooks")]
+impl EventSink for WebhookSink {
+    fn send<'a>(&'a mut self, event: &'a AccountEvent) -> BoxFuture<'a, Result<(), EngineError>> {
+        Box::pin(async move {
+            if self.filter.is_some_and(|filter| !filter(event)) {
+                return Ok(());
+            }
+            let outcome = self
+                .client
+                .post(&self.url)
+                .json(event)
+                .timeout(Self::TIMEOUT)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = outcome {
+                tracing::warn!(url = %self.url, error = %e, "Webhook notification failed");
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Sends the events received from `events` to all the `sinks`, until the channel is closed.
+/// Returns the number of events received.
+pub async fn forward_events(
+    mut events: mpsc::UnboundedReceiver<AccountEvent>,
+    sinks: &mut [Box<dyn EventSink + '_>],
+) -> Result<u64, EngineError> {
+    let mut count = 0;
+    while let Some(event) = events.recv().await {
+        for sink in sinks.iter_mut() {
+            sink.send(&event).await?;
+        }
+        count += 1;
+    }
+    for sink in sinks.iter_mut() {
+        sink.flush().await?;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod sink_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_forward_events() {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        for tx in [1, 2] {
+            sender
+                .send(AccountEvent::AccountLocked { client: 1, tx })
+                .unwrap();
+        }
+        drop(sender);
+
+        let mut data = Vec::new();
+        let mut sinks: Vec<Box<dyn EventSink>> = vec![Box::new(JsonLinesSink::new(&mut data))];
+        assert_eq!(2, forward_events(receiver, &mut sinks).await.unwrap());
+        drop(sinks);
+        assert_eq!(
+            "{\"event\":\"account_locked\",\"client\":1,\"tx\":1}\n{\"event\":\"account_locked\",\"client\":1,\"tx\":2}\n",
+            String::from_utf8(data).unwrap()
+        );
+    }
+}
@@ -0,0 +1,1669 @@
+use std::{
+    future::Future,
+    io::{Cursor, SeekFrom},
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError, RwLock,
+    },
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use csv_async::{AsyncReaderBuilder, StringRecord, Trim};
+use csv_core::ReadRecordResult;
+use futures::{future::BoxFuture, TryStreamExt};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::{
+    fs::File,
+    io::{self, AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader, ReadBuf},
+    time::{Instant, Sleep},
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tracing::{info, info_span, Span};
+
+use super::{
+    error::EngineError,
+    mapping::ColumnMapping,
+    metrics,
+    model::{parse_amount, Transaction, TransactionType},
+    object::{is_object_url, object_len, open_object},
+};
+
+/// A stream of transaction records coming from a single source.
+pub type TransactionStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<Transaction, EngineError>> + Send + 'a>>;
+
+/// A source of transaction records.
+///
+/// Implementors take care of reading and deserializing the records in their own format, so
+/// that the processor only deals with a stream of [`Transaction`]s.
+pub trait TransactionSource<'a> {
+    /// Turns the source into a stream of transaction records.
+    fn into_stream(self) -> TransactionStream<'a>;
+}
+
+/// The dialect of CSV data: how fields are delimited and quoted, and whether the first row is
+/// a header one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvDialect {
+    /// Delimiter of the fields, `,` by default
+    pub delimiter: u8,
+    /// Character quoting the fields, `"` by default
+    pub quote: u8,
+    /// Whether quotes are interpreted, or read as any other character
+    pub quoting: bool,
+    /// Names of the columns, in order, if the data has no header row
+    pub columns: Option<Vec<String>>,
+    /// Fields of the transactions read from the columns
+    pub mapping: ColumnMapping,
+    /// Whether amounts are read as floats, as by previous versions, rather than parsed exactly
+    /// from their decimal representation
+    pub float_amounts: bool,
+    /// Whether the records are parsed straight from their bytes rather than deserialized, when
+    /// they only have the `type`, `client`, `tx` and `amount` fields and amounts aren't floats
+    pub fast_parse: bool,
+}
+
+impl CsvDialect {
+    /// Columns of headerless data, unless specified otherwise.
+    pub const DEFAULT_COLUMNS: [&'static str; 4] = ["type", "client", "tx", "amount"];
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            quoting: true,
+            columns: None,
+            mapping: ColumnMapping::default(),
+            float_amounts: false,
+            fast_parse: false,
+        }
+    }
+}
+
+/// Transaction records in CSV format, with a header row unless the [`CsvDialect`] specifies
+/// the columns.
+pub struct CsvSource<AR> {
+    rdr: AR,
+    dialect: CsvDialect,
+}
+
+impl<AR> CsvSource<AR> {
+    pub fn new(rdr: AR) -> Self {
+        Self {
+            rdr,
+            dialect: CsvDialect::default(),
+        }
+    }
+
+    /// Sets the dialect of the data, the standard one by default.
+    pub fn dialect(mut self, dialect: CsvDialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+}
+
+impl<'a, AR: io::AsyncRead + Send + Unpin + 'a> TransactionSource<'a> for CsvSource<AR> {
+    fn into_stream(self) -> TransactionStream<'a> {
+        let dialect = self.dialect;
+        if dialect.fast_parse && !dialect.float_amounts {
+            return fast_records(self.rdr, dialect);
+        }
+        let mut reader = AsyncReaderBuilder::new()
+            .trim(Trim::All)
+            .flexible(true)
+            .delimiter(dialect.delimiter)
+            .quote(dialect.quote)
+            .quoting(dialect.quoting)
+            .has_headers(dialect.columns.is_none())
+            .create_reader(self.rdr);
+        let records = async move {
+            let headers = match dialect.columns {
+                Some(columns) => StringRecord::from(columns),
+                None => reader.headers().await?.clone(),
+            };
+            let headers: StringRecord = headers
+                .iter()
+                .map(|column| dialect.mapping.field(column))
+                .collect::<Vec<_>>()
+                .into();
+            let type_column = headers.iter().position(|header| header == "type");
+            // Amounts are parsed from the raw fields, as CSV ones would be read as floats
+            let amount_column = headers
+                .iter()
+                .position(|header| header == "amount")
+                .filter(|_| !dialect.float_amounts);
+            Ok::<_, EngineError>(reader.into_records().map(move |record| {
+                let record = record.map_err(|e| match e.kind() {
+                    // Invalid data doesn't prevent reading the following records
+                    csv_async::ErrorKind::Utf8 { pos, .. } => {
+                        let line = pos.as_ref().map_or(0, |pos| pos.line());
+                        record_error(line, String::new(), None, e)
+                    }
+                    _ => e.into(),
+                })?;
+                let line = record.position().map_or(0, |pos| pos.line());
+                let raw = || record.iter().collect::<Vec<_>>().join(",");
+                if record.iter().any(|field| field.len() > MAX_FIELD_LEN) {
+                    return Err(record_error(line, raw(), None, FieldTooLong));
+                }
+                let tx_type = type_column.and_then(|column| record.get(column));
+                let mut tx: Transaction = record
+                    .deserialize(Some(&headers))
+                    .map_err(|e| record_error(line, raw(), tx_type, e))?;
+                if let Some(amount) = amount_column
+                    .and_then(|column| record.get(column))
+                    .filter(|amount| !amount.is_empty())
+                {
+                    let amount =
+                        parse_amount(amount).map_err(|e| record_error(line, raw(), tx_type, e))?;
+                    tx.amount = Some(amount);
+                }
+                tx.offset = record.position().map_or(0, |pos| pos.byte());
+                Ok(tx)
+            }))
+        };
+        Box::pin(futures::stream::once(records).try_flatten())
+    }
+}
+
+// Fields of the transactions that are only read by deserializing the records
+const DESERIALIZED_FIELDS: [&str; 6] = [
+    "currency",
+    "counterparty",
+    "idempotency_key",
+    "origin",
+    "timestamp",
+    "status",
+];
+
+// Reads the CSV records with `csv_core`, parsing the fields of the fixed schema straight from
+// the bytes of each record, without allocating them nor going through serde. Data with other
+// fields of the transactions is deserialized as usual.
+fn fast_records<'a, AR: io::AsyncRead + Send + Unpin + 'a>(
+    rdr: AR,
+    dialect: CsvDialect,
+) -> TransactionStream<'a> {
+    let records = async move {
+        let mut records = ByteRecords::new(rdr, &dialect);
+        let mut header = Vec::new();
+        let columns = match &dialect.columns {
+            Some(columns) => columns.clone(),
+            None => {
+                records.read(Some(&mut header)).await?;
+                (0..records.len)
+                    .filter_map(|i| records.field(i))
+                    .map(|field| String::from_utf8_lossy(field).trim().to_string())
+                    .collect()
+            }
+        };
+        let fields: Vec<_> = columns
+            .iter()
+            .map(|column| dialect.mapping.field(column))
+            .collect();
+        if fields
+            .iter()
+            .any(|field| DESERIALIZED_FIELDS.contains(field))
+        {
+            // The header row has been read already
+            let dialect = CsvDialect {
+                fast_parse: false,
+                ..dialect
+            };
+            let rdr: Pin<Box<dyn io::AsyncRead + Send + 'a>> =
+                Box::pin(Cursor::new(header).chain(records.rdr));
+            return Ok(CsvSource::new(rdr).dialect(dialect).into_stream());
+        }
+        let column = |names: &[&str]| fields.iter().position(|field| names.contains(field));
+        let schema = FastSchema {
+            columns: fields.len(),
+            tx_type: column(&["type", "tx_type"]),
+            client_id: column(&["client", "client_id"]),
+            tx_id: column(&["tx", "tx_id"]),
+            amount: column(&["amount"]),
+        };
+        let stream = futures::stream::unfold(Some(records), move |records| async move {
+            let mut records = records?;
+            match records.read(None).await {
+                Ok(Some((offset, line))) => {
+                    Some((schema.parse(&records, offset, line), Some(records)))
+                }
+                Ok(None) => None,
+                Err(e) => Some((Err(e.into()), None)),
+            }
+        });
+        Ok::<_, EngineError>(Box::pin(stream) as TransactionStream<'a>)
+    };
+    Box::pin(futures::stream::once(records).try_flatten())
+}
+
+// Reader of raw CSV records, reusing the buffers of their fields
+struct ByteRecords<AR> {
+    rdr: BufReader<AR>,
+    csv: csv_core::Reader,
+    // Fields of the last record read, and their ends
+    fields: Vec<u8>,
+    ends: Vec<usize>,
+    // Number of fields of the last record read
+    len: usize,
+    // Byte offset and line number of the data read next
+    offset: u64,
+    line: u64,
+}
+
+impl<AR: io::AsyncRead + Unpin> ByteRecords<AR> {
+    fn new(rdr: AR, dialect: &CsvDialect) -> Self {
+        let csv = csv_core::ReaderBuilder::new()
+            .delimiter(dialect.delimiter)
+            .quote(dialect.quote)
+            .quoting(dialect.quoting)
+            .build();
+        Self {
+            rdr: BufReader::new(rdr),
+            csv,
+            fields: vec![0; 1024],
+            ends: vec![0; 16],
+            len: 0,
+            offset: 0,
+            line: 1,
+        }
+    }
+
+    // Reads the next record, returning its byte offset and line number unless the data is over.
+    // The bytes read are appended to `raw`, if any.
+    async fn read(&mut self, mut raw: Option<&mut Vec<u8>>) -> io::Result<Option<(u64, u64)>> {
+        // As with `csv_async`, the record starts right after the terminator of the previous one,
+        // before the empty lines skipped by `csv_core`
+        let position = (self.offset, self.line);
+        let (mut nfields, mut nends) = (0, 0);
+        loop {
+            let input = self.rdr.fill_buf().await?;
+            let (result, nin, nout, nend) =
+                self.csv
+                    .read_record(input, &mut self.fields[nfields..], &mut self.ends[nends..]);
+            (nfields, nends) = (nfields + nout, nends + nend);
+            self.advance(nin, raw.as_deref_mut());
+            match result {
+                ReadRecordResult::InputEmpty => {}
+                ReadRecordResult::OutputFull => self.fields.resize(self.fields.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    self.len = nends;
+                    return Ok(Some(position));
+                }
+                ReadRecordResult::End => {
+                    self.len = 0;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    // Consumes `len` bytes of the buffered data
+    fn advance(&mut self, len: usize, raw: Option<&mut Vec<u8>>) {
+        let input = &self.rdr.buffer()[..len];
+        self.line += input.iter().filter(|&&byte| byte == b'\n').count() as u64;
+        if let Some(raw) = raw {
+            raw.extend_from_slice(input);
+        }
+        self.offset += len as u64;
+        self.rdr.consume(len);
+    }
+}
+
+impl<AR> ByteRecords<AR> {
+    // Field `i` of the last record read, if it has one
+    fn field(&self, i: usize) -> Option<&[u8]> {
+        let start = i.checked_sub(1).map_or(0, |i| self.ends[i]);
+        (i < self.len).then(|| &self.fields[start..self.ends[i]])
+    }
+}
+
+// Columns of the fields of the transactions read by the fast path
+#[derive(Debug, Clone, Copy)]
+struct FastSchema {
+    // Number of columns of the header
+    columns: usize,
+    tx_type: Option<usize>,
+    client_id: Option<usize>,
+    tx_id: Option<usize>,
+    amount: Option<usize>,
+}
+
+impl FastSchema {
+    // Parses the transaction in the last record read, at `offset` and `line`
+    fn parse<AR>(
+        &self,
+        records: &ByteRecords<AR>,
+        offset: u64,
+        line: u64,
+    ) -> Result<Transaction, EngineError> {
+        let mut fields = Vec::with_capacity(records.len);
+        for i in 0..records.len {
+            let field = std::str::from_utf8(records.field(i).unwrap_or_default())
+                .map_err(|e| record_error(line, String::new(), None, e))?;
+            fields.push(field.trim());
+        }
+        let raw = || fields.join(",");
+        if fields.iter().any(|field| field.len() > MAX_FIELD_LEN) {
+            return Err(record_error(line, raw(), None, FieldTooLong));
+        }
+        let get = |column: Option<usize>| column.and_then(|column| fields.get(column).copied());
+        let tx_type = get(self.tx_type);
+        let error = |e| record_error(line, raw(), tx_type, e);
+        // Only the amount can be missing from a short record, as when deserialized
+        if (fields.len()..self.columns).any(|column| Some(column) != self.amount) {
+            return Err(error(FieldError::EndOfRow));
+        }
+        let mut tx = Transaction::new(
+            parse_field(tx_type, "tx_type").map_err(error)?,
+            parse_field(get(self.client_id), "client_id").map_err(error)?,
+            parse_field(get(self.tx_id), "tx_id").map_err(error)?,
+            None,
+        );
+        if let Some(amount) = get(self.amount).filter(|amount| !amount.is_empty()) {
+            let amount = parse_amount(amount).map_err(|e| record_error(line, raw(), tx_type, e))?;
+            tx.amount = Some(amount);
+        }
+        tx.offset = offset;
+        Ok(tx)
+    }
+}
+
+// A field of a CSV record missing, or that can't be parsed
+#[derive(Debug, thiserror::Error)]
+enum FieldError {
+    #[error("missing field `{0}`")]
+    Missing(&'static str),
+    #[error("invalid field `{0}`: {1}")]
+    Invalid(&'static str, String),
+    #[error("unexpected end of record")]
+    EndOfRow,
+}
+
+fn parse_field<T: FromStr>(field: Option<&str>, name: &'static str) -> Result<T, FieldError>
+where
+    T::Err: std::fmt::Display,
+{
+    field
+        .ok_or(FieldError::Missing(name))?
+        .parse()
+        .map_err(|e: T::Err| FieldError::Invalid(name, e.to_string()))
+}
+
+/// Transaction records in JSON Lines format, i.e. a JSON object per line.
+pub struct JsonLinesSource<AR>(AR);
+
+impl<AR> JsonLinesSource<AR> {
+    pub fn new(rdr: AR) -> Self {
+        Self(rdr)
+    }
+}
+
+impl<'a, AR: io::AsyncRead + Send + Unpin + 'a> TransactionSource<'a> for JsonLinesSource<AR> {
+    fn into_stream(self) -> TransactionStream<'a> {
+        // Lines are yielded together with their offset and number, the reader is dropped after
+        // an error
+        let lines =
+            futures::stream::unfold(Some((BufReader::new(self.0), 0, 1)), |state| async move {
+                let (mut rdr, offset, number) = state?;
+                let mut line = String::new();
+                match rdr.read_line(&mut line).await {
+                    Ok(0) => None,
+                    Ok(len) => Some((
+                        Ok((offset, number, line)),
+                        Some((rdr, offset + len as u64, number + 1)),
+                    )),
+                    Err(e) => Some((Err(e), None)),
+                }
+            });
+        Box::pin(
+            lines
+                .filter(|line| !matches!(line, Ok((_, _, line)) if line.trim().is_empty()))
+                .map(|line| {
+                    let (offset, number, line) = line?;
+                    let mut tx: Transaction = serde_json::from_str(&line).map_err(|e| {
+                        let value = serde_json::from_str::<serde_json::Value>(&line).ok();
+                        let tx_type = value.as_ref().and_then(|value| value["type"].as_str());
+                        record_error(number, line.trim_end().into(), tx_type, e)
+                    })?;
+                    tx.offset = offset;
+                    Ok(tx)
+                }),
+        )
+    }
+}
+
+// Maximum length of a field of a CSV record, in bytes
+const MAX_FIELD_LEN: usize = 1024;
+
+// Maximum length of a raw record kept in an error, in bytes
+const MAX_RECORD_LEN: usize = 256;
+
+// A CSV record with a field longer than `MAX_FIELD_LEN`
+#[derive(Debug, thiserror::Error)]
+#[error("field longer than {MAX_FIELD_LEN} bytes")]
+struct FieldTooLong;
+
+// Error of the `record` at `line` that can't be deserialized, given its raw type if any
+fn record_error(
+    line: u64,
+    mut record: String,
+    tx_type: Option<&str>,
+    source: impl std::error::Error + Send + Sync + 'static,
+) -> EngineError {
+    if record.len() > MAX_RECORD_LEN {
+        let end = (0..=MAX_RECORD_LEN)
+            .rev()
+            .find(|&end| record.is_char_boundary(end))
+            .unwrap_or_default();
+        record.truncate(end);
+        record.push_str("...");
+    }
+    match tx_type {
+        Some(tx_type) if tx_type.parse::<TransactionType>().is_err() => {
+            EngineError::UnknownTransactionType {
+                line,
+                record,
+                tx_type: tx_type.into(),
+            }
+        }
+        _ => EngineError::MalformedRecord {
+            line,
+            record,
+            source: Box::new(source),
+        },
+    }
+}
+
+/// A reader of input data, already decompressed.
+type InputReader = Pin<Box<dyn io::AsyncRead + Send>>;
+
+// Limit of the rate the input files are read at, see `limit_read_rate`
+static READ_RATE_LIMIT: RwLock<Option<RateLimit>> = RwLock::new(None);
+
+/// Limits the rate the input files are read at, across all of them, e.g. so that reading them
+/// from shared storage doesn't starve other workloads. Unlimited by default.
+pub fn limit_read_rate(limit: Option<RateLimit>) {
+    *READ_RATE_LIMIT
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = limit;
+}
+
+// Bytes read from the input files, see `input_bytes_read`
+static INPUT_BYTES_READ: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the bytes read from the input files so far, across all of them and before any
+/// decompression, e.g. to report the progress of the processing.
+pub fn input_bytes_read() -> u64 {
+    INPUT_BYTES_READ.load(Ordering::Relaxed)
+}
+
+// Applies the limit of the rate the input files are read at, if any, to `rdr`, counting the
+// bytes read from it
+fn rate_limited<R: io::AsyncRead + Send + Unpin + 'static>(rdr: R) -> InputReader {
+    let limit = READ_RATE_LIMIT
+        .read()
+        .unwrap_or_else(PoisonError::into_inner);
+    match limit.as_ref() {
+        Some(limit) => Box::pin(Counted(RateLimited::new(rdr, limit.clone()))),
+        None => Box::pin(Counted(rdr)),
+    }
+}
+
+// A reader adding the bytes read to `INPUT_BYTES_READ`
+struct Counted<R>(R);
+
+impl<R: io::AsyncRead + Unpin> io::AsyncRead for Counted<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.0).poll_read(cx, buf))?;
+        let len = (buf.filled().len() - filled) as u64;
+        INPUT_BYTES_READ.fetch_add(len, Ordering::Relaxed);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The supported compressions of the input files. Decompression requires the `compression` feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Guesses the compression from the extension of `path`, e.g. `.csv.gz`.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("gz") => Self::Gzip,
+            Some(ext) if ["zst", "zstd"].contains(&ext.to_lowercase().as_str()) => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    /// Strips the compression extension from `path`, if any.
+    pub fn data_path(path: &Path) -> &Path {
+        match Self::from_path(path) {
+            Self::None => path,
+            _ => Path::new(path.file_stem().unwrap_or_default()),
+        }
+    }
+
+    fn decoder<R: io::AsyncBufRead + Send + Unpin + 'static>(
+        &self,
+        rdr: R,
+    ) -> Result<InputReader, EngineError> {
+        #[cfg(feature = "compression")]
+        use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+
+        match self {
+            Self::None => Ok(Box::pin(rdr)),
+            #[cfg(feature = "compression")]
+            Self::Gzip => {
+                let mut decoder = GzipDecoder::new(rdr);
+                decoder.multiple_members(true);
+                Ok(Box::pin(decoder))
+            }
+            #[cfg(feature = "compression")]
+            Self::Zstd => Ok(Box::pin(ZstdDecoder::new(rdr))),
+            #[cfg(not(feature = "compression"))]
+            Self::Gzip | Self::Zstd => Err(EngineError::IoError(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "compressed input requires the `compression` feature",
+            ))),
+        }
+    }
+
+    /// Opens the file at `path`, positioned at byte `offset` of the decompressed data.
+    async fn open_at(&self, path: &Path, offset: u64) -> Result<InputReader, EngineError> {
+        if is_object_url(path) {
+            if *self == Self::None {
+                return Ok(rate_limited(open_object(path, offset).await?));
+            }
+            // Compressed data can't be read from an offset, it's decompressed up to it instead
+            let mut rdr =
+                self.decoder(BufReader::new(rate_limited(open_object(path, 0).await?)))?;
+            io::copy(&mut (&mut rdr).take(offset), &mut io::sink()).await?;
+            return Ok(rdr);
+        }
+
+        let mut file = File::open(path).await?;
+        if *self == Self::None {
+            file.seek(SeekFrom::Start(offset)).await?;
+            return Ok(rate_limited(BufReader::new(file)));
+        }
+
+        // Compressed data can't be seeked, it's decompressed up to the offset instead
+        let mut rdr = self.decoder(BufReader::new(rate_limited(file)))?;
+        io::copy(&mut (&mut rdr).take(offset), &mut io::sink()).await?;
+        Ok(rdr)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "gzip" => Ok(Self::Gzip),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(format!("Unknown compression `{s}`")),
+        }
+    }
+}
+
+/// The supported formats of the input data
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Csv,
+    JsonLines,
+}
+
+impl InputFormat {
+    /// Guesses the format from the extension of `path`, defaulting to CSV. The compression
+    /// extension, if any, is ignored.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let path = Compression::data_path(path.as_ref());
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ["jsonl", "ndjson"].contains(&ext.to_lowercase().as_str()) => {
+                Self::JsonLines
+            }
+            _ => Self::Csv,
+        }
+    }
+
+    /// Whether the file at `path` is an input one, i.e. CSV or JSON Lines as told by its
+    /// extension, possibly compressed.
+    pub fn is_input_file(path: impl AsRef<Path>) -> bool {
+        let path = Compression::data_path(path.as_ref());
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ["csv", "jsonl", "ndjson"].contains(&ext.to_lowercase().as_str()))
+    }
+
+    /// Creates a source reading records in this format from `rdr`, in the given `dialect` if
+    /// CSV.
+    pub fn source<'a, AR: io::AsyncRead + Send + Unpin + 'a>(
+        &self,
+        rdr: AR,
+        dialect: &CsvDialect,
+    ) -> TransactionStream<'a> {
+        match self {
+            Self::Csv => CsvSource::new(rdr).dialect(dialect.clone()).into_stream(),
+            Self::JsonLines => JsonLinesSource::new(rdr).into_stream(),
+        }
+    }
+
+    /// Opens the file at `path` starting from the record at byte `offset`, as reported by
+    /// [`Transaction::offset`]. Offsets of the records read are still relative to the whole
+    /// (decompressed) file.
+    pub async fn open_at(
+        &self,
+        path: impl AsRef<Path>,
+        compression: Compression,
+        dialect: &CsvDialect,
+        offset: u64,
+    ) -> Result<TransactionStream<'static>, EngineError> {
+        let path = path.as_ref();
+        if offset == 0 {
+            return Ok(self.source(compression.open_at(path, 0).await?, dialect));
+        }
+
+        let (records, skipped) = match self {
+            Self::Csv if dialect.columns.is_none() => {
+                // The header row is still needed to deserialize the records
+                let mut headers = String::new();
+                BufReader::new(compression.open_at(path, 0).await?)
+                    .read_line(&mut headers)
+                    .await?;
+                let len = headers.len() as u64;
+                let rdr = Cursor::new(headers.into_bytes())
+                    .chain(compression.open_at(path, offset).await?);
+                (self.source(rdr, dialect), len)
+            }
+            Self::Csv | Self::JsonLines => (
+                self.source(compression.open_at(path, offset).await?, dialect),
+                0,
+            ),
+        };
+        Ok(Box::pin(records.map(move |tx| {
+            tx.map(|mut tx| {
+                tx.offset = tx.offset - skipped + offset;
+                tx
+            })
+        })))
+    }
+
+    /// Opens the uncompressed file at `path` and follows it as it grows, like `tail -f`: the
+    /// stream waits for records appended to the file instead of ending, checking for them every
+    /// `poll_interval`.
+    pub async fn follow(
+        &self,
+        path: impl AsRef<Path>,
+        dialect: &CsvDialect,
+        poll_interval: Duration,
+    ) -> Result<TransactionStream<'static>, EngineError> {
+        let file = rate_limited(File::open(path).await?);
+        Ok(self.source(Follow::new(file, poll_interval), dialect))
+    }
+
+    /// Opens the uncompressed local file at `path` split in chunks of about `chunk_size` bytes,
+    /// each starting at the beginning of a line, up to `parallelism` of them being parsed
+    /// concurrently. Records are still yielded in the order of the file, with their offset in it,
+    /// but the line numbers of the invalid ones are relative to their chunk.
+    ///
+    /// Records are expected not to span several lines, e.g. with line breaks in quoted fields.
+    pub async fn open_chunked(
+        &self,
+        path: impl AsRef<Path>,
+        dialect: &CsvDialect,
+        chunk_size: u64,
+        parallelism: usize,
+    ) -> Result<TransactionStream<'static>, EngineError> {
+        let path = path.as_ref().to_path_buf();
+        let len = tokio::fs::metadata(&path).await?.len();
+        let mut rdr = BufReader::new(File::open(&path).await?);
+        // The header row is prepended to each chunk to deserialize its records
+        let mut headers = Vec::new();
+        if *self == Self::Csv && dialect.columns.is_none() {
+            rdr.read_until(b'\n', &mut headers).await?;
+        }
+
+        // Each chunk ends after the first line break past its size
+        let mut bounds = vec![headers.len() as u64];
+        let mut line = Vec::new();
+        loop {
+            let end = bounds[bounds.len() - 1] + chunk_size.max(1);
+            if end >= len {
+                break;
+            }
+            rdr.seek(SeekFrom::Start(end - 1)).await?;
+            line.clear();
+            let end = end - 1 + rdr.read_until(b'\n', &mut line).await? as u64;
+            if end >= len {
+                break;
+            }
+            bounds.push(end);
+        }
+        bounds.push(len);
+
+        let format = *self;
+        let dialect = dialect.clone();
+        let headers: Arc<[u8]> = headers.into();
+        let chunks: Vec<(u64, u64)> = bounds.windows(2).map(|b| (b[0], b[1])).collect();
+        let chunks = futures::stream::iter(chunks).map(move |(start, end)| {
+            let (path, dialect, headers) = (path.clone(), dialect.clone(), headers.clone());
+            tokio::spawn(async move {
+                let mut file = File::open(&path).await?;
+                file.seek(SeekFrom::Start(start)).await?;
+                let skipped = headers.len() as u64;
+                let rdr = Cursor::new(headers).chain(rate_limited(file.take(end - start)));
+                let records: Vec<_> = format
+                    .source(rdr, &dialect)
+                    .map(|tx| {
+                        tx.map(|mut tx| {
+                            tx.offset = tx.offset - skipped + start;
+                            tx
+                        })
+                    })
+                    .collect()
+                    .await;
+                Ok::<_, EngineError>(records)
+            })
+        });
+        let chunks = futures::StreamExt::buffered(chunks, parallelism.max(1));
+        let records = futures::StreamExt::flat_map(chunks, |chunk| {
+            futures::stream::iter(match chunk {
+                Ok(Ok(records)) => records,
+                Ok(Err(e)) => vec![Err(e)],
+                Err(e) => vec![Err(io::Error::other(e).into())],
+            })
+        });
+        Ok(Box::pin(records))
+    }
+}
+
+/// A reader of data growing while read: at its end, it waits for more data instead of reporting
+/// the end of the data, so reading never ends.
+pub struct Follow<R> {
+    rdr: R,
+    poll_interval: Duration,
+    // Wait before reading again, once at the end of the data
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> Follow<R> {
+    pub fn new(rdr: R, poll_interval: Duration) -> Self {
+        Self {
+            rdr,
+            poll_interval,
+            sleep: None,
+        }
+    }
+}
+
+impl<R: io::AsyncRead + Unpin> io::AsyncRead for Follow<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if let Some(sleep) = &mut self.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                self.sleep = None;
+            }
+            let filled = buf.filled().len();
+            ready!(Pin::new(&mut self.rdr).poll_read(cx, buf))?;
+            if buf.filled().len() > filled || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            self.sleep = Some(Box::pin(tokio::time::sleep(self.poll_interval)));
+        }
+    }
+}
+
+/// A limit of the rate data is read at, in bytes per second, shared by all the readers it's
+/// applied to, see [`RateLimited`].
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    bytes_per_sec: f64,
+    // When the data read so far is within the limit, i.e. when reading can go on
+    ready_at: Arc<Mutex<Instant>>,
+}
+
+impl RateLimit {
+    pub fn new(bytes_per_sec: f64) -> Self {
+        Self {
+            bytes_per_sec,
+            ready_at: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    // Returns when reading can go on
+    fn ready_at(&self) -> Instant {
+        *self.ready_at.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Accounts for `len` bytes just read
+    fn consume(&self, len: usize) {
+        let mut ready_at = self.ready_at.lock().unwrap_or_else(PoisonError::into_inner);
+        let elapsed = Duration::from_secs_f64(len as f64 / self.bytes_per_sec);
+        *ready_at = (*ready_at).max(Instant::now()) + elapsed;
+    }
+}
+
+/// A reader within a [`RateLimit`]: once data is read, reading again waits for the time it
+/// takes to read it at the limit.
+pub struct RateLimited<R> {
+    rdr: R,
+    limit: RateLimit,
+    // Wait before reading again, once over the limit
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> RateLimited<R> {
+    pub fn new(rdr: R, limit: RateLimit) -> Self {
+        Self {
+            rdr,
+            limit,
+            sleep: None,
+        }
+    }
+}
+
+impl<R: io::AsyncRead + Unpin> io::AsyncRead for RateLimited<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if let Some(sleep) = &mut self.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                self.sleep = None;
+            }
+            let ready_at = self.limit.ready_at();
+            if ready_at > Instant::now() {
+                self.sleep = Some(Box::pin(tokio::time::sleep_until(ready_at)));
+                continue;
+            }
+            let filled = buf.filled().len();
+            ready!(Pin::new(&mut self.rdr).poll_read(cx, buf))?;
+            self.limit.consume(buf.filled().len() - filled);
+            return Poll::Ready(Ok(()));
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::JsonLines),
+            _ => Err(format!("Unknown input format `{s}`")),
+        }
+    }
+}
+
+/// Reads and deserializes the records of `source` in a separate task, ahead of the processing:
+/// up to `depth` records are buffered, so that parsing them overlaps with applying the previous
+/// ones. An error ends the stream, as for the other sources.
+pub fn pipelined(
+    mut source: TransactionStream<'static>,
+    depth: usize,
+) -> TransactionStream<'static> {
+    let (sender, receiver) = mpsc::channel(depth.max(1));
+    tokio::spawn(async move {
+        while let Some(record) = source.next().await {
+            let record = match sender.try_send(record) {
+                Ok(()) => continue,
+                Err(TrySendError::Full(record)) => record,
+                // Stop reading once the records are not consumed anymore
+                Err(TrySendError::Closed(_)) => break,
+            };
+            metrics::record_stall("pipeline");
+            if sender.send(record).await.is_err() {
+                break;
+            }
+        }
+    });
+    Box::pin(ReceiverStream::new(receiver))
+}
+
+/// The order the input files found in a directory are processed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileOrder {
+    /// Lexicographic order of the file names.
+    #[default]
+    Name,
+    /// Order of last modification, the oldest file first.
+    Mtime,
+}
+
+impl FromStr for FileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "mtime" => Ok(Self::Mtime),
+            _ => Err(format!("Unknown file order `{s}`")),
+        }
+    }
+}
+
+/// Lists the input files in the directory at `dir`, see [`InputFormat::is_input_file`], in the
+/// given `order`. Subdirectories are ignored.
+pub async fn list_input_files(
+    dir: impl AsRef<Path>,
+    order: FileOrder,
+) -> Result<Vec<PathBuf>, EngineError> {
+    let mut files = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() && InputFormat::is_input_file(entry.path()) {
+            files.push((metadata.modified()?, entry.path()));
+        }
+    }
+    match order {
+        FileOrder::Name => files.sort_by(|(_, a), (_, b)| a.cmp(b)),
+        FileOrder::Mtime => files.sort(),
+    }
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Opens the files at `paths` as a single source, reading them one after the other. The format
+/// and compression of each file are guessed from its extension if not specified, CSV files
+/// being read in the given `dialect`. Paths can also be the URLs of objects in object storage,
+/// see [`is_object_url`].
+///
+/// Offsets of the records are relative to the concatenation of the files, and reading starts
+/// from the record at `offset`, see [`InputFormat::open_at`].
+pub async fn open_files<P: AsRef<Path>>(
+    paths: &[P],
+    format: Option<InputFormat>,
+    compression: Option<Compression>,
+    dialect: &CsvDialect,
+    offset: u64,
+) -> Result<TransactionStream<'static>, EngineError> {
+    open_files_at(paths, format, compression, dialect, offset, None).await
+}
+
+/// Opens the files at `paths` as a single source, as [`open_files`] does, parsing the
+/// uncompressed local ones in chunks of about `chunk_size` bytes, up to `parallelism` of them
+/// concurrently, see [`InputFormat::open_chunked`].
+pub async fn open_files_chunked<P: AsRef<Path>>(
+    paths: &[P],
+    format: Option<InputFormat>,
+    compression: Option<Compression>,
+    dialect: &CsvDialect,
+    chunk_size: u64,
+    parallelism: usize,
+) -> Result<TransactionStream<'static>, EngineError> {
+    let chunks = Some((chunk_size, parallelism));
+    open_files_at(paths, format, compression, dialect, 0, chunks).await
+}
+
+// Opens the files at `paths` as a single source from `offset`, the uncompressed local ones
+// being parsed in chunks if their size and the parallelism are given
+async fn open_files_at<P: AsRef<Path>>(
+    paths: &[P],
+    format: Option<InputFormat>,
+    compression: Option<Compression>,
+    dialect: &CsvDialect,
+    offset: u64,
+    chunks: Option<(u64, usize)>,
+) -> Result<TransactionStream<'static>, EngineError> {
+    let mut records: TransactionStream<'static> = Box::pin(tokio_stream::empty());
+    let mut base = 0;
+    for (idx, path) in paths.iter().enumerate() {
+        let path = path.as_ref();
+        let compression = compression.unwrap_or_else(|| Compression::from_path(path));
+        let len = match compression {
+            Compression::None if is_object_url(path) => object_len(path).await?,
+            Compression::None => tokio::fs::metadata(path).await?.len(),
+            // The size of the decompressed data is only known once read, and it's needed only
+            // to compute the offsets of the files following this one
+            _ if idx + 1 == paths.len() => u64::MAX - base,
+            _ => {
+                let mut rdr = compression.open_at(path, 0).await?;
+                io::copy(&mut rdr, &mut io::sink()).await?
+            }
+        };
+        // Files entirely before the offset have already been read
+        if offset < base + len {
+            let format = format.unwrap_or_else(|| InputFormat::from_path(path));
+            let file_records = match chunks {
+                Some((chunk_size, parallelism))
+                    if offset <= base
+                        && compression == Compression::None
+                        && !is_object_url(path) =>
+                {
+                    format
+                        .open_chunked(path, dialect, chunk_size, parallelism)
+                        .await?
+                }
+                _ => {
+                    format
+                        .open_at(path, compression, dialect, offset.saturating_sub(base))
+                        .await?
+                }
+            };
+            let file_records = FileRecords {
+                records: file_records,
+                span: info_span!("ingest", path = %path.display()),
+                rows: 0,
+                invalid: 0,
+            };
+            let file_base = base;
+            records = Box::pin(records.chain(file_records.map(move |tx| {
+                tx.map(|mut tx| {
+                    tx.offset += file_base;
+                    tx
+                })
+            })));
+        }
+        base = base.saturating_add(len);
+    }
+    Ok(records)
+}
+
+// Records of an input file, read within the span of its ingestion. A summary of the file is
+// logged once read.
+struct FileRecords {
+    records: TransactionStream<'static>,
+    span: Span,
+    // Records read, and the invalid ones among them
+    rows: u64,
+    invalid: u64,
+}
+
+impl Stream for FileRecords {
+    type Item = Result<Transaction, EngineError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let _entered = this.span.enter();
+        let next = this.records.as_mut().poll_next(cx);
+        match &next {
+            Poll::Ready(Some(record)) => {
+                this.rows += 1;
+                if record.as_ref().is_err_and(EngineError::is_invalid_record) {
+                    this.invalid += 1;
+                }
+            }
+            Poll::Ready(None) => info!(rows = this.rows, invalid = this.invalid, "File read"),
+            Poll::Pending => {}
+        }
+        next
+    }
+}
+
+/// How records coming from several sources are merged into a single stream.
+///
+/// Whatever the policy, records of the same source are always applied in their original order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Sources take turns, one ready record at a time.
+    #[default]
+    Interleave,
+    /// Sources are polled in the order they are given: a record is taken from a source only
+    /// if all the sources before it have no record ready.
+    Priority,
+}
+
+impl FromStr for MergePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interleave" => Ok(Self::Interleave),
+            "priority" => Ok(Self::Priority),
+            _ => Err(format!("Unknown merge policy `{s}`")),
+        }
+    }
+}
+
+/// Statistics about the records read from a single source.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceStats {
+    /// Records read from the source
+    pub rows: u64,
+    /// Records successfully applied to the accounts
+    pub applied: u64,
+    /// Records rejected by the engine
+    pub rejected: u64,
+    /// Invalid records skipped, see [`EngineBuilder::skip_invalid`](super::EngineBuilder::skip_invalid)
+    pub invalid: u64,
+    /// Records skipped being outside the time window, see [`EngineBuilder::window`](super::EngineBuilder::window)
+    pub out_of_window: u64,
+    /// Records skipped being of clients or types not selected, see [`EngineBuilder::clients`](super::EngineBuilder::clients)
+    /// and [`EngineBuilder::types`](super::EngineBuilder::types)
+    pub filtered: u64,
+    /// Records skipped by the hooks, see [`EngineBuilder::hooks`](super::EngineBuilder::hooks)
+    pub skipped: u64,
+    /// Maximum number of records of other sources processed between two consecutive records
+    /// of this source, i.e. how much the source has been lagging behind the others.
+    pub max_lag: u64,
+    // Position in the merged stream of the last record read from the source
+    #[serde(skip)]
+    last_seen: Option<u64>,
+}
+
+impl SourceStats {
+    /// Registers a record read from the source at position `pos` of the merged stream.
+    pub fn record_read(&mut self, pos: u64) {
+        let lag = match self.last_seen {
+            Some(last) => pos - last - 1,
+            None => pos,
+        };
+        self.max_lag = self.max_lag.max(lag);
+        self.last_seen = Some(pos);
+        self.rows += 1;
+    }
+}
+
+/// Window of time the transactions applied while processing sources must be in, from `from`
+/// (inclusive) to `to` (exclusive). Transactions without a timestamp are always in the window.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    /// Start of the window, in seconds since the Unix epoch
+    pub from: Option<u64>,
+    /// End of the window, in seconds since the Unix epoch
+    pub to: Option<u64>,
+}
+
+impl TimeWindow {
+    /// Whether a transaction with the given timestamp is in the window.
+    pub fn contains(&self, timestamp: Option<u64>) -> bool {
+        let Some(timestamp) = timestamp else {
+            return true;
+        };
+        self.from.is_none_or(|from| timestamp >= from) && self.to.is_none_or(|to| timestamp < to)
+    }
+}
+
+/// Merges several transaction sources into a single stream, following a [`MergePolicy`].
+///
+/// Every record is yielded together with the index of the source it comes from.
+pub struct MergedSource<'a> {
+    // Sources still producing records, `None` once exhausted
+    sources: Vec<Option<TransactionStream<'a>>>,
+    policy: MergePolicy,
+    next: usize,
+    // Completes when reading has to stop, before the sources are exhausted
+    stop: Option<BoxFuture<'a, ()>>,
+    interrupted: bool,
+}
+
+impl<'a> MergedSource<'a> {
+    pub fn new(sources: Vec<TransactionStream<'a>>, policy: MergePolicy) -> Self {
+        Self {
+            sources: sources.into_iter().map(Some).collect(),
+            policy,
+            next: 0,
+            stop: None,
+            interrupted: false,
+        }
+    }
+
+    /// Stops reading the sources once `stop` completes, ending the stream.
+    pub fn until(mut self, stop: impl Future<Output = ()> + Send + 'a) -> Self {
+        self.stop = Some(Box::pin(stop));
+        self
+    }
+
+    /// Whether the stream has been ended by the future set with [`MergedSource::until`], with
+    /// records possibly left in the sources.
+    pub fn interrupted(&self) -> bool {
+        self.interrupted
+    }
+}
+
+impl<'a> Stream for MergedSource<'a> {
+    type Item = (usize, Result<Transaction, EngineError>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(stop) = &mut self.stop {
+            if stop.as_mut().poll(cx).is_ready() {
+                self.stop = None;
+                self.interrupted = self.sources.iter().any(Option::is_some);
+                self.sources.clear();
+            }
+        }
+        let len = self.sources.len();
+        let start = match self.policy {
+            MergePolicy::Interleave => self.next,
+            MergePolicy::Priority => 0,
+        };
+
+        let mut pending = false;
+        for offset in 0..len {
+            let idx = (start + offset) % len;
+            let Some(source) = self.sources[idx].as_mut() else {
+                continue;
+            };
+            match source.as_mut().poll_next(cx) {
+                Poll::Ready(Some(record)) => {
+                    self.next = (idx + 1) % len;
+                    return Poll::Ready(Some((idx, record)));
+                }
+                Poll::Ready(None) => self.sources[idx] = None,
+                Poll::Pending => pending = true,
+            }
+        }
+
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod source_tests {
+    use super::*;
+    use crate::engine::{amount::Decimal, model::TransactionType};
+
+    #[tokio::test]
+    async fn test_compressed_paths() {
+        assert_eq!(Compression::Gzip, Compression::from_path("day1.csv.gz"));
+        assert_eq!(Compression::Zstd, Compression::from_path("day1.jsonl.ZST"));
+        assert_eq!(Compression::None, Compression::from_path("day1.csv"));
+        assert_eq!(
+            InputFormat::JsonLines,
+            InputFormat::from_path("day1.jsonl.gz")
+        );
+        assert_eq!(InputFormat::Csv, InputFormat::from_path("day1.csv.zst"));
+
+        #[cfg(not(feature = "compression"))]
+        assert!(Compression::Gzip
+            .open_at(Path::new("res/transactions.csv"), 0)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_malformed_records() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\nrefund,1,2,1.0\ndeposit,x,3,1.0\n";
+        let records: Vec<_> = CsvSource::new(data.as_bytes())
+            .into_stream()
+            .collect()
+            .await;
+        assert!(records[0].is_ok());
+        assert!(matches!(
+            &records[1],
+            Err(EngineError::UnknownTransactionType { line: 3, tx_type, .. }) if tx_type == "refund"
+        ));
+        assert!(matches!(
+            &records[2],
+            Err(EngineError::MalformedRecord { line: 4, record, .. }) if record == "deposit,x,3,1.0"
+        ));
+
+        let data = "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1}\n\n{\"type\": \"refund\"}\n";
+        let records: Vec<_> = JsonLinesSource::new(data.as_bytes())
+            .into_stream()
+            .collect()
+            .await;
+        assert!(records[0].is_ok());
+        assert!(matches!(
+            &records[1],
+            Err(EngineError::UnknownTransactionType { line: 3, tx_type, .. }) if tx_type == "refund"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_open_files() {
+        let dir = std::env::temp_dir().join(format!("tpe_source_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = [dir.join("day1.csv"), dir.join("day2.jsonl")];
+        std::fs::write(
+            &paths[0],
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &paths[1],
+            "{\"type\": \"deposit\", \"client\": 1, \"tx\": 3, \"amount\": 1.0}\n{\"type\": \"deposit\", \"client\": 1, \"tx\": 4, \"amount\": 1.0}\n",
+        )
+        .unwrap();
+
+        let records: Vec<_> = open_files(&paths, None, None, &CsvDialect::default(), 0)
+            .await
+            .unwrap()
+            .map(|record| record.unwrap())
+            .collect()
+            .await;
+        let tx_ids: Vec<_> = records.iter().map(|tx| tx.tx_id).collect();
+        assert_eq!(vec![1, 2, 3, 4], tx_ids);
+
+        // Resuming from the offset of a record of the second file
+        let resumed: Vec<_> = open_files(
+            &paths,
+            None,
+            None,
+            &CsvDialect::default(),
+            records[3].offset,
+        )
+        .await
+        .unwrap()
+        .map(|record| record.unwrap().tx_id)
+        .collect()
+        .await;
+        assert_eq!(vec![4], resumed);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_open_chunked() {
+        let dir = std::env::temp_dir().join(format!("tpe_chunked_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("transactions.csv");
+        let mut data = String::from("type,client,tx,amount\n");
+        for tx in 1..=100 {
+            data.push_str(&format!("deposit,{},{tx},{}.5\n", tx % 7, tx * 3));
+        }
+        std::fs::write(&path, data).unwrap();
+
+        let read = |records: TransactionStream<'static>| {
+            records
+                .map(|record| {
+                    let tx = record.unwrap();
+                    (tx.tx_id, tx.client_id, tx.amount, tx.offset)
+                })
+                .collect::<Vec<_>>()
+        };
+        let dialect = CsvDialect::default();
+        let expected = read(open_files(&[&path], None, None, &dialect, 0).await.unwrap()).await;
+        assert_eq!(100, expected.len());
+        // Chunks ending in the middle of lines, and of a single line
+        for chunk_size in [1, 7, 50, 10_000] {
+            let records = InputFormat::Csv
+                .open_chunked(&path, &dialect, chunk_size, 4)
+                .await
+                .unwrap();
+            assert_eq!(expected, read(records).await);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_list_input_files() {
+        let dir = std::env::temp_dir().join(format!("tpe_list_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub.csv")).unwrap();
+        for name in ["b.csv", "a.jsonl", "c.csv.gz", "notes.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let files = list_input_files(&dir, FileOrder::Name).await.unwrap();
+        let names: Vec<_> = files.iter().map(|path| path.file_name().unwrap()).collect();
+        assert_eq!(vec!["a.jsonl", "b.csv", "c.csv.gz"], names);
+        let files = list_input_files(&dir, FileOrder::Mtime).await.unwrap();
+        assert_eq!(3, files.len());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    async fn merged_order(policy: MergePolicy) -> Vec<(usize, u32)> {
+        let first = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,1.0\ndeposit,1,3,1.0";
+        let second = "type,client,tx,amount\ndeposit,2,10,1.0\ndeposit,2,11,1.0";
+        let merged = MergedSource::new(
+            vec![
+                CsvSource::new(first.as_bytes()).into_stream(),
+                CsvSource::new(second.as_bytes()).into_stream(),
+            ],
+            policy,
+        );
+
+        merged
+            .map(|(source, record)| (source, record.unwrap().tx_id))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_interleave() {
+        let order = merged_order(MergePolicy::Interleave).await;
+        assert_eq!(vec![(0, 1), (1, 10), (0, 2), (1, 11), (0, 3)], order);
+    }
+
+    #[tokio::test]
+    async fn test_json_lines() {
+        let data = r#"{"type": "deposit", "client": 1, "tx": 1, "amount": 1.5}
+
+{"type": "dispute", "client": 1, "tx": 1}
+{"type": "resolve", "client": 1, "tx": 1, "amount": null}"#;
+        let records: Vec<_> = JsonLinesSource::new(data.as_bytes())
+            .into_stream()
+            .collect::<Result<_, _>>()
+            .await
+            .unwrap();
+
+        assert_eq!(3, records.len());
+        assert_eq!(Some(Decimal::new(15, 1)), records[0].amount);
+        assert_eq!(TransactionType::Dispute, records[1].tx_type);
+        assert_eq!(None, records[2].amount);
+    }
+
+    #[tokio::test]
+    async fn test_csv_dialect() {
+        let data = "'deposit';1;1;1.5\nwithdrawal;1;2;1\n";
+        let dialect = CsvDialect {
+            delimiter: b';',
+            quote: b'\'',
+            columns: Some(CsvDialect::DEFAULT_COLUMNS.map(String::from).to_vec()),
+            ..Default::default()
+        };
+        let records: Vec<_> = CsvSource::new(data.as_bytes())
+            .dialect(dialect)
+            .into_stream()
+            .collect::<Result<_, _>>()
+            .await
+            .unwrap();
+
+        assert_eq!(2, records.len());
+        assert_eq!(TransactionType::Deposit, records[0].tx_type);
+        assert_eq!(Some(Decimal::ONE), records[1].amount);
+    }
+
+    #[tokio::test]
+    async fn test_exact_amounts() {
+        let data = "type,client,tx,amount\ndeposit,1,1,12345678901234.5678\ndeposit,1,2,0.1003\n";
+        let amounts = |float_amounts| async move {
+            let dialect = CsvDialect {
+                float_amounts,
+                ..Default::default()
+            };
+            CsvSource::new(data.as_bytes())
+                .dialect(dialect)
+                .into_stream()
+                .map(|tx| tx.unwrap().amount.unwrap())
+                .collect::<Vec<_>>()
+                .await
+        };
+
+        let exact = amounts(false).await;
+        assert_eq!(Decimal::new(123456789012345678, 4), exact[0]);
+        assert_eq!(Decimal::new(1003, 4), exact[1]);
+        assert_ne!(exact[0], amounts(true).await[0]);
+
+        let data = "type,client,tx,amount\ndeposit,1,1,0.00000000000000000000000000001\n";
+        let records: Vec<_> = CsvSource::new(data.as_bytes())
+            .into_stream()
+            .collect()
+            .await;
+        assert!(matches!(
+            records[0],
+            Err(EngineError::MalformedRecord { line: 2, .. })
+        ));
+
+        let tx: Transaction =
+            serde_json::from_str(r#"{"type":"deposit","client":1,"tx":1,"amount":"0.1003"}"#)
+                .unwrap();
+        assert_eq!(Some(Decimal::new(1003, 4)), tx.amount);
+    }
+
+    #[tokio::test]
+    async fn test_fast_parse() {
+        let records = |data: &'static [u8], dialect: CsvDialect| async move {
+            let parse = |fast_parse| {
+                let dialect = CsvDialect {
+                    fast_parse,
+                    ..dialect.clone()
+                };
+                CsvSource::new(data)
+                    .dialect(dialect)
+                    .into_stream()
+                    .map(|record| match record {
+                        Ok(tx) => format!("{tx:?}"),
+                        Err(EngineError::MalformedRecord { line, record, .. }) => {
+                            format!("malformed {line}: {record}")
+                        }
+                        Err(e) => format!("{e}"),
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let records = parse(true).await;
+            assert_eq!(parse(false).await, records);
+            records
+        };
+
+        let long = "1".repeat(MAX_FIELD_LEN + 1);
+        let mut data = format!(
+            " type , client,tx,amount,note\r\ndeposit,1,1,1.5,x\r\n\r\n\"withdrawal\", 1 ,2,\"0.5\"\n\
+             dispute,1,1,,\nrefund,1,3,1\ndeposit,x,4,1\ndeposit,1\ndeposit,1,5,1.2.3\n\
+             deposit,1,6,{long}\ndeposit,2,7,0.0001\n"
+        )
+        .into_bytes();
+        data.extend_from_slice(b"deposit,1,8,\xff\n");
+        let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+        let fast = records(data, CsvDialect::default()).await;
+        assert_eq!(10, fast.len());
+        // The record starts after the `\r` ending the header, as reported by `csv_async`
+        assert!(fast[0].contains("offset: 30"));
+        assert!(fast[3].starts_with("Unknown transaction type"));
+        assert_eq!("malformed 8: deposit,1", fast[5]);
+
+        let data = b"client,type,tx,amount,currency\n1,deposit,1,1.0,EUR\n";
+        assert!(records(data, CsvDialect::default()).await[0].contains("EUR"));
+
+        let dialect = CsvDialect {
+            delimiter: b';',
+            columns: Some(CsvDialect::DEFAULT_COLUMNS.map(String::from).to_vec()),
+            ..Default::default()
+        };
+        assert_eq!(
+            2,
+            records(b"deposit;1;1;1.5\nwithdrawal;1;2;1\n", dialect)
+                .await
+                .len()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_follow() {
+        let dir = std::env::temp_dir().join(format!("tpe_follow_{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("tx.csv");
+        tokio::fs::write(&path, "type,client,tx,amount\ndeposit,1,1,1.0\n")
+            .await
+            .unwrap();
+        let mut records = InputFormat::Csv
+            .follow(&path, &CsvDialect::default(), Duration::from_millis(10))
+            .await
+            .unwrap();
+        assert_eq!(1, records.next().await.unwrap().unwrap().tx_id);
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .await
+            .unwrap();
+        tokio::io::AsyncWriteExt::write_all(&mut file, b"deposit,1,2,1.0\n")
+            .await
+            .unwrap();
+        let next = tokio::time::timeout(Duration::from_secs(5), records.next()).await;
+        assert_eq!(2, next.unwrap().unwrap().unwrap().tx_id);
+        tokio::fs::remove_dir_all(dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited() {
+        // 3 reads of 1000 bytes at 10000 bytes per second, the first one not waiting
+        let limit = RateLimit::new(10_000.0);
+        let data = vec![b'x'; 3000];
+        let mut rdr = RateLimited::new(data.as_slice(), limit.clone());
+        let start = Instant::now();
+        let mut buf = [0; 1000];
+        let mut read = 0;
+        while read < data.len() {
+            read += rdr.read(&mut buf).await.unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(190));
+
+        // The limit is shared with the other readers
+        let mut other = RateLimited::new(data.as_slice(), limit);
+        let start = Instant::now();
+        assert_eq!(1000, other.read(&mut buf).await.unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn test_pipelined() {
+        let data = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,x,1.0\ndeposit,1,3,2.0";
+        let records: Vec<_> = pipelined(CsvSource::new(data.as_bytes()).into_stream(), 1)
+            .collect()
+            .await;
+
+        assert_eq!(3, records.len());
+        assert_eq!(1, records[0].as_ref().unwrap().tx_id);
+        assert!(matches!(
+            records[1],
+            Err(EngineError::MalformedRecord { line: 3, .. })
+        ));
+        assert_eq!(3, records[2].as_ref().unwrap().tx_id);
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(InputFormat::JsonLines, InputFormat::from_path("tx.JSONL"));
+        assert_eq!(InputFormat::Csv, InputFormat::from_path("tx.csv"));
+    }
+
+    #[test]
+    fn test_source_stats_lag() {
+        let mut stats = SourceStats::default();
+        for pos in [1, 2, 6, 7] {
+            stats.record_read(pos);
+        }
+        assert_eq!(4, stats.rows);
+        assert_eq!(3, stats.max_lag);
+    }
+
+    #[tokio::test]
+    async fn test_priority() {
+        let order = merged_order(MergePolicy::Priority).await;
+        assert_eq!(vec![(0, 1), (0, 2), (0, 3), (1, 10), (1, 11)], order);
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+};
+
+use super::{
+    error::EngineError,
+    model::{ClientAccount, StoredTx},
+    store::TransactionState,
+};
+
+// Approximate memory taken by a transaction kept in an account history, with its place in
+// the eviction order
+const TX_SIZE: usize =
+    std::mem::size_of::<(u32, StoredTx)>() + 2 * std::mem::size_of::<(u64, (u16, u32))>() + 32;
+
+// Approximate memory taken by the index entry of a spilled transaction
+const INDEX_ENTRY_SIZE: usize = std::mem::size_of::<((u16, u32), (u64, u32))>() + 8;
+
+/// Transactions history spilled to disk while processing sources, once the transactions kept
+/// in memory exceed a budget. The least recently used ones are spilled first, and loaded back
+/// as soon as a record references them.
+///
+/// The budget covers the transactions kept in memory and the index of the spilled ones, as
+/// estimated from their size, not the balances of the accounts. Once the index alone exceeds
+/// it, all the transactions are spilled and memory still grows with the index. Transactions
+/// loaded back leave a gap in the file, which is compacted once the gaps take more space than
+/// the transactions still spilled.
+#[derive(Debug)]
+pub(super) struct Spill {
+    path: PathBuf,
+    // Created on the first transaction spilled
+    file: Option<File>,
+    // Offset and length in the file of the spilled transactions, by client and tx id
+    index: HashMap<(u16, u32), (u64, u32)>,
+    // Transactions in memory by the time they have been last used, and the other way around
+    recency: BTreeMap<u64, (u16, u32)>,
+    last_used: HashMap<(u16, u32), u64>,
+    // Incremented on every use of a transaction
+    clock: u64,
+    max_bytes: usize,
+    // Bytes of the file taken by the spilled transactions, and by the ones loaded back
+    live: u64,
+    garbage: u64,
+}
+
+impl Spill {
+    pub(super) fn new(path: PathBuf, max_bytes: usize) -> Self {
+        Self {
+            path,
+            file: None,
+            index: HashMap::new(),
+            recency: BTreeMap::new(),
+            last_used: HashMap::new(),
+            clock: 0,
+            max_bytes,
+            live: 0,
+            garbage: 0,
+        }
+    }
+
+    /// Number of transactions currently spilled to disk.
+    pub(super) fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Registers a transaction just added to the history of an account.
+    pub(super) fn track(&mut self, client_id: u16, tx_id: u32) {
+        self.clock += 1;
+        if let Some(used) = self.last_used.insert((client_id, tx_id), self.clock) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.clock, (client_id, tx_id));
+    }
+
+    /// Loads back in the account history the transaction with id `tx_id` of the client, if
+    /// it has been spilled, marking it as the most recently used.
+    pub(super) fn reload(
+        &mut self,
+        accounts: &mut HashMap<u16, ClientAccount>,
+        client_id: u16,
+        tx_id: u32,
+    ) -> Result<(), EngineError> {
+        if self.last_used.contains_key(&(client_id, tx_id)) {
+            self.track(client_id, tx_id);
+            return Ok(());
+        }
+        let Some((offset, len)) = self.index.remove(&(client_id, tx_id)) else {
+            return Ok(());
+        };
+        self.live -= u64::from(len);
+        self.garbage += u64::from(len);
+        let (_, tx) = self.read(offset, len)?.into_stored();
+        if let Some(account) = accounts.get_mut(&client_id) {
+            account.txs.insert(tx_id, tx);
+            self.track(client_id, tx_id);
+        }
+        Ok(())
+    }
+
+    /// Spills the least recently used transactions, until the ones in memory and the index
+    /// of the spilled ones are within the budget.
+    pub(super) fn evict(
+        &mut self,
+        accounts: &mut HashMap<u16, ClientAccount>,
+    ) -> Result<(), EngineError> {
+        while self.memory() > self.max_bytes {
+            let Some((_, (client_id, tx_id))) = self.recency.pop_first() else {
+                break;
+            };
+            self.last_used.remove(&(client_id, tx_id));
+            let Some(tx) = accounts
+                .get_mut(&client_id)
+                .and_then(|account| account.txs.remove(&tx_id))
+            else {
+                continue;
+            };
+            let entry = self.write(&TransactionState::new(tx_id, &tx))?;
+            self.index.insert((client_id, tx_id), entry);
+        }
+        if self.garbage > self.live {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    // Estimated memory taken by the transactions in memory and the index of the spilled ones
+    fn memory(&self) -> usize {
+        self.last_used.len() * TX_SIZE + self.index.len() * INDEX_ENTRY_SIZE
+    }
+
+    /// Returns the transaction with id `tx_id` of the client, if it has been spilled. It's read
+    /// from the file without being loaded back, e.g. to report it once the sources are processed.
+    pub(super) fn get(&self, client_id: u16, tx_id: u32) -> Result<Option<StoredTx>, EngineError> {
+        self.index
+            .get(&(client_id, tx_id))
+            .map(|&(offset, len)| Ok(self.read(offset, len)?.into_stored().1))
+            .transpose()
+    }
+
+    /// Adds all the spilled transactions to the history of their accounts.
+    pub(super) fn restore_all(
+        &self,
+        accounts: &mut HashMap<u16, ClientAccount>,
+    ) -> Result<(), EngineError> {
+        for (&(client_id, tx_id), &(offset, len)) in &self.index {
+            let (_, tx) = self.read(offset, len)?.into_stored();
+            if let Some(account) = accounts.get_mut(&client_id) {
+                account.txs.insert(tx_id, tx);
+            }
+        }
+        Ok(())
+    }
+
+    /// Forgets all the transactions, spilled or not.
+    pub(super) fn clear(&mut self) {
+        self.index.clear();
+        self.recency.clear();
+        self.last_used.clear();
+        (self.live, self.garbage) = (0, 0);
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    fn write(&mut self, tx: &TransactionState) -> Result<(u64, u32), EngineError> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .truncate(true)
+                    .read(true)
+                    .write(true)
+                    .open(&self.path)?,
+            ),
+        };
+        let data = serde_json::to_vec(tx)?;
+        let offset = file.seek(SeekFrom::End(0))?;
+        file.write_all(&data)?;
+        self.live += data.len() as u64;
+        Ok((offset, data.len() as u32))
+    }
+
+    fn read(&self, offset: u64, len: u32) -> Result<TransactionState, EngineError> {
+        let mut file = self
+            .file
+            .as_ref()
+            .expect("file is created with the first spilled transaction");
+        let mut data = vec![0; len as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut data)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    // Rewrites the file with the spilled transactions only, dropping the gaps left by the ones
+    // loaded back. The file is replaced only once fully written.
+    fn compact(&mut self) -> Result<(), EngineError> {
+        let Some(mut old) = self.file.as_ref() else {
+            return Ok(());
+        };
+        let path = self.path.with_extension("compact");
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        // Read in file order
+        let mut entries: Vec<_> = self
+            .index
+            .iter()
+            .map(|(&key, &entry)| (entry, key))
+            .collect();
+        entries.sort_unstable();
+        let mut offsets = Vec::with_capacity(entries.len());
+        let (mut offset, mut data) = (0, Vec::new());
+        for ((from, len), key) in entries {
+            data.resize(len as usize, 0);
+            old.seek(SeekFrom::Start(from))?;
+            old.read_exact(&mut data)?;
+            file.write_all(&data)?;
+            offsets.push((key, offset));
+            offset += u64::from(len);
+        }
+        // The previous file is closed first, to be replaced on any platform
+        self.file = None;
+        if let Err(e) = fs::rename(&path, &self.path) {
+            let _ = fs::remove_file(&path);
+            self.file = Some(OpenOptions::new().read(true).write(true).open(&self.path)?);
+            return Err(e.into());
+        }
+        self.file = Some(file);
+        for (key, offset) in offsets {
+            if let Some(entry) = self.index.get_mut(&key) {
+                entry.0 = offset;
+            }
+        }
+        self.garbage = 0;
+        Ok(())
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod spill_tests {
+    use super::*;
+    use crate::engine::{
+        amount::Decimal,
+        config::EngineConfig,
+        model::{Transaction, TransactionType},
+    };
+
+    // Deposits `tx_id` to the account of client 1, tracking it like the processing does
+    fn deposit(spill: &mut Spill, accounts: &mut HashMap<u16, ClientAccount>, tx_id: u32) {
+        let deposit = Transaction::new(TransactionType::Deposit, 1, tx_id, Some(Decimal::ONE));
+        let account = accounts.get_mut(&1).unwrap();
+        account.update(deposit, &EngineConfig::default()).unwrap();
+        spill.track(1, tx_id);
+        spill.evict(accounts).unwrap();
+    }
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tpe_{name}_{}.jsonl", std::process::id()))
+    }
+
+    #[test]
+    fn test_least_recently_used() {
+        let mut spill = Spill::new(path("spill_lru"), 2 * TX_SIZE + 2 * INDEX_ENTRY_SIZE);
+        let mut accounts = HashMap::from([(1, ClientAccount::new(1))]);
+        for tx_id in 1..=3 {
+            deposit(&mut spill, &mut accounts, tx_id);
+        }
+        assert_eq!(1, spill.len());
+        assert!(accounts[&1].transaction(1).is_none());
+
+        // Referencing tx 2 makes tx 3 the least recently used
+        spill.reload(&mut accounts, 1, 2).unwrap();
+        deposit(&mut spill, &mut accounts, 4);
+        assert_eq!(2, spill.len());
+        assert!(accounts[&1].transaction(2).is_some());
+        assert!(accounts[&1].transaction(3).is_none());
+        assert!(spill.get(1, 3).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_compaction() {
+        let path = path("spill_compaction");
+        let mut spill = Spill::new(path.clone(), TX_SIZE + 2 * INDEX_ENTRY_SIZE);
+        let mut accounts = HashMap::from([(1, ClientAccount::new(1))]);
+        deposit(&mut spill, &mut accounts, 1);
+        deposit(&mut spill, &mut accounts, 2);
+
+        // Every reload spills the other transaction, without the file growing
+        for tx_id in [1, 2].repeat(50) {
+            spill.reload(&mut accounts, 1, tx_id).unwrap();
+            spill.evict(&mut accounts).unwrap();
+            assert_eq!(1, spill.len());
+        }
+        let len = fs::metadata(&path).unwrap().len();
+        assert!(len <= 2 * spill.live, "{len} bytes");
+        assert_eq!(Decimal::ONE, spill.get(1, 1).unwrap().unwrap().amount);
+
+        drop(spill);
+        assert!(!path.exists());
+    }
+}
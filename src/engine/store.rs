@@ -0,0 +1,414 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    amount::Decimal,
+    error::EngineError,
+    model::{
+        Balances, ClientAccount, CurrencyCode, DisputeOrigin, LockInfo, StoredTx,
+        TransactionStatus, TransactionType,
+    },
+};
+
+/// Name of the file holding the accounts in a [`FileStore`] directory
+const ACCOUNTS_FILE: &str = "accounts.jsonl";
+
+/// Name of the file listing the input files ingested so far in a [`FileStore`] directory
+const MANIFEST_FILE: &str = "manifest.jsonl";
+
+/// Storage of the engine state, so that accounts and their transactions history survive
+/// process restarts.
+pub trait StateStore {
+    /// Loads the accounts persisted so far, indexed by client id. An empty store has no accounts.
+    fn load(&mut self) -> Result<HashMap<u16, ClientAccount>, EngineError>;
+
+    /// Persists the accounts, replacing the state saved before.
+    fn save(&mut self, accounts: &HashMap<u16, ClientAccount>) -> Result<(), EngineError>;
+}
+
+/// A store keeping the state in memory, for the lifetime of the store itself.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    accounts: HashMap<u16, ClientAccount>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StateStore for MemoryStore {
+    fn load(&mut self) -> Result<HashMap<u16, ClientAccount>, EngineError> {
+        Ok(self.accounts.clone())
+    }
+
+    fn save(&mut self, accounts: &HashMap<u16, ClientAccount>) -> Result<(), EngineError> {
+        self.accounts = accounts.clone();
+        Ok(())
+    }
+}
+
+/// A store keeping the state in a directory, as a JSON Lines file with one account per line.
+///
+/// The file is replaced atomically on save, so an interrupted run leaves the previous state intact.
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// Opens the store in `dir`, creating the directory if it doesn't exist.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, EngineError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Whether a file with the given digest, see [`file_digest`], has already been ingested
+    /// in a previous run.
+    pub fn is_ingested(&self, digest: &str) -> Result<bool, EngineError> {
+        let path = self.dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(false);
+        }
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: ManifestEntry = serde_json::from_str(&line)?;
+            if entry.sha256 == digest {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Records a file as ingested, so that it's detected when given again in a later run.
+    ///
+    /// It's meant to be called once the state including the file has been saved.
+    pub fn record_ingested(&mut self, digest: &str, path: &str) -> Result<(), EngineError> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(MANIFEST_FILE))?;
+        let entry = ManifestEntry {
+            sha256: digest.to_string(),
+            path: path.to_string(),
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+impl StateStore for FileStore {
+    fn load(&mut self) -> Result<HashMap<u16, ClientAccount>, EngineError> {
+        let path = self.dir.join(ACCOUNTS_FILE);
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let mut accounts = HashMap::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let account: ClientAccount = serde_json::from_str::<AccountState>(&line)?.into();
+            accounts.insert(account.client_id, account);
+        }
+        Ok(accounts)
+    }
+
+    fn save(&mut self, accounts: &HashMap<u16, ClientAccount>) -> Result<(), EngineError> {
+        let path = self.dir.join(ACCOUNTS_FILE);
+        let tmp_path = self.dir.join(format!(".{ACCOUNTS_FILE}.tmp"));
+
+        let mut wrt = BufWriter::new(File::create(&tmp_path)?);
+        for account in accounts.values() {
+            serde_json::to_writer(&mut wrt, &AccountState::from(account))?;
+            wrt.write_all(b"\n")?;
+        }
+        let file = wrt.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// An input file ingested in a previous run, identified by the SHA-256 digest of its content
+/// so that it's detected even if renamed.
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    sha256: String,
+    // Path of the file when ingested, for reference only
+    path: String,
+}
+
+/// Computes the SHA-256 digest of the content of a file, as a hex string.
+pub fn file_digest(path: impl AsRef<Path>) -> Result<String, EngineError> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// What to do with an input file already ingested in a previous run, or given twice.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateFilePolicy {
+    /// The file is skipped, with a warning.
+    #[default]
+    Skip,
+    /// The run fails before processing any file.
+    Reject,
+}
+
+impl FromStr for DuplicateFilePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "reject" => Ok(Self::Reject),
+            _ => Err(format!("Unknown duplicate file policy `{s}`")),
+        }
+    }
+}
+
+/// Persisted form of an account. Amounts are stored as strings, so that they are restored
+/// without any loss of precision.
+#[derive(Serialize, Deserialize)]
+pub(super) struct AccountState {
+    client: u16,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    closed: bool,
+    #[serde(default)]
+    error: bool,
+    #[serde(default)]
+    currencies: BTreeMap<CurrencyCode, Balances>,
+    #[serde(default)]
+    fees_collected: BTreeMap<CurrencyCode, Decimal>,
+    #[serde(default)]
+    withdrawn: BTreeMap<CurrencyCode, Decimal>,
+    #[serde(default)]
+    withdrawal_day: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lock: Option<LockInfo>,
+    txs: Vec<TransactionState>,
+}
+
+/// Persisted form of a transaction of an account history.
+#[derive(Serialize, Deserialize)]
+pub(super) struct TransactionState {
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    tx: u32,
+    amount: Option<Decimal>,
+    #[serde(default)]
+    currency: Option<CurrencyCode>,
+    status: TransactionStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dispute_origin: Option<DisputeOrigin>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    disputed: Option<Decimal>,
+    #[serde(default, skip_serializing_if = "Decimal::is_zero")]
+    settled: Decimal,
+}
+
+impl TransactionState {
+    pub(super) fn new(tx_id: u32, tx: &StoredTx) -> Self {
+        Self {
+            tx_type: tx.kind,
+            tx: tx_id,
+            amount: Some(tx.amount),
+            currency: tx.currency.clone(),
+            status: tx.status,
+            timestamp: tx.timestamp,
+            dispute_origin: tx.dispute_origin,
+            disputed: tx.disputed,
+            settled: tx.settled,
+        }
+    }
+
+    /// Restores the transaction, returned with its id.
+    pub(super) fn into_stored(self) -> (u32, StoredTx) {
+        let tx = StoredTx {
+            kind: self.tx_type,
+            amount: self.amount.unwrap_or_default(),
+            currency: self.currency,
+            status: self.status,
+            source: 0,
+            timestamp: self.timestamp,
+            dispute_origin: self.dispute_origin,
+            disputed: self.disputed,
+            settled: self.settled,
+        };
+        (self.tx, tx)
+    }
+}
+
+impl From<&ClientAccount> for AccountState {
+    fn from(account: &ClientAccount) -> Self {
+        Self {
+            client: account.client_id,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+            closed: account.closed,
+            error: account.error,
+            currencies: account.currencies.clone(),
+            fees_collected: account.fees_collected.clone(),
+            withdrawn: account.withdrawn.clone(),
+            withdrawal_day: account.withdrawal_day,
+            lock: account.lock,
+            txs: account
+                .transactions()
+                .map(|(tx_id, tx)| TransactionState::new(tx_id, tx))
+                .collect(),
+        }
+    }
+}
+
+impl From<AccountState> for ClientAccount {
+    fn from(state: AccountState) -> Self {
+        let client_id = state.client;
+        Self {
+            client_id,
+            available: state.available,
+            held: state.held,
+            total: state.total,
+            locked: state.locked,
+            closed: state.closed,
+            error: state.error,
+            currencies: state.currencies,
+            fees_collected: state.fees_collected,
+            withdrawn: state.withdrawn,
+            withdrawal_day: state.withdrawal_day,
+            lock: state.lock,
+            txs: state
+                .txs
+                .into_iter()
+                .map(TransactionState::into_stored)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod store_tests {
+    use super::*;
+    use crate::engine::{PaymentEngine, Transaction};
+
+    fn engine_state() -> HashMap<u16, ClientAccount> {
+        let mut engine = PaymentEngine::new();
+        let txs = [
+            (
+                TransactionType::Deposit,
+                1,
+                1,
+                Some(Decimal::new(123456, 4)),
+            ),
+            (TransactionType::Deposit, 2, 2, Some(Decimal::TEN)),
+            (TransactionType::Dispute, 2, 2, None),
+        ];
+        for (tx_type, client_id, tx_id, amount) in txs {
+            engine
+                .apply(Transaction::new(tx_type, client_id, tx_id, amount))
+                .unwrap();
+        }
+        let mut deposit = Transaction::new(TransactionType::Deposit, 2, 3, Some(Decimal::ONE));
+        deposit.currency = Some("EUR".into());
+        engine.apply(deposit).unwrap();
+        engine.into_accounts()
+    }
+
+    fn assert_same_state(
+        expected: &HashMap<u16, ClientAccount>,
+        actual: &HashMap<u16, ClientAccount>,
+    ) {
+        assert_eq!(expected.len(), actual.len());
+        for (client_id, account) in expected {
+            let restored = &actual[client_id];
+            assert_eq!(account.available, restored.available);
+            assert_eq!(account.held, restored.held);
+            assert_eq!(account.total, restored.total);
+            assert_eq!(account.locked, restored.locked);
+            assert_eq!(account.currencies, restored.currencies);
+            assert_eq!(account.txs, restored.txs);
+        }
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryStore::new();
+        assert!(store.load().unwrap().is_empty());
+
+        let accounts = engine_state();
+        store.save(&accounts).unwrap();
+        assert_same_state(&accounts, &store.load().unwrap());
+    }
+
+    #[test]
+    fn test_file_store() {
+        let dir = std::env::temp_dir().join(format!("tpe_store_{}", std::process::id()));
+        let mut store = FileStore::open(&dir).unwrap();
+        assert!(store.load().unwrap().is_empty());
+
+        let accounts = engine_state();
+        store.save(&accounts).unwrap();
+        let mut store = FileStore::open(&dir).unwrap();
+        assert_same_state(&accounts, &store.load().unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_manifest() {
+        let dir = std::env::temp_dir().join(format!("tpe_manifest_{}", std::process::id()));
+        let mut store = FileStore::open(&dir).unwrap();
+        let input = dir.join("input.csv");
+        std::fs::write(&input, "type,client,tx,amount\ndeposit,1,1,1.0\n").unwrap();
+
+        let digest = file_digest(&input).unwrap();
+        assert_eq!(digest.len(), 64);
+        assert!(!store.is_ingested(&digest).unwrap());
+        store.record_ingested(&digest, "input.csv").unwrap();
+
+        // The same content is detected under another name, and by a reopened store
+        let copy = dir.join("copy.csv");
+        std::fs::copy(&input, &copy).unwrap();
+        let store = FileStore::open(&dir).unwrap();
+        assert!(store.is_ingested(&file_digest(&copy).unwrap()).unwrap());
+        std::fs::write(&copy, "type,client,tx,amount\ndeposit,1,2,1.0\n").unwrap();
+        assert!(!store.is_ingested(&file_digest(&copy).unwrap()).unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
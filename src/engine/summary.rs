@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use super::{
+    amount::Decimal,
+    model::{CurrencyCode, DisputeOrigin, TransactionType},
+};
+
+/// Summary of a batch of transactions processed by the engine, see
+/// [`PaymentEngine::summary`](super::PaymentEngine::summary).
+///
+/// Volumes are by currency, with an empty code for the default one, and saturate at
+/// [`Decimal::MAX`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    /// Number of client accounts
+    pub clients: usize,
+    /// Number of locked accounts
+    pub locked_accounts: usize,
+    /// Total amount of the deposits applied
+    pub deposits: BTreeMap<CurrencyCode, Decimal>,
+    /// Total amount of the withdrawals applied
+    pub withdrawals: BTreeMap<CurrencyCode, Decimal>,
+    /// Number of disputes applied
+    pub disputes: u64,
+    /// Number of chargebacks applied
+    pub chargebacks: u64,
+    /// Number of reversals applied
+    pub reversals: u64,
+    /// Number of disputes applied with a known origin, by origin code
+    pub disputes_by_origin: BTreeMap<&'static str, u64>,
+    /// Number of chargebacks applied to transactions disputed with a known origin, by origin code
+    pub chargebacks_by_origin: BTreeMap<&'static str, u64>,
+    /// Number of records rejected while processing sources, by reason code
+    pub rejected: BTreeMap<&'static str, usize>,
+}
+
+impl Summary {
+    /// Counts a transaction applied by the engine, with its amount once rounded and the origin
+    /// of the dispute it refers to, if any.
+    pub(super) fn record(
+        &mut self,
+        tx_type: TransactionType,
+        amount: Option<Decimal>,
+        currency: Option<&CurrencyCode>,
+        origin: Option<DisputeOrigin>,
+    ) {
+        let (count, by_origin) = match tx_type {
+            TransactionType::Dispute => (&mut self.disputes, &mut self.disputes_by_origin),
+            TransactionType::Chargeback => (&mut self.chargebacks, &mut self.chargebacks_by_origin),
+            TransactionType::Reversal => return self.reversals += 1,
+            _ => return self.record_volume(tx_type, amount, currency),
+        };
+        *count += 1;
+        if let Some(origin) = origin {
+            *by_origin.entry(origin.code()).or_default() += 1;
+        }
+    }
+
+    fn record_volume(
+        &mut self,
+        tx_type: TransactionType,
+        amount: Option<Decimal>,
+        currency: Option<&CurrencyCode>,
+    ) {
+        let volumes = match tx_type {
+            TransactionType::Deposit => &mut self.deposits,
+            TransactionType::Withdrawal => &mut self.withdrawals,
+            _ => return,
+        };
+        let volume = volumes
+            .entry(currency.cloned().unwrap_or_default())
+            .or_default();
+        *volume = volume.saturating_add(amount.unwrap_or_default());
+    }
+
+    /// Adds the transactions counted by `other` (e.g. a shard) to this summary.
+    pub(super) fn merge(&mut self, other: Summary) {
+        for (volumes, other) in [
+            (&mut self.deposits, other.deposits),
+            (&mut self.withdrawals, other.withdrawals),
+        ] {
+            for (currency, volume) in other {
+                let total = volumes.entry(currency).or_default();
+                *total = total.saturating_add(volume);
+            }
+        }
+        self.disputes += other.disputes;
+        self.chargebacks += other.chargebacks;
+        self.reversals += other.reversals;
+        for (counts, other) in [
+            (&mut self.disputes_by_origin, other.disputes_by_origin),
+            (&mut self.chargebacks_by_origin, other.chargebacks_by_origin),
+        ] {
+            for (origin, count) in other {
+                *counts.entry(origin).or_default() += count;
+            }
+        }
+    }
+}
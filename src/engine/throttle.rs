@@ -0,0 +1,143 @@
+use std::{collections::HashMap, fmt::Arguments};
+
+use tracing::{info, warn};
+
+use super::reject::RejectReason;
+
+/// Throttles the warnings emitted for rejected transactions, so that pathological inputs
+/// don't flood the logs with millions of identical lines.
+///
+/// For every rejection reason only the first N occurrences are logged, while the others
+/// are just counted and reported in the final summary.
+#[derive(Debug, Default, Clone)]
+pub struct WarnThrottle {
+    // Limit applied to the reasons without a specific one. `None` means unlimited.
+    default_limit: Option<usize>,
+    limits: HashMap<RejectReason, usize>,
+    counts: HashMap<RejectReason, usize>,
+}
+
+impl WarnThrottle {
+    pub fn new(default_limit: Option<usize>) -> Self {
+        Self {
+            default_limit,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the maximum number of warnings logged for a specific reason.
+    pub fn with_limit(mut self, reason: RejectReason, limit: usize) -> Self {
+        self.limits.insert(reason, limit);
+        self
+    }
+
+    fn limit(&self, reason: RejectReason) -> Option<usize> {
+        self.limits.get(&reason).copied().or(self.default_limit)
+    }
+
+    /// Registers an occurrence of `reason` for the tx `tx_id` of the client, logging `msg` only
+    /// if the limit has not been reached.
+    pub fn warn(&mut self, reason: RejectReason, client_id: u16, tx_id: u32, msg: Arguments) {
+        let count = self.counts.entry(reason).or_default();
+        *count += 1;
+        let count = *count;
+
+        match self.limit(reason) {
+            Some(limit) if count > limit => {
+                if count == limit + 1 {
+                    warn!(
+                        reject_reason = reason.code(),
+                        limit,
+                        "Limit of warnings reached, further occurrences will only be counted"
+                    );
+                }
+            }
+            _ => warn!(client_id, tx_id, reject_reason = reason.code(), "{msg}"),
+        }
+    }
+
+    /// Returns a throttle with the same limits, but without any occurrence registered.
+    pub fn fork(&self) -> Self {
+        Self {
+            default_limit: self.default_limit,
+            limits: self.limits.clone(),
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Adds the occurrences registered by `other` (e.g. a forked throttle) to this one.
+    pub fn merge(&mut self, other: WarnThrottle) {
+        for (reason, count) in other.counts {
+            *self.counts.entry(reason).or_default() += count;
+        }
+    }
+
+    /// Number of occurrences registered for `reason`.
+    pub fn count(&self, reason: RejectReason) -> usize {
+        self.counts.get(&reason).copied().unwrap_or_default()
+    }
+
+    /// Logs the number of occurrences (and suppressed warnings) per reason.
+    pub fn log_summary(&self) {
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort();
+        for (reason, count) in counts {
+            let suppressed = self
+                .limit(*reason)
+                .map_or(0, |limit| count.saturating_sub(limit));
+            info!(
+                reject_reason = reason.code(),
+                count, suppressed, "Rejected transactions"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod throttle_tests {
+    use super::*;
+
+    #[test]
+    fn test_count_beyond_limit() {
+        let mut throttle = WarnThrottle::new(Some(2));
+        for _ in 0..5 {
+            throttle.warn(RejectReason::AccountLocked, 1, 2, format_args!("locked"));
+        }
+        throttle.warn(RejectReason::MissingAmount, 1, 3, format_args!("missing"));
+
+        assert_eq!(5, throttle.count(RejectReason::AccountLocked));
+        assert_eq!(1, throttle.count(RejectReason::MissingAmount));
+        assert_eq!(0, throttle.count(RejectReason::InsufficientFunds));
+    }
+
+    #[test]
+    fn test_reason_limit_overrides_default() {
+        let throttle = WarnThrottle::new(None).with_limit(RejectReason::DuplicateTransaction, 0);
+        assert_eq!(Some(0), throttle.limit(RejectReason::DuplicateTransaction));
+        assert_eq!(None, throttle.limit(RejectReason::AccountLocked));
+    }
+
+    #[test]
+    fn test_fork_and_merge() {
+        let mut throttle = WarnThrottle::new(Some(1));
+        throttle.warn(RejectReason::AccountLocked, 1, 2, format_args!("locked"));
+
+        let mut forked = throttle.fork();
+        assert_eq!(0, forked.count(RejectReason::AccountLocked));
+        assert_eq!(Some(1), forked.limit(RejectReason::AccountLocked));
+        forked.warn(RejectReason::AccountLocked, 1, 2, format_args!("locked"));
+        forked.warn(RejectReason::MissingAmount, 1, 3, format_args!("missing"));
+
+        throttle.merge(forked);
+        assert_eq!(2, throttle.count(RejectReason::AccountLocked));
+        assert_eq!(1, throttle.count(RejectReason::MissingAmount));
+    }
+
+    #[test]
+    fn test_reason_codes_roundtrip() {
+        for reason in RejectReason::ALL {
+            assert_eq!(reason, reason.code().parse().unwrap());
+        }
+        assert!("unknown".parse::<RejectReason>().is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use std::{fmt::Debug, sync::Arc};
+
+use super::{
+    amount::Decimal,
+    model::{ClientAccount, Transaction, TransactionType},
+    reject::RejectReason,
+};
+
+/// A rule a transaction has to satisfy to be applied to the account of its client.
+///
+/// Rules are checked in order before the transaction changes the account, with its amount
+/// already rounded, see [`Validators`].
+pub trait Validator: Debug + Send + Sync {
+    /// Returns the reason the transaction is rejected for, if it breaks the rule.
+    fn validate(&self, tx: &Transaction, account: &ClientAccount) -> Result<(), RejectReason>;
+}
+
+/// Deposits, withdrawals, transfers and interest can't be applied to a locked account.
+#[derive(Debug, Clone, Copy)]
+pub struct AccountNotLocked;
+
+impl Validator for AccountNotLocked {
+    fn validate(&self, tx: &Transaction, account: &ClientAccount) -> Result<(), RejectReason> {
+        if tx.tx_type.moves_funds() && account.locked {
+            return Err(RejectReason::AccountLocked);
+        }
+        Ok(())
+    }
+}
+
+/// Deposits, withdrawals and transfers can't reuse the tx id of a transaction of the account
+/// history.
+#[derive(Debug, Clone, Copy)]
+pub struct UniqueTransaction;
+
+impl Validator for UniqueTransaction {
+    fn validate(&self, tx: &Transaction, account: &ClientAccount) -> Result<(), RejectReason> {
+        if tx.tx_type.is_registered() && account.transaction(tx.tx_id).is_some() {
+            return Err(RejectReason::DuplicateTransaction);
+        }
+        Ok(())
+    }
+}
+
+/// Deposits, withdrawals, transfers and interest need a positive amount.
+#[derive(Debug, Clone, Copy)]
+pub struct PositiveAmount;
+
+impl Validator for PositiveAmount {
+    fn validate(&self, tx: &Transaction, _account: &ClientAccount) -> Result<(), RejectReason> {
+        if !tx.tx_type.moves_funds() {
+            return Ok(());
+        }
+        match tx.amount {
+            None => Err(RejectReason::MissingAmount),
+            Some(amount) if amount <= Decimal::ZERO => Err(RejectReason::InvalidAmount),
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+/// Limits on the amount of withdrawals: of a single transaction, and of all the withdrawals
+/// of an account in the same currency and on the same day.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WithdrawalLimit {
+    /// Maximum amount of a single withdrawal
+    pub per_tx: Option<Decimal>,
+    /// Maximum amount withdrawn from an account in a day, or in total without timestamps
+    pub cumulative: Option<Decimal>,
+}
+
+impl Validator for WithdrawalLimit {
+    fn validate(&self, tx: &Transaction, account: &ClientAccount) -> Result<(), RejectReason> {
+        let (TransactionType::Withdrawal, Some(amount)) = (tx.tx_type, tx.amount) else {
+            return Ok(());
+        };
+        if self.per_tx.is_some_and(|limit| amount > limit) {
+            return Err(RejectReason::WithdrawalLimitExceeded);
+        }
+        let withdrawn = account.withdrawn(tx.currency.as_deref(), tx.timestamp);
+        if self.cumulative.is_some_and(|limit| {
+            withdrawn
+                .checked_add(amount)
+                .is_none_or(|total| total > limit)
+        }) {
+            return Err(RejectReason::WithdrawalLimitExceeded);
+        }
+        Ok(())
+    }
+}
+
+/// The chain of rules checked before applying a transaction, the first broken one rejecting it.
+///
+/// It starts with the built-in rules ([`AccountNotLocked`], [`UniqueTransaction`] and
+/// [`PositiveAmount`]), followed by the custom ones added with [`Validators::with`].
+#[derive(Debug, Clone)]
+pub struct Validators(Vec<Arc<dyn Validator>>);
+
+impl Default for Validators {
+    fn default() -> Self {
+        Self(vec![
+            Arc::new(AccountNotLocked),
+            Arc::new(UniqueTransaction),
+            Arc::new(PositiveAmount),
+        ])
+    }
+}
+
+impl Validators {
+    /// Adds a rule at the end of the chain.
+    pub fn with(mut self, validator: impl Validator + 'static) -> Self {
+        self.0.push(Arc::new(validator));
+        self
+    }
+
+    /// Checks all the rules in order, returning the reason of the first broken one.
+    pub fn validate(&self, tx: &Transaction, account: &ClientAccount) -> Result<(), RejectReason> {
+        self.0
+            .iter()
+            .try_for_each(|validator| validator.validate(tx, account))
+    }
+}
+
+#[cfg(test)]
+mod validation_tests {
+    use super::*;
+    use crate::engine::config::EngineConfig;
+
+    // Rejects deposits above a maximum amount
+    #[derive(Debug)]
+    struct MaxDeposit(Decimal);
+
+    impl Validator for MaxDeposit {
+        fn validate(&self, tx: &Transaction, _account: &ClientAccount) -> Result<(), RejectReason> {
+            match tx.amount {
+                Some(amount) if tx.tx_type == TransactionType::Deposit && amount > self.0 => {
+                    Err(RejectReason::RuleViolated)
+                }
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_withdrawal_limit() {
+        let config = EngineConfig {
+            validators: Validators::default().with(WithdrawalLimit {
+                per_tx: Some(Decimal::new(5, 0)),
+                cumulative: Some(Decimal::new(8, 0)),
+            }),
+            ..Default::default()
+        };
+        let mut account = ClientAccount::new(1);
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::new(20, 0)));
+        account.update(deposit, &config).unwrap();
+
+        let withdrawals = [(2, 6, false), (3, 5, true), (4, 4, false), (5, 3, true)];
+        for (tx_id, amount, applied) in withdrawals {
+            let withdrawal =
+                Transaction::new(TransactionType::Withdrawal, 1, tx_id, Some(amount.into()));
+            let outcome = account.update(withdrawal, &config);
+            assert_eq!(applied, outcome.is_ok(), "withdrawal {tx_id}");
+            if !applied {
+                assert_eq!(Err(RejectReason::WithdrawalLimitExceeded), outcome);
+            }
+        }
+        assert_eq!(Decimal::new(12, 0), account.total);
+
+        // The cumulative limit applies per day
+        let mut withdrawal =
+            Transaction::new(TransactionType::Withdrawal, 1, 6, Some(Decimal::new(5, 0)));
+        withdrawal.timestamp = Some(24 * 60 * 60);
+        assert!(account.update(withdrawal, &config).is_ok());
+    }
+
+    #[test]
+    fn test_custom_validator() {
+        let config = EngineConfig {
+            validators: Validators::default().with(MaxDeposit(Decimal::TEN)),
+            ..Default::default()
+        };
+        let mut account = ClientAccount::new(1);
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        account.update(deposit.clone(), &config).unwrap();
+        // Built-in rules come first
+        assert_eq!(
+            Err(RejectReason::DuplicateTransaction),
+            account.update(deposit, &config)
+        );
+
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::new(11, 0)));
+        assert_eq!(
+            Err(RejectReason::RuleViolated),
+            account.update(deposit, &config)
+        );
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 3, Some(Decimal::ZERO));
+        assert_eq!(
+            Err(RejectReason::InvalidAmount),
+            account.update(deposit, &config)
+        );
+        assert_eq!(Decimal::TEN, account.total);
+    }
+}
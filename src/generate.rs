@@ -0,0 +1,92 @@
+use serde::Serialize;
+use tokio::io::AsyncWrite;
+
+use crate::{testkit::TransactionGenerator, Decimal, EngineError, TransactionType};
+
+/// Options of the synthetic transactions generated by [`generate`].
+#[derive(Debug, Clone, Copy)]
+pub struct GenerateOptions {
+    /// Number of clients the transactions are spread over
+    pub clients: u16,
+    /// Number of transactions generated
+    pub transactions: u64,
+    /// Share of the transactions disputing a previous deposit, between 0 and 1. As many are
+    /// resolved or chargebacked later on.
+    pub dispute_ratio: f64,
+    /// Seed of the random generator, the same one always generating the same transactions
+    pub seed: u64,
+}
+
+impl Default for GenerateOptions {
+    fn default() -> Self {
+        Self {
+            clients: 1000,
+            transactions: 100_000,
+            dispute_ratio: 0.01,
+            seed: 0,
+        }
+    }
+}
+
+// Row of the generated CSV, in the format of the input files
+#[derive(Serialize)]
+struct GeneratedRow {
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    client: u16,
+    tx: u32,
+    amount: Option<Decimal>,
+}
+
+/// Writes synthetic transactions in CSV format, e.g. to benchmark the engine, as generated by
+/// a [`TransactionGenerator`] with the `options`. Returns the number of transactions written.
+pub async fn generate<W: AsyncWrite + Unpin>(
+    options: &GenerateOptions,
+    wrt: W,
+) -> Result<u64, EngineError> {
+    let generator = TransactionGenerator::new(options.seed)
+        .clients(options.clients)
+        .dispute_ratio(options.dispute_ratio);
+    let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+    for tx in generator.take(options.transactions as usize) {
+        wrt.serialize(GeneratedRow {
+            tx_type: tx.tx_type,
+            client: tx.client_id,
+            tx: tx.tx_id,
+            amount: tx.amount,
+        })
+        .await?;
+    }
+    wrt.flush().await?;
+    Ok(options.transactions)
+}
+
+#[cfg(test)]
+mod generate_tests {
+    use super::*;
+    use crate::{CsvSource, PaymentEngine};
+
+    #[tokio::test]
+    async fn test_generate() {
+        let options = GenerateOptions {
+            clients: 10,
+            transactions: 1000,
+            dispute_ratio: 0.1,
+            seed: 42,
+        };
+        let mut data = Vec::new();
+        assert_eq!(1000, generate(&options, &mut data).await.unwrap());
+        let mut again = Vec::new();
+        generate(&options, &mut again).await.unwrap();
+        assert_eq!(data, again);
+
+        let mut engine = PaymentEngine::new();
+        let stats = engine
+            .process(CsvSource::new(data.as_slice()))
+            .await
+            .unwrap();
+        assert_eq!(1000, stats.rows);
+        assert!(engine.summary().disputes > 0);
+        assert!(engine.accounts().count() <= 10);
+    }
+}
@@ -0,0 +1,142 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use crate::{AccountRegistry, Decimal, EngineError, Transaction};
+
+/// The accounts shared by the HTTP request handlers, applying the transactions of clients of
+/// different partitions in parallel.
+pub type SharedEngine = Arc<AccountRegistry>;
+
+/// Returns the router of the HTTP API:
+/// - `POST /transactions` applies the transaction in the JSON body
+/// - `GET /accounts` lists the accounts by client id, a page at a time: `limit` accounts (1000
+///   by default) after the client `after`, if any. The `next` client id of the response is the
+///   `after` of the following page. With `locked_only=true` only the locked accounts are
+///   listed, with `min_balance` only the ones with at least those total funds.
+/// - `GET /accounts/:client_id` returns the account of a single client
+///
+/// With the `prometheus` feature, [`serve`] also exports the metrics on `GET /metrics`.
+pub fn router(engine: SharedEngine) -> Router {
+    Router::new()
+        .route("/transactions", post(apply_transaction))
+        .route("/accounts", get(list_accounts))
+        .route("/accounts/:client_id", get(get_account))
+        .with_state(engine)
+}
+
+/// Serves the HTTP API on `addr`.
+pub async fn serve(engine: SharedEngine, addr: SocketAddr) -> Result<(), EngineError> {
+    let router = router(engine);
+    #[cfg(feature = "prometheus")]
+    let router = {
+        let handle = metrics_exporter_prometheus::PrometheusBuilder::new()
+            .install_recorder()
+            .map_err(std::io::Error::other)?;
+        router.route("/metrics", get(move || async move { handle.render() }))
+    };
+
+    let listener = TcpListener::bind(addr).await?;
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+async fn apply_transaction(
+    State(engine): State<SharedEngine>,
+    Json(tx): Json<Transaction>,
+) -> (StatusCode, Json<Value>) {
+    match engine.apply(tx).await {
+        Ok(()) => (StatusCode::OK, Json(json!({ "status": "applied" }))),
+        Err(reason) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({ "status": "rejected", "reason": reason.code() })),
+        ),
+    }
+}
+
+// Accounts listed per page, unless fewer are requested
+const MAX_PAGE_LEN: usize = 1000;
+
+/// The page of accounts requested from `GET /accounts`, and their filters.
+#[derive(Debug, Default, Deserialize)]
+struct AccountsQuery {
+    // Client id of the last account of the previous page
+    after: Option<u16>,
+    limit: Option<usize>,
+    #[serde(default)]
+    locked_only: bool,
+    // Minimum total funds
+    min_balance: Option<Decimal>,
+}
+
+async fn list_accounts(
+    State(engine): State<SharedEngine>,
+    Query(query): Query<AccountsQuery>,
+) -> Json<Value> {
+    let limit = query
+        .limit
+        .map_or(MAX_PAGE_LEN, |limit| limit.min(MAX_PAGE_LEN));
+    let (accounts, next) = engine
+        .accounts_page(query.after, limit, |account| {
+            (account.locked || !query.locked_only)
+                && query.min_balance.is_none_or(|min| account.total >= min)
+        })
+        .await;
+    Json(json!({ "accounts": accounts, "next": next }))
+}
+
+async fn get_account(
+    State(engine): State<SharedEngine>,
+    Path(client_id): Path<u16>,
+) -> Result<Json<Value>, StatusCode> {
+    let account = engine
+        .account(client_id)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(json!(account)))
+}
+
+#[cfg(test)]
+mod http_tests {
+    use super::*;
+    use crate::{Decimal, PaymentEngine, TransactionType};
+
+    #[tokio::test]
+    async fn test_handlers() {
+        let engine = Arc::new(AccountRegistry::new(PaymentEngine::new(), 2));
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        let (status, _) = apply_transaction(State(engine.clone()), Json(deposit)).await;
+        assert_eq!(StatusCode::OK, status);
+
+        let withdrawal =
+            Transaction::new(TransactionType::Withdrawal, 1, 2, Some(Decimal::new(11, 0)));
+        let (status, Json(body)) = apply_transaction(State(engine.clone()), Json(withdrawal)).await;
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, status);
+        assert_eq!("insufficient_funds", body["reason"]);
+
+        let Json(accounts) =
+            list_accounts(State(engine.clone()), Query(AccountsQuery::default())).await;
+        assert_eq!(1, accounts["accounts"].as_array().unwrap().len());
+        assert!(accounts["next"].is_null());
+        let query = AccountsQuery {
+            min_balance: Some(Decimal::ONE_HUNDRED),
+            ..Default::default()
+        };
+        let Json(accounts) = list_accounts(State(engine.clone()), Query(query)).await;
+        assert!(accounts["accounts"].as_array().unwrap().is_empty());
+        let Json(account) = get_account(State(engine.clone()), Path(1)).await.unwrap();
+        assert_eq!(1, account["client"]);
+        assert_eq!(
+            Err(StatusCode::NOT_FOUND),
+            get_account(State(engine), Path(2)).await.map(|_| ())
+        );
+    }
+}
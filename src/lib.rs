@@ -1,69 +1,73 @@
-use std::path::Path;
-use tokio::fs::File;
-use tokio::io::{self, BufReader};
-
-use clap::Parser;
-use log::info;
+//! The engine applying the transactions to the accounts of the clients.
+//!
+//! The core of the engine, i.e. [`ClientAccount`] and the types it's made of, doesn't depend on
+//! any I/O and builds for `wasm32-unknown-unknown` with `--no-default-features`, to run the same
+//! logic e.g. in the browser. Reading and writing the transactions and the reports, and the
+//! command-line interface, are behind the `io` feature, enabled by default.
 
+#[cfg(feature = "io")]
+mod cli;
+#[cfg(all(feature = "io", any(test, feature = "conformance")))]
+pub mod conformance;
+#[cfg(feature = "io")]
+mod diff;
 mod engine;
-pub use engine::EngineError;
-
-// Using a struct here for maintanaibility reasons, so that if the application/engine needs
-// to handle other future command-line arguments, they can be easily added.
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    // Input CSV file path
-    #[arg(index = 1, value_parser = parse_filepath)]
-    pub file_path: String,
-}
-
-fn parse_filepath(file_path: &str) -> Result<String, String> {
-    let path = Path::new(file_path);
-
-    // Check that the path exists
-    if !path.exists() {
-        return Err(String::from("File path doesn't exist"));
-    }
-
-    // Check that the file is a CSV
-    if let Some(ext) = path.extension() {
-        if let Some(ext_str) = ext.to_str() {
-            if ext_str.to_lowercase() == "csv" {
-                Ok(file_path.into())
-            } else {
-                Err(String::from("File is not in CSV format"))
-            }
-        } else {
-            Err(String::from("Unable to convert file path to string"))
-        }
-    } else {
-        Err(String::from("File path hasn't any extension"))
-    }
-}
-
-pub async fn run() -> Result<(), engine::EngineError> {
-    // Init
-    env_logger::init();
-    info!("Payment engine started.");
-    let args = Args::parse();
-
-    // Read CSV file containing transactions
-    info!("Reading data from CSV file.");
-    let file = File::open(args.file_path).await?;
-    let rdr = BufReader::new(file);
-
-    // Process transactions data
-    info!("Processing transactions data");
-    let accounts = engine::process_transactions(rdr).await?;
-
-    // Output info on accounts
-    let mut wrt = csv_async::AsyncSerializer::from_writer(io::stdout());
-    for (_, acc) in accounts {
-        wrt.serialize(acc).await?;
-    }
-
-    wrt.flush().await?;
-    info!("All transactions data processed");
-    Ok(())
-}
+#[cfg(feature = "io")]
+mod generate;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "io")]
+mod logging;
+#[cfg(feature = "io")]
+mod output;
+#[cfg(feature = "io")]
+mod repl;
+#[cfg(feature = "io")]
+pub mod testkit;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "io")]
+pub use cli::{
+    exit_code, run, Outcome, EXIT_DISCREPANCIES, EXIT_FAILURE, EXIT_INTERRUPTED, EXIT_INVALID_ARGS,
+    EXIT_PARSE_ERROR, EXIT_REJECTED, EXIT_SUCCESS,
+};
+#[cfg(feature = "io")]
+pub use diff::{check_report, diff_reports};
+#[cfg(feature = "webhooks")]
+pub use engine::WebhookSink;
+#[cfg(feature = "io")]
+pub use engine::{
+    file_digest, forward_events, input_bytes_read, is_object_url, limit_read_rate,
+    list_input_files, open_files, open_files_chunked, pipelined, process_transactions,
+    process_transactions_bytes, process_transactions_with_hooks, AccountRegistry, AccrueInterest,
+    AtomicFile, AuditDigests, BalanceHistory, Checkpoint, ClientFilter, ColumnMapping, Compression,
+    CsvDialect, CsvSource, Decision, DuplicateFilePolicy, EngineBuilder, EventSink, FileOrder,
+    FileStore, Finalizer, Follow, InputFormat, JsonLinesSink, JsonLinesSource, LedgerEntry,
+    MemoryStore, MergePolicy, MergedSource, PaymentEngine, RateLimit, RateLimited,
+    ReleaseHeldFunds, ResolveStaleDisputes, RiskReport, SourceStats, StateStore, Summary,
+    TimeWindow, TransactionHooks, TransactionSource, TransactionStream, TypeFilter, WarnThrottle,
+};
+#[cfg(feature = "signing")]
+pub use engine::{verify_report, ReportSigner};
+pub use engine::{
+    AccountEvent, AccountNotLocked, Amount, Balances, ClientAccount, CurrencyCode, Decimal,
+    DisputeOrigin, EngineConfig, EngineError, Fee, FeeSchedule, FixedDecimal, FundsMovement,
+    HeldFundsPolicy, InvalidDecimal, LockInfo, NegativeBalancePolicy, PositiveAmount,
+    PrecisionPolicy, RecordOrdering, RejectReason, RejectedTransaction, Rounding, SortBy, StoredTx,
+    Transaction, TransactionStatus, TransactionType, UniqueTransaction, Validator, Validators,
+    WithdrawalLimit,
+};
+#[cfg(feature = "io")]
+pub use generate::{generate, GenerateOptions};
+#[cfg(feature = "io")]
+pub use logging::LogFormat;
+#[cfg(feature = "io")]
+pub use output::{
+    account_status, write_account, write_account_stream, write_accounts, write_ledger,
+    write_lock_report, write_open_disputes, write_rejects, write_summary, write_validation,
+    Anonymizer, OutputFormat, OutputScale, ReportOptions,
+};
+#[cfg(feature = "io")]
+pub use repl::repl;
+#[cfg(feature = "tui")]
+pub use tui::{Dashboard, DashboardHandle};
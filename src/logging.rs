@@ -0,0 +1,41 @@
+use std::str::FromStr;
+
+use tracing_subscriber::EnvFilter;
+
+/// The formats the logs can be written in, to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// A JSON object per line, with the fields of the event and its spans
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown log format `{s}`")),
+        }
+    }
+}
+
+/// Installs the global subscriber writing the logs in `format`, filtered with the `level`
+/// directives, e.g. `info`, or else with the `RUST_LOG` environment variable.
+pub fn init(format: LogFormat, level: Option<&str>) {
+    let filter = match level {
+        Some(level) => EnvFilter::new(level),
+        None => EnvFilter::from_default_env(),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
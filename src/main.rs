@@ -1,4 +1,14 @@
+use std::process::ExitCode;
+
+use toy_payment_engine::EngineError;
+
 #[tokio::main]
-async fn main() -> Result<(), toy_payment_engine::EngineError> {
-    toy_payment_engine::run().await
+async fn main() -> ExitCode {
+    let result = toy_payment_engine::run().await;
+    match &result {
+        // Already logged, the exit code tells the reports are partial
+        Err(EngineError::Interrupted) | Ok(_) => {}
+        Err(e) => eprintln!("Error: {e:?}"),
+    }
+    ExitCode::from(toy_payment_engine::exit_code(&result))
 }
@@ -0,0 +1,1077 @@
+use std::{collections::BTreeMap, str::FromStr, time::UNIX_EPOCH};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{self, AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+};
+
+use crate::{
+    BalanceHistory, Balances, ClientAccount, ClientFilter, CurrencyCode, Decimal, DisputeOrigin,
+    EngineError, PaymentEngine, PrecisionPolicy, RejectedTransaction, SourceStats, Summary,
+    TransactionStatus, TransactionType,
+};
+
+/// The formats the accounts report can be written in. Parquet requires the `parquet` feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// A JSON array with an object per row
+    Json,
+    Parquet,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "parquet" => Ok(Self::Parquet),
+            _ => Err(format!("Unknown output format `{s}`")),
+        }
+    }
+}
+
+/// How many decimal places the balances are written with, once rounded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputScale {
+    /// As many as the balances have, e.g. `1.50` for `1.00 + 0.50`.
+    #[default]
+    Preserve,
+    /// Exactly the scale of the precision policy, e.g. `1.5000`.
+    Fixed,
+    /// No trailing zeros, e.g. `1.5`.
+    Minimal,
+}
+
+impl OutputScale {
+    /// Writes `amount` with the decimal places of this option, given the `scale` of the
+    /// precision policy.
+    pub fn apply(&self, amount: Decimal, scale: u32) -> Decimal {
+        match self {
+            Self::Preserve => amount,
+            Self::Fixed => {
+                let mut amount = amount;
+                amount.rescale(scale);
+                amount
+            }
+            Self::Minimal => amount.normalize(),
+        }
+    }
+}
+
+impl FromStr for OutputScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "preserve" => Ok(Self::Preserve),
+            "fixed" => Ok(Self::Fixed),
+            "minimal" => Ok(Self::Minimal),
+            _ => Err(format!("Unknown output scale `{s}`")),
+        }
+    }
+}
+
+/// Options affecting how the accounts report is written.
+#[derive(Debug, Default, Clone)]
+pub struct ReportOptions {
+    /// Precision the balances are rounded to
+    pub precision: PrecisionPolicy,
+    /// Format of the report
+    pub format: OutputFormat,
+    /// Decimal places of the balances
+    pub scale: OutputScale,
+    /// Clients whose accounts are written
+    pub clients: ClientFilter,
+    /// Replaces the client ids with pseudonyms, if set
+    pub anonymizer: Option<Anonymizer>,
+    /// Whether the report has a `status` column, see [`account_status`], which the readers of
+    /// the original schema may not expect
+    pub status_column: bool,
+}
+
+/// Returns the status of an account in the accounts report: `closed`, `locked` or `active`.
+pub fn account_status(account: &ClientAccount) -> &'static str {
+    match account {
+        ClientAccount { closed: true, .. } => "closed",
+        ClientAccount { locked: true, .. } => "locked",
+        _ => "active",
+    }
+}
+
+/// Maps the client ids to pseudonyms, so that reports can be shared without exposing them.
+///
+/// The pseudonym of a client is the HMAC-SHA256 of its id, in decimal, keyed with a secret:
+/// the same for every report written with the same key, and reversible only with the mapping
+/// of [`Anonymizer::write_mapping`] or by whoever knows the key.
+#[derive(Clone)]
+pub struct Anonymizer {
+    // Key of the HMAC, hashed when longer than a block
+    key: [u8; HMAC_BLOCK_SIZE],
+}
+
+// Block size of SHA-256, in bytes
+const HMAC_BLOCK_SIZE: usize = 64;
+
+impl Anonymizer {
+    /// Creates an anonymizer with a secret key.
+    pub fn new(key: &[u8]) -> Self {
+        let mut block = [0; HMAC_BLOCK_SIZE];
+        if key.len() > HMAC_BLOCK_SIZE {
+            block[..32].copy_from_slice(&Sha256::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        Self { key: block }
+    }
+
+    /// Returns the pseudonym of a client, as a hex string.
+    pub fn pseudonym(&self, client_id: u16) -> String {
+        let pad = |byte: u8| self.key.map(|k| k ^ byte);
+        let mut inner = Sha256::new();
+        inner.update(pad(0x36));
+        inner.update(client_id.to_string());
+        let mut outer = Sha256::new();
+        outer.update(pad(0x5c));
+        outer.update(inner.finalize());
+        format!("{:x}", outer.finalize())
+    }
+
+    /// Writes the pseudonyms of the clients in CSV format, with their id, for the authorized
+    /// users to map them back.
+    pub async fn write_mapping<W: AsyncWrite + Unpin>(
+        &self,
+        clients: impl Iterator<Item = u16>,
+        wrt: W,
+    ) -> Result<(), EngineError> {
+        let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+        for client in clients {
+            wrt.serialize(MappingRow {
+                client,
+                pseudonym: self.pseudonym(client),
+            })
+            .await?;
+        }
+        wrt.flush().await?;
+        Ok(())
+    }
+}
+
+// The key is kept out of the logs
+impl std::fmt::Debug for Anonymizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Anonymizer").finish_non_exhaustive()
+    }
+}
+
+// Row of the mapping of the pseudonyms to the client ids
+#[derive(Serialize)]
+struct MappingRow {
+    client: u16,
+    pseudonym: String,
+}
+
+// Client column of the accounts report: the id of the client, or its pseudonym if anonymized
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ClientColumn {
+    Id(u16),
+    Pseudonym(String),
+}
+
+// Row of the accounts report. The currency is set only when multiple currencies are used,
+// empty for the default one.
+#[derive(Serialize)]
+struct AccountRow<'a> {
+    client: ClientColumn,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<&'a str>,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<&'static str>,
+}
+
+/// Writes the accounts report in the format of `options`.
+///
+/// If any account has funds in a currency other than the default one, the report has a row per
+/// client and currency, with a `currency` column left empty for the default currency.
+pub async fn write_accounts<'a, W: AsyncWrite + Unpin>(
+    accounts: impl Iterator<Item = &'a ClientAccount>,
+    options: &ReportOptions,
+    mut wrt: W,
+) -> Result<(), EngineError> {
+    let accounts: Vec<_> = accounts
+        .filter(|acc| options.clients.contains(acc.client_id))
+        .collect();
+    let per_currency = accounts.iter().any(|acc| !acc.currencies.is_empty());
+    let rows: Vec<_> = accounts
+        .into_iter()
+        .flat_map(|acc| account_rows(acc, per_currency, options))
+        .collect();
+
+    match options.format {
+        OutputFormat::Csv => {
+            let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+            for row in rows {
+                wrt.serialize(row).await?;
+            }
+            wrt.flush().await?;
+        }
+        OutputFormat::Json => {
+            wrt.write_all(&serde_json::to_vec(&rows)?).await?;
+            wrt.write_all(b"\n").await?;
+            wrt.flush().await?;
+        }
+        OutputFormat::Parquet => {
+            wrt.write_all(&parquet_report(&rows, options.precision.scale)?)
+                .await?;
+            wrt.flush().await?;
+        }
+    }
+    Ok(())
+}
+
+// Rows of an account in the report: a single one, or one per currency if `per_currency`
+fn account_rows<'a>(
+    acc: &'a ClientAccount,
+    per_currency: bool,
+    options: &ReportOptions,
+) -> Vec<AccountRow<'a>> {
+    let precision = options.precision;
+    let round = |amount| {
+        options
+            .scale
+            .apply(precision.apply(amount), precision.scale)
+    };
+    let client = || match &options.anonymizer {
+        Some(anonymizer) => ClientColumn::Pseudonym(anonymizer.pseudonym(acc.client_id)),
+        None => ClientColumn::Id(acc.client_id),
+    };
+    let status = options.status_column.then(|| account_status(acc));
+    if !per_currency {
+        return vec![AccountRow {
+            client: client(),
+            currency: None,
+            available: round(acc.available),
+            held: round(acc.held),
+            total: round(acc.total),
+            locked: acc.locked,
+            status,
+        }];
+    }
+    let default = acc
+        .uses_default_currency()
+        .then_some((None, acc.balances(None)));
+    let currencies = acc
+        .currencies
+        .iter()
+        .map(|(currency, balances)| (Some(currency.as_str()), *balances));
+    default
+        .into_iter()
+        .chain(currencies)
+        .map(|(currency, balances)| AccountRow {
+            client: client(),
+            currency: Some(currency.unwrap_or_default()),
+            available: round(balances.available),
+            held: round(balances.held),
+            total: round(balances.total),
+            locked: acc.locked,
+            status,
+        })
+        .collect()
+}
+
+/// Writes the accounts report in CSV format, whatever the format of `options`, from the
+/// accounts received on `accounts`, as they come, see [`EngineBuilder::stream_accounts`](crate::EngineBuilder::stream_accounts). Which
+/// currencies are used isn't known upfront, so the report always has a row per client and
+/// currency. Returns the number of accounts written.
+pub async fn write_account_stream<W: AsyncWrite + Unpin>(
+    mut accounts: mpsc::Receiver<ClientAccount>,
+    options: &ReportOptions,
+    wrt: W,
+) -> Result<u64, EngineError> {
+    let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+    let mut count = 0;
+    while let Some(acc) = accounts.recv().await {
+        if !options.clients.contains(acc.client_id) {
+            continue;
+        }
+        for row in account_rows(&acc, true, options) {
+            wrt.serialize(row).await?;
+        }
+        count += 1;
+    }
+    wrt.flush().await?;
+    Ok(count)
+}
+
+// Encodes the rows in Parquet format, with balances as decimals of the given scale
+#[cfg(feature = "parquet")]
+fn parquet_report(rows: &[AccountRow], scale: u32) -> Result<Vec<u8>, EngineError> {
+    use std::sync::Arc;
+
+    use arrow::{
+        array::{ArrayRef, BooleanArray, Decimal128Array, StringArray, UInt16Array},
+        datatypes::{DataType, Field, Schema},
+        record_batch::RecordBatch,
+    };
+    use parquet::arrow::ArrowWriter;
+
+    let decimals = |amount: fn(&AccountRow) -> Decimal| -> Result<ArrayRef, EngineError> {
+        let mantissas: Vec<i128> = rows
+            .iter()
+            .map(|row| {
+                let mut amount = amount(row);
+                amount.rescale(scale);
+                amount.mantissa()
+            })
+            .collect();
+        let array = Decimal128Array::from(mantissas)
+            .with_precision_and_scale(38, scale as i8)
+            .map_err(io::Error::other)?;
+        Ok(Arc::new(array))
+    };
+    let decimal_type = DataType::Decimal128(38, scale as i8);
+
+    let (mut fields, mut columns): (Vec<Field>, Vec<ArrayRef>) =
+        match rows.first().map(|row| &row.client) {
+            Some(ClientColumn::Pseudonym(_)) => {
+                let pseudonyms = rows.iter().map(|row| match &row.client {
+                    ClientColumn::Pseudonym(pseudonym) => pseudonym.clone(),
+                    ClientColumn::Id(id) => id.to_string(),
+                });
+                (
+                    vec![Field::new("client", DataType::Utf8, false)],
+                    vec![Arc::new(StringArray::from(pseudonyms.collect::<Vec<_>>()))],
+                )
+            }
+            _ => {
+                let ids = rows.iter().map(|row| match row.client {
+                    ClientColumn::Id(id) => id,
+                    ClientColumn::Pseudonym(_) => 0,
+                });
+                (
+                    vec![Field::new("client", DataType::UInt16, false)],
+                    vec![Arc::new(UInt16Array::from(ids.collect::<Vec<_>>()))],
+                )
+            }
+        };
+    if rows.iter().any(|row| row.currency.is_some()) {
+        fields.push(Field::new("currency", DataType::Utf8, false));
+        columns.push(Arc::new(StringArray::from(
+            rows.iter()
+                .map(|row| row.currency.unwrap_or_default())
+                .collect::<Vec<_>>(),
+        )));
+    }
+    fields.push(Field::new("available", decimal_type.clone(), false));
+    columns.push(decimals(|row| row.available)?);
+    fields.push(Field::new("held", decimal_type.clone(), false));
+    columns.push(decimals(|row| row.held)?);
+    fields.push(Field::new("total", decimal_type, false));
+    columns.push(decimals(|row| row.total)?);
+    fields.push(Field::new("locked", DataType::Boolean, false));
+    columns.push(Arc::new(BooleanArray::from(
+        rows.iter().map(|row| row.locked).collect::<Vec<_>>(),
+    )));
+    if rows.iter().any(|row| row.status.is_some()) {
+        fields.push(Field::new("status", DataType::Utf8, false));
+        columns.push(Arc::new(StringArray::from(
+            rows.iter()
+                .map(|row| row.status.unwrap_or_default())
+                .collect::<Vec<_>>(),
+        )));
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), columns).map_err(io::Error::other)?;
+    let mut data = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut data, schema, None).map_err(io::Error::other)?;
+    writer.write(&batch).map_err(io::Error::other)?;
+    writer.close().map_err(io::Error::other)?;
+    Ok(data)
+}
+
+#[cfg(not(feature = "parquet"))]
+fn parquet_report(_rows: &[AccountRow], _scale: u32) -> Result<Vec<u8>, EngineError> {
+    Err(EngineError::IoError(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Parquet output requires the `parquet` feature",
+    )))
+}
+
+// Row of the rejected transactions report
+#[derive(Serialize)]
+struct RejectRow {
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    client: u16,
+    tx: u32,
+    amount: Option<Decimal>,
+    reason: &'static str,
+}
+
+/// Writes the rejected transactions report in CSV format, with the code of the rejection reason.
+pub async fn write_rejects<'a, W: AsyncWrite + Unpin>(
+    rejected: impl Iterator<Item = &'a RejectedTransaction>,
+    wrt: W,
+) -> Result<(), EngineError> {
+    let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+    for rejected in rejected {
+        wrt.serialize(RejectRow {
+            tx_type: rejected.tx.tx_type,
+            client: rejected.tx.client_id,
+            tx: rejected.tx.tx_id,
+            amount: rejected.tx.amount,
+            reason: rejected.reason.code(),
+        })
+        .await?;
+    }
+    wrt.flush().await?;
+    Ok(())
+}
+
+// Row of the report of the locked accounts
+#[derive(Serialize)]
+struct LockRow {
+    client: u16,
+    tx: Option<u32>,
+    source: Option<usize>,
+    offset: Option<u64>,
+    timestamp: Option<u64>,
+}
+
+/// Writes the locked accounts in CSV format, with the chargeback that locked each one of them:
+/// its tx id, the index of the source it's been read from, its byte offset in the source and
+/// its timestamp. They are empty if unknown, see [`ClientAccount::lock_info`].
+pub async fn write_lock_report<'a, W: AsyncWrite + Unpin>(
+    accounts: impl Iterator<Item = &'a ClientAccount>,
+    wrt: W,
+) -> Result<(), EngineError> {
+    let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+    for account in accounts.filter(|account| account.locked) {
+        let lock = account.lock_info();
+        wrt.serialize(LockRow {
+            client: account.client_id,
+            tx: lock.map(|lock| lock.tx),
+            source: lock.map(|lock| lock.source),
+            offset: lock.map(|lock| lock.offset),
+            timestamp: lock.and_then(|lock| lock.timestamp),
+        })
+        .await?;
+    }
+    wrt.flush().await?;
+    Ok(())
+}
+
+// Row of the report of the transactions under dispute
+#[derive(Serialize)]
+struct DisputeRow<'a> {
+    client: u16,
+    tx: u32,
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    amount: Decimal,
+    currency: &'a str,
+    timestamp: Option<u64>,
+    age_secs: Option<u64>,
+    origin: Option<DisputeOrigin>,
+}
+
+/// Writes the transactions still under dispute in `engine`, in CSV format, sorted by client
+/// and tx id, with the amount held by their dispute.
+///
+/// When the transactions have timestamps, every row has the age of the transaction in seconds,
+/// up to the latest timestamp of the transactions applied, and the origin of the dispute is
+/// left empty when unknown.
+pub async fn write_open_disputes<W: AsyncWrite + Unpin>(
+    engine: &PaymentEngine,
+    wrt: W,
+) -> Result<(), EngineError> {
+    let latest = engine.latest_timestamp();
+    let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+    for (client, tx_id, tx) in engine.open_disputes()? {
+        wrt.serialize(DisputeRow {
+            client,
+            tx: tx_id,
+            tx_type: tx.kind,
+            amount: tx.held(),
+            currency: tx.currency.as_deref().unwrap_or_default(),
+            timestamp: tx.timestamp,
+            age_secs: latest
+                .zip(tx.timestamp)
+                .map(|(latest, timestamp)| latest.saturating_sub(timestamp)),
+            origin: tx.dispute_origin,
+        })
+        .await?;
+    }
+    wrt.flush().await?;
+    Ok(())
+}
+
+// Row of the ledger of applied transactions
+#[derive(Serialize)]
+struct LedgerRow<'a> {
+    processed_at: u64,
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    client: u16,
+    tx: u32,
+    amount: Option<Decimal>,
+    currency: &'a str,
+    status: Option<TransactionStatus>,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+}
+
+/// Writes the ledger of the transactions applied by `engine` in CSV format (see
+/// [`EngineBuilder::collect_ledger`](crate::EngineBuilder::collect_ledger)).
+///
+/// Every row has the time the transaction has been applied, in milliseconds since the Unix
+/// epoch, and the balances of the account right after it. The status is the final one of the
+/// transaction, or of the referenced one for disputes, resolves and chargebacks.
+pub async fn write_ledger<W: AsyncWrite + Unpin>(
+    engine: &PaymentEngine,
+    wrt: W,
+) -> Result<(), EngineError> {
+    let precision = engine.config().precision;
+    let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+    for entry in engine.ledger() {
+        let tx = &entry.tx;
+        let status = engine
+            .transaction(tx.client_id, tx.tx_id)?
+            .map(|stored| stored.status);
+        let processed_at = entry
+            .processed_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        wrt.serialize(LedgerRow {
+            processed_at,
+            tx_type: tx.tx_type,
+            client: tx.client_id,
+            tx: tx.tx_id,
+            amount: tx.amount.map(|amount| precision.apply(amount)),
+            currency: tx.currency.as_deref().unwrap_or_default(),
+            status,
+            available: entry.balances.available,
+            held: entry.balances.held,
+            total: entry.balances.total,
+            locked: entry.locked,
+        })
+        .await?;
+    }
+    wrt.flush().await?;
+    Ok(())
+}
+
+// Row of the end-of-day balances of the accounts
+#[derive(Serialize)]
+struct BalanceHistoryRow<'a> {
+    day: u64,
+    client: u16,
+    currency: &'a str,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+}
+
+/// Writes the end-of-day balances of the accounts collected by `engine` in CSV format (see
+/// [`EngineBuilder::collect_balance_history`](crate::EngineBuilder::collect_balance_history)),
+/// a row per day, client and currency, sorted in this order.
+///
+/// Days are given by the time they start at, in seconds since the Unix epoch.
+pub async fn write_balance_history<W: AsyncWrite + Unpin>(
+    engine: &PaymentEngine,
+    wrt: W,
+) -> Result<(), EngineError> {
+    let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+    for (day, client, currency, balances) in engine
+        .balance_history()
+        .into_iter()
+        .flat_map(BalanceHistory::iter)
+    {
+        wrt.serialize(BalanceHistoryRow {
+            day,
+            client,
+            currency,
+            available: balances.available,
+            held: balances.held,
+            total: balances.total,
+        })
+        .await?;
+    }
+    wrt.flush().await?;
+    Ok(())
+}
+
+// Row of the report of the accounts to triage
+#[derive(Serialize)]
+struct RiskRow {
+    ranking: &'static str,
+    rank: usize,
+    client: u16,
+    value: Decimal,
+}
+
+/// Writes the `n` accounts with the most funds held, with the most chargebacks and with the
+/// largest withdrawal in `engine` in CSV format (see [`PaymentEngine::risk_report`]), a
+/// ranking after the other: `held`, `chargebacks` and `withdrawals`.
+pub async fn write_risk_report<W: AsyncWrite + Unpin>(
+    engine: &PaymentEngine,
+    n: usize,
+    wrt: W,
+) -> Result<(), EngineError> {
+    let report = engine.risk_report(n)?;
+    let chargebacks = report
+        .chargebacks
+        .into_iter()
+        .map(|(client, count)| (client, Decimal::from(count)))
+        .collect();
+    let mut wrt = csv_async::AsyncSerializer::from_writer(wrt);
+    for (ranking, clients) in [
+        ("held", report.held),
+        ("chargebacks", chargebacks),
+        ("withdrawals", report.withdrawals),
+    ] {
+        for (i, (client, value)) in clients.into_iter().enumerate() {
+            wrt.serialize(RiskRow {
+                ranking,
+                rank: i + 1,
+                client,
+                value,
+            })
+            .await?;
+        }
+    }
+    wrt.flush().await?;
+    Ok(())
+}
+
+// An account with its transactions history
+#[derive(Serialize)]
+struct AccountDetail<'a> {
+    #[serde(flatten)]
+    account: &'a ClientAccount,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    currencies: &'a BTreeMap<CurrencyCode, Balances>,
+    transactions: Vec<HistoryRow<'a>>,
+}
+
+// Transaction of the history of an account
+#[derive(Serialize)]
+struct HistoryRow<'a> {
+    tx: u32,
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    amount: Decimal,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    currency: Option<&'a str>,
+    status: TransactionStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+}
+
+/// Writes the balances of an account and its transactions history, sorted by id, as a
+/// pretty-printed JSON object.
+pub async fn write_account<W: AsyncWrite + Unpin>(
+    account: &ClientAccount,
+    mut wrt: W,
+) -> Result<(), EngineError> {
+    let mut transactions: Vec<_> = account
+        .transactions()
+        .map(|(tx, stored)| HistoryRow {
+            tx,
+            tx_type: stored.kind,
+            amount: stored.amount,
+            currency: stored.currency.as_deref(),
+            status: stored.status,
+            timestamp: stored.timestamp,
+        })
+        .collect();
+    transactions.sort_unstable_by_key(|row| row.tx);
+    let detail = AccountDetail {
+        account,
+        currencies: &account.currencies,
+        transactions,
+    };
+    wrt.write_all(&serde_json::to_vec_pretty(&detail)?).await?;
+    wrt.write_all(b"\n").await?;
+    wrt.flush().await?;
+    Ok(())
+}
+
+// Report of a validation-only run
+#[derive(Serialize)]
+struct ValidationReport<'a> {
+    valid: bool,
+    sources: Vec<SourceReport<'a>>,
+    // Transactions rejected, by reason
+    rejects: BTreeMap<&'static str, u64>,
+}
+
+// Records of a source checked by a validation-only run
+#[derive(Serialize)]
+struct SourceReport<'a> {
+    source: &'a str,
+    #[serde(flatten)]
+    stats: &'a SourceStats,
+}
+
+/// Writes the report of a validation-only run as a pretty-printed JSON object: the records
+/// read from each source, how many of them are invalid or would be rejected, and the rejects
+/// by reason. The data is valid if there are neither.
+pub async fn write_validation<'a, W: AsyncWrite + Unpin>(
+    sources: impl Iterator<Item = (&'a str, &'a SourceStats)>,
+    rejected: &[RejectedTransaction],
+    mut wrt: W,
+) -> Result<(), EngineError> {
+    let sources: Vec<_> = sources
+        .map(|(source, stats)| SourceReport { source, stats })
+        .collect();
+    let mut rejects = BTreeMap::new();
+    for reject in rejected {
+        *rejects.entry(reject.reason.code()).or_insert(0) += 1;
+    }
+    let report = ValidationReport {
+        valid: sources
+            .iter()
+            .all(|source| source.stats.rejected + source.stats.invalid == 0),
+        sources,
+        rejects,
+    };
+    wrt.write_all(&serde_json::to_vec_pretty(&report)?).await?;
+    wrt.write_all(b"\n").await?;
+    wrt.flush().await?;
+    Ok(())
+}
+
+/// Writes the summary of a batch as a pretty-printed JSON object.
+pub async fn write_summary<W: AsyncWrite + Unpin>(
+    summary: &Summary,
+    mut wrt: W,
+) -> Result<(), EngineError> {
+    wrt.write_all(&serde_json::to_vec_pretty(summary)?).await?;
+    wrt.write_all(b"\n").await?;
+    wrt.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod output_tests {
+    use super::*;
+    use crate::{PaymentEngine, RejectReason, Transaction};
+
+    #[tokio::test]
+    async fn test_write_accounts_per_currency() {
+        let mut engine = PaymentEngine::new();
+        let txs = [
+            (1, 1, Some("EUR"), Decimal::TEN),
+            (1, 2, Some("USD"), Decimal::ONE),
+            (2, 3, None, Decimal::TWO),
+        ];
+        for (client_id, tx_id, currency, amount) in txs {
+            let mut tx = Transaction::new(TransactionType::Deposit, client_id, tx_id, Some(amount));
+            tx.currency = currency.map(String::from);
+            engine.apply(tx).unwrap();
+        }
+
+        let mut accounts: Vec<_> = engine.accounts().collect();
+        accounts.sort_by_key(|acc| acc.client_id);
+        let mut data = Vec::new();
+        write_accounts(accounts.into_iter(), &ReportOptions::default(), &mut data)
+            .await
+            .unwrap();
+        assert_eq!(
+            "client,currency,available,held,total,locked\n1,EUR,10,0,10,false\n1,USD,1,0,1,false\n2,,2,0,2,false\n",
+            String::from_utf8(data).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_accounts_anonymized() {
+        let account = ClientAccount::new(1);
+        let anonymizer = Anonymizer::new(b"key");
+        let options = ReportOptions {
+            anonymizer: Some(anonymizer.clone()),
+            ..Default::default()
+        };
+        let pseudonym = "6da91fb91517be1f5cdcf3af91d7d40c717dd638a306157606fb2e584f7ae926";
+
+        let mut data = Vec::new();
+        write_accounts([&account].into_iter(), &options, &mut data)
+            .await
+            .unwrap();
+        assert_eq!(
+            format!("client,available,held,total,locked\n{pseudonym},0,0,0,false\n"),
+            String::from_utf8(data).unwrap()
+        );
+        // Keys longer than a block are hashed
+        assert_eq!(
+            "de119ffd5c359df11a0b38aeb36ec8599823592772ca53816e428bff31cdc0ff",
+            Anonymizer::new(&[b'k'; 100]).pseudonym(2)
+        );
+
+        let mut mapping = Vec::new();
+        anonymizer
+            .write_mapping([1].into_iter(), &mut mapping)
+            .await
+            .unwrap();
+        assert_eq!(
+            format!("client,pseudonym\n1,{pseudonym}\n"),
+            String::from_utf8(mapping).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_accounts_rounded() {
+        let mut account = ClientAccount::new(1);
+        account.available = Decimal::new(123456, 5);
+        account.total = Decimal::new(123456, 5);
+        let options = ReportOptions {
+            precision: PrecisionPolicy::new(2, crate::Rounding::HalfUp),
+            ..Default::default()
+        };
+
+        let mut data = Vec::new();
+        write_accounts([&account].into_iter(), &options, &mut data)
+            .await
+            .unwrap();
+        assert_eq!(
+            "client,available,held,total,locked\n1,1.23,0,1.23,false\n",
+            String::from_utf8(data).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_accounts_scale() {
+        let mut account = ClientAccount::new(1);
+        account.available = Decimal::new(150, 2);
+        account.total = Decimal::new(150, 2);
+        for (scale, expected) in [
+            (OutputScale::Preserve, "1,1.50,0,1.50,false"),
+            (OutputScale::Fixed, "1,1.5000,0.0000,1.5000,false"),
+            (OutputScale::Minimal, "1,1.5,0,1.5,false"),
+        ] {
+            let options = ReportOptions {
+                scale,
+                ..Default::default()
+            };
+            let mut data = Vec::new();
+            write_accounts([&account].into_iter(), &options, &mut data)
+                .await
+                .unwrap();
+            assert_eq!(
+                format!("client,available,held,total,locked\n{expected}\n"),
+                String::from_utf8(data).unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_accounts_status() {
+        let (mut locked, mut closed) = (ClientAccount::new(2), ClientAccount::new(3));
+        locked.locked = true;
+        closed.closed = true;
+        let accounts = [ClientAccount::new(1), locked, closed];
+        let options = ReportOptions {
+            status_column: true,
+            ..Default::default()
+        };
+
+        let mut data = Vec::new();
+        write_accounts(accounts.iter(), &options, &mut data)
+            .await
+            .unwrap();
+        assert_eq!(
+            "client,available,held,total,locked,status\n1,0,0,0,false,active\n2,0,0,0,true,locked\n3,0,0,0,false,closed\n",
+            String::from_utf8(data).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_accounts_json() {
+        let mut account = ClientAccount::new(1);
+        account.available = Decimal::new(15, 1);
+        account.total = Decimal::new(15, 1);
+        let options = ReportOptions {
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+
+        let mut data = Vec::new();
+        write_accounts([&account].into_iter(), &options, &mut data)
+            .await
+            .unwrap();
+        assert_eq!(
+            "[{\"client\":1,\"available\":\"1.5\",\"held\":\"0\",\"total\":\"1.5\",\"locked\":false}]\n",
+            String::from_utf8(data).unwrap()
+        );
+
+        let options = ReportOptions {
+            format: OutputFormat::Parquet,
+            ..Default::default()
+        };
+        let mut data = Vec::new();
+        let result = write_accounts([&account].into_iter(), &options, &mut data).await;
+        #[cfg(feature = "parquet")]
+        assert!(result.is_ok() && data.starts_with(b"PAR1"));
+        #[cfg(not(feature = "parquet"))]
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_rejects() {
+        let rejected = [
+            RejectedTransaction {
+                tx: Transaction::new(TransactionType::Withdrawal, 2, 5, Some(Decimal::new(15, 1))),
+                reason: RejectReason::InsufficientFunds,
+            },
+            RejectedTransaction {
+                tx: Transaction::new(TransactionType::Dispute, 1, 9, None),
+                reason: RejectReason::TransactionNotFound,
+            },
+        ];
+
+        let mut data = Vec::new();
+        write_rejects(rejected.iter(), &mut data).await.unwrap();
+        assert_eq!(
+            "type,client,tx,amount,reason\nwithdrawal,2,5,1.5,insufficient_funds\ndispute,1,9,,tx_not_found\n",
+            String::from_utf8(data).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_account() {
+        let mut engine = PaymentEngine::new();
+        for tx in [
+            Transaction::new(TransactionType::Deposit, 1, 2, Some(Decimal::TEN)),
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE)),
+            Transaction::new(TransactionType::Dispute, 1, 2, None),
+        ] {
+            engine.apply(tx).unwrap();
+        }
+
+        let mut data = Vec::new();
+        write_account(engine.account(1).unwrap(), &mut data)
+            .await
+            .unwrap();
+        let detail: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(1, detail["client"]);
+        assert_eq!("10", detail["held"]);
+        assert_eq!(1, detail["transactions"][0]["tx"]);
+        assert_eq!("deposit", detail["transactions"][0]["type"]);
+        assert_eq!("Disputed", detail["transactions"][1]["status"]);
+    }
+
+    #[tokio::test]
+    async fn test_write_validation() {
+        let mut stats = SourceStats::default();
+        (stats.rows, stats.applied, stats.rejected, stats.invalid) = (3, 1, 1, 1);
+        let rejected = [RejectedTransaction {
+            tx: Transaction::new(TransactionType::Withdrawal, 2, 5, Some(Decimal::ONE)),
+            reason: RejectReason::InsufficientFunds,
+        }];
+
+        let mut data = Vec::new();
+        write_validation([("input.csv", &stats)].into_iter(), &rejected, &mut data)
+            .await
+            .unwrap();
+        let report: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(false, report["valid"]);
+        assert_eq!("input.csv", report["sources"][0]["source"]);
+        assert_eq!(1, report["sources"][0]["invalid"]);
+        assert_eq!(1, report["rejects"]["insufficient_funds"]);
+    }
+
+    #[tokio::test]
+    async fn test_write_open_disputes() {
+        let mut engine = PaymentEngine::new();
+        let mut deposit = Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::TEN));
+        deposit.timestamp = Some(1000);
+        let mut dispute = Transaction::new(TransactionType::Dispute, 1, 1, None);
+        dispute.timestamp = Some(4600);
+        dispute.origin = Some(DisputeOrigin::Bank);
+        for tx in [
+            deposit,
+            Transaction::new(TransactionType::Deposit, 2, 2, Some(Decimal::ONE)),
+            Transaction::new(TransactionType::Dispute, 2, 2, None),
+            Transaction::new(TransactionType::Resolve, 2, 2, None),
+            Transaction::new(TransactionType::Deposit, 3, 3, Some(Decimal::ONE)),
+            Transaction::new(TransactionType::Dispute, 3, 3, None),
+            dispute,
+        ] {
+            engine.apply(tx).unwrap();
+        }
+
+        let mut data = Vec::new();
+        write_open_disputes(&engine, &mut data).await.unwrap();
+        assert_eq!(
+            "client,tx,type,amount,currency,timestamp,age_secs,origin\n\
+             1,1,deposit,10,,1000,3600,bank\n3,3,deposit,1,,,,\n",
+            String::from_utf8(data).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_lock_report() {
+        let mut engine = PaymentEngine::new();
+        let mut chargeback = Transaction::new(TransactionType::Chargeback, 2, 2, None);
+        chargeback.offset = 120;
+        for tx in [
+            Transaction::new(TransactionType::Deposit, 1, 1, Some(Decimal::ONE)),
+            Transaction::new(TransactionType::Deposit, 2, 2, Some(Decimal::ONE)),
+            Transaction::new(TransactionType::Dispute, 2, 2, None),
+            chargeback,
+        ] {
+            engine.apply(tx).unwrap();
+        }
+        assert_eq!(2, engine.account(2).unwrap().lock_info().unwrap().tx);
+
+        let mut data = Vec::new();
+        write_lock_report(engine.sorted_accounts().into_iter(), &mut data)
+            .await
+            .unwrap();
+        assert_eq!(
+            "client,tx,source,offset,timestamp\n2,2,0,120,\n",
+            String::from_utf8(data).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_ledger() {
+        let data = "type,client,tx,amount\ndeposit,1,1,3.12345\ndispute,1,1,\nresolve,1,1,";
+        let mut engine = PaymentEngine::builder().collect_ledger(true).build();
+        engine
+            .process(crate::CsvSource::new(data.as_bytes()))
+            .await
+            .unwrap();
+
+        let mut data = Vec::new();
+        write_ledger(&engine, &mut data).await.unwrap();
+        let data = String::from_utf8(data).unwrap();
+        let rows: Vec<_> = data
+            .lines()
+            .map(|line| line.split_once(',').unwrap().1)
+            .collect();
+        assert_eq!(
+            vec![
+                "type,client,tx,amount,currency,status,available,held,total,locked",
+                "deposit,1,1,3.1234,,Resolved,3.1234,0,3.1234,false",
+                "dispute,1,1,,,Resolved,0.0000,3.1234,3.1234,false",
+                "resolve,1,1,,,Resolved,3.1234,0.0000,3.1234,false",
+            ],
+            rows
+        );
+    }
+}
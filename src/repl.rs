@@ -0,0 +1,193 @@
+use std::{fmt::Display, str::FromStr};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_stream::StreamExt;
+
+use crate::{
+    open_files, output, CsvDialect, CsvSource, EngineError, PaymentEngine, ReportOptions,
+    TransactionSource,
+};
+
+// Commands of the REPL
+const HELP: &str = "\
+<type> <client> <tx> [amount] [counterparty]  applies a transaction, e.g. `deposit 1 100 5.0`
+account <client>                             prints the balances and the history of a client
+tx <client> <tx>                             prints a transaction of a client and its status
+accounts                                     prints the accounts report
+disputes                                     prints the open disputes
+load <path>                                  applies the transactions of an input file
+help                                         prints this help
+quit                                         exits
+";
+
+// Columns of the transactions typed in the REPL
+const COLUMNS: [&str; 5] = ["type", "client", "tx", "amount", "counterparty"];
+
+/// Runs an interactive session on `engine`: reads a command per line from `input`, e.g. a
+/// transaction applied to the accounts or a query about them, and writes its outcome to
+/// `output`, until `quit` or the end of the input. Invalid commands and rejected transactions
+/// are reported without ending the session.
+pub async fn repl<R, W>(
+    engine: &mut PaymentEngine,
+    input: R,
+    mut output: W,
+) -> Result<(), EngineError>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = input.lines();
+    loop {
+        output.write_all(b"> ").await?;
+        output.flush().await?;
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        let words: Vec<_> = line.split_whitespace().collect();
+        let mut out = Vec::new();
+        match words.as_slice() {
+            [] => continue,
+            ["quit" | "exit"] => break,
+            ["help"] => out.extend_from_slice(HELP.as_bytes()),
+            words => {
+                if let Err(e) = command(engine, words, &mut out).await {
+                    out = format!("error: {e}\n").into_bytes();
+                }
+            }
+        }
+        output.write_all(&out).await?;
+    }
+    output.write_all(b"\n").await?;
+    output.flush().await?;
+    Ok(())
+}
+
+// Runs a command other than `help` and `quit`, writing its outcome to `out`
+async fn command(
+    engine: &mut PaymentEngine,
+    words: &[&str],
+    out: &mut Vec<u8>,
+) -> Result<(), String> {
+    match words {
+        ["account", client] => {
+            let client = parse(client, "client")?;
+            let account = engine
+                .account(client)
+                .ok_or(EngineError::ClientNotFound(client).to_string())?;
+            output::write_account(account, out)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        ["tx", client, tx_id] => {
+            let (client, tx_id) = (parse(client, "client")?, parse(tx_id, "tx")?);
+            let tx = engine
+                .transaction(client, tx_id)
+                .map_err(|e| e.to_string())?
+                .ok_or(format!("No tx {tx_id} for client {client}"))?;
+            let line = format!("{} {} {:?}\n", tx.kind.code(), tx.amount, tx.status);
+            out.extend_from_slice(line.as_bytes());
+            Ok(())
+        }
+        ["accounts"] => {
+            let accounts = engine.sorted_accounts();
+            output::write_accounts(accounts.into_iter(), &ReportOptions::default(), out)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        ["disputes"] => output::write_open_disputes(engine, out)
+            .await
+            .map_err(|e| e.to_string()),
+        ["load", path] => load(engine, path, out).await.map_err(|e| e.to_string()),
+        words => apply(engine, words, out).await,
+    }
+}
+
+// Applies the transaction typed in `words`, with the fields of `COLUMNS`
+async fn apply(
+    engine: &mut PaymentEngine,
+    words: &[&str],
+    out: &mut Vec<u8>,
+) -> Result<(), String> {
+    let dialect = CsvDialect {
+        columns: Some(COLUMNS.map(String::from).to_vec()),
+        ..Default::default()
+    };
+    let record = words.join(",");
+    let mut records = CsvSource::new(record.as_bytes())
+        .dialect(dialect)
+        .into_stream();
+    let tx = match records.next().await {
+        Some(Ok(tx)) => tx,
+        Some(Err(EngineError::UnknownTransactionType { .. })) | None => {
+            return Err(format!("Unknown command `{}`, see `help`", words[0]));
+        }
+        Some(Err(e)) => return Err(e.to_string()),
+    };
+    let outcome = match engine.apply(tx) {
+        Ok(()) => "applied\n".to_string(),
+        Err(reason) => format!("rejected: {}\n", reason.code()),
+    };
+    out.extend_from_slice(outcome.as_bytes());
+    Ok(())
+}
+
+// Applies the transactions of the input file at `path`, reporting how many were applied
+async fn load(
+    engine: &mut PaymentEngine,
+    path: &str,
+    out: &mut Vec<u8>,
+) -> Result<(), EngineError> {
+    let mut records = open_files(&[path], None, None, &CsvDialect::default(), 0).await?;
+    let (mut applied, mut rejected, mut invalid) = (0, 0, 0);
+    while let Some(record) = records.next().await {
+        match record {
+            Ok(tx) => match engine.apply(tx) {
+                Ok(()) => applied += 1,
+                Err(_) => rejected += 1,
+            },
+            Err(e) if e.is_invalid_record() => invalid += 1,
+            Err(e) => return Err(e),
+        }
+    }
+    let line = format!("applied {applied}, rejected {rejected}, invalid {invalid}\n");
+    out.extend_from_slice(line.as_bytes());
+    Ok(())
+}
+
+// Parses the `name` argument of a command
+fn parse<T: FromStr>(arg: &str, name: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    arg.parse()
+        .map_err(|e| format!("Invalid {name} `{arg}`: {e}"))
+}
+
+#[cfg(test)]
+mod repl_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_repl() {
+        let input = "deposit 1 1 5.0\n\nwithdrawal 1 2 6\ndispute 1 1\ntx 1 1\nrefund 1 3\n\
+                     deposit x 4 1\naccount 2\ndisputes\nquit\ndeposit 1 5 1\n";
+        let mut engine = PaymentEngine::new();
+        let mut output = Vec::new();
+        repl(&mut engine, input.as_bytes(), &mut output)
+            .await
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+
+        assert_eq!("> applied", lines[0]);
+        assert_eq!("> > rejected: insufficient_funds", lines[1]);
+        assert_eq!("> applied", lines[2]);
+        assert_eq!("> deposit 5.0 Disputed", lines[3]);
+        assert_eq!("> error: Unknown command `refund`, see `help`", lines[4]);
+        assert!(lines[5].starts_with("> error: Malformed record"));
+        assert_eq!("> error: No account for client 2", lines[6]);
+        assert!(lines[8].starts_with("1,1,deposit,5"));
+        assert_eq!(1, engine.accounts().count());
+        assert_eq!(None, engine.transaction(1, 5).unwrap());
+    }
+}
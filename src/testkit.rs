@@ -0,0 +1,215 @@
+//! Generation of synthetic transactions for tests.
+//!
+//! A [`TransactionGenerator`] yields the same transactions for the same seed and options, so
+//! that integration tests built on the engine are reproducible, e.g.
+//! `TransactionGenerator::new(42).clients(10).dispute_ratio(0.1).take(1000)`. The random
+//! numbers come from ChaCha8, whose output is the same on every platform and release.
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{Decimal, Transaction, TransactionType};
+
+/// How the transactions are spread over the clients.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ClientDistribution {
+    /// Every client is as likely as the others.
+    #[default]
+    Uniform,
+    /// Client `k` is `1 / k^exponent` as likely as client 1, e.g. to have a few clients with
+    /// most of the transactions.
+    Zipf { exponent: f64 },
+}
+
+/// An endless sequence of synthetic transactions: deposits and withdrawals of random amounts
+/// from random clients, disputes of previous deposits, and their resolutions or chargebacks.
+///
+/// Deposits and withdrawals have increasing tx ids from 1, and amounts with 4 decimal places
+/// up to 1000. Withdrawals may exceed the available funds, to be rejected.
+#[derive(Debug, Clone)]
+pub struct TransactionGenerator {
+    rng: ChaCha8Rng,
+    clients: u16,
+    distribution: ClientDistribution,
+    dispute_ratio: f64,
+    chargeback_ratio: f64,
+    deposit_ratio: f64,
+    // Cumulative weights of the clients with a Zipf distribution, computed once needed
+    weights: Vec<f64>,
+    // Deposits that can be disputed, and the ones under dispute
+    deposits: Vec<(u16, u32)>,
+    disputed: Vec<(u16, u32)>,
+    next_tx: u32,
+}
+
+impl TransactionGenerator {
+    /// Returns a generator seeded with `seed`, over 1000 clients distributed uniformly, with 1%
+    /// of disputes, 10% of them chargebacked, and 70% of deposits among the other transactions.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            clients: 1000,
+            distribution: ClientDistribution::Uniform,
+            dispute_ratio: 0.01,
+            chargeback_ratio: 0.1,
+            deposit_ratio: 0.7,
+            weights: Vec::new(),
+            deposits: Vec::new(),
+            disputed: Vec::new(),
+            next_tx: 1,
+        }
+    }
+
+    /// Spreads the transactions over clients 1 to `clients`.
+    pub fn clients(mut self, clients: u16) -> Self {
+        self.clients = clients.max(1);
+        self.weights.clear();
+        self
+    }
+
+    /// Sets how the transactions are spread over the clients.
+    pub fn client_distribution(mut self, distribution: ClientDistribution) -> Self {
+        self.distribution = distribution;
+        self.weights.clear();
+        self
+    }
+
+    /// Sets the share of the transactions disputing a previous deposit, between 0 and 1. As
+    /// many settle a dispute later on.
+    pub fn dispute_ratio(mut self, ratio: f64) -> Self {
+        self.dispute_ratio = ratio;
+        self
+    }
+
+    /// Sets the share of the disputes settled by a chargeback rather than a resolution,
+    /// between 0 and 1.
+    pub fn chargeback_ratio(mut self, ratio: f64) -> Self {
+        self.chargeback_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the share of deposits among the transactions neither disputing nor settling a
+    /// dispute, the others being withdrawals, between 0 and 1.
+    pub fn deposit_ratio(mut self, ratio: f64) -> Self {
+        self.deposit_ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    // Picks the client of a deposit or withdrawal
+    fn client(&mut self) -> u16 {
+        let exponent = match self.distribution {
+            ClientDistribution::Uniform => return self.rng.gen_range(1..=self.clients),
+            ClientDistribution::Zipf { exponent } => exponent,
+        };
+        if self.weights.is_empty() {
+            let mut total = 0.0;
+            self.weights = (1..=self.clients)
+                .map(|k| {
+                    total += 1.0 / f64::from(k).powf(exponent);
+                    total
+                })
+                .collect();
+        }
+        let roll = self.rng.gen::<f64>() * self.weights[self.weights.len() - 1];
+        let index = self.weights.partition_point(|&weight| weight <= roll);
+        index.min(self.weights.len() - 1) as u16 + 1
+    }
+}
+
+impl Iterator for TransactionGenerator {
+    type Item = Transaction;
+
+    fn next(&mut self) -> Option<Transaction> {
+        let roll: f64 = self.rng.gen();
+        let tx = if roll < self.dispute_ratio && !self.deposits.is_empty() {
+            let index = self.rng.gen_range(0..self.deposits.len());
+            let (client, tx) = self.deposits.swap_remove(index);
+            self.disputed.push((client, tx));
+            Transaction::new(TransactionType::Dispute, client, tx, None)
+        } else if roll < 2.0 * self.dispute_ratio && !self.disputed.is_empty() {
+            let index = self.rng.gen_range(0..self.disputed.len());
+            let (client, tx) = self.disputed.swap_remove(index);
+            let tx_type = if self.rng.gen_bool(self.chargeback_ratio) {
+                TransactionType::Chargeback
+            } else {
+                TransactionType::Resolve
+            };
+            Transaction::new(tx_type, client, tx, None)
+        } else {
+            let client = self.client();
+            let tx = self.next_tx;
+            self.next_tx = self.next_tx.wrapping_add(1);
+            let tx_type = if self.rng.gen_bool(self.deposit_ratio) {
+                self.deposits.push((client, tx));
+                TransactionType::Deposit
+            } else {
+                TransactionType::Withdrawal
+            };
+            let amount = Decimal::new(self.rng.gen_range(1..10_000_000), 4);
+            Transaction::new(tx_type, client, tx, Some(amount))
+        };
+        Some(tx)
+    }
+}
+
+#[cfg(test)]
+mod testkit_tests {
+    use super::*;
+    use crate::PaymentEngine;
+
+    #[test]
+    fn test_transaction_generator() {
+        let generator = TransactionGenerator::new(42)
+            .clients(100)
+            .dispute_ratio(0.2)
+            .chargeback_ratio(1.0)
+            .client_distribution(ClientDistribution::Zipf { exponent: 1.5 });
+        let txs: Vec<_> = generator.clone().take(2000).collect();
+        assert_eq!(txs, generator.take(2000).collect::<Vec<_>>());
+        assert_ne!(
+            txs,
+            TransactionGenerator::new(43).take(2000).collect::<Vec<_>>()
+        );
+
+        // The output of a seed is pinned, not to change with the dependencies
+        let first: Vec<_> = txs
+            .iter()
+            .take(3)
+            .map(|tx| (tx.tx_type, tx.client_id, tx.tx_id, tx.amount))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    TransactionType::Deposit,
+                    39,
+                    1,
+                    Some(Decimal::new(6273605, 4))
+                ),
+                (
+                    TransactionType::Deposit,
+                    1,
+                    2,
+                    Some(Decimal::new(7712488, 4))
+                ),
+                (
+                    TransactionType::Withdrawal,
+                    2,
+                    3,
+                    Some(Decimal::new(5929786, 4))
+                ),
+            ],
+            first
+        );
+
+        // No dispute is resolved, and the first clients get most of the transactions
+        assert!(txs.iter().all(|tx| tx.tx_type != TransactionType::Resolve));
+        let count = |client| txs.iter().filter(|tx| tx.client_id == client).count();
+        assert!(count(1) > count(2) && count(2) > count(50));
+        assert!(txs.iter().all(|tx| (1..=100).contains(&tx.client_id)));
+
+        let mut engine = PaymentEngine::new();
+        for tx in txs {
+            let _ = engine.apply(tx);
+        }
+        assert!(engine.summary().chargebacks > 0);
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use futures::future::BoxFuture;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    widgets::{Block, Gauge, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+use tokio::{sync::watch, task::JoinHandle};
+
+use crate::{input_bytes_read, AccountEvent, Decimal, EngineError, EventSink};
+
+// Time between two refreshes of the dashboard
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+// Accounts listed by volume
+const TOP_ACCOUNTS: usize = 10;
+
+/// A live view of a batch run in the terminal: the progress through the input files, the
+/// throughput, the accounts with the most volume and the rejected transactions by reason.
+///
+/// The dashboard is fed the events of the accounts as an [`EventSink`], and drawn by
+/// [`Dashboard::show`] until closed.
+#[derive(Debug, Clone, Default)]
+pub struct Dashboard {
+    stats: Arc<Mutex<Stats>>,
+}
+
+// Counts of the events sent to the dashboard
+#[derive(Debug, Default)]
+struct Stats {
+    transactions: u64,
+    // Funds deposited, withdrawn and transferred, by client
+    volume: HashMap<u16, Decimal>,
+    rejects: BTreeMap<&'static str, u64>,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of transactions applied or rejected so far.
+    pub fn transactions(&self) -> u64 {
+        self.stats().transactions
+    }
+
+    /// Returns the `n` clients with the most volume, i.e. funds deposited, withdrawn and
+    /// transferred, by decreasing volume.
+    pub fn top_accounts(&self, n: usize) -> Vec<(u16, Decimal)> {
+        let mut accounts: Vec<_> = self.stats().volume.clone().into_iter().collect();
+        accounts.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        accounts.truncate(n);
+        accounts
+    }
+
+    /// Returns the number of rejected transactions, by reason code.
+    pub fn rejects(&self) -> BTreeMap<&'static str, u64> {
+        self.stats().rejects.clone()
+    }
+
+    /// Draws the dashboard in the terminal until it's closed, with the progress through the
+    /// `total_bytes` of the input files, unknown if 0, see [`input_bytes_read`].
+    ///
+    /// The terminal doesn't send SIGINT while the dashboard is shown: pressing `q` or Ctrl-C
+    /// sends `true` to `interrupt` instead.
+    pub fn show(&self, total_bytes: u64, interrupt: Arc<watch::Sender<bool>>) -> DashboardHandle {
+        let (close, closed) = watch::channel(false);
+        let dashboard = self.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            let mut terminal = ratatui::try_init()?;
+            let result = dashboard.run(&mut terminal, total_bytes, &interrupt, &closed);
+            ratatui::restore();
+            result
+        });
+        DashboardHandle { close, task }
+    }
+
+    // Redraws the dashboard until `closed`, handling the keys pressed in the meantime
+    fn run(
+        &self,
+        terminal: &mut DefaultTerminal,
+        total_bytes: u64,
+        interrupt: &watch::Sender<bool>,
+        closed: &watch::Receiver<bool>,
+    ) -> io::Result<()> {
+        let started = Instant::now();
+        let mut last = (started, 0);
+        while !*closed.borrow() {
+            let (now, transactions) = (Instant::now(), self.transactions());
+            let rate = (transactions - last.1) as f64 / (now - last.0).as_secs_f64().max(1e-3);
+            last = (now, transactions);
+            terminal.draw(|frame| self.draw(frame, total_bytes, now - started, rate))?;
+            if event::poll(REFRESH_INTERVAL)? {
+                if let Event::Key(key) = event::read()? {
+                    let ctrl_c = key.code == KeyCode::Char('c')
+                        && key.modifiers.contains(KeyModifiers::CONTROL);
+                    if key.kind == KeyEventKind::Press && (key.code == KeyCode::Char('q') || ctrl_c)
+                    {
+                        interrupt.send_replace(true);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Draws the dashboard `elapsed` after the start, with the current throughput `rate`
+    fn draw(&self, frame: &mut Frame, total_bytes: u64, elapsed: Duration, rate: f64) {
+        let [progress, throughput, details] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(3),
+            Constraint::Min(0),
+        ])
+        .areas(frame.area());
+        let [accounts, rejects] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(details);
+
+        let read = input_bytes_read();
+        let (ratio, label) = match total_bytes {
+            0 => (0.0, format!("{} MB", read / 1_000_000)),
+            total => (
+                (read as f64 / total as f64).min(1.0),
+                format!("{} / {} MB", read / 1_000_000, total / 1_000_000),
+            ),
+        };
+        let gauge = Gauge::default()
+            .block(Block::bordered().title("Progress"))
+            .ratio(ratio)
+            .label(label);
+        frame.render_widget(gauge, progress);
+
+        let transactions = self.transactions();
+        let average = transactions as f64 / elapsed.as_secs_f64().max(1e-3);
+        let text = format!(
+            "{transactions} transactions in {:.1}s: {rate:.0}/s now, {average:.0}/s on average",
+            elapsed.as_secs_f64()
+        );
+        let paragraph = Paragraph::new(text).block(Block::bordered().title("Throughput"));
+        frame.render_widget(paragraph, throughput);
+
+        let rows = self
+            .top_accounts(TOP_ACCOUNTS)
+            .into_iter()
+            .map(|(client, volume)| Row::new([client.to_string(), volume.to_string()]));
+        let table = Table::new(rows, [Constraint::Length(8), Constraint::Min(12)])
+            .header(Row::new(["client", "volume"]))
+            .block(Block::bordered().title("Top accounts by volume"));
+        frame.render_widget(table, accounts);
+
+        let rows = self
+            .rejects()
+            .into_iter()
+            .map(|(reason, count)| Row::new([reason.to_string(), count.to_string()]));
+        let table = Table::new(rows, [Constraint::Min(24), Constraint::Length(10)])
+            .header(Row::new(["reason", "count"]))
+            .block(Block::bordered().title("Rejects"));
+        frame.render_widget(table, rejects);
+    }
+
+    // Counts an event of the accounts
+    fn record(&self, event: &AccountEvent) {
+        let mut stats = self.stats();
+        let mut add_volume = |client, amount: Decimal| {
+            let volume = stats.volume.entry(client).or_default();
+            *volume = volume.saturating_add(amount);
+        };
+        match event {
+            AccountEvent::FundsDeposited(funds) | AccountEvent::FundsWithdrawn(funds) => {
+                add_volume(funds.client, funds.amount);
+            }
+            AccountEvent::FundsTransferred {
+                funds,
+                counterparty,
+            } => {
+                add_volume(funds.client, funds.amount);
+                add_volume(*counterparty, funds.amount);
+            }
+            AccountEvent::TxRejected { reason, .. } => {
+                *stats.rejects.entry(reason.code()).or_default() += 1;
+            }
+            // Side effects of the transactions of other events
+            AccountEvent::FeeCharged(_) | AccountEvent::AccountLocked { .. } => return,
+            _ => {}
+        }
+        stats.transactions += 1;
+    }
+
+    fn stats(&self) -> MutexGuard<'_, Stats> {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl EventSink for Dashboard {
+    fn send<'a>(&'a mut self, event: &'a AccountEvent) -> BoxFuture<'a, Result<(), EngineError>> {
+        self.record(event);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// A [`Dashboard`] drawn in the terminal, see [`Dashboard::show`].
+pub struct DashboardHandle {
+    close: watch::Sender<bool>,
+    task: JoinHandle<io::Result<()>>,
+}
+
+impl DashboardHandle {
+    /// Stops drawing the dashboard, and restores the terminal.
+    pub async fn close(self) -> Result<(), EngineError> {
+        self.close.send_replace(true);
+        match self.task.await {
+            Ok(result) => Ok(result?),
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tui_tests {
+    use super::*;
+    use crate::{FundsMovement, RejectReason, TransactionType};
+
+    #[tokio::test]
+    async fn test_dashboard() {
+        let funds = |client, tx, amount| FundsMovement {
+            client,
+            tx,
+            amount: Decimal::new(amount, 0),
+            currency: None,
+        };
+        let mut dashboard = Dashboard::new();
+        let events = [
+            AccountEvent::FundsDeposited(funds(1, 1, 10)),
+            AccountEvent::FeeCharged(funds(1, 1, 1)),
+            AccountEvent::FundsDeposited(funds(2, 2, 5)),
+            AccountEvent::FundsTransferred {
+                funds: funds(2, 3, 4),
+                counterparty: 3,
+            },
+            AccountEvent::TxRejected {
+                client: 3,
+                tx: 4,
+                tx_type: TransactionType::Withdrawal,
+                reason: RejectReason::InsufficientFunds,
+            },
+        ];
+        for event in &events {
+            dashboard.send(event).await.unwrap();
+        }
+
+        assert_eq!(4, dashboard.transactions());
+        assert_eq!(
+            vec![(1, Decimal::TEN), (2, Decimal::new(9, 0))],
+            dashboard.top_accounts(2)
+        );
+        assert_eq!(Some(&1), dashboard.rejects().get("insufficient_funds"));
+    }
+}